@@ -0,0 +1 @@
+ALTER TABLE event ADD COLUMN geo_location TEXT;
@@ -0,0 +1 @@
+ALTER TABLE devices DROP COLUMN twofactor_remember_at;
@@ -0,0 +1 @@
+ALTER TABLE users ADD COLUMN delete_scheduled_at DATETIME DEFAULT NULL;
@@ -0,0 +1,4 @@
+DROP INDEX devices_refresh_token_family ON devices;
+ALTER TABLE devices DROP COLUMN refresh_token_rotated_at;
+ALTER TABLE devices DROP COLUMN previous_refresh_token;
+ALTER TABLE devices DROP COLUMN refresh_token_family;
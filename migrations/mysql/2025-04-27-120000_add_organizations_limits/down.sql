@@ -0,0 +1,2 @@
+ALTER TABLE organizations DROP COLUMN max_seats;
+ALTER TABLE organizations DROP COLUMN max_collections;
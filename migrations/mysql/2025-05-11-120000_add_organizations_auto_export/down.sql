@@ -0,0 +1,2 @@
+ALTER TABLE organizations DROP COLUMN auto_export_mail;
+ALTER TABLE organizations DROP COLUMN auto_export;
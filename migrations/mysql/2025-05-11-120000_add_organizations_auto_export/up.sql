@@ -0,0 +1,2 @@
+ALTER TABLE organizations ADD COLUMN auto_export BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE organizations ADD COLUMN auto_export_mail BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,4 @@
+ALTER TABLE devices ADD COLUMN twofactor_remember_at TIMESTAMP DEFAULT NULL;
+
+-- Existing remember tokens get the last update of the device as their starting point
+UPDATE devices SET twofactor_remember_at = updated_at WHERE twofactor_remember IS NOT NULL;
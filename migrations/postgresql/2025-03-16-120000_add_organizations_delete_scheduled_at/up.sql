@@ -0,0 +1,2 @@
+ALTER TABLE organizations ADD COLUMN delete_scheduled_at TIMESTAMP DEFAULT NULL;
+ALTER TABLE organizations ADD COLUMN delete_requested_at TIMESTAMP DEFAULT NULL;
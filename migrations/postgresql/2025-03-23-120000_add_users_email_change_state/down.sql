@@ -0,0 +1,2 @@
+ALTER TABLE users DROP COLUMN email_new_requested_at;
+ALTER TABLE users DROP COLUMN email_new_confirmed_at;
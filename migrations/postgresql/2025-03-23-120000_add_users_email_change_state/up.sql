@@ -0,0 +1,2 @@
+ALTER TABLE users ADD COLUMN email_new_requested_at TIMESTAMP DEFAULT NULL;
+ALTER TABLE users ADD COLUMN email_new_confirmed_at TIMESTAMP DEFAULT NULL;
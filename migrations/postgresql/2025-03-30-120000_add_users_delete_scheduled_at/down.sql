@@ -0,0 +1 @@
+ALTER TABLE users DROP COLUMN delete_scheduled_at;
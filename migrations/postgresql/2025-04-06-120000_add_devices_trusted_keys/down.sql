@@ -0,0 +1,3 @@
+ALTER TABLE devices DROP COLUMN encrypted_user_key;
+ALTER TABLE devices DROP COLUMN encrypted_public_key;
+ALTER TABLE devices DROP COLUMN encrypted_private_key;
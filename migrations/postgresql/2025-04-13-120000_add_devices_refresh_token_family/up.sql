@@ -0,0 +1,5 @@
+ALTER TABLE devices ADD COLUMN refresh_token_family TEXT;
+ALTER TABLE devices ADD COLUMN previous_refresh_token TEXT;
+ALTER TABLE devices ADD COLUMN refresh_token_rotated_at TIMESTAMP DEFAULT NULL;
+
+CREATE INDEX devices_refresh_token_family ON devices (refresh_token_family);
@@ -0,0 +1,14 @@
+CREATE TABLE organization_domains (
+	uuid                CHAR(36) NOT NULL PRIMARY KEY,
+	org_uuid            CHAR(36) NOT NULL,
+	domain_name         VARCHAR(255) NOT NULL,
+	txt                 TEXT NOT NULL,
+	creation_date       TIMESTAMP NOT NULL,
+	verified_date       TIMESTAMP,
+	last_checked_date   TIMESTAMP,
+	job_run_count       INTEGER NOT NULL DEFAULT 0,
+	FOREIGN KEY(org_uuid) REFERENCES organizations(uuid)
+);
+
+-- A domain can only belong to one organization
+CREATE UNIQUE INDEX organization_domains_domain_name ON organization_domains (domain_name);
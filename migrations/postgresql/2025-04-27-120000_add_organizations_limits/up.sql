@@ -0,0 +1,2 @@
+ALTER TABLE organizations ADD COLUMN max_seats INTEGER DEFAULT NULL;
+ALTER TABLE organizations ADD COLUMN max_collections INTEGER DEFAULT NULL;
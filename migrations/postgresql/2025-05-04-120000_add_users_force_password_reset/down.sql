@@ -0,0 +1 @@
+ALTER TABLE users DROP COLUMN force_password_reset;
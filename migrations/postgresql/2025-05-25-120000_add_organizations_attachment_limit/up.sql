@@ -0,0 +1 @@
+ALTER TABLE organizations ADD COLUMN attachment_limit BIGINT DEFAULT NULL;
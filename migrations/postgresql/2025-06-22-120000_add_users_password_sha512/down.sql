@@ -0,0 +1 @@
+ALTER TABLE users DROP COLUMN password_sha512;
@@ -0,0 +1,2 @@
+ALTER TABLE organizations DROP COLUMN delete_requested_at;
+ALTER TABLE organizations DROP COLUMN delete_scheduled_at;
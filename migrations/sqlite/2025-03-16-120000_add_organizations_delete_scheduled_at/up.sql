@@ -0,0 +1,2 @@
+ALTER TABLE organizations ADD COLUMN delete_scheduled_at DATETIME DEFAULT NULL;
+ALTER TABLE organizations ADD COLUMN delete_requested_at DATETIME DEFAULT NULL;
@@ -0,0 +1,14 @@
+CREATE TABLE organization_domains (
+	uuid                TEXT NOT NULL PRIMARY KEY,
+	org_uuid            TEXT NOT NULL,
+	domain_name         TEXT NOT NULL,
+	txt                 TEXT NOT NULL,
+	creation_date       DATETIME NOT NULL,
+	verified_date       DATETIME,
+	last_checked_date   DATETIME,
+	job_run_count       INTEGER NOT NULL DEFAULT 0,
+	FOREIGN KEY(org_uuid) REFERENCES organizations(uuid)
+);
+
+-- A domain can only belong to one organization
+CREATE UNIQUE INDEX organization_domains_domain_name ON organization_domains (domain_name);
@@ -0,0 +1 @@
+ALTER TABLE users_organizations ADD COLUMN invited_at DATETIME DEFAULT NULL;
@@ -0,0 +1 @@
+ALTER TABLE users ADD COLUMN password_sha512 BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,6 @@
+CREATE TABLE login_backoffs (
+    backoff_key   TEXT NOT NULL PRIMARY KEY,
+    failures      INTEGER NOT NULL,
+    last_failure  BIGINT NOT NULL,
+    blocked_until BIGINT NOT NULL
+);
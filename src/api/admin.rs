@@ -7,7 +7,7 @@ use std::env;
 use rocket::serde::json::Json;
 use rocket::{
     form::Form,
-    http::{Cookie, CookieJar, MediaType, SameSite, Status},
+    http::{ContentType, Cookie, CookieJar, Header, MediaType, SameSite, Status},
     request::{FromRequest, Outcome, Request},
     response::{content::RawHtml as Html, Redirect},
     Catcher, Route,
@@ -15,8 +15,8 @@ use rocket::{
 
 use crate::{
     api::{
-        core::{log_event, two_factor},
-        unregister_push_device, ApiResult, EmptyResult, JsonResult, Notify,
+        core::{delete_user_account, invite_members_bulk, log_event, log_user_event, two_factor},
+        send_user_webhook, unregister_push_device, ApiResult, EmptyResult, JsonResult, Notify, UserWebhookEvent,
     },
     auth::{decode_admin, encode_jwt, generate_admin_claims, ClientIp, Secure},
     config::ConfigBuilder,
@@ -41,6 +41,8 @@ pub fn routes() -> Vec<Route> {
         get_user_json,
         get_user_by_mail_json,
         post_admin_login,
+        generate_admin_totp,
+        enable_admin_totp,
         admin_page,
         admin_page_login,
         invite_user,
@@ -55,14 +57,24 @@ pub fn routes() -> Vec<Route> {
         post_config,
         delete_config,
         backup_db,
+        export_config,
+        import_config,
         test_smtp,
         users_overview,
+        get_users_weak_kdf,
         organizations_overview,
+        update_organization_limits,
+        invite_organization_members,
+        update_organization_auto_export,
         delete_organization,
         diagnostics,
         get_diagnostics_config,
         resend_user_invite,
         get_diagnostics_http,
+        data_browser,
+        data_browser_export,
+        get_usage_stats,
+        get_usage_stats_export,
     ]
 }
 
@@ -138,7 +150,16 @@ enum AdminResponse {
     #[response(status = 401)]
     Unauthorized(ApiResult<Html<String>>),
     #[response(status = 429)]
-    TooManyRequests(ApiResult<Html<String>>),
+    TooManyRequests(ApiResult<Html<String>>, Header<'static>),
+}
+
+impl AdminResponse {
+    fn too_many_requests(retry_after: u64, redirect: Option<String>) -> Self {
+        Self::TooManyRequests(
+            render_admin_login(Some("Too many requests, try again later."), redirect),
+            Header::new("Retry-After", retry_after.to_string()),
+        )
+    }
 }
 
 #[catch(401)]
@@ -156,6 +177,8 @@ fn render_admin_login(msg: Option<&str>, redirect: Option<String>) -> ApiResult<
     let json = json!({
         "page_content": "admin/login",
         "error": msg,
+        "token_required": !CONFIG.disable_admin_token(),
+        "totp_required": CONFIG.admin_totp_secret().is_some(),
         "redirect": redirect,
         "urlpath": CONFIG.domain_path()
     });
@@ -167,32 +190,44 @@ fn render_admin_login(msg: Option<&str>, redirect: Option<String>) -> ApiResult<
 
 #[derive(FromForm)]
 struct LoginForm {
-    token: String,
+    // Not sent when the token is disabled and only the TOTP code is asked
+    token: Option<String>,
+    totp: Option<String>,
     redirect: Option<String>,
 }
 
 #[post("/", format = "application/x-www-form-urlencoded", data = "<data>")]
-fn post_admin_login(
+async fn post_admin_login(
     data: Form<LoginForm>,
     cookies: &CookieJar<'_>,
     ip: ClientIp,
     secure: Secure,
+    mut conn: DbConn,
 ) -> Result<Redirect, AdminResponse> {
     let data = data.into_inner();
     let redirect = data.redirect;
 
-    if crate::ratelimit::check_limit_admin(&ip.ip).is_err() {
-        return Err(AdminResponse::TooManyRequests(render_admin_login(
-            Some("Too many requests, try again later."),
-            redirect,
-        )));
+    let backoff_key = crate::ratelimit::admin_backoff_key(&ip.ip);
+    let limited = match crate::ratelimit::check_limit_admin(&ip.ip) {
+        Ok(()) => crate::ratelimit::check_backoff(&backoff_key, "Too many failed admin logins", &mut conn).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = limited {
+        return Err(AdminResponse::too_many_requests(e.retry_after().unwrap_or(1), redirect));
     }
 
     // If the token is invalid, redirect to login page
-    if !_validate_token(&data.token) {
+    if !CONFIG.disable_admin_token() && !data.token.as_deref().is_some_and(_validate_token) {
         error!("Invalid admin token. IP: {}", ip.ip);
+        crate::ratelimit::register_failure(&backoff_key, &mut conn).await.ok();
         Err(AdminResponse::Unauthorized(render_admin_login(Some("Invalid admin token, please try again."), redirect)))
+    } else if !_validate_admin_totp(data.totp.as_deref()) {
+        error!("Invalid admin TOTP code. IP: {}", ip.ip);
+        crate::ratelimit::register_failure(&backoff_key, &mut conn).await.ok();
+        Err(AdminResponse::Unauthorized(render_admin_login(Some("Invalid TOTP code, please try again."), redirect)))
     } else {
+        crate::ratelimit::reset_failures(&backoff_key, &mut conn).await.ok();
+
         // If the token received is valid, generate JWT and save it as a cookie
         let claims = generate_admin_claims();
         let jwt = encode_jwt(&claims);
@@ -213,6 +248,80 @@ fn post_admin_login(
     }
 }
 
+// The last time step for which a valid admin TOTP code was used, to prevent replays
+static ADMIN_TOTP_LAST_STEP: std::sync::atomic::AtomicI64 = std::sync::atomic::AtomicI64::new(0);
+
+fn _check_totp_code(secret: &str, code: &str, prevent_replay: bool) -> bool {
+    use std::sync::atomic::Ordering;
+    use totp_lite::{totp_custom, Sha1};
+
+    let Ok(decoded_secret) = data_encoding::BASE32.decode(secret.as_bytes()) else {
+        return false;
+    };
+
+    let steps = i64::from(CONFIG.authenticator_time_drift_steps());
+    let current_timestamp = chrono::Utc::now().timestamp();
+    for step in -steps..=steps {
+        let time_step = current_timestamp / 30i64 + step;
+        let generated = totp_custom::<Sha1>(30, 6, &decoded_secret, (current_timestamp + step * 30i64) as u64);
+        if !crate::crypto::ct_eq(&generated, code.trim()) {
+            continue;
+        }
+        if !prevent_replay {
+            return true;
+        }
+        // Only time steps larger then the last used one are allowed
+        return ADMIN_TOTP_LAST_STEP.fetch_max(time_step, Ordering::SeqCst) < time_step;
+    }
+    false
+}
+
+// Always valid when no admin TOTP secret is configured
+fn _validate_admin_totp(code: Option<&str>) -> bool {
+    match (CONFIG.admin_totp_secret(), code) {
+        (None, _) => true,
+        (Some(secret), Some(code)) => _check_totp_code(&secret, code, true),
+        (Some(_), None) => false,
+    }
+}
+
+#[post("/totp/generate", format = "application/json")]
+fn generate_admin_totp(_token: AdminToken) -> JsonResult {
+    let secret = crate::crypto::encode_random_bytes::<20>(data_encoding::BASE32);
+    let label = format!("Vaultwarden Admin ({})", CONFIG.domain());
+    let uri = format!(
+        "otpauth://totp/{}?secret={secret}&issuer={}",
+        percent_encoding::utf8_percent_encode(&label, percent_encoding::NON_ALPHANUMERIC),
+        percent_encoding::utf8_percent_encode("Vaultwarden Admin", percent_encoding::NON_ALPHANUMERIC),
+    );
+
+    Ok(Json(json!({
+        "secret": secret,
+        "uri": uri,
+    })))
+}
+
+#[derive(Deserialize)]
+struct EnableAdminTotpData {
+    secret: String,
+    code: String,
+}
+
+#[post("/totp/enable", format = "application/json", data = "<data>")]
+fn enable_admin_totp(data: Json<EnableAdminTotpData>, _token: AdminToken, ip: ClientIp) -> EmptyResult {
+    let data: EnableAdminTotpData = data.into_inner();
+    if !_check_totp_code(&data.secret, &data.code, false) {
+        err!("Invalid TOTP code, make sure the time of the server and your device are correct")
+    }
+
+    let builder: ConfigBuilder = serde_json::from_value(json!({ "admin_totp_secret": data.secret }))?;
+    if let Err(e) = CONFIG.update_config_partial(builder) {
+        err!(format!("Unable to save config: {e:?}"))
+    }
+    warn!("Admin two-step login enabled. IP: {}", ip.ip);
+    Ok(())
+}
+
 fn _validate_token(token: &str) -> bool {
     match CONFIG.admin_token().as_ref() {
         None => false,
@@ -260,6 +369,7 @@ fn render_admin_page() -> ApiResult<Html<String>> {
     let settings_json = json!({
         "config": CONFIG.prepare_json(),
         "can_backup": *CAN_BACKUP,
+        "admin_totp_enabled": CONFIG.admin_totp_secret().is_some(),
     });
     let text = AdminTemplateData::new("admin/settings", settings_json).render()?;
     Ok(Html(text))
@@ -311,6 +421,7 @@ async fn invite_user(data: Json<InviteData>, _token: AdminToken, mut conn: DbCon
 
     _generate_invite(&user, &mut conn).await.map_err(|e| e.with_code(Status::InternalServerError.code))?;
     user.save(&mut conn).await.map_err(|e| e.with_code(Status::InternalServerError.code))?;
+    send_user_webhook(UserWebhookEvent::Invited, &user.uuid, &user.email);
 
     Ok(Json(user.to_json(&mut conn).await))
 }
@@ -339,6 +450,8 @@ async fn get_users_json(_token: AdminToken, mut conn: DbConn) -> Json<Value> {
     for u in users {
         let mut usr = u.to_json(&mut conn).await;
         usr["userEnabled"] = json!(u.enabled);
+        usr["emailChangePending"] = json!(u.email_new);
+        usr["deleteScheduledAt"] = json!(u.delete_scheduled_at.map(|dt| format_naive_datetime_local(&dt, DT_FMT)));
         usr["createdAt"] = json!(format_naive_datetime_local(&u.created_at, DT_FMT));
         usr["lastActive"] = match u.last_active(&mut conn).await {
             Some(dt) => json!(format_naive_datetime_local(&dt, DT_FMT)),
@@ -359,7 +472,12 @@ async fn users_overview(_token: AdminToken, mut conn: DbConn) -> ApiResult<Html<
         usr["cipher_count"] = json!(Cipher::count_owned_by_user(&u.uuid, &mut conn).await);
         usr["attachment_count"] = json!(Attachment::count_by_user(&u.uuid, &mut conn).await);
         usr["attachment_size"] = json!(get_display_size(Attachment::size_by_user(&u.uuid, &mut conn).await));
+        usr["attachment_limit"] =
+            json!(CONFIG.user_attachment_limit().map(|kb| get_display_size(kb.saturating_mul(1024))));
         usr["user_enabled"] = json!(u.enabled);
+        usr["email_change_pending"] = json!(u.email_new);
+        usr["delete_scheduled_at"] = json!(u.delete_scheduled_at.map(|dt| format_naive_datetime_local(&dt, DT_FMT)));
+        usr["weak_kdf"] = json!(u.has_weak_kdf());
         usr["created_at"] = json!(format_naive_datetime_local(&u.created_at, DT_FMT));
         usr["last_active"] = match u.last_active(&mut conn).await {
             Some(dt) => json!(format_naive_datetime_local(&dt, DT_FMT)),
@@ -372,6 +490,28 @@ async fn users_overview(_token: AdminToken, mut conn: DbConn) -> ApiResult<Html<
     Ok(Html(text))
 }
 
+// Accounts with a client side KDF below the configured minimum, the users need to change it in their account settings
+#[get("/users/weak-kdf")]
+async fn get_users_weak_kdf(_token: AdminToken, mut conn: DbConn) -> Json<Value> {
+    let users: Vec<Value> = User::get_all(&mut conn)
+        .await
+        .into_iter()
+        .filter(|u| u.has_weak_kdf())
+        .map(|u| {
+            json!({
+                "id": u.uuid,
+                "email": u.email,
+                "kdf": u.client_kdf_type,
+                "kdfIterations": u.client_kdf_iter,
+                "kdfMemory": u.client_kdf_memory,
+                "kdfParallelism": u.client_kdf_parallelism,
+            })
+        })
+        .collect();
+
+    Json(Value::Array(users))
+}
+
 #[get("/users/by-mail/<mail>")]
 async fn get_user_by_mail_json(mail: &str, _token: AdminToken, mut conn: DbConn) -> JsonResult {
     if let Some(u) = User::find_by_mail(mail, &mut conn).await {
@@ -394,12 +534,12 @@ async fn get_user_json(user_id: UserId, _token: AdminToken, mut conn: DbConn) ->
 }
 
 #[post("/users/<user_id>/delete", format = "application/json")]
-async fn delete_user(user_id: UserId, token: AdminToken, mut conn: DbConn) -> EmptyResult {
+async fn delete_user(user_id: UserId, token: AdminToken, mut conn: DbConn, nt: Notify<'_>) -> EmptyResult {
     let user = get_user_or_404(&user_id, &mut conn).await?;
 
     // Get the membership records before deleting the actual user
     let memberships = Membership::find_any_state_by_user(&user_id, &mut conn).await;
-    let res = user.delete(&mut conn).await;
+    let res = delete_user_account(user, &nt, &mut conn).await;
 
     for membership in memberships {
         log_event(
@@ -441,6 +581,15 @@ async fn deauth_user(user_id: UserId, _token: AdminToken, mut conn: DbConn, nt:
 #[post("/users/<user_id>/disable", format = "application/json")]
 async fn disable_user(user_id: UserId, _token: AdminToken, mut conn: DbConn, nt: Notify<'_>) -> EmptyResult {
     let mut user = get_user_or_404(&user_id, &mut conn).await?;
+
+    if CONFIG.push_enabled() {
+        for device in Device::find_push_devices_by_user(&user.uuid, &mut conn).await {
+            if let Err(e) = unregister_push_device(device.push_uuid).await {
+                error!("Unable to unregister devices from Bitwarden server: {}", e);
+            }
+        }
+    }
+
     Device::delete_all_by_user(&user.uuid, &mut conn).await?;
     user.reset_security_stamp();
     user.enabled = false;
@@ -463,10 +612,17 @@ async fn enable_user(user_id: UserId, _token: AdminToken, mut conn: DbConn) -> E
 #[post("/users/<user_id>/remove-2fa", format = "application/json")]
 async fn remove_2fa(user_id: UserId, token: AdminToken, mut conn: DbConn) -> EmptyResult {
     let mut user = get_user_or_404(&user_id, &mut conn).await?;
-    TwoFactor::delete_all_by_user(&user.uuid, &mut conn).await?;
+    TwoFactor::delete_two_step_login_by_user(&user.uuid, &mut conn).await?;
     two_factor::enforce_2fa_policy(&user, &ACTING_ADMIN_USER.into(), 14, &token.ip.ip, &mut conn).await?;
     user.totp_recover = None;
-    user.save(&mut conn).await
+    user.save(&mut conn).await?;
+
+    warn!(
+        "All two-step login methods of user {} have been removed via the admin panel. IP: {}",
+        user.email, token.ip.ip
+    );
+    log_user_event(EventType::UserDisabled2fa as i32, &user.uuid, 14, &token.ip.ip, &mut conn).await;
+    Ok(())
 }
 
 #[post("/users/<user_id>/invite/resend", format = "application/json")]
@@ -532,6 +688,9 @@ async fn update_membership_type(data: Json<MembershipTypeData>, token: AdminToke
             Err(OrgPolicyErr::SingleOrgEnforced) => {
                 err!("You cannot modify this user to this type because it is a member of an organization which forbids it");
             }
+            Err(OrgPolicyErr::SingleOrgOtherMembership) => {
+                err!("You cannot modify this user to this type because this organization forbids its members from being a member of any other organization");
+            }
         }
     }
 
@@ -568,6 +727,10 @@ async fn organizations_overview(_token: AdminToken, mut conn: DbConn) -> ApiResu
         org["event_count"] = json!(Event::count_by_org(&o.uuid, &mut conn).await);
         org["attachment_count"] = json!(Attachment::count_by_org(&o.uuid, &mut conn).await);
         org["attachment_size"] = json!(get_display_size(Attachment::size_by_org(&o.uuid, &mut conn).await));
+        org["attachment_limit"] = json!(o.attachment_limit);
+        org["attachment_limit_size"] = json!(o.attachment_limit.map(|kb| get_display_size(kb.saturating_mul(1024))));
+        org["auto_export"] = json!(o.auto_export);
+        org["auto_export_mail"] = json!(o.auto_export_mail);
         organizations_json.push(org);
     }
 
@@ -575,6 +738,98 @@ async fn organizations_overview(_token: AdminToken, mut conn: DbConn) -> ApiResu
     Ok(Html(text))
 }
 
+#[derive(Deserialize)]
+struct OrgLimitsData {
+    max_seats: Option<i32>,
+    max_collections: Option<i32>,
+    // Attachment storage limit (KB), 0 disables attachments for the organization
+    attachment_limit: Option<i64>,
+}
+
+#[post("/organizations/<org_id>/limits", format = "application/json", data = "<data>")]
+async fn update_organization_limits(
+    org_id: OrganizationId,
+    data: Json<OrgLimitsData>,
+    _token: AdminToken,
+    mut conn: DbConn,
+) -> EmptyResult {
+    let data = data.into_inner();
+    if data.max_seats.is_some_and(|s| s < 1) || data.max_collections.is_some_and(|c| c < 1) {
+        err!("A limit must be at least 1, or empty for no limit")
+    }
+    if data.attachment_limit.is_some_and(|l| !(0..=i64::MAX >> 10).contains(&l)) {
+        err!("The attachment storage limit is out of bounds")
+    }
+
+    let mut org = Organization::find_by_uuid(&org_id, &mut conn).await.map_res("Organization doesn't exist")?;
+    org.max_seats = data.max_seats;
+    org.max_collections = data.max_collections;
+    org.attachment_limit = data.attachment_limit;
+    org.save(&mut conn).await
+}
+
+#[derive(Deserialize)]
+struct OrgAutoExportData {
+    enabled: bool,
+}
+
+#[post("/organizations/<org_id>/auto-export", format = "application/json", data = "<data>")]
+async fn update_organization_auto_export(
+    org_id: OrganizationId,
+    data: Json<OrgAutoExportData>,
+    _token: AdminToken,
+    mut conn: DbConn,
+) -> EmptyResult {
+    let mut org = Organization::find_by_uuid(&org_id, &mut conn).await.map_res("Organization doesn't exist")?;
+    org.auto_export = data.into_inner().enabled;
+    org.save(&mut conn).await
+}
+
+#[derive(Deserialize)]
+struct OrgInviteData {
+    // The email addresses, separated by commas, semicolons or whitespace
+    emails: String,
+}
+
+#[post("/organizations/<org_id>/invite", format = "application/json", data = "<data>")]
+async fn invite_organization_members(
+    org_id: OrganizationId,
+    data: Json<OrgInviteData>,
+    token: AdminToken,
+    mut conn: DbConn,
+) -> JsonResult {
+    let org = Organization::find_by_uuid(&org_id, &mut conn).await.map_res("Organization doesn't exist")?;
+    let emails: Vec<String> = data
+        .into_inner()
+        .emails
+        .split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+        .filter(|e| !e.is_empty())
+        .map(String::from)
+        .collect();
+    if emails.is_empty() {
+        err!("No email addresses given")
+    }
+
+    // The billing email of the organization is notified when an invitation is accepted
+    let invited_by = Some(org.billing_email.clone());
+    let results = invite_members_bulk(&org, emails, MembershipType::User as i32, false, invited_by, &mut conn).await;
+
+    for member_id in results.iter().filter_map(|r| r.member_id.as_ref()) {
+        log_event(
+            EventType::OrganizationUserInvited as i32,
+            member_id,
+            &org_id,
+            &ACTING_ADMIN_USER.into(),
+            14, // Use UnknownBrowser type
+            &token.ip.ip,
+            &mut conn,
+        )
+        .await;
+    }
+
+    Ok(Json(json!(results)))
+}
+
 #[post("/organizations/<org_id>/delete", format = "application/json")]
 async fn delete_organization(org_id: OrganizationId, _token: AdminToken, mut conn: DbConn) -> EmptyResult {
     let org = Organization::find_by_uuid(&org_id, &mut conn).await.map_res("Organization doesn't exist")?;
@@ -727,6 +982,7 @@ async fn diagnostics(_token: AdminToken, ip_header: IpHeader, mut conn: DbConn)
         "server_time_local": Local::now().format("%Y-%m-%d %H:%M:%S %Z").to_string(),
         "server_time": Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string(), // Run the server date/time check as late as possible to minimize the time difference
         "ntp_time": get_ntp_time(has_http_access).await, // Run the ntp check as late as possible to minimize the time difference
+        "watchdog": crate::watchdog::status_json(),
     });
 
     let text = AdminTemplateData::new("admin/diagnostics", diagnostics_json).render()?;
@@ -773,6 +1029,345 @@ async fn backup_db(_token: AdminToken, mut conn: DbConn) -> ApiResult<String> {
     }
 }
 
+/// Maximum number of rows shown in the data browser, the CSV export is not limited.
+const DATA_BROWSER_MAX_ROWS: i64 = 1000;
+/// Only the most recent events are loaded, the events table can become very large.
+const DATA_BROWSER_MAX_EVENTS: i64 = 10_000;
+const DATA_BROWSER_ENTITIES: [(&str, &str); 4] =
+    [("users", "Users"), ("organizations", "Organizations"), ("devices", "Devices"), ("events", "Events")];
+
+struct DataTable {
+    columns: &'static [&'static str],
+    rows: Vec<Vec<String>>,
+}
+
+/// Loads a read-only view of at most `limit` rows of the requested entity,
+/// only the rows of which an id, name, email or IP address contains the (case-insensitive) filter are loaded.
+async fn load_data_table(entity: &str, filter: Option<&str>, limit: i64, conn: &mut DbConn) -> ApiResult<DataTable> {
+    fn opt<T: std::fmt::Display>(v: Option<T>) -> String {
+        v.map(|v| v.to_string()).unwrap_or_default()
+    }
+
+    let filter = filter.map(str::trim).filter(|f| !f.is_empty());
+    let (columns, rows): (&'static [&'static str], Vec<Vec<String>>) = match entity {
+        "users" => (
+            &["Id", "Email", "Name", "Enabled", "Email verified", "Created", "Updated"],
+            User::search(filter, limit, conn)
+                .await
+                .into_iter()
+                .map(|u| {
+                    vec![
+                        u.uuid.to_string(),
+                        u.email,
+                        u.name,
+                        u.enabled.to_string(),
+                        u.verified_at.is_some().to_string(),
+                        format_naive_datetime_local(&u.created_at, DT_FMT),
+                        format_naive_datetime_local(&u.updated_at, DT_FMT),
+                    ]
+                })
+                .collect(),
+        ),
+        "organizations" => (
+            &["Id", "Name", "Billing email"],
+            Organization::search(filter, limit, conn)
+                .await
+                .into_iter()
+                .map(|o| vec![o.uuid.to_string(), o.name, o.billing_email])
+                .collect(),
+        ),
+        "devices" => (
+            &["Id", "User id", "Name", "Type", "Push registered", "Created", "Last active"],
+            Device::search(filter, limit, conn)
+                .await
+                .into_iter()
+                .map(|d| {
+                    vec![
+                        d.uuid.to_string(),
+                        d.user_uuid.to_string(),
+                        d.name.clone(),
+                        DeviceType::from_i32(d.atype).to_string(),
+                        d.is_registered().to_string(),
+                        format_naive_datetime_local(&d.created_at, DT_FMT),
+                        format_naive_datetime_local(&d.updated_at, DT_FMT),
+                    ]
+                })
+                .collect(),
+        ),
+        "events" => (
+            &["Date", "Type", "User id", "Acting user id", "Organization id", "Cipher id", "Device type", "IP address"],
+            Event::search(filter, limit.min(DATA_BROWSER_MAX_EVENTS), conn)
+                .await
+                .into_iter()
+                .map(|e| {
+                    vec![
+                        format_naive_datetime_local(&e.event_date, DT_FMT),
+                        e.event_type.to_string(),
+                        opt(e.user_uuid),
+                        opt(e.act_user_uuid),
+                        opt(e.org_uuid),
+                        opt(e.cipher_uuid),
+                        opt(e.device_type.map(DeviceType::from_i32)),
+                        opt(e.ip_address),
+                    ]
+                })
+                .collect(),
+        ),
+        _ => err_code!("Unknown entity", Status::NotFound.code),
+    };
+
+    Ok(DataTable {
+        columns,
+        rows,
+    })
+}
+
+#[get("/data?<entity>&<filter>")]
+async fn data_browser(
+    entity: Option<&str>,
+    filter: Option<&str>,
+    _token: AdminToken,
+    mut conn: DbConn,
+) -> ApiResult<Html<String>> {
+    let entity = entity.unwrap_or("users");
+    // One more row than shown is loaded to know if the result was truncated
+    let mut table = load_data_table(entity, filter, DATA_BROWSER_MAX_ROWS + 1, &mut conn).await?;
+
+    let total = table.rows.len();
+    table.rows.truncate(DATA_BROWSER_MAX_ROWS as usize);
+
+    let entities: Vec<Value> = DATA_BROWSER_ENTITIES
+        .iter()
+        .map(|(value, label)| json!({ "value": value, "label": label, "selected": *value == entity }))
+        .collect();
+
+    let page_data = json!({
+        "entity": entity,
+        "filter": filter.unwrap_or_default(),
+        "entities": entities,
+        "columns": table.columns,
+        "rows": table.rows,
+        "total": total,
+        "truncated": total > DATA_BROWSER_MAX_ROWS as usize,
+        "max_rows": DATA_BROWSER_MAX_ROWS,
+    });
+
+    let text = AdminTemplateData::new("admin/data", page_data).render()?;
+    Ok(Html(text))
+}
+
+fn csv_field(value: &str) -> String {
+    // Prevent spreadsheet applications from interpreting a value as a formula
+    let value = if value.starts_with(['=', '+', '-', '@']) {
+        format!("'{value}")
+    } else {
+        value.to_string()
+    };
+
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+#[get("/data/export?<entity>&<filter>")]
+async fn data_browser_export(
+    entity: &str,
+    filter: Option<&str>,
+    _token: AdminToken,
+    mut conn: DbConn,
+) -> ApiResult<(ContentType, String)> {
+    let table = load_data_table(entity, filter, i64::MAX, &mut conn).await?;
+
+    let mut csv = table.columns.iter().map(|c| csv_field(c)).collect::<Vec<_>>().join(",");
+    csv.push_str("\r\n");
+    for row in table.rows {
+        csv.push_str(&row.iter().map(|c| csv_field(c)).collect::<Vec<_>>().join(","));
+        csv.push_str("\r\n");
+    }
+
+    Ok((ContentType::CSV, csv))
+}
+
+#[get("/stats")]
+fn get_usage_stats(_token: AdminToken) -> Json<Value> {
+    Json(crate::usage_stats::stats_json())
+}
+
+#[get("/stats/export")]
+fn get_usage_stats_export(_token: AdminToken) -> (ContentType, String) {
+    (ContentType::CSV, crate::usage_stats::stats_csv())
+}
+
+/// Number of PBKDF2 iterations used to derive the key protecting a configuration backup.
+const CONFIG_BACKUP_KDF_ITERATIONS: u32 = 600_000;
+const CONFIG_BACKUP_VERSION: i32 = 1;
+const CONFIG_BACKUP_MIN_PASSPHRASE_LEN: usize = 12;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ConfigExportData {
+    passphrase: String,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ConfigBackupBundle {
+    version: i32,
+    kdf_iterations: u32,
+    salt: String,
+    nonce: String,
+    data: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ConfigImportData {
+    passphrase: String,
+    bundle: ConfigBackupBundle,
+}
+
+/// The decrypted content of a configuration backup.
+/// Holds the settings saved via the admin panel and any custom (branding) templates.
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ConfigBackupContent {
+    config: ConfigBuilder,
+    templates: std::collections::BTreeMap<String, String>,
+}
+
+/// Collects all custom `.hbs` templates from the templates folder, keyed by their path relative to that folder.
+fn read_custom_templates() -> Result<std::collections::BTreeMap<String, String>, Error> {
+    fn visit(
+        base: &std::path::Path,
+        dir: &std::path::Path,
+        out: &mut std::collections::BTreeMap<String, String>,
+    ) -> Result<(), Error> {
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                visit(base, &path, out)?;
+            } else if path.extension().is_some_and(|ext| ext == "hbs") {
+                if let Ok(rel) = path.strip_prefix(base) {
+                    out.insert(rel.to_string_lossy().replace('\\', "/"), std::fs::read_to_string(&path)?);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    let mut templates = std::collections::BTreeMap::new();
+    let folder = CONFIG.templates_folder();
+    let base = std::path::Path::new(&folder);
+    if base.is_dir() {
+        visit(base, base, &mut templates)?;
+    }
+    Ok(templates)
+}
+
+/// Checks that all templates of a backup stay inside the templates folder, and returns the paths to write them to.
+fn custom_template_paths(
+    templates: &std::collections::BTreeMap<String, String>,
+) -> Result<Vec<(std::path::PathBuf, &String)>, Error> {
+    use std::path::{Component, Path};
+
+    let folder = CONFIG.templates_folder();
+    let mut paths = Vec::with_capacity(templates.len());
+    for (name, content) in templates {
+        let rel = Path::new(name);
+        if !name.ends_with(".hbs") || !rel.components().all(|c| matches!(c, Component::Normal(_))) {
+            err!(format!("Invalid template path in backup: {name}"))
+        }
+        paths.push((Path::new(&folder).join(rel), content));
+    }
+    Ok(paths)
+}
+
+fn write_custom_templates(paths: Vec<(std::path::PathBuf, &String)>) -> EmptyResult {
+    for (path, content) in paths {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, content)?;
+    }
+    Ok(())
+}
+
+#[post("/config/export", format = "application/json", data = "<data>")]
+async fn export_config(data: Json<ConfigExportData>, _token: AdminToken) -> JsonResult {
+    let data: ConfigExportData = data.into_inner();
+    if data.passphrase.chars().count() < CONFIG_BACKUP_MIN_PASSPHRASE_LEN {
+        err!(format!("The passphrase must be at least {CONFIG_BACKUP_MIN_PASSPHRASE_LEN} characters long"))
+    }
+
+    let content = ConfigBackupContent {
+        config: CONFIG.user_config(),
+        templates: read_custom_templates()?,
+    };
+    let plaintext = serde_json::to_vec(&content)?;
+
+    // Deriving the key takes a while, don't block one of the async workers meanwhile
+    let encrypted = crate::db::run_blocking(move || {
+        crate::crypto::encrypt_with_passphrase(data.passphrase.as_bytes(), &plaintext, CONFIG_BACKUP_KDF_ITERATIONS)
+    })
+    .await;
+
+    use data_encoding::BASE64;
+    let bundle = ConfigBackupBundle {
+        version: CONFIG_BACKUP_VERSION,
+        kdf_iterations: encrypted.iterations,
+        salt: BASE64.encode(&encrypted.salt),
+        nonce: BASE64.encode(&encrypted.nonce),
+        data: BASE64.encode(&encrypted.data),
+    };
+
+    Ok(Json(serde_json::to_value(bundle)?))
+}
+
+#[post("/config/import", format = "application/json", data = "<data>")]
+async fn import_config(data: Json<ConfigImportData>, _token: AdminToken) -> EmptyResult {
+    let data: ConfigImportData = data.into_inner();
+    let bundle = data.bundle;
+    if bundle.version != CONFIG_BACKUP_VERSION {
+        err!(format!("Unsupported configuration backup version: {}", bundle.version))
+    }
+
+    use data_encoding::BASE64;
+    let (Ok(salt), Ok(nonce), Ok(ciphertext)) = (
+        BASE64.decode(bundle.salt.as_bytes()),
+        BASE64.decode(bundle.nonce.as_bytes()),
+        BASE64.decode(bundle.data.as_bytes()),
+    ) else {
+        err!("The configuration backup is malformed")
+    };
+    let encrypted = crate::crypto::PassphraseEncrypted {
+        iterations: bundle.kdf_iterations,
+        salt,
+        nonce,
+        data: ciphertext,
+    };
+
+    let passphrase = data.passphrase;
+    let decrypted =
+        crate::db::run_blocking(move || crate::crypto::decrypt_with_passphrase(passphrase.as_bytes(), &encrypted))
+            .await;
+    let Some(plaintext) = decrypted else {
+        err!("Unable to decrypt the configuration backup, the passphrase is wrong or the file is corrupted")
+    };
+    let content: ConfigBackupContent = serde_json::from_slice(&plaintext)?;
+
+    // Nothing is written before both the templates and the config are known to be valid
+    let template_paths = custom_template_paths(&content.templates)?;
+    if let Err(e) = CONFIG.update_config(content.config, true) {
+        err!(format!("Unable to restore config: {e:?}"))
+    }
+    write_custom_templates(template_paths)?;
+    CONFIG.reload_template_folder();
+
+    Ok(())
+}
+
 pub struct AdminToken {
     ip: ClientIp,
 }
@@ -787,7 +1382,8 @@ impl<'r> FromRequest<'r> for AdminToken {
             _ => err_handler!("Error getting Client IP"),
         };
 
-        if CONFIG.disable_admin_token() {
+        // A configured TOTP secret still requires a login, even when the token is disabled
+        if CONFIG.disable_admin_token() && CONFIG.admin_totp_secret().is_none() {
             Outcome::Success(Self {
                 ip,
             })
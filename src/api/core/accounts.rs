@@ -1,19 +1,24 @@
 use std::collections::HashSet;
 
 use crate::db::DbPool;
-use chrono::Utc;
-use rocket::serde::json::Json;
+use chrono::{TimeDelta, Utc};
+use rocket::{form::Form, response::content::RawHtml as Html, serde::json::Json};
 use serde_json::Value;
 
 use crate::{
     api::{
         core::{log_user_event, two_factor::email},
-        register_push_device, unregister_push_device, AnonymousNotify, EmptyResult, JsonResult, Notify,
-        PasswordOrOtpData, UpdateType,
+        instance_master_password_policy_json, link_confirm_page, link_result_page, register_push_device,
+        send_user_webhook, unregister_push_device, AnonymousNotify, ApiResult, EmptyResult, JsonResult, LinkTokenData,
+        Notify, PasswordOrOtpData, UpdateType, UserWebhookEvent,
+    },
+    auth::{
+        decode_delete, decode_email_change, decode_invite, decode_user_delete_cancel, decode_verify_email,
+        ClientHeaders, ClientIp, Headers,
     },
-    auth::{decode_delete, decode_invite, decode_verify_email, ClientHeaders, Headers},
     crypto,
     db::{models::*, DbConn},
+    error::ApiErrorCode,
     mail,
     util::{format_date, NumberOrString},
     CONFIG,
@@ -31,13 +36,18 @@ pub fn routes() -> Vec<rocket::Route> {
         put_profile,
         post_profile,
         put_avatar,
+        post_avatar,
         get_public_keys,
         post_keys,
         post_password,
+        put_update_temp_password,
         post_kdf,
         post_rotatekey,
         post_sstamp,
+        post_revoke_sessions,
         post_email_token,
+        confirm_email_change_current_page,
+        confirm_email_change_current,
         post_email,
         post_verify_email,
         post_verify_email_token,
@@ -45,6 +55,8 @@ pub fn routes() -> Vec<rocket::Route> {
         post_delete_recover_token,
         post_delete_account,
         delete_account,
+        cancel_delete_account_page,
+        cancel_delete_account_token,
         revision_date,
         password_hint,
         prelogin,
@@ -52,17 +64,22 @@ pub fn routes() -> Vec<rocket::Route> {
         api_key,
         rotate_api_key,
         get_known_device,
+        get_known_device_from_path,
         get_all_devices,
         get_device,
         post_device_token,
         put_device_token,
         put_clear_device_token,
         post_clear_device_token,
+        put_device_keys,
+        post_update_devices_trust,
+        post_untrust_devices,
         post_auth_request,
         get_auth_request,
         put_auth_request,
         get_auth_request_response,
         get_auth_requests,
+        get_pending_auth_requests,
     ]
 }
 
@@ -95,6 +112,8 @@ pub struct RegisterData {
     accept_emergency_access_invite_token: Option<String>,
     #[serde(alias = "token")]
     org_invite_token: Option<String>,
+
+    captcha_response: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -148,6 +167,11 @@ pub async fn _register(data: Json<RegisterData>, email_verification: bool, mut c
 
     let mut pending_emergency_access = None;
 
+    // The register/finish endpoint is protected by the emailed verification token instead
+    if !email_verification {
+        crate::captcha::verify(data.captcha_response.as_deref()).await?;
+    }
+
     // First, validate the provided verification tokens
     if email_verification {
         match (
@@ -225,7 +249,7 @@ pub async fn _register(data: Json<RegisterData>, email_verification: bool, mut c
     let mut user = match User::find_by_mail(&email, &mut conn).await {
         Some(user) => {
             if !user.password_hash.is_empty() {
-                err!("Registration not allowed or user already exists")
+                return existing_account_response(&data, &email, &mut conn).await;
             }
 
             if let Some(token) = data.org_invite_token {
@@ -234,6 +258,8 @@ pub async fn _register(data: Json<RegisterData>, email_verification: bool, mut c
                     // Verify the email address when signing up via a valid invite token
                     email_verified = true;
                     user
+                } else if CONFIG.anti_enumeration() {
+                    err!("Registration not allowed or user already exists")
                 } else {
                     err!("Registration email does not match invite email")
                 }
@@ -256,12 +282,19 @@ pub async fn _register(data: Json<RegisterData>, email_verification: bool, mut c
             // Order is important here; the invitation check must come first
             // because the vaultwarden admin can invite anyone, regardless
             // of other signup restrictions.
-            if Invitation::take(&email, &mut conn).await
-                || CONFIG.is_signup_allowed(&email)
-                || pending_emergency_access.is_some()
+            if Invitation::take(&email, &mut conn).await || pending_emergency_access.is_some() {
+                User::new(email.clone())
+            } else if CONFIG.is_signup_allowed(&email)
+                || super::is_verified_domain_signup_allowed(&email, &mut conn).await
             {
+                if CONFIG.signups_normalize_plus_addresses()
+                    && User::find_by_normalized_mail(&email, &mut conn).await.is_some()
+                {
+                    return existing_account_response(&data, &email, &mut conn).await;
+                }
                 User::new(email.clone())
             } else {
+                // Same error as for an existing account, the specific reason is only given before an account is involved
                 err!("Registration not allowed or user already exists")
             }
         }
@@ -270,17 +303,13 @@ pub async fn _register(data: Json<RegisterData>, email_verification: bool, mut c
     // Make sure we don't leave a lingering invitation.
     Invitation::take(&email, &mut conn).await;
 
-    if let Some(client_kdf_type) = data.kdf {
-        user.client_kdf_type = client_kdf_type;
-    }
-
-    if let Some(client_kdf_iter) = data.kdf_iterations {
-        user.client_kdf_iter = client_kdf_iter;
+    // Older clients don't send the KDF, in which case the server defaults are kept
+    if let Some(kdf) = data.kdf {
+        let iterations = data.kdf_iterations.unwrap_or(user.client_kdf_iter);
+        validate_kdf(kdf, iterations, data.kdf_memory, data.kdf_parallelism)?;
+        set_client_kdf(&mut user, kdf, iterations, data.kdf_memory, data.kdf_parallelism);
     }
 
-    user.client_kdf_memory = data.kdf_memory;
-    user.client_kdf_parallelism = data.kdf_parallelism;
-
     user.set_password(&data.master_password_hash, Some(data.key), true, None);
     user.password_hint = password_hint;
 
@@ -314,6 +343,11 @@ pub async fn _register(data: Json<RegisterData>, email_verification: bool, mut c
     }
 
     user.save(&mut conn).await?;
+    send_user_webhook(UserWebhookEvent::Registered, &user.uuid, &user.email);
+
+    if let Err(e) = super::org_domains::join_verified_domain_org(&user, &mut conn).await {
+        error!("Error inviting the new user to the organization of their verified domain: {e:#?}");
+    }
 
     // accept any open emergency access invitations
     if !CONFIG.mail_enabled() && CONFIG.emergency_access_allowed() {
@@ -322,10 +356,32 @@ pub async fn _register(data: Json<RegisterData>, email_verification: bool, mut c
         }
     }
 
-    Ok(Json(json!({
+    Ok(Json(register_response()))
+}
+
+fn register_response() -> Value {
+    json!({
       "object": "register",
       "captchaBypassToken": "",
-    })))
+    })
+}
+
+/// The answer to a registration of an address which already has an account.
+/// With the anti-enumeration mode it is the same as for an unknown address: a successful registration
+/// when signups are allowed for it, without changing anything, and after spending the same time hashing the password.
+async fn existing_account_response(data: &RegisterData, email: &str, conn: &mut DbConn) -> JsonResult {
+    if CONFIG.anti_enumeration()
+        && (CONFIG.is_signup_allowed(email) || super::is_verified_domain_signup_allowed(email, conn).await)
+    {
+        crate::crypto::hash_password(
+            data.master_password_hash.as_bytes(),
+            &[0u8; 64],
+            CONFIG.password_iterations() as u32,
+        );
+        warn!("Ignored the registration of {email}, the address already has an account");
+        return Ok(Json(register_response()));
+    }
+    err!("Registration not allowed or user already exists")
 }
 
 #[get("/accounts/profile")]
@@ -369,6 +425,11 @@ struct AvatarData {
     avatar_color: Option<String>,
 }
 
+#[post("/accounts/avatar", data = "<data>")]
+async fn post_avatar(data: Json<AvatarData>, headers: Headers, conn: DbConn) -> JsonResult {
+    put_avatar(data, headers, conn).await
+}
+
 #[put("/accounts/avatar", data = "<data>")]
 async fn put_avatar(data: Json<AvatarData>, headers: Headers, mut conn: DbConn) -> JsonResult {
     let data: AvatarData = data.into_inner();
@@ -377,7 +438,8 @@ async fn put_avatar(data: Json<AvatarData>, headers: Headers, mut conn: DbConn)
     // If you try to add the short value it will not show that color.
     // Check and force 7 chars, including the #.
     if let Some(color) = &data.avatar_color {
-        if color.len() != 7 {
+        let is_hex_color = color.strip_prefix('#').is_some_and(|hex| hex.chars().all(|c| c.is_ascii_hexdigit()));
+        if color.len() != 7 || !is_hex_color {
             err!("The field AvatarColor must be a HTML/Hex color code with a length of 7 characters")
         }
     }
@@ -437,7 +499,7 @@ async fn post_password(data: Json<ChangePassData>, headers: Headers, mut conn: D
     let mut user = headers.user;
 
     if !user.check_valid_password(&data.master_password_hash) {
-        err!("Invalid password")
+        err_api!(ApiErrorCode::InvalidPassword, "Invalid password")
     }
 
     user.password_hint = clean_password_hint(&data.master_password_hint);
@@ -454,7 +516,7 @@ async fn post_password(data: Json<ChangePassData>, headers: Headers, mut conn: D
             String::from("post_rotatekey"),
             String::from("get_contacts"),
             String::from("get_public_keys"),
-            String::from("get_api_webauthn"),
+            String::from("get_passkeys"),
         ]),
     );
 
@@ -468,6 +530,46 @@ async fn post_password(data: Json<ChangePassData>, headers: Headers, mut conn: D
     save_result
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UpdateTempPasswordData {
+    new_master_password_hash: String,
+    master_password_hint: Option<String>,
+    key: String,
+}
+
+// Used by the clients to replace a password which was set by an organization admin via account recovery.
+// The current password isn't asked for, the user only knows the temporary one the admin has chosen.
+#[put("/accounts/update-temp-password", data = "<data>")]
+async fn put_update_temp_password(
+    data: Json<UpdateTempPasswordData>,
+    headers: Headers,
+    mut conn: DbConn,
+    nt: Notify<'_>,
+) -> EmptyResult {
+    let data: UpdateTempPasswordData = data.into_inner();
+    let mut user = headers.user;
+
+    if !user.force_password_reset {
+        err!("The password of this account does not have to be updated")
+    }
+
+    user.password_hint = clean_password_hint(&data.master_password_hint);
+    enforce_password_hint_setting(&user.password_hint)?;
+
+    log_user_event(EventType::UserChangedPassword as i32, &user.uuid, headers.device.atype, &headers.ip.ip, &mut conn)
+        .await;
+
+    // This also clears the force_password_reset flag
+    user.set_password(&data.new_master_password_hash, Some(data.key), true, None);
+
+    let save_result = user.save(&mut conn).await;
+
+    nt.send_logout(&user, Some(headers.device.uuid.clone())).await;
+
+    save_result
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct ChangeKdfData {
@@ -481,45 +583,61 @@ struct ChangeKdfData {
     key: String,
 }
 
+/// Validates the KDF parameters chosen by the client, Argon2id needs the memory and parallelism parameters.
+/// The configured minimums are checked as well, the upper limits are fixed.
+fn validate_kdf(kdf: i32, iterations: i32, memory: Option<i32>, parallelism: Option<i32>) -> EmptyResult {
+    if kdf == UserKdfType::Pbkdf2 as i32 {
+        let min_iterations = CONFIG.kdf_min_pbkdf2_iterations();
+        if iterations < min_iterations {
+            err!(format!("PBKDF2 KDF iterations must be at least {min_iterations}."))
+        }
+    } else if kdf == UserKdfType::Argon2id as i32 {
+        let min_iterations = CONFIG.kdf_min_argon2_iterations();
+        if iterations < min_iterations {
+            err!(format!("Argon2 KDF iterations must be at least {min_iterations}."))
+        }
+        let min_memory = CONFIG.kdf_min_argon2_memory();
+        match memory {
+            Some(m) if !(min_memory..=1024).contains(&m) => {
+                err!(format!("Argon2 memory must be between {min_memory} MB and 1024 MB."))
+            }
+            Some(_) => (),
+            None => err!("Argon2 memory parameter is required."),
+        }
+        let min_parallelism = CONFIG.kdf_min_argon2_parallelism();
+        match parallelism {
+            Some(p) if !(min_parallelism..=16).contains(&p) => {
+                err!(format!("Argon2 parallelism must be between {min_parallelism} and 16."))
+            }
+            Some(_) => (),
+            None => err!("Argon2 parallelism parameter is required."),
+        }
+    } else {
+        err!("Unsupported KDF type.")
+    }
+    Ok(())
+}
+
+/// Stores the validated KDF parameters, the Argon2id parameters are cleared when switching to PBKDF2.
+fn set_client_kdf(user: &mut User, kdf: i32, iterations: i32, memory: Option<i32>, parallelism: Option<i32>) {
+    let is_argon2 = kdf == UserKdfType::Argon2id as i32;
+    user.client_kdf_type = kdf;
+    user.client_kdf_iter = iterations;
+    user.client_kdf_memory = memory.filter(|_| is_argon2);
+    user.client_kdf_parallelism = parallelism.filter(|_| is_argon2);
+}
+
 #[post("/accounts/kdf", data = "<data>")]
 async fn post_kdf(data: Json<ChangeKdfData>, headers: Headers, mut conn: DbConn, nt: Notify<'_>) -> EmptyResult {
     let data: ChangeKdfData = data.into_inner();
     let mut user = headers.user;
 
     if !user.check_valid_password(&data.master_password_hash) {
-        err!("Invalid password")
+        err_api!(ApiErrorCode::InvalidPassword, "Invalid password")
     }
 
-    if data.kdf == UserKdfType::Pbkdf2 as i32 && data.kdf_iterations < 100_000 {
-        err!("PBKDF2 KDF iterations must be at least 100000.")
-    }
-
-    if data.kdf == UserKdfType::Argon2id as i32 {
-        if data.kdf_iterations < 1 {
-            err!("Argon2 KDF iterations must be at least 1.")
-        }
-        if let Some(m) = data.kdf_memory {
-            if !(15..=1024).contains(&m) {
-                err!("Argon2 memory must be between 15 MB and 1024 MB.")
-            }
-            user.client_kdf_memory = data.kdf_memory;
-        } else {
-            err!("Argon2 memory parameter is required.")
-        }
-        if let Some(p) = data.kdf_parallelism {
-            if !(1..=16).contains(&p) {
-                err!("Argon2 parallelism must be between 1 and 16.")
-            }
-            user.client_kdf_parallelism = data.kdf_parallelism;
-        } else {
-            err!("Argon2 parallelism parameter is required.")
-        }
-    } else {
-        user.client_kdf_memory = None;
-        user.client_kdf_parallelism = None;
-    }
-    user.client_kdf_iter = data.kdf_iterations;
-    user.client_kdf_type = data.kdf;
+    validate_kdf(data.kdf, data.kdf_iterations, data.kdf_memory, data.kdf_parallelism)?;
+    set_client_kdf(&mut user, data.kdf, data.kdf_iterations, data.kdf_memory, data.kdf_parallelism);
     user.set_password(&data.new_master_password_hash, Some(data.key), true, None);
     let save_result = user.save(&mut conn).await;
 
@@ -552,7 +670,16 @@ struct UpdateResetPasswordData {
     reset_password_key: String,
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UpdatePasskeyKeyData {
+    id: String,
+    encrypted_user_key: String,
+    encrypted_public_key: String,
+}
+
 use super::ciphers::CipherData;
+use super::passkeys::{get_passkey_credentials, save_passkey_credentials, PasskeyCredential};
 use super::sends::{update_send_from_data, SendData};
 
 #[derive(Deserialize)]
@@ -563,6 +690,9 @@ struct KeyData {
     sends: Vec<SendData>,
     emergency_access_keys: Vec<UpdateEmergencyAccessData>,
     reset_password_keys: Vec<UpdateResetPasswordData>,
+    // Older clients do not send the keys of the passkeys used for vault decryption
+    #[serde(default, alias = "webAuthnKeys")]
+    webauthn_keys: Vec<UpdatePasskeyKeyData>,
     key: String,
     master_password_hash: String,
     private_key: String,
@@ -575,6 +705,7 @@ fn validate_keydata(
     existing_emergency_access: &[EmergencyAccess],
     existing_memberships: &[Membership],
     existing_sends: &[Send],
+    existing_passkeys: &[PasskeyCredential],
 ) -> EmptyResult {
     // Check that we're correctly rotating all the user's ciphers
     let existing_cipher_ids = existing_ciphers.iter().map(|c| &c.uuid).collect::<HashSet<&CipherId>>();
@@ -620,16 +751,26 @@ fn validate_keydata(
         err!("All existing sends must be included in the rotation")
     }
 
+    // Check that we're correctly rotating the keys of all the passkeys which can decrypt the vault
+    let existing_passkey_ids = existing_passkeys
+        .iter()
+        .filter(|p| p.encrypted_user_key.is_some())
+        .map(|p| &p.id)
+        .collect::<HashSet<&String>>();
+    let provided_passkey_ids = data.webauthn_keys.iter().map(|p| &p.id).collect::<HashSet<&String>>();
+    if !provided_passkey_ids.is_superset(&existing_passkey_ids) {
+        err!("All existing passkeys with encryption enabled must be included in the rotation")
+    }
+
     Ok(())
 }
 
 #[post("/accounts/key", data = "<data>")]
 async fn post_rotatekey(data: Json<KeyData>, headers: Headers, mut conn: DbConn, nt: Notify<'_>) -> EmptyResult {
-    // TODO: See if we can wrap everything within a SQL Transaction. If something fails it should revert everything.
     let data: KeyData = data.into_inner();
 
     if !headers.user.check_valid_password(&data.master_password_hash) {
-        err!("Invalid password")
+        err_api!(ApiErrorCode::InvalidPassword, "Invalid password")
     }
 
     // Validate the import before continuing
@@ -638,17 +779,41 @@ async fn post_rotatekey(data: Json<KeyData>, headers: Headers, mut conn: DbConn,
     // TODO: See if we can optimize the whole cipher adding/importing and prevent duplicate code and checks.
     Cipher::validate_cipher_data(&data.ciphers)?;
 
-    let user_id = &headers.user.uuid;
+    let key = data.key.clone();
+    let private_key = data.private_key.clone();
+
+    // Everything is updated in one transaction, a failure halfway would leave the vault encrypted with two keys
+    conn.begin_transaction().await?;
+    let mut result = update_rotated_data(data, &headers, &mut conn, &nt).await;
+    let mut user = headers.user;
+    if result.is_ok() {
+        user.akey = key;
+        user.private_key = Some(private_key);
+        user.reset_security_stamp();
+        result = user.save(&mut conn).await;
+    }
+    conn.end_transaction(result).await?;
+
+    // Prevent logging out the client where the user requested this endpoint from.
+    // If you do logout the user it will causes issues at the client side.
+    // Adding the device uuid will prevent this.
+    nt.send_logout(&user, Some(headers.device.uuid.clone())).await;
+
+    Ok(())
+}
 
-    // TODO: Ideally we'd do everything after this point in a single transaction.
+/// Saves the vault data which was re-encrypted with the new user key.
+async fn update_rotated_data(data: KeyData, headers: &Headers, conn: &mut DbConn, nt: &Notify<'_>) -> EmptyResult {
+    let user_id = &headers.user.uuid;
 
-    let mut existing_ciphers = Cipher::find_owned_by_user(user_id, &mut conn).await;
-    let mut existing_folders = Folder::find_by_user(user_id, &mut conn).await;
-    let mut existing_emergency_access = EmergencyAccess::find_all_by_grantor_uuid(user_id, &mut conn).await;
-    let mut existing_memberships = Membership::find_by_user(user_id, &mut conn).await;
+    let mut existing_ciphers = Cipher::find_owned_by_user(user_id, conn).await;
+    let mut existing_folders = Folder::find_by_user(user_id, conn).await;
+    let mut existing_emergency_access = EmergencyAccess::find_all_by_grantor_uuid(user_id, conn).await;
+    let mut existing_memberships = Membership::find_by_user(user_id, conn).await;
     // We only rotate the reset password key if it is set.
     existing_memberships.retain(|m| m.reset_password_key.is_some());
-    let mut existing_sends = Send::find_by_user(user_id, &mut conn).await;
+    let mut existing_sends = Send::find_by_user(user_id, conn).await;
+    let mut existing_passkeys = get_passkey_credentials(user_id, conn).await?;
 
     validate_keydata(
         &data,
@@ -657,6 +822,7 @@ async fn post_rotatekey(data: Json<KeyData>, headers: Headers, mut conn: DbConn,
         &existing_emergency_access,
         &existing_memberships,
         &existing_sends,
+        &existing_passkeys,
     )?;
 
     // Update folder data
@@ -669,7 +835,7 @@ async fn post_rotatekey(data: Json<KeyData>, headers: Headers, mut conn: DbConn,
             };
 
             saved_folder.name = folder_data.name;
-            saved_folder.save(&mut conn).await?
+            saved_folder.save(conn).await?
         }
     }
 
@@ -682,7 +848,7 @@ async fn post_rotatekey(data: Json<KeyData>, headers: Headers, mut conn: DbConn,
         };
 
         saved_emergency_access.key_encrypted = Some(emergency_access_data.key_encrypted);
-        saved_emergency_access.save(&mut conn).await?
+        saved_emergency_access.save(conn).await?
     }
 
     // Update reset password data
@@ -694,7 +860,7 @@ async fn post_rotatekey(data: Json<KeyData>, headers: Headers, mut conn: DbConn,
         };
 
         membership.reset_password_key = Some(reset_password_data.reset_password_key);
-        membership.save(&mut conn).await?
+        membership.save(conn).await?
     }
 
     // Update send data
@@ -703,7 +869,23 @@ async fn post_rotatekey(data: Json<KeyData>, headers: Headers, mut conn: DbConn,
             err!("Send doesn't exist")
         };
 
-        update_send_from_data(send, send_data, &headers, &mut conn, &nt, UpdateType::None).await?;
+        update_send_from_data(send, send_data, headers, conn, nt, UpdateType::None).await?;
+    }
+
+    // Update the keys of the passkeys used for vault decryption
+    if !data.webauthn_keys.is_empty() {
+        for passkey_data in data.webauthn_keys {
+            let Some(passkey) = existing_passkeys.iter_mut().find(|p| p.id == passkey_data.id) else {
+                err!("Passkey doesn't exist")
+            };
+            if !passkey.supports_prf {
+                err!("This passkey does not support encryption")
+            }
+
+            passkey.encrypted_user_key = Some(passkey_data.encrypted_user_key);
+            passkey.encrypted_public_key = Some(passkey_data.encrypted_public_key);
+        }
+        save_passkey_credentials(user_id, &existing_passkeys, conn).await?;
     }
 
     // Update cipher data
@@ -719,35 +901,47 @@ async fn post_rotatekey(data: Json<KeyData>, headers: Headers, mut conn: DbConn,
             // Prevent triggering cipher updates via WebSockets by settings UpdateType::None
             // The user sessions are invalidated because all the ciphers were re-encrypted and thus triggering an update could cause issues.
             // We force the users to logout after the user has been saved to try and prevent these issues.
-            update_cipher_from_data(saved_cipher, cipher_data, &headers, None, &mut conn, &nt, UpdateType::None).await?
+            update_cipher_from_data(saved_cipher, cipher_data, headers, None, conn, None, UpdateType::None).await?
         }
     }
 
-    // Update user data
+    // The trusted devices hold the old user key, the clients send the re-encrypted keys to `/devices/update-trust`
+    Device::clear_trust_keys_by_user(user_id, conn).await?;
+
+    Ok(())
+}
+
+#[post("/accounts/security-stamp", data = "<data>")]
+async fn post_sstamp(data: Json<PasswordOrOtpData>, headers: Headers, mut conn: DbConn, nt: Notify<'_>) -> EmptyResult {
+    let data: PasswordOrOtpData = data.into_inner();
     let mut user = headers.user;
 
-    user.akey = data.key;
-    user.private_key = Some(data.private_key);
-    user.reset_security_stamp();
+    data.validate(&user, true, &mut conn).await?;
 
+    Device::delete_all_by_user(&user.uuid, &mut conn).await?;
+    user.reset_security_stamp();
     let save_result = user.save(&mut conn).await;
 
-    // Prevent logging out the client where the user requested this endpoint from.
-    // If you do logout the user it will causes issues at the client side.
-    // Adding the device uuid will prevent this.
-    nt.send_logout(&user, Some(headers.device.uuid.clone())).await;
+    nt.send_logout(&user, None).await;
 
     save_result
 }
 
-#[post("/accounts/security-stamp", data = "<data>")]
-async fn post_sstamp(data: Json<PasswordOrOtpData>, headers: Headers, mut conn: DbConn, nt: Notify<'_>) -> EmptyResult {
+// Logs out all the sessions of the account, but unlike a security stamp reset the devices and their settings are kept
+#[post("/accounts/sessions/revoke", data = "<data>")]
+async fn post_revoke_sessions(
+    data: Json<PasswordOrOtpData>,
+    headers: Headers,
+    mut conn: DbConn,
+    nt: Notify<'_>,
+) -> EmptyResult {
     let data: PasswordOrOtpData = data.into_inner();
     let mut user = headers.user;
 
     data.validate(&user, true, &mut conn).await?;
 
-    Device::delete_all_by_user(&user.uuid, &mut conn).await?;
+    Device::revoke_refresh_tokens_by_user(&user.uuid, &mut conn).await?;
+    // Also invalidates the access tokens which were already issued
     user.reset_security_stamp();
     let save_result = user.save(&mut conn).await;
 
@@ -759,7 +953,8 @@ async fn post_sstamp(data: Json<PasswordOrOtpData>, headers: Headers, mut conn:
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct EmailTokenData {
-    master_password_hash: String,
+    master_password_hash: Option<String>,
+    otp: Option<String>,
     new_email: String,
 }
 
@@ -772,9 +967,12 @@ async fn post_email_token(data: Json<EmailTokenData>, headers: Headers, mut conn
     let data: EmailTokenData = data.into_inner();
     let mut user = headers.user;
 
-    if !user.check_valid_password(&data.master_password_hash) {
-        err!("Invalid password")
+    PasswordOrOtpData {
+        master_password_hash: data.master_password_hash,
+        otp: data.otp,
     }
+    .validate(&user, true, &mut conn)
+    .await?;
 
     if User::find_by_mail(&data.new_email, &mut conn).await.is_some() {
         err!("Email already in use");
@@ -785,20 +983,70 @@ async fn post_email_token(data: Json<EmailTokenData>, headers: Headers, mut conn
     }
 
     let token = crypto::generate_email_token(6);
+    let requested_at = Utc::now().naive_utc();
 
     if CONFIG.mail_enabled() {
         if let Err(e) = mail::send_change_email(&data.new_email, &token).await {
             error!("Error sending change-email email: {:#?}", e);
         }
+
+        // The current address has to confirm the change as well, a hijacked session alone is not enough
+        if CONFIG.email_change_confirm_current() {
+            if let Err(e) =
+                mail::send_change_email_confirm_current(&user.email, &user.uuid, &data.new_email, &requested_at).await
+            {
+                error!("Error sending change-email confirmation email: {:#?}", e);
+            }
+        }
     } else {
         debug!("Email change request for user ({}) to email ({}) with token ({})", user.uuid, data.new_email, token);
     }
 
     user.email_new = Some(data.new_email);
     user.email_new_token = Some(token);
+    user.email_new_requested_at = Some(requested_at);
+    user.email_new_confirmed_at = None;
     user.save(&mut conn).await
 }
 
+/// The page of the link sent to the current address, the change is confirmed by the post from it.
+#[get("/accounts/email/confirm-current?<token>")]
+fn confirm_email_change_current_page(token: &str) -> ApiResult<Html<String>> {
+    let Ok(claims) = decode_email_change(token) else {
+        err!("Invalid claim")
+    };
+    link_confirm_page(
+        "Confirm email change",
+        &format!("Confirm the change of the email address of your account to {}.", claims.new_email),
+        "Confirm email change",
+        "/api/accounts/email/confirm-current",
+        token,
+    )
+}
+
+/// Confirms a pending email change via the link sent to the current address.
+#[post("/accounts/email/confirm-current", data = "<data>")]
+async fn confirm_email_change_current(data: Form<LinkTokenData>, mut conn: DbConn) -> ApiResult<Html<String>> {
+    let Ok(claims) = decode_email_change(&data.token) else {
+        err!("Invalid claim")
+    };
+    let Some(mut user) = User::find_by_uuid(&claims.sub, &mut conn).await else {
+        err!("Invalid claim")
+    };
+
+    let requested_at = user.email_new_requested_at.map(|r| r.and_utc().timestamp());
+    if user.email_new.as_ref() != Some(&claims.new_email) || requested_at != Some(claims.requested_at) {
+        err!("This email change is no longer pending")
+    }
+
+    user.email_new_confirmed_at = Some(Utc::now().naive_utc());
+    user.save(&mut conn).await?;
+    link_result_page(
+        "Email change confirmed",
+        "The email change has been confirmed. Enter the code sent to the new address in the web vault to finish it.",
+    )
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct ChangeEmailData {
@@ -820,7 +1068,7 @@ async fn post_email(data: Json<ChangeEmailData>, headers: Headers, mut conn: DbC
     let mut user = headers.user;
 
     if !user.check_valid_password(&data.master_password_hash) {
-        err!("Invalid password")
+        err_api!(ApiErrorCode::InvalidPassword, "Invalid password")
     }
 
     if User::find_by_mail(&data.new_email, &mut conn).await.is_some() {
@@ -836,6 +1084,15 @@ async fn post_email(data: Json<ChangeEmailData>, headers: Headers, mut conn: DbC
         None => err!("No email change pending"),
     }
 
+    let expiration = TimeDelta::try_hours(i64::from(CONFIG.email_change_expiration_hours())).unwrap();
+    if user.email_new_requested_at.is_none_or(|r| Utc::now().naive_utc() > r + expiration) {
+        err!("The email change request has expired, please request a new one");
+    }
+
+    if CONFIG.mail_enabled() && CONFIG.email_change_confirm_current() && user.email_new_confirmed_at.is_none() {
+        err!("Please confirm the email change using the link sent to your current email address first");
+    }
+
     if CONFIG.mail_enabled() {
         // Only check the token if we sent out an email...
         match user.email_new_token {
@@ -854,6 +1111,8 @@ async fn post_email(data: Json<ChangeEmailData>, headers: Headers, mut conn: DbC
     user.email = data.new_email;
     user.email_new = None;
     user.email_new_token = None;
+    user.email_new_requested_at = None;
+    user.email_new_confirmed_at = None;
 
     user.set_password(&data.new_master_password_hash, Some(data.key), true, None);
 
@@ -905,6 +1164,11 @@ async fn post_verify_email_token(data: Json<VerifyEmailTokenData>, mut conn: DbC
     user.login_verify_count = 0;
     if let Err(e) = user.save(&mut conn).await {
         error!("Error saving email verification: {:#?}", e);
+    } else {
+        send_user_webhook(UserWebhookEvent::EmailVerified, &user.uuid, &user.email);
+        if let Err(e) = super::org_domains::join_verified_domain_org(&user, &mut conn).await {
+            error!("Error inviting the user to the organization of their verified domain: {e:#?}");
+        }
     }
 
     Ok(())
@@ -944,7 +1208,11 @@ struct DeleteRecoverTokenData {
 }
 
 #[post("/accounts/delete-recover-token", data = "<data>")]
-async fn post_delete_recover_token(data: Json<DeleteRecoverTokenData>, mut conn: DbConn) -> EmptyResult {
+async fn post_delete_recover_token(
+    data: Json<DeleteRecoverTokenData>,
+    mut conn: DbConn,
+    nt: Notify<'_>,
+) -> EmptyResult {
     let data: DeleteRecoverTokenData = data.into_inner();
 
     let Ok(claims) = decode_delete(&data.token) else {
@@ -958,22 +1226,109 @@ async fn post_delete_recover_token(data: Json<DeleteRecoverTokenData>, mut conn:
     if claims.sub != *user.uuid {
         err!("Invalid claim");
     }
-    user.delete(&mut conn).await
+    delete_user_account(user, &nt, &mut conn).await
 }
 
 #[post("/accounts/delete", data = "<data>")]
-async fn post_delete_account(data: Json<PasswordOrOtpData>, headers: Headers, conn: DbConn) -> EmptyResult {
-    delete_account(data, headers, conn).await
+async fn post_delete_account(
+    data: Json<PasswordOrOtpData>,
+    headers: Headers,
+    conn: DbConn,
+    nt: Notify<'_>,
+) -> EmptyResult {
+    delete_account(data, headers, conn, nt).await
 }
 
 #[delete("/accounts", data = "<data>")]
-async fn delete_account(data: Json<PasswordOrOtpData>, headers: Headers, mut conn: DbConn) -> EmptyResult {
+async fn delete_account(
+    data: Json<PasswordOrOtpData>,
+    headers: Headers,
+    mut conn: DbConn,
+    nt: Notify<'_>,
+) -> EmptyResult {
     let data: PasswordOrOtpData = data.into_inner();
     let user = headers.user;
 
     data.validate(&user, true, &mut conn).await?;
 
-    user.delete(&mut conn).await
+    delete_user_account(user, &nt, &mut conn).await
+}
+
+#[get("/accounts/delete/cancel?<token>")]
+fn cancel_delete_account_page(token: &str) -> ApiResult<Html<String>> {
+    if decode_user_delete_cancel(token).is_err() {
+        err!("Invalid claim")
+    }
+    link_confirm_page(
+        "Restore account",
+        "Your account is scheduled for deletion. Restore it to keep your account and its data.",
+        "Restore account",
+        "/api/accounts/delete/cancel",
+        token,
+    )
+}
+
+#[post("/accounts/delete/cancel", data = "<data>")]
+async fn cancel_delete_account_token(data: Form<LinkTokenData>, mut conn: DbConn) -> ApiResult<Html<String>> {
+    let Ok(claims) = decode_user_delete_cancel(&data.token) else {
+        err!("Invalid claim")
+    };
+    let user_id: UserId = claims.sub.into();
+    let Some(mut user) = User::find_by_uuid(&user_id, &mut conn).await else {
+        err!("The account has already been deleted")
+    };
+    if user.delete_scheduled_at.is_none() {
+        err!("The deletion of this account is not scheduled")
+    }
+
+    user.delete_scheduled_at = None;
+    user.save(&mut conn).await?;
+    info!("The deletion of user {} has been cancelled", user.email);
+    link_result_page("Account restored", "Your account has been restored, you can log in again.")
+}
+
+/// Deletes the user, or when `USER_DELETE_DELAY_DAYS` is set, starts the retention period after which the account
+/// is deleted by the `user_deletion_schedule` job. An account which is already scheduled is deleted directly.
+pub async fn delete_user_account(mut user: User, nt: &Notify<'_>, conn: &mut DbConn) -> EmptyResult {
+    let delay_days = CONFIG.user_delete_delay_days();
+    if delay_days == 0 || user.delete_scheduled_at.is_some() {
+        let (user_id, email) = (user.uuid.clone(), user.email.clone());
+        user.delete(conn).await?;
+        send_user_webhook(UserWebhookEvent::Deleted, &user_id, &email);
+        return Ok(());
+    }
+    user.check_deletable(conn).await?;
+
+    let delete_at = Utc::now().naive_utc() + TimeDelta::try_days(i64::from(delay_days)).unwrap();
+    user.delete_scheduled_at = Some(delete_at);
+    user.reset_security_stamp();
+    user.save(conn).await?;
+    nt.send_logout(&user, None).await;
+
+    if CONFIG.mail_enabled() {
+        if let Err(e) = mail::send_delete_account_scheduled(&user.email, &user.uuid, &delete_at).await {
+            error!("Error sending account deletion scheduled email: {e:#?}");
+        }
+    }
+    Ok(())
+}
+
+pub async fn purge_scheduled_user_deletions(pool: DbPool) {
+    debug!("Deleting users scheduled for deletion");
+    let Ok(mut conn) = pool.get().await else {
+        error!("Failed to get DB connection while deleting scheduled users");
+        return;
+    };
+
+    let now = Utc::now().naive_utc();
+    for user in User::find_scheduled_for_deletion_before(&now, &mut conn).await {
+        info!("Deleting user {} after its deletion delay has passed", user.email);
+        let (user_id, email) = (user.uuid.clone(), user.email.clone());
+        match user.delete(&mut conn).await {
+            Ok(()) => send_user_webhook(UserWebhookEvent::Deleted, &user_id, &email),
+            Err(e) => error!("Error deleting scheduled user: {e:#?}"),
+        }
+    }
 }
 
 #[get("/accounts/revision-date")]
@@ -986,23 +1341,64 @@ fn revision_date(headers: Headers) -> JsonResult {
 #[serde(rename_all = "camelCase")]
 struct PasswordHintData {
     email: String,
+    captcha_response: Option<String>,
+}
+
+// The number of recent login IP addresses which are kept for every account
+const KNOWN_LOGIN_IPS: usize = 10;
+
+async fn find_known_login_ips(user_id: &UserId, conn: &mut DbConn) -> (TwoFactor, Vec<String>) {
+    let twofactor = TwoFactor::find_by_user_and_type(user_id, TwoFactorType::KnownLoginIps as i32, conn)
+        .await
+        .unwrap_or_else(|| TwoFactor::new(user_id.clone(), TwoFactorType::KnownLoginIps, String::new()));
+    let ips = serde_json::from_str(&twofactor.data).unwrap_or_default();
+    (twofactor, ips)
+}
+
+/// Remembers the IP address of a successful login, when password hints are restricted to known IP addresses.
+pub async fn record_login_ip(user_id: &UserId, ip: &ClientIp, conn: &mut DbConn) -> EmptyResult {
+    if !CONFIG.password_hints_require_known_ip() {
+        return Ok(());
+    }
+
+    let (mut twofactor, mut ips) = find_known_login_ips(user_id, conn).await;
+    let ip = ip.ip.to_string();
+    ips.retain(|known| known != &ip);
+    ips.insert(0, ip);
+    ips.truncate(KNOWN_LOGIN_IPS);
+
+    twofactor.data = serde_json::to_string(&ips)?;
+    twofactor.save(conn).await
 }
 
 #[post("/accounts/password-hint", data = "<data>")]
-async fn password_hint(data: Json<PasswordHintData>, mut conn: DbConn) -> EmptyResult {
-    if !CONFIG.password_hints_allowed() || (!CONFIG.mail_enabled() && !CONFIG.show_password_hint()) {
+async fn password_hint(data: Json<PasswordHintData>, ip: ClientIp, mut conn: DbConn) -> EmptyResult {
+    let send_mail = CONFIG.mail_enabled() && CONFIG.password_hints_email();
+    if !CONFIG.password_hints_allowed() || (!send_mail && !CONFIG.show_password_hint()) {
         err!("This server is not configured to provide password hints.");
     }
+    crate::ratelimit::check_limit_password_hint(&ip.ip)?;
 
     const NO_HINT: &str = "Sorry, you have no password hint...";
 
     let data: PasswordHintData = data.into_inner();
+    crate::captcha::verify(data.captcha_response.as_deref()).await?;
     let email = &data.email;
 
-    match User::find_by_mail(email, &mut conn).await {
+    // A request from an unknown IP address is handled like one for an unknown account,
+    // so the response does not reveal whether the account exists either
+    let user = match User::find_by_mail(email, &mut conn).await {
+        Some(user) if CONFIG.password_hints_require_known_ip() => {
+            let (_, ips) = find_known_login_ips(&user.uuid, &mut conn).await;
+            ips.contains(&ip.ip.to_string()).then_some(user)
+        }
+        user => user,
+    };
+
+    match user {
         None => {
             // To prevent user enumeration, act as if the user exists.
-            if CONFIG.mail_enabled() {
+            if send_mail {
                 // There is still a timing side channel here in that the code
                 // paths that send mail take noticeably longer than ones that
                 // don't. Add a randomized sleep to mitigate this somewhat.
@@ -1018,7 +1414,7 @@ async fn password_hint(data: Json<PasswordHintData>, mut conn: DbConn) -> EmptyR
         }
         Some(user) => {
             let hint: Option<String> = user.password_hint;
-            if CONFIG.mail_enabled() {
+            if send_mail {
                 mail::send_password_hint(email, hint).await?;
                 Ok(())
             } else if let Some(hint) = hint {
@@ -1041,11 +1437,23 @@ async fn prelogin(data: Json<PreloginData>, conn: DbConn) -> Json<Value> {
     _prelogin(data, conn).await
 }
 
+/// The KDF settings returned for an unknown email, one of the client defaults chosen by a keyed hash of the email.
+/// They stay the same for every request, like the settings of an existing account would.
+fn unknown_account_kdf(email: &str) -> (i32, i32, Option<i32>, Option<i32>) {
+    let hash = crypto::hmac_sign_sha256(crate::auth::key_derived_secret(), &email.trim().to_lowercase());
+    if hash.as_bytes()[0] % 2 == 0 {
+        (User::CLIENT_KDF_TYPE_DEFAULT, User::CLIENT_KDF_ITER_DEFAULT, None, None)
+    } else {
+        (UserKdfType::Argon2id as i32, 3, Some(64), Some(4))
+    }
+}
+
 pub async fn _prelogin(data: Json<PreloginData>, mut conn: DbConn) -> Json<Value> {
     let data: PreloginData = data.into_inner();
 
     let (kdf_type, kdf_iter, kdf_mem, kdf_para) = match User::find_by_mail(&data.email, &mut conn).await {
         Some(user) => (user.client_kdf_type, user.client_kdf_iter, user.client_kdf_memory, user.client_kdf_parallelism),
+        None if CONFIG.anti_enumeration() => unknown_account_kdf(&data.email),
         None => (User::CLIENT_KDF_TYPE_DEFAULT, User::CLIENT_KDF_ITER_DEFAULT, None, None),
     };
 
@@ -1054,26 +1462,18 @@ pub async fn _prelogin(data: Json<PreloginData>, mut conn: DbConn) -> Json<Value
         "kdfIterations": kdf_iter,
         "kdfMemory": kdf_mem,
         "kdfParallelism": kdf_para,
+        "masterPasswordPolicy": instance_master_password_policy_json(),
     }))
 }
 
 // https://github.com/bitwarden/server/blob/master/src/Api/Models/Request/Accounts/SecretVerificationRequestModel.cs
-#[derive(Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct SecretVerificationRequest {
-    master_password_hash: String,
-}
-
+// Both a master password hash or a protected action OTP are accepted, a successful verification allows protected actions for a short while
 #[post("/accounts/verify-password", data = "<data>")]
-fn verify_password(data: Json<SecretVerificationRequest>, headers: Headers) -> EmptyResult {
-    let data: SecretVerificationRequest = data.into_inner();
+async fn verify_password(data: Json<PasswordOrOtpData>, headers: Headers, mut conn: DbConn) -> EmptyResult {
+    let data: PasswordOrOtpData = data.into_inner();
     let user = headers.user;
 
-    if !user.check_valid_password(&data.master_password_hash) {
-        err!("Invalid password")
-    }
-
-    Ok(())
+    data.validate(&user, true, &mut conn).await
 }
 
 async fn _api_key(data: Json<PasswordOrOtpData>, rotate: bool, headers: Headers, mut conn: DbConn) -> JsonResult {
@@ -1108,11 +1508,20 @@ async fn rotate_api_key(data: Json<PasswordOrOtpData>, headers: Headers, conn: D
 
 #[get("/devices/knowndevice")]
 async fn get_known_device(device: KnownDevice, mut conn: DbConn) -> JsonResult {
-    let mut result = false;
-    if let Some(user) = User::find_by_mail(&device.email, &mut conn).await {
-        result = Device::find_by_uuid_and_user(&device.uuid, &user.uuid, &mut conn).await.is_some();
+    Ok(Json(json!(is_known_device(&device.email, &device.uuid, &mut conn).await)))
+}
+
+// Older clients send the email and the device identifier as part of the path instead of the headers
+#[get("/devices/knowndevice/<email>/<uuid>")]
+async fn get_known_device_from_path(email: &str, uuid: DeviceId, mut conn: DbConn) -> JsonResult {
+    Ok(Json(json!(is_known_device(email, &uuid, &mut conn).await)))
+}
+
+async fn is_known_device(email: &str, device_id: &DeviceId, conn: &mut DbConn) -> bool {
+    match User::find_by_mail(email, conn).await {
+        Some(user) => Device::find_by_uuid_and_user(device_id, &user.uuid, conn).await.is_some(),
+        None => false,
     }
-    Ok(Json(json!(result)))
 }
 
 struct KnownDevice {
@@ -1245,6 +1654,118 @@ async fn post_clear_device_token(device_id: DeviceId, conn: DbConn) -> EmptyResu
     put_clear_device_token(device_id, conn).await
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DeviceKeysData {
+    encrypted_user_key: String,
+    encrypted_public_key: String,
+    encrypted_private_key: String,
+}
+
+// Trusted device encryption, the client stores its keys to unlock the vault without the master password on this device
+#[put("/devices/<device_id>/keys", data = "<data>")]
+async fn put_device_keys(
+    device_id: DeviceId,
+    data: Json<DeviceKeysData>,
+    headers: Headers,
+    mut conn: DbConn,
+) -> JsonResult {
+    let data = data.into_inner();
+
+    let Some(mut device) = Device::find_by_uuid_and_user(&device_id, &headers.user.uuid, &mut conn).await else {
+        err!("No device found")
+    };
+    device.set_trust_keys(data.encrypted_user_key, data.encrypted_public_key, data.encrypted_private_key);
+    device.save(&mut conn).await?;
+
+    Ok(Json(device.to_json()))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DeviceTrustKeysData {
+    encrypted_user_key: String,
+    encrypted_public_key: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OtherDeviceTrustKeysData {
+    device_id: DeviceId,
+    encrypted_user_key: String,
+    encrypted_public_key: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UpdateDevicesTrustData {
+    current_device: Option<DeviceTrustKeysData>,
+    #[serde(default)]
+    other_devices: Vec<OtherDeviceTrustKeysData>,
+}
+
+// Sent by the client after a key rotation, with the keys of the trusted devices encrypted with the new user key.
+// The private key of a device is encrypted with its own device key, so it does not change.
+#[post("/devices/update-trust", data = "<data>")]
+async fn post_update_devices_trust(
+    data: Json<UpdateDevicesTrustData>,
+    headers: Headers,
+    mut conn: DbConn,
+) -> EmptyResult {
+    let data = data.into_inner();
+    let user_id = &headers.user.uuid;
+
+    let mut updates: Vec<(DeviceId, DeviceTrustKeysData)> = data
+        .other_devices
+        .into_iter()
+        .map(|d| {
+            (
+                d.device_id,
+                DeviceTrustKeysData {
+                    encrypted_user_key: d.encrypted_user_key,
+                    encrypted_public_key: d.encrypted_public_key,
+                },
+            )
+        })
+        .collect();
+    if let Some(current_device) = data.current_device {
+        updates.push((headers.device.uuid.clone(), current_device));
+    }
+
+    for (device_id, keys) in updates {
+        let Some(mut device) = Device::find_by_uuid_and_user(&device_id, user_id, &mut conn).await else {
+            err!("No device found")
+        };
+        // Only a device which has a private key can be trusted again
+        let Some(private_key) = device.encrypted_private_key.take() else {
+            err!(format!("Device {device_id} is not trusted"))
+        };
+        device.set_trust_keys(keys.encrypted_user_key, keys.encrypted_public_key, private_key);
+        device.save(&mut conn).await?;
+    }
+
+    Ok(())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UntrustDevicesData {
+    devices: Vec<DeviceId>,
+}
+
+#[post("/devices/untrust", data = "<data>")]
+async fn post_untrust_devices(data: Json<UntrustDevicesData>, headers: Headers, mut conn: DbConn) -> EmptyResult {
+    for device_id in data.into_inner().devices {
+        let Some(mut device) = Device::find_by_uuid_and_user(&device_id, &headers.user.uuid, &mut conn).await else {
+            err!("No device found")
+        };
+        device.delete_trust_keys();
+        device.save(&mut conn).await?;
+    }
+
+    Ok(())
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct AuthRequestRequest {
@@ -1297,19 +1818,7 @@ async fn post_auth_request(
     )
     .await;
 
-    Ok(Json(json!({
-        "id": auth_request.uuid,
-        "publicKey": auth_request.public_key,
-        "requestDeviceType": DeviceType::from_i32(auth_request.device_type).to_string(),
-        "requestIpAddress": auth_request.request_ip,
-        "key": null,
-        "masterPasswordHash": null,
-        "creationDate": format_date(&auth_request.creation_date),
-        "responseDate": null,
-        "requestApproved": false,
-        "origin": CONFIG.domain_origin(),
-        "object": "auth-request"
-    })))
+    Ok(Json(auth_request.to_json()))
 }
 
 #[get("/auth-requests/<auth_request_id>")]
@@ -1319,21 +1828,7 @@ async fn get_auth_request(auth_request_id: AuthRequestId, headers: Headers, mut
         err!("AuthRequest doesn't exist", "Record not found or user uuid does not match")
     };
 
-    let response_date_utc = auth_request.response_date.map(|response_date| format_date(&response_date));
-
-    Ok(Json(json!({
-        "id": &auth_request_id,
-        "publicKey": auth_request.public_key,
-        "requestDeviceType": DeviceType::from_i32(auth_request.device_type).to_string(),
-        "requestIpAddress": auth_request.request_ip,
-        "key": auth_request.enc_key,
-        "masterPasswordHash": auth_request.master_password_hash,
-        "creationDate": format_date(&auth_request.creation_date),
-        "responseDate": response_date_utc,
-        "requestApproved": auth_request.approved,
-        "origin": CONFIG.domain_origin(),
-        "object":"auth-request"
-    })))
+    Ok(Json(auth_request.to_json()))
 }
 
 #[derive(Debug, Deserialize)]
@@ -1365,15 +1860,17 @@ async fn put_auth_request(
         err!("An authentication request with the same device already exists")
     }
 
-    let response_date = Utc::now().naive_utc();
-    let response_date_utc = format_date(&response_date);
+    if auth_request.is_expired() {
+        err!("This login request has expired")
+    }
+
+    auth_request.approved = Some(data.request_approved);
+    auth_request.response_date = Some(Utc::now().naive_utc());
 
     if data.request_approved {
-        auth_request.approved = Some(data.request_approved);
         auth_request.enc_key = Some(data.key);
         auth_request.master_password_hash = data.master_password_hash;
         auth_request.response_device_id = Some(data.device_identifier.clone());
-        auth_request.response_date = Some(response_date);
         auth_request.save(&mut conn).await?;
 
         ant.send_auth_response(&auth_request.user_uuid, &auth_request.uuid).await;
@@ -1400,19 +1897,7 @@ async fn put_auth_request(
         .await;
     }
 
-    Ok(Json(json!({
-        "id": &auth_request_id,
-        "publicKey": auth_request.public_key,
-        "requestDeviceType": DeviceType::from_i32(auth_request.device_type).to_string(),
-        "requestIpAddress": auth_request.request_ip,
-        "key": auth_request.enc_key,
-        "masterPasswordHash": auth_request.master_password_hash,
-        "creationDate": format_date(&auth_request.creation_date),
-        "responseDate": response_date_utc,
-        "requestApproved": auth_request.approved,
-        "origin": CONFIG.domain_origin(),
-        "object":"auth-request"
-    })))
+    Ok(Json(auth_request.to_json()))
 }
 
 #[get("/auth-requests/<auth_request_id>/response?<code>")]
@@ -1433,21 +1918,7 @@ async fn get_auth_request_response(
         err!("AuthRequest doesn't exist", "Invalid device, IP or code")
     }
 
-    let response_date_utc = auth_request.response_date.map(|response_date| format_date(&response_date));
-
-    Ok(Json(json!({
-        "id": &auth_request_id,
-        "publicKey": auth_request.public_key,
-        "requestDeviceType": DeviceType::from_i32(auth_request.device_type).to_string(),
-        "requestIpAddress": auth_request.request_ip,
-        "key": auth_request.enc_key,
-        "masterPasswordHash": auth_request.master_password_hash,
-        "creationDate": format_date(&auth_request.creation_date),
-        "responseDate": response_date_utc,
-        "requestApproved": auth_request.approved,
-        "origin": CONFIG.domain_origin(),
-        "object":"auth-request"
-    })))
+    Ok(Json(auth_request.to_json()))
 }
 
 #[get("/auth-requests")]
@@ -1457,24 +1928,38 @@ async fn get_auth_requests(headers: Headers, mut conn: DbConn) -> JsonResult {
     Ok(Json(json!({
         "data": auth_requests
             .iter()
-            .filter(|request| request.approved.is_none())
-            .map(|request| {
-            let response_date_utc = request.response_date.map(|response_date| format_date(&response_date));
-
-            json!({
-                "id": request.uuid,
-                "publicKey": request.public_key,
-                "requestDeviceType": DeviceType::from_i32(request.device_type).to_string(),
-                "requestIpAddress": request.request_ip,
-                "key": request.enc_key,
-                "masterPasswordHash": request.master_password_hash,
-                "creationDate": format_date(&request.creation_date),
-                "responseDate": response_date_utc,
-                "requestApproved": request.approved,
-                "origin": CONFIG.domain_origin(),
-                "object":"auth-request"
-            })
-        }).collect::<Vec<Value>>(),
+            .filter(|request| request.approved.is_none() && !request.is_expired())
+            .map(AuthRequest::to_json)
+            .collect::<Vec<Value>>(),
+        "continuationToken": null,
+        "object": "list"
+    })))
+}
+
+/// Only the most recent pending request of every requesting device, used by the newer clients to show the approval dialog
+#[get("/auth-requests/pending")]
+async fn get_pending_auth_requests(headers: Headers, mut conn: DbConn) -> JsonResult {
+    let mut auth_requests: Vec<AuthRequest> = AuthRequest::find_by_user(&headers.user.uuid, &mut conn)
+        .await
+        .into_iter()
+        .filter(|request| request.approved.is_none() && !request.is_expired())
+        .collect();
+    auth_requests.sort_by(|a, b| b.creation_date.cmp(&a.creation_date));
+
+    let mut seen_devices = HashSet::new();
+    let pending: Vec<Value> = auth_requests
+        .iter()
+        .filter(|request| seen_devices.insert(request.request_device_identifier.clone()))
+        .map(|request| {
+            let mut json = request.to_json();
+            json["requestDeviceId"] = json!(request.request_device_identifier);
+            json["object"] = json!("pending-auth-request");
+            json
+        })
+        .collect();
+
+    Ok(Json(json!({
+        "data": pending,
         "continuationToken": null,
         "object": "list"
     })))
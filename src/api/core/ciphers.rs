@@ -13,10 +13,11 @@ use serde_json::Value;
 use crate::auth::ClientVersion;
 use crate::util::NumberOrString;
 use crate::{
-    api::{self, core::log_event, EmptyResult, JsonResult, Notify, PasswordOrOtpData, UpdateType},
+    api::{self, core::log_event, ApiResult, EmptyResult, JsonResult, Notify, PasswordOrOtpData, UpdateType},
     auth::Headers,
     crypto,
     db::{models::*, DbConn, DbPool},
+    storage::{FileBackend, FileStorage},
     CONFIG,
 };
 
@@ -98,6 +99,15 @@ pub async fn purge_trashed_ciphers(pool: DbPool) {
     }
 }
 
+pub async fn trim_password_histories(pool: DbPool) {
+    debug!("Trimming password histories");
+    if let Ok(mut conn) = pool.get().await {
+        Cipher::trim_password_histories(&mut conn).await;
+    } else {
+        error!("Failed to get DB connection while trimming password histories")
+    }
+}
+
 #[derive(FromForm, Default)]
 struct SyncData {
     #[field(name = "excludeDomains")]
@@ -111,6 +121,7 @@ async fn sync(
     client_version: Option<ClientVersion>,
     mut conn: DbConn,
 ) -> Json<Value> {
+    crate::usage_stats::record_sync();
     let user_json = headers.user.to_json(&mut conn).await;
 
     // Get all ciphers which are visible by the user
@@ -150,8 +161,12 @@ async fn sync(
     let sends_json: Vec<Value> =
         Send::find_by_user(&headers.user.uuid, &mut conn).await.iter().map(Send::to_json).collect();
 
-    let policies_json: Vec<Value> =
-        OrgPolicy::find_confirmed_by_user(&headers.user.uuid, &mut conn).await.iter().map(OrgPolicy::to_json).collect();
+    let policies_json: Vec<Value> = OrgPolicy::find_confirmed_by_user(&headers.user.uuid, &mut conn)
+        .await
+        .iter()
+        .filter(|p| p.is_client_visible())
+        .map(OrgPolicy::to_json)
+        .collect();
 
     let domains_json = if data.exclude_domains {
         Value::Null
@@ -241,7 +256,7 @@ pub struct CipherData {
     fields: Option<Value>,
 
     // Only one of these should exist, depending on type
-    login: Option<Value>,
+    pub login: Option<Value>,
     secure_note: Option<Value>,
     card: Option<Value>,
     identity: Option<Value>,
@@ -322,7 +337,7 @@ async fn post_ciphers_create(
     // or otherwise), we can just ignore this field entirely.
     data.cipher.last_known_revision_date = None;
 
-    share_cipher_by_uuid(&cipher.uuid, data, &headers, &mut conn, &nt).await
+    share_cipher_by_uuid(&cipher.uuid, data, &headers, &mut conn, Some(&nt)).await
 }
 
 /// Called when creating a new user-owned cipher.
@@ -337,7 +352,8 @@ async fn post_ciphers(data: Json<CipherData>, headers: Headers, mut conn: DbConn
     data.last_known_revision_date = None;
 
     let mut cipher = Cipher::new(data.r#type, data.name.clone());
-    update_cipher_from_data(&mut cipher, data, &headers, None, &mut conn, &nt, UpdateType::SyncCipherCreate).await?;
+    update_cipher_from_data(&mut cipher, data, &headers, None, &mut conn, Some(&nt), UpdateType::SyncCipherCreate)
+        .await?;
 
     Ok(Json(cipher.to_json(&headers.host, &headers.user.uuid, None, CipherSyncType::User, &mut conn).await))
 }
@@ -370,10 +386,14 @@ pub async fn update_cipher_from_data(
     headers: &Headers,
     shared_to_collections: Option<Vec<CollectionId>>,
     conn: &mut DbConn,
-    nt: &Notify<'_>,
+    nt: Option<&Notify<'_>>, // None when the caller sends one notification for multiple ciphers
     ut: UpdateType,
 ) -> EmptyResult {
-    enforce_personal_ownership_policy(Some(&data), headers, conn).await?;
+    // Imports and key rotations use UpdateType::None. Imports check the policy themselves before
+    // creating any cipher, and a key rotation has to be able to re-encrypt the existing personal ciphers.
+    if ut != UpdateType::None {
+        enforce_personal_ownership_policy(Some(&data), headers, conn).await?;
+    }
 
     // Check that the client isn't updating an existing cipher with stale data.
     // And only perform this check when not importing ciphers, else the date/time check will fail.
@@ -404,6 +424,13 @@ pub async fn update_cipher_from_data(
     // Check if this cipher is being transferred from a personal to an organization vault
     let transfer_cipher = cipher.organization_uuid.is_none() && data.organization_id.is_some();
 
+    // Members who can edit an organization item, but aren't allowed to see its password, can't change it either.
+    // The clients only hide these values, so this has to be enforced here.
+    let keep_hidden_values = ut != UpdateType::None
+        && !transfer_cipher
+        && cipher.organization_uuid.is_some()
+        && matches!(cipher.get_access_restrictions(&headers.user.uuid, None, conn).await, Some((_, true, false)));
+
     if let Some(org_id) = data.organization_id {
         match Membership::find_by_user_and_org(&headers.user.uuid, &org_id, conn).await {
             None => err!("You don't have permission to add item to organization"),
@@ -486,7 +513,7 @@ pub async fn update_cipher_from_data(
         _ => err!("Invalid type"),
     };
 
-    let type_data = match type_data_opt {
+    let mut type_data = match type_data_opt {
         Some(mut data) => {
             // Remove the 'Response' key from the base object.
             data.as_object_mut().unwrap().remove("response");
@@ -494,18 +521,50 @@ pub async fn update_cipher_from_data(
             if data["uris"].is_array() {
                 data["uris"] = _clean_cipher_data(data["uris"].clone());
             }
+            // The same goes for the passkeys stored by the browser extensions
+            if data["fido2Credentials"].is_array() {
+                data["fido2Credentials"] = _clean_cipher_data(data["fido2Credentials"].clone());
+            }
             data
         }
         None => err!("Data missing"),
     };
 
+    if keep_hidden_values {
+        // The stored encrypted values are only valid as long as the key of the item doesn't change
+        if data.key != cipher.key {
+            err!("You don't have permission to change the encryption key of this item")
+        }
+        if data.r#type != cipher.atype {
+            err!("You don't have permission to change the type of this item")
+        }
+        if cipher.atype == CipherType::Login as i32 {
+            keep_hidden_login_values(&mut type_data, &cipher.data);
+        }
+    }
+
+    let fields = if keep_hidden_values {
+        keep_hidden_fields(data.fields, cipher.fields.as_deref())
+    } else {
+        data.fields
+    };
+
     cipher.key = data.key;
     cipher.name = data.name;
     cipher.notes = data.notes;
-    cipher.fields = data.fields.map(|f| _clean_cipher_data(f).to_string());
+    cipher.fields = fields.map(|f| _clean_cipher_data(f).to_string());
     cipher.data = type_data.to_string();
-    cipher.password_history = data.password_history.map(|f| f.to_string());
-    cipher.reprompt = data.reprompt.filter(|r| *r == RepromptType::None as i32 || *r == RepromptType::Password as i32);
+    // The password history only contains hidden values
+    if !keep_hidden_values {
+        cipher.password_history = data.password_history.map(|f| f.to_string());
+        cipher.trim_password_history();
+    }
+    // Clients which support the re-prompt always send it, keep the current value when it's missing,
+    // else an update from an other client would silently turn it off.
+    if data.reprompt.is_some() {
+        cipher.reprompt =
+            data.reprompt.filter(|r| *r == RepromptType::None as i32 || *r == RepromptType::Password as i32);
+    }
 
     cipher.save(conn).await?;
     cipher.move_to_folder(data.folder_id, &headers.user.uuid, conn).await?;
@@ -531,15 +590,17 @@ pub async fn update_cipher_from_data(
             )
             .await;
         }
-        nt.send_cipher_update(
-            ut,
-            cipher,
-            &cipher.update_users_revision(conn).await,
-            &headers.device.uuid,
-            shared_to_collections,
-            conn,
-        )
-        .await;
+        if let Some(nt) = nt {
+            nt.send_cipher_update(
+                ut,
+                cipher,
+                &cipher.update_users_revision(conn).await,
+                &headers.device.uuid,
+                shared_to_collections,
+                conn,
+            )
+            .await;
+        }
     }
     Ok(())
 }
@@ -561,6 +622,44 @@ struct RelationsData {
     value: usize,
 }
 
+/// Replaces the password, TOTP secret and passkeys in the login data of an update with the stored values.
+/// Older ciphers can have these stored with different casing.
+fn keep_hidden_login_values(login: &mut Value, stored_data: &str) {
+    let stored = serde_json::from_str::<Value>(stored_data).unwrap_or_default();
+    for key in ["password", "totp", "fido2Credentials"] {
+        let value = get_ignore_case(&stored, key).cloned().unwrap_or_default();
+        login[key] = value;
+    }
+}
+
+/// Replaces the hidden custom fields of an update with the stored ones, the other fields are taken from the update.
+/// The values are encrypted, so the stored hidden fields can't be matched to the submitted ones and are appended.
+fn keep_hidden_fields(fields: Option<Value>, stored_fields: Option<&str>) -> Option<Value> {
+    const FIELD_TYPE_HIDDEN: u64 = 1;
+    let is_hidden = |field: &Value| get_ignore_case(field, "type").and_then(Value::as_u64) == Some(FIELD_TYPE_HIDDEN);
+
+    let stored_hidden: Vec<Value> = stored_fields
+        .and_then(|f| serde_json::from_str::<Vec<Value>>(f).ok())
+        .unwrap_or_default()
+        .into_iter()
+        .filter(is_hidden)
+        .collect();
+    if fields.is_none() && stored_hidden.is_empty() {
+        return None;
+    }
+
+    let mut kept: Vec<Value> = match fields {
+        Some(Value::Array(fields)) => fields.into_iter().filter(|f| !is_hidden(f)).collect(),
+        _ => Vec::new(),
+    };
+    kept.extend(stored_hidden);
+    Some(Value::Array(kept))
+}
+
+fn get_ignore_case<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    value.as_object()?.iter().find(|(k, _)| k.eq_ignore_ascii_case(key)).map(|(_, v)| v)
+}
+
 #[post("/ciphers/import", data = "<data>")]
 async fn post_ciphers_import(
     data: Json<ImportData>,
@@ -607,7 +706,7 @@ async fn post_ciphers_import(
         cipher_data.folder_id = folder_id;
 
         let mut cipher = Cipher::new(cipher_data.r#type, cipher_data.name.clone());
-        update_cipher_from_data(&mut cipher, cipher_data, &headers, None, &mut conn, &nt, UpdateType::None).await?;
+        update_cipher_from_data(&mut cipher, cipher_data, &headers, None, &mut conn, None, UpdateType::None).await?;
     }
 
     let mut user = headers.user;
@@ -623,10 +722,11 @@ async fn put_cipher_admin(
     cipher_id: CipherId,
     data: Json<CipherData>,
     headers: Headers,
+    client_version: Option<ClientVersion>,
     conn: DbConn,
     nt: Notify<'_>,
 ) -> JsonResult {
-    put_cipher(cipher_id, data, headers, conn, nt).await
+    put_cipher(cipher_id, data, headers, client_version, conn, nt).await
 }
 
 #[post("/ciphers/<cipher_id>/admin", data = "<data>")]
@@ -634,10 +734,11 @@ async fn post_cipher_admin(
     cipher_id: CipherId,
     data: Json<CipherData>,
     headers: Headers,
+    client_version: Option<ClientVersion>,
     conn: DbConn,
     nt: Notify<'_>,
 ) -> JsonResult {
-    post_cipher(cipher_id, data, headers, conn, nt).await
+    post_cipher(cipher_id, data, headers, client_version, conn, nt).await
 }
 
 #[post("/ciphers/<cipher_id>", data = "<data>")]
@@ -645,10 +746,11 @@ async fn post_cipher(
     cipher_id: CipherId,
     data: Json<CipherData>,
     headers: Headers,
+    client_version: Option<ClientVersion>,
     conn: DbConn,
     nt: Notify<'_>,
 ) -> JsonResult {
-    put_cipher(cipher_id, data, headers, conn, nt).await
+    put_cipher(cipher_id, data, headers, client_version, conn, nt).await
 }
 
 #[put("/ciphers/<cipher_id>", data = "<data>")]
@@ -656,6 +758,7 @@ async fn put_cipher(
     cipher_id: CipherId,
     data: Json<CipherData>,
     headers: Headers,
+    client_version: Option<ClientVersion>,
     mut conn: DbConn,
     nt: Notify<'_>,
 ) -> JsonResult {
@@ -674,7 +777,19 @@ async fn put_cipher(
         err!("Cipher is not write accessible")
     }
 
-    update_cipher_from_data(&mut cipher, data, &headers, None, &mut conn, &nt, UpdateType::SyncCipherUpdate).await?;
+    // Clients without support for individual cipher keys would drop the key of the cipher on an update.
+    // Without it, the data they didn't re-encrypt can't be decrypted anymore.
+    if cipher.key.is_some() && data.key.is_none() {
+        if let Some(client_version) = client_version {
+            let ver_match = semver::VersionReq::parse(">=2024.2.0").unwrap();
+            if !ver_match.matches(&client_version.0) {
+                err!("Cannot edit item. Update to the latest version of Bitwarden and try again.")
+            }
+        }
+    }
+
+    update_cipher_from_data(&mut cipher, data, &headers, None, &mut conn, Some(&nt), UpdateType::SyncCipherUpdate)
+        .await?;
 
     Ok(Json(cipher.to_json(&headers.host, &headers.user.uuid, None, CipherSyncType::User, &mut conn).await))
 }
@@ -924,7 +1039,7 @@ async fn post_cipher_share(
 ) -> JsonResult {
     let data: ShareCipherData = data.into_inner();
 
-    share_cipher_by_uuid(&cipher_id, data, &headers, &mut conn, &nt).await
+    share_cipher_by_uuid(&cipher_id, data, &headers, &mut conn, Some(&nt)).await
 }
 
 #[put("/ciphers/<cipher_id>/share", data = "<data>")]
@@ -937,7 +1052,7 @@ async fn put_cipher_share(
 ) -> JsonResult {
     let data: ShareCipherData = data.into_inner();
 
-    share_cipher_by_uuid(&cipher_id, data, &headers, &mut conn, &nt).await
+    share_cipher_by_uuid(&cipher_id, data, &headers, &mut conn, Some(&nt)).await
 }
 
 #[derive(Deserialize)]
@@ -964,24 +1079,56 @@ async fn put_cipher_share_selected(
         err!("You must select at least one collection.")
     }
 
+    let mut cipher_ids = Vec::with_capacity(data.ciphers.len());
+    let mut org_ids = HashSet::new();
     for cipher in data.ciphers.iter() {
-        if cipher.id.is_none() {
+        let Some(cipher_id) = &cipher.id else {
             err!("Request missing ids field")
+        };
+        cipher_ids.push(cipher_id.clone());
+        if let Some(org_id) = &cipher.organization_id {
+            org_ids.insert(org_id.clone());
         }
     }
 
-    while let Some(cipher) = data.ciphers.pop() {
-        let mut shared_cipher_data = ShareCipherData {
-            cipher,
-            collection_ids: data.collection_ids.clone(),
-        };
+    // Check all ciphers and collections first, so an invalid one doesn't leave the selection partially shared
+    find_bulk_ciphers(&cipher_ids, &headers.user.uuid, true, &mut conn).await?;
+    for org_id in &org_ids {
+        for col_id in &data.collection_ids {
+            match Collection::find_by_uuid_and_org(col_id, org_id, &mut conn).await {
+                None => err!("Invalid collection ID provided"),
+                Some(collection) if !collection.is_writable_by_user(&headers.user.uuid, &mut conn).await => {
+                    err!("No rights to modify the collection")
+                }
+                Some(_) => (),
+            }
+        }
+    }
 
-        match shared_cipher_data.cipher.id.take() {
-            Some(id) => share_cipher_by_uuid(&id, shared_cipher_data, &headers, &mut conn, &nt).await?,
-            None => err!("Request missing ids field"),
-        };
+    // All ciphers are shared in one transaction, a failure leaves all of them unchanged
+    conn.begin_transaction().await?;
+    let result: ApiResult<HashSet<UserId>> = async {
+        let mut user_ids = HashSet::new();
+        while let Some(cipher) = data.ciphers.pop() {
+            let mut shared_cipher_data = ShareCipherData {
+                cipher,
+                collection_ids: data.collection_ids.clone(),
+            };
+
+            let Some(cipher_id) = shared_cipher_data.cipher.id.take() else {
+                continue;
+            };
+            share_cipher_by_uuid(&cipher_id, shared_cipher_data, &headers, &mut conn, None).await?;
+            if let Some(cipher) = Cipher::find_by_uuid(&cipher_id, &mut conn).await {
+                user_ids.extend(cipher.update_users_revision(&mut conn).await);
+            }
+        }
+        Ok(user_ids)
     }
+    .await;
+    let user_ids = conn.end_transaction(result).await?;
 
+    send_bulk_cipher_update(user_ids, &mut conn, &nt).await;
     Ok(())
 }
 
@@ -990,7 +1137,7 @@ async fn share_cipher_by_uuid(
     data: ShareCipherData,
     headers: &Headers,
     conn: &mut DbConn,
-    nt: &Notify<'_>,
+    nt: Option<&Notify<'_>>,
 ) -> JsonResult {
     let mut cipher = match Cipher::find_by_uuid(cipher_id, conn).await {
         Some(cipher) => {
@@ -1100,10 +1247,18 @@ async fn post_attachment_v2(
     if file_size < 0 {
         err!("Attachment size can't be negative")
     }
+
+    // Check the size before the client starts the upload, this is checked again when the data is received
+    if let Some(size_left) = attachment_size_left(&cipher, 0, &mut conn).await? {
+        if file_size > size_left {
+            err!("Attachment storage limit exceeded with this file");
+        }
+    }
+
     let attachment_id = crypto::generate_attachment_id();
     let attachment =
         Attachment::new(attachment_id.clone(), cipher.uuid.clone(), data.file_name, file_size, Some(data.key));
-    attachment.save(&mut conn).await.expect("Error saving attachment");
+    attachment.save(&mut conn).await?;
 
     let url = format!("/ciphers/{}/attachment/{}", cipher.uuid, attachment_id);
     let response_key = match data.admin_request {
@@ -1120,6 +1275,63 @@ async fn post_attachment_v2(
     })))
 }
 
+/// Returns how many bytes of attachments the owner of the cipher can still store, None when there is no limit.
+/// `size_adjust` is the size of an attachment which is already counted, but is being uploaded.
+async fn attachment_size_left(cipher: &Cipher, size_adjust: i64, conn: &mut DbConn) -> ApiResult<Option<i64>> {
+    let size_left = if let Some(ref user_id) = cipher.user_uuid {
+        match CONFIG.user_attachment_limit() {
+            Some(0) => err!("Attachments are disabled"),
+            Some(limit_kb) => {
+                let already_used = Attachment::size_by_user(user_id, conn).await;
+                let left = limit_kb
+                    .checked_mul(1024)
+                    .and_then(|l| l.checked_sub(already_used))
+                    .and_then(|l| l.checked_add(size_adjust));
+
+                let Some(left) = left else {
+                    err!("Attachment size overflow");
+                };
+
+                if left <= 0 {
+                    err!("Attachment storage limit reached! Delete some attachments to free up space")
+                }
+
+                Some(left)
+            }
+            None => None,
+        }
+    } else if let Some(ref org_id) = cipher.organization_uuid {
+        let Some(org) = Organization::find_by_uuid(org_id, conn).await else {
+            err!("Organization not found")
+        };
+        match org.attachment_limit_kb() {
+            Some(0) => err!("Attachments are disabled for this organization"),
+            Some(limit_kb) => {
+                let already_used = Attachment::size_by_org(org_id, conn).await;
+                let left = limit_kb
+                    .checked_mul(1024)
+                    .and_then(|l| l.checked_sub(already_used))
+                    .and_then(|l| l.checked_add(size_adjust));
+
+                let Some(left) = left else {
+                    err!("Attachment size overflow");
+                };
+
+                if left <= 0 {
+                    err!("The attachment storage limit of the organization is reached! Delete some of its attachments to free up space")
+                }
+
+                Some(left)
+            }
+            None => None,
+        }
+    } else {
+        err!("Cipher is neither owned by a user nor an organization");
+    };
+
+    Ok(size_left)
+}
+
 #[derive(FromForm)]
 struct UploadData<'f> {
     key: Option<String>,
@@ -1166,53 +1378,7 @@ async fn save_attachment(
         Some(a) => a.file_size, // v2 API
     };
 
-    let size_limit = if let Some(ref user_id) = cipher.user_uuid {
-        match CONFIG.user_attachment_limit() {
-            Some(0) => err!("Attachments are disabled"),
-            Some(limit_kb) => {
-                let already_used = Attachment::size_by_user(user_id, &mut conn).await;
-                let left = limit_kb
-                    .checked_mul(1024)
-                    .and_then(|l| l.checked_sub(already_used))
-                    .and_then(|l| l.checked_add(size_adjust));
-
-                let Some(left) = left else {
-                    err!("Attachment size overflow");
-                };
-
-                if left <= 0 {
-                    err!("Attachment storage limit reached! Delete some attachments to free up space")
-                }
-
-                Some(left)
-            }
-            None => None,
-        }
-    } else if let Some(ref org_id) = cipher.organization_uuid {
-        match CONFIG.org_attachment_limit() {
-            Some(0) => err!("Attachments are disabled"),
-            Some(limit_kb) => {
-                let already_used = Attachment::size_by_org(org_id, &mut conn).await;
-                let left = limit_kb
-                    .checked_mul(1024)
-                    .and_then(|l| l.checked_sub(already_used))
-                    .and_then(|l| l.checked_add(size_adjust));
-
-                let Some(left) = left else {
-                    err!("Attachment size overflow");
-                };
-
-                if left <= 0 {
-                    err!("Attachment storage limit reached! Delete some attachments to free up space")
-                }
-
-                Some(left)
-            }
-            None => None,
-        }
-    } else {
-        err!("Cipher is neither owned by a user nor an organization");
-    };
+    let size_limit = attachment_size_left(&cipher, size_adjust, &mut conn).await?;
 
     if let Some(size_limit) = size_limit {
         if size > size_limit {
@@ -1243,7 +1409,7 @@ async fn save_attachment(
             if size != attachment.file_size {
                 // Update the attachment with the actual file size.
                 attachment.file_size = size;
-                attachment.save(&mut conn).await.expect("Error updating attachment");
+                attachment.save(&mut conn).await?;
             }
         } else {
             attachment.delete(&mut conn).await.ok();
@@ -1266,16 +1432,12 @@ async fn save_attachment(
         }
         let attachment =
             Attachment::new(file_id.clone(), cipher_id.clone(), encrypted_filename.unwrap(), size, data.key);
-        attachment.save(&mut conn).await.expect("Error saving attachment");
+        attachment.save(&mut conn).await?;
     }
 
-    let folder_path = tokio::fs::canonicalize(&CONFIG.attachments_folder()).await?.join(cipher_id.as_ref());
-    let file_path = folder_path.join(file_id.as_ref());
-    tokio::fs::create_dir_all(&folder_path).await?;
-
-    if let Err(_err) = data.data.persist_to(&file_path).await {
-        data.data.move_copy_to(file_path).await?
-    }
+    let io_permit = crate::util::acquire_io_permit(crate::util::FileStore::Attachments).await;
+    FileStorage::attachments().save(&format!("{cipher_id}/{file_id}"), &mut data.data).await?;
+    drop(io_permit);
 
     nt.send_cipher_update(
         UpdateType::SyncCipherUpdate,
@@ -1566,29 +1728,20 @@ async fn move_cipher_selected(
         }
     }
 
-    for cipher_id in data.ids {
-        let Some(cipher) = Cipher::find_by_uuid(&cipher_id, &mut conn).await else {
-            err!("Cipher doesn't exist")
-        };
-
-        if !cipher.is_accessible_to_user(&user_id, &mut conn).await {
-            err!("Cipher is not accessible by user")
+    let ciphers = find_bulk_ciphers(&data.ids, &user_id, false, &mut conn).await?;
+    // All ciphers are moved in one transaction, a failure leaves all of them in their folder
+    conn.begin_transaction().await?;
+    let result: EmptyResult = async {
+        for cipher in ciphers {
+            cipher.move_to_folder(data.folder_id.clone(), &user_id, &mut conn).await?;
         }
-
-        // Move cipher
-        cipher.move_to_folder(data.folder_id.clone(), &user_id, &mut conn).await?;
-
-        nt.send_cipher_update(
-            UpdateType::SyncCipherUpdate,
-            &cipher,
-            &[user_id.clone()],
-            &headers.device.uuid,
-            None,
-            &mut conn,
-        )
-        .await;
+        Ok(())
     }
+    .await;
+    conn.end_transaction(result).await?;
 
+    // Folders are personal, so only the devices of the user itself need to sync
+    send_bulk_cipher_update(HashSet::from([user_id]), &mut conn, &nt).await;
     Ok(())
 }
 
@@ -1684,42 +1837,47 @@ async fn _delete_cipher_by_uuid(
         err!("Cipher can't be deleted by user")
     }
 
-    if soft_delete {
+    let mut attachment_files = Vec::new();
+    _delete_cipher(&mut cipher, headers, conn, soft_delete, &mut attachment_files, Some(nt)).await?;
+    Attachment::delete_files(&attachment_files).await
+}
+
+/// Deletes the cipher, returns the users of which the vault changed.
+/// The attachment files of a hard deleted cipher are only added to `attachment_files`,
+/// the caller removes them from the storage once the deletion can't be rolled back anymore.
+async fn _delete_cipher(
+    cipher: &mut Cipher,
+    headers: &Headers,
+    conn: &mut DbConn,
+    soft_delete: bool,
+    attachment_files: &mut Vec<String>,
+    nt: Option<&Notify<'_>>, // None when the caller sends one notification for multiple ciphers
+) -> ApiResult<Vec<UserId>> {
+    let ut = if soft_delete {
         cipher.deleted_at = Some(Utc::now().naive_utc());
         cipher.save(conn).await?;
-        nt.send_cipher_update(
-            UpdateType::SyncCipherUpdate,
-            &cipher,
-            &cipher.update_users_revision(conn).await,
-            &headers.device.uuid,
-            None,
-            conn,
-        )
-        .await;
+        UpdateType::SyncCipherUpdate
     } else {
-        cipher.delete(conn).await?;
-        nt.send_cipher_update(
-            UpdateType::SyncCipherDelete,
-            &cipher,
-            &cipher.update_users_revision(conn).await,
-            &headers.device.uuid,
-            None,
-            conn,
-        )
-        .await;
+        attachment_files.extend(cipher.delete_records(conn).await?);
+        UpdateType::SyncCipherDelete
+    };
+
+    let user_ids = cipher.update_users_revision(conn).await;
+    if let Some(nt) = nt {
+        nt.send_cipher_update(ut, cipher, &user_ids, &headers.device.uuid, None, conn).await;
     }
 
-    if let Some(org_id) = cipher.organization_uuid {
+    if let Some(org_id) = &cipher.organization_uuid {
         let event_type = match soft_delete {
             true => EventType::CipherSoftDeleted as i32,
             false => EventType::CipherDeleted as i32,
         };
 
-        log_event(event_type, &cipher.uuid, &org_id, &headers.user.uuid, headers.device.atype, &headers.ip.ip, conn)
+        log_event(event_type, &cipher.uuid, org_id, &headers.user.uuid, headers.device.atype, &headers.ip.ip, conn)
             .await;
     }
 
-    Ok(())
+    Ok(user_ids)
 }
 
 #[derive(Deserialize)]
@@ -1737,12 +1895,28 @@ async fn _delete_multiple_ciphers(
 ) -> EmptyResult {
     let data = data.into_inner();
 
-    for cipher_id in data.ids {
-        if let error @ Err(_) = _delete_cipher_by_uuid(&cipher_id, &headers, &mut conn, soft_delete, &nt).await {
-            return error;
-        };
+    let ciphers = find_bulk_ciphers(&data.ids, &headers.user.uuid, true, &mut conn).await?;
+    // All ciphers are deleted in one transaction, a failure leaves all of them in place.
+    // The attachment files of hard deleted ciphers are only removed from the storage after the commit.
+    let mut attachment_files = Vec::new();
+    conn.begin_transaction().await?;
+    let result: ApiResult<HashSet<UserId>> = async {
+        let mut user_ids = HashSet::new();
+        for mut cipher in ciphers {
+            user_ids.extend(
+                _delete_cipher(&mut cipher, &headers, &mut conn, soft_delete, &mut attachment_files, None).await?,
+            );
+        }
+        Ok(user_ids)
     }
+    .await;
+    let user_ids = conn.end_transaction(result).await?;
 
+    if let Err(e) = Attachment::delete_files(&attachment_files).await {
+        error!("Error removing the attachment files of deleted ciphers: {e:#?}");
+    }
+
+    send_bulk_cipher_update(user_ids, &mut conn, &nt).await;
     Ok(())
 }
 
@@ -1760,18 +1934,25 @@ async fn _restore_cipher_by_uuid(
         err!("Cipher can't be restored by user")
     }
 
+    _restore_cipher(&mut cipher, headers, conn, Some(nt)).await?;
+
+    Ok(Json(cipher.to_json(&headers.host, &headers.user.uuid, None, CipherSyncType::User, conn).await))
+}
+
+/// Restores the cipher from the trash, returns the users of which the vault changed.
+async fn _restore_cipher(
+    cipher: &mut Cipher,
+    headers: &Headers,
+    conn: &mut DbConn,
+    nt: Option<&Notify<'_>>, // None when the caller sends one notification for multiple ciphers
+) -> ApiResult<Vec<UserId>> {
     cipher.deleted_at = None;
     cipher.save(conn).await?;
 
-    nt.send_cipher_update(
-        UpdateType::SyncCipherUpdate,
-        &cipher,
-        &cipher.update_users_revision(conn).await,
-        &headers.device.uuid,
-        None,
-        conn,
-    )
-    .await;
+    let user_ids = cipher.update_users_revision(conn).await;
+    if let Some(nt) = nt {
+        nt.send_cipher_update(UpdateType::SyncCipherUpdate, cipher, &user_ids, &headers.device.uuid, None, conn).await;
+    }
 
     if let Some(org_id) = &cipher.organization_uuid {
         log_event(
@@ -1786,7 +1967,7 @@ async fn _restore_cipher_by_uuid(
         .await;
     }
 
-    Ok(Json(cipher.to_json(&headers.host, &headers.user.uuid, None, CipherSyncType::User, conn).await))
+    Ok(user_ids)
 }
 
 async fn _restore_multiple_ciphers(
@@ -1797,12 +1978,26 @@ async fn _restore_multiple_ciphers(
 ) -> JsonResult {
     let data = data.into_inner();
 
-    let mut ciphers: Vec<Value> = Vec::new();
-    for cipher_id in data.ids {
-        match _restore_cipher_by_uuid(&cipher_id, headers, conn, nt).await {
-            Ok(json) => ciphers.push(json.into_inner()),
-            err => return err,
+    let ciphers = find_bulk_ciphers(&data.ids, &headers.user.uuid, true, conn).await?;
+    // All ciphers are restored in one transaction, a failure leaves all of them in the trash
+    conn.begin_transaction().await?;
+    let result: ApiResult<(HashSet<UserId>, Vec<Cipher>)> = async {
+        let mut user_ids = HashSet::new();
+        let mut restored = Vec::with_capacity(ciphers.len());
+        for mut cipher in ciphers {
+            user_ids.extend(_restore_cipher(&mut cipher, headers, conn, None).await?);
+            restored.push(cipher);
         }
+        Ok((user_ids, restored))
+    }
+    .await;
+    let (user_ids, restored) = conn.end_transaction(result).await?;
+
+    send_bulk_cipher_update(user_ids, conn, nt).await;
+
+    let mut ciphers: Vec<Value> = Vec::with_capacity(restored.len());
+    for cipher in restored {
+        ciphers.push(cipher.to_json(&headers.host, &headers.user.uuid, None, CipherSyncType::User, conn).await);
     }
 
     Ok(Json(json!({
@@ -1812,6 +2007,39 @@ async fn _restore_multiple_ciphers(
     })))
 }
 
+/// Looks up all ciphers of a bulk operation before any of them is changed,
+/// so a missing or inaccessible cipher doesn't leave the operation half done.
+async fn find_bulk_ciphers(
+    cipher_ids: &[CipherId],
+    user_id: &UserId,
+    write: bool,
+    conn: &mut DbConn,
+) -> ApiResult<Vec<Cipher>> {
+    let mut ciphers = Vec::with_capacity(cipher_ids.len());
+    for cipher_id in cipher_ids {
+        let Some(cipher) = Cipher::find_by_uuid(cipher_id, conn).await else {
+            err!("Cipher doesn't exist")
+        };
+        if write && !cipher.is_write_accessible_to_user(user_id, conn).await {
+            err!("Cipher is not write accessible")
+        }
+        if !write && !cipher.is_accessible_to_user(user_id, conn).await {
+            err!("Cipher is not accessible by user")
+        }
+        ciphers.push(cipher);
+    }
+    Ok(ciphers)
+}
+
+/// Sends one notification after a bulk operation instead of one per cipher, the clients of the users do a sync.
+async fn send_bulk_cipher_update(user_ids: HashSet<UserId>, conn: &mut DbConn, nt: &Notify<'_>) {
+    for user_id in user_ids {
+        if let Some(user) = User::find_by_uuid(&user_id, conn).await {
+            nt.send_user_update(UpdateType::SyncCiphers, &user).await;
+        }
+    }
+}
+
 async fn _delete_cipher_attachment_by_id(
     cipher_id: &CipherId,
     attachment_id: &AttachmentId,
@@ -1961,3 +2189,48 @@ impl CipherSyncData {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keep_hidden_login_values_restores_stored_secrets() {
+        let stored =
+            r#"{"Username":"2.user","Password":"2.stored","Totp":"2.totp","fido2Credentials":[{"keyValue":"2.key"}]}"#;
+        let mut login = json!({"username": "2.changed", "password": "2.new", "totp": null, "fido2Credentials": []});
+
+        keep_hidden_login_values(&mut login, stored);
+
+        assert_eq!(login["username"], "2.changed");
+        assert_eq!(login["password"], "2.stored");
+        assert_eq!(login["totp"], "2.totp");
+        assert_eq!(login["fido2Credentials"], json!([{"keyValue": "2.key"}]));
+    }
+
+    #[test]
+    fn keep_hidden_fields_restores_stored_hidden_fields() {
+        let stored = r#"[{"type":0,"name":"2.text","value":"2.old"},{"Type":1,"Name":"2.hidden","Value":"2.secret"}]"#;
+        let fields = json!([
+            {"type": 0, "name": "2.text", "value": "2.new"},
+            {"type": 1, "name": "2.hidden", "value": "2.overwritten"},
+            {"type": 1, "name": "2.added", "value": "2.added"},
+        ]);
+
+        let kept = keep_hidden_fields(Some(fields), Some(stored)).unwrap();
+
+        assert_eq!(
+            kept,
+            json!([
+                {"type": 0, "name": "2.text", "value": "2.new"},
+                {"Type": 1, "Name": "2.hidden", "Value": "2.secret"},
+            ])
+        );
+        // Removing all fields also keeps the hidden ones
+        assert_eq!(
+            keep_hidden_fields(None, Some(stored)).unwrap(),
+            json!([{"Type": 1, "Name": "2.hidden", "Value": "2.secret"}])
+        );
+        assert_eq!(keep_hidden_fields(None, None), None);
+    }
+}
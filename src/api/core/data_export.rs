@@ -0,0 +1,162 @@
+//
+// Export of the personal data the server stores about a user, to answer data subject access requests
+// The vault contents stay encrypted and can be exported by the clients, this only lists their metadata.
+// Vaultwarden does not keep a log of the sent emails, so there are no email entries to export.
+//
+use rocket::{serde::json::Json, Route};
+use serde_json::Value;
+
+use crate::{
+    api::{JsonResult, PasswordOrOtpData},
+    auth::Headers,
+    db::{models::*, DbConn},
+    util::format_date,
+};
+
+pub fn routes() -> Vec<Route> {
+    routes![post_data_export]
+}
+
+#[post("/accounts/data-export", data = "<data>")]
+async fn post_data_export(data: Json<PasswordOrOtpData>, headers: Headers, mut conn: DbConn) -> JsonResult {
+    let data: PasswordOrOtpData = data.into_inner();
+    let user = headers.user;
+
+    data.validate(&user, true, &mut conn).await?;
+    crate::ratelimit::check_limit_export(user.uuid.as_ref())?;
+
+    let profile = json!({
+        "id": user.uuid,
+        "email": user.email,
+        "name": user.name,
+        "enabled": user.enabled,
+        "creationDate": format_date(&user.created_at),
+        "revisionDate": format_date(&user.updated_at),
+        "emailVerifiedDate": user.verified_at.as_ref().map(format_date),
+        "pendingEmail": user.email_new,
+        "passwordHint": user.password_hint,
+        "avatarColor": user.avatar_color,
+        "externalId": user.external_id,
+        "kdf": user.client_kdf_type,
+        "kdfIterations": user.client_kdf_iter,
+        "kdfMemory": user.client_kdf_memory,
+        "kdfParallelism": user.client_kdf_parallelism,
+        "apiKeyEnabled": user.api_key.is_some(),
+        "deletionScheduledDate": user.delete_scheduled_at.as_ref().map(format_date),
+    });
+
+    let devices: Vec<Value> = Device::find_by_user(&user.uuid, &mut conn)
+        .await
+        .iter()
+        .map(|d| {
+            json!({
+                "id": d.uuid,
+                "name": d.name,
+                "type": d.atype,
+                "creationDate": format_date(&d.created_at),
+                "lastActivityDate": format_date(&d.updated_at),
+                "pushRegistered": d.is_registered(),
+                "isTrusted": d.is_trusted(),
+            })
+        })
+        .collect();
+
+    let mut ciphers = Vec::new();
+    for cipher in Cipher::find_owned_by_user(&user.uuid, &mut conn).await {
+        let attachments = Attachment::find_by_cipher(&cipher.uuid, &mut conn).await;
+        ciphers.push(json!({
+            "id": cipher.uuid,
+            "type": cipher.atype,
+            "creationDate": format_date(&cipher.created_at),
+            "revisionDate": format_date(&cipher.updated_at),
+            "deletedDate": cipher.deleted_at.as_ref().map(format_date),
+            "attachments": attachments.len(),
+            "attachmentsSize": attachments.iter().map(|a| a.file_size).sum::<i64>(),
+        }));
+    }
+
+    let folders: Vec<Value> = Folder::find_by_user(&user.uuid, &mut conn)
+        .await
+        .iter()
+        .map(|f| {
+            json!({
+                "id": f.uuid,
+                "creationDate": format_date(&f.created_at),
+                "revisionDate": format_date(&f.updated_at),
+            })
+        })
+        .collect();
+
+    let sends: Vec<Value> = Send::find_by_user(&user.uuid, &mut conn)
+        .await
+        .iter()
+        .map(|s| {
+            json!({
+                "id": s.uuid,
+                "type": s.atype,
+                "accessCount": s.access_count,
+                "creationDate": format_date(&s.creation_date),
+                "revisionDate": format_date(&s.revision_date),
+                "expirationDate": s.expiration_date.as_ref().map(format_date),
+                "deletionDate": format_date(&s.deletion_date),
+            })
+        })
+        .collect();
+
+    let mut memberships = Vec::new();
+    for member in Membership::find_by_user(&user.uuid, &mut conn).await {
+        let org_name = Organization::find_by_uuid(&member.org_uuid, &mut conn).await.map(|o| o.name);
+        memberships.push(json!({
+            "id": member.uuid,
+            "organizationId": member.org_uuid,
+            "organizationName": org_name,
+            "type": member.atype,
+            "status": member.status,
+            "accessAll": member.access_all,
+            "resetPasswordEnrolled": member.reset_password_key.is_some(),
+            "externalId": member.external_id,
+        }));
+    }
+
+    let two_factor_providers: Vec<Value> =
+        TwoFactor::find_by_user(&user.uuid, &mut conn).await.iter().map(TwoFactor::to_json_provider).collect();
+
+    let emergency_access_granted: Vec<Value> =
+        EmergencyAccess::find_all_by_grantor_uuid(&user.uuid, &mut conn).await.iter().map(|e| e.to_json()).collect();
+    let emergency_access_trusted: Vec<Value> =
+        EmergencyAccess::find_all_by_grantee_uuid(&user.uuid, &mut conn).await.iter().map(|e| e.to_json()).collect();
+
+    let auth_requests: Vec<Value> = AuthRequest::find_by_user(&user.uuid, &mut conn)
+        .await
+        .iter()
+        .map(|a| {
+            json!({
+                "id": a.uuid,
+                "deviceIdentifier": a.request_device_identifier,
+                "deviceType": a.device_type,
+                "requestIpAddress": a.request_ip,
+                "approved": a.approved,
+                "creationDate": format_date(&a.creation_date),
+                "responseDate": a.response_date.as_ref().map(format_date),
+            })
+        })
+        .collect();
+
+    let events: Vec<Value> = Event::find_by_user(&user.uuid, &mut conn).await.iter().map(|e| e.to_json()).collect();
+
+    Ok(Json(json!({
+        "exportDate": format_date(&chrono::Utc::now().naive_utc()),
+        "profile": profile,
+        "devices": devices,
+        "ciphers": ciphers,
+        "folders": folders,
+        "sends": sends,
+        "organizationMemberships": memberships,
+        "twoFactorProviders": two_factor_providers,
+        "emergencyAccessGranted": emergency_access_granted,
+        "emergencyAccessTrusted": emergency_access_trusted,
+        "authRequests": auth_requests,
+        "events": events,
+        "object": "dataExport",
+    })))
+}
@@ -0,0 +1,116 @@
+//
+// Approval of logins from unknown devices
+// When `NEW_DEVICE_APPROVAL` is enabled, a login from a device which was never used by the user is held pending
+// until the user approves it via the emailed link. Logging in with an approved auth request ("Log in with device")
+// already is an approval by one of the existing devices of the user.
+//
+use chrono::Utc;
+use rocket::{form::Form, response::content::RawHtml as Html, Route};
+
+use crate::{
+    api::{link_confirm_page, link_result_page, ApiResult, EmptyResult, LinkTokenData},
+    auth::{decode_device_approval, ClientIp},
+    db::{models::*, DbConn},
+    error::ErrorEvent,
+    mail, CONFIG,
+};
+
+pub fn routes() -> Vec<Route> {
+    routes![approve_device_page, approve_device]
+}
+
+#[derive(Deserialize, Serialize)]
+struct PendingDevice {
+    device_id: DeviceId,
+    requested_at: i64,
+    approved: bool,
+}
+
+async fn find_pending(user_id: &UserId, conn: &mut DbConn) -> (TwoFactor, Vec<PendingDevice>) {
+    let twofactor = TwoFactor::find_by_user_and_type(user_id, TwoFactorType::NewDeviceApproval as i32, conn)
+        .await
+        .unwrap_or_else(|| TwoFactor::new(user_id.clone(), TwoFactorType::NewDeviceApproval, String::new()));
+    let mut pending: Vec<PendingDevice> = serde_json::from_str(&twofactor.data).unwrap_or_default();
+
+    let expired_before = Utc::now().timestamp() - i64::from(CONFIG.new_device_approval_expiration_minutes()) * 60;
+    pending.retain(|p| p.requested_at > expired_before);
+    (twofactor, pending)
+}
+
+async fn save_pending(mut twofactor: TwoFactor, pending: &[PendingDevice], conn: &mut DbConn) -> EmptyResult {
+    if pending.is_empty() {
+        return twofactor.delete(conn).await;
+    }
+    twofactor.data = serde_json::to_string(pending)?;
+    twofactor.save(conn).await
+}
+
+/// Returns an error when the login from this new device still needs to be approved by the user.
+/// The approval email is only sent once for every pending device, until the request has expired.
+pub async fn check_device_approval(user: &User, device: &Device, ip: &ClientIp, conn: &mut DbConn) -> EmptyResult {
+    if !CONFIG.new_device_approval() {
+        return Ok(());
+    }
+
+    let (twofactor, mut pending) = find_pending(&user.uuid, conn).await;
+    match pending.iter().position(|p| p.device_id == device.uuid) {
+        Some(idx) if pending[idx].approved => {
+            pending.remove(idx);
+            return save_pending(twofactor, &pending, conn).await;
+        }
+        Some(_) => (),
+        None => {
+            let now = Utc::now();
+            pending.push(PendingDevice {
+                device_id: device.uuid.clone(),
+                requested_at: now.timestamp(),
+                approved: false,
+            });
+            save_pending(twofactor, &pending, conn).await?;
+
+            if let Err(e) =
+                mail::send_new_device_approval(&user.email, &ip.ip.to_string(), &now.naive_utc(), device).await
+            {
+                error!("Error sending new device approval email: {e:#?}");
+            }
+        }
+    }
+
+    err!(
+        "This device needs to be approved. Use the link sent to your email address, or log in with a device you already use.",
+        format!("Login from a new device is pending approval. IP: {}. Username: {}.", ip.ip, user.email),
+        ErrorEvent {
+            event: EventType::UserFailedLogIn
+        }
+    )
+}
+
+#[get("/devices/approve?<token>")]
+fn approve_device_page(token: &str) -> ApiResult<Html<String>> {
+    if decode_device_approval(token).is_err() {
+        err!("Invalid claim")
+    }
+    link_confirm_page(
+        "Approve new device",
+        "A device is trying to log in to your account. Only approve it when this was you.",
+        "Approve device",
+        "/api/devices/approve",
+        token,
+    )
+}
+
+#[post("/devices/approve", data = "<data>")]
+async fn approve_device(data: Form<LinkTokenData>, mut conn: DbConn) -> ApiResult<Html<String>> {
+    let Ok(claims) = decode_device_approval(&data.token) else {
+        err!("Invalid claim")
+    };
+
+    let (twofactor, mut pending) = find_pending(&claims.sub, &mut conn).await;
+    let Some(device) = pending.iter_mut().find(|p| p.device_id == claims.device_id) else {
+        err!("The login request of this device has expired. Please log in again to request a new approval.")
+    };
+    device.approved = true;
+    save_pending(twofactor, &pending, &mut conn).await?;
+
+    link_result_page("Device approved", "The device has been approved. You can now log in on it.")
+}
@@ -5,7 +5,7 @@ use serde_json::Value;
 use crate::{
     api::{
         core::{CipherSyncData, CipherSyncType},
-        EmptyResult, JsonResult,
+        send_user_webhook, EmptyResult, JsonResult, UserWebhookEvent,
     },
     auth::{decode_emergency_access_invite, Headers},
     db::{models::*, DbConn, DbPool},
@@ -148,6 +148,8 @@ async fn post_emergency_access(
         None => err!("Invalid emergency access type."),
     };
 
+    check_wait_time_days(data.wait_time_days)?;
+
     emergency_access.atype = new_type;
     emergency_access.wait_time_days = data.wait_time_days;
     if data.key_encrypted.is_some() {
@@ -209,6 +211,7 @@ async fn send_invite(data: Json<EmergencyAccessInviteData>, headers: Headers, mu
     let data: EmergencyAccessInviteData = data.into_inner();
     let email = data.email.to_lowercase();
     let wait_time_days = data.wait_time_days;
+    check_wait_time_days(wait_time_days)?;
 
     let emergency_access_status = EmergencyAccessStatus::Invited as i32;
 
@@ -226,12 +229,18 @@ async fn send_invite(data: Json<EmergencyAccessInviteData>, headers: Headers, mu
 
     let (grantee_user, new_user) = match User::find_by_mail(&email, &mut conn).await {
         None => {
+            // Do not reveal that the grantee has no account, the invitation is just not sent
+            if CONFIG.anti_enumeration() && !(CONFIG.invitations_allowed() && CONFIG.is_email_domain_allowed(&email)) {
+                warn!("Emergency access invitation of unknown user {email} is not allowed and was ignored");
+                return Ok(());
+            }
+
             if !CONFIG.invitations_allowed() {
                 err!(format!("Grantee user does not exist: {}", &email))
             }
 
             if !CONFIG.is_email_domain_allowed(&email) {
-                err!("Email domain not eligible for invitations")
+                err!(format!("The email domain of {email} is not allowed by the signup domain whitelist"))
             }
 
             if !CONFIG.mail_enabled() {
@@ -241,6 +250,7 @@ async fn send_invite(data: Json<EmergencyAccessInviteData>, headers: Headers, mu
 
             let mut user = User::new(email.clone());
             user.save(&mut conn).await?;
+            send_user_webhook(UserWebhookEvent::Invited, &user.uuid, &user.email);
             (user, true)
         }
         Some(user) if user.password_hash.is_empty() => (user, true),
@@ -664,7 +674,7 @@ async fn password_emergency_access(
     grantor_user.save(&mut conn).await?;
 
     // Disable TwoFactor providers since they will otherwise block logins
-    TwoFactor::delete_all_by_user(&grantor_user.uuid, &mut conn).await?;
+    TwoFactor::delete_two_step_login_by_user(&grantor_user.uuid, &mut conn).await?;
 
     // Remove grantor from all organisations unless Owner
     for member in Membership::find_any_state_by_user(&grantor_user.uuid, &mut conn).await {
@@ -695,7 +705,8 @@ async fn policies_emergency_access(emer_id: EmergencyAccessId, headers: Headers,
     };
 
     let policies = OrgPolicy::find_confirmed_by_user(&grantor_user.uuid, &mut conn);
-    let policies_json: Vec<Value> = policies.await.iter().map(OrgPolicy::to_json).collect();
+    let policies_json: Vec<Value> =
+        policies.await.iter().filter(|p| p.is_client_visible()).map(OrgPolicy::to_json).collect();
 
     Ok(Json(json!({
         "data": policies_json,
@@ -722,6 +733,31 @@ fn check_emergency_access_enabled() -> EmptyResult {
     Ok(())
 }
 
+// Same bounds as the upstream server, the jobs compute the approval and reminder dates from it
+fn check_wait_time_days(wait_time_days: i32) -> EmptyResult {
+    if !(1..=i32::from(i16::MAX)).contains(&wait_time_days) {
+        err!("Invalid wait time days.")
+    }
+    Ok(())
+}
+
+// A broken record is logged and skipped, so it doesn't stop the jobs from handling the other requests
+async fn find_emergency_access_users(emer: &EmergencyAccess, conn: &mut DbConn) -> Option<(User, User)> {
+    let Some(grantor_user) = User::find_by_uuid(&emer.grantor_uuid, conn).await else {
+        error!("Grantor user of emergency access {} not found", emer.uuid);
+        return None;
+    };
+    let Some(grantee_uuid) = emer.grantee_uuid.as_ref() else {
+        error!("Emergency access {} has no grantee", emer.uuid);
+        return None;
+    };
+    let Some(grantee_user) = User::find_by_uuid(grantee_uuid, conn).await else {
+        error!("Grantee user of emergency access {} not found", emer.uuid);
+        return None;
+    };
+    Some((grantor_user, grantee_user))
+}
+
 pub async fn emergency_request_timeout_job(pool: DbPool) {
     debug!("Start emergency_request_timeout_job");
     if !CONFIG.emergency_access_allowed() {
@@ -743,32 +779,35 @@ pub async fn emergency_request_timeout_job(pool: DbPool) {
             if recovery_allowed_at.le(&now) {
                 // Only update the access status
                 // Updating the whole record could cause issues when the emergency_notification_reminder_job is also active
-                emer.update_access_status_and_save(EmergencyAccessStatus::RecoveryApproved as i32, &now, &mut conn)
+                if let Err(e) = emer
+                    .update_access_status_and_save(EmergencyAccessStatus::RecoveryApproved as i32, &now, &mut conn)
                     .await
-                    .expect("Unable to update emergency access status");
+                {
+                    error!("Unable to update status of emergency access {}: {e:#?}", emer.uuid);
+                    continue;
+                }
 
                 if CONFIG.mail_enabled() {
-                    // get grantor user to send Accepted email
-                    let grantor_user =
-                        User::find_by_uuid(&emer.grantor_uuid, &mut conn).await.expect("Grantor user not found");
-
-                    // get grantee user to send Accepted email
-                    let grantee_user =
-                        User::find_by_uuid(&emer.grantee_uuid.clone().expect("Grantee user invalid"), &mut conn)
-                            .await
-                            .expect("Grantee user not found");
+                    // get grantor and grantee user to send the Accepted emails
+                    let Some((grantor_user, grantee_user)) = find_emergency_access_users(&emer, &mut conn).await else {
+                        continue;
+                    };
 
-                    mail::send_emergency_access_recovery_timed_out(
+                    if let Err(e) = mail::send_emergency_access_recovery_timed_out(
                         &grantor_user.email,
                         &grantee_user.name,
                         emer.get_type_as_str(),
                     )
                     .await
-                    .expect("Error on sending email");
-
-                    mail::send_emergency_access_recovery_approved(&grantee_user.email, &grantor_user.name)
-                        .await
-                        .expect("Error on sending email");
+                    {
+                        error!("Error sending emergency access timed out email to {}: {e:#?}", grantor_user.email);
+                    }
+
+                    if let Err(e) =
+                        mail::send_emergency_access_recovery_approved(&grantee_user.email, &grantor_user.name).await
+                    {
+                        error!("Error sending emergency access approved email to {}: {e:#?}", grantee_user.email);
+                    }
                 }
             }
         }
@@ -805,29 +844,27 @@ pub async fn emergency_notification_reminder_job(pool: DbPool) {
             if final_recovery_reminder_at.le(&now) && next_recovery_reminder_at.le(&now) {
                 // Only update the last notification date
                 // Updating the whole record could cause issues when the emergency_request_timeout_job is also active
-                emer.update_last_notification_date_and_save(&now, &mut conn)
-                    .await
-                    .expect("Unable to update emergency access notification date");
+                if let Err(e) = emer.update_last_notification_date_and_save(&now, &mut conn).await {
+                    error!("Unable to update notification date of emergency access {}: {e:#?}", emer.uuid);
+                    continue;
+                }
 
                 if CONFIG.mail_enabled() {
-                    // get grantor user to send Accepted email
-                    let grantor_user =
-                        User::find_by_uuid(&emer.grantor_uuid, &mut conn).await.expect("Grantor user not found");
-
-                    // get grantee user to send Accepted email
-                    let grantee_user =
-                        User::find_by_uuid(&emer.grantee_uuid.clone().expect("Grantee user invalid"), &mut conn)
-                            .await
-                            .expect("Grantee user not found");
+                    // get grantor and grantee user to send the reminder email
+                    let Some((grantor_user, grantee_user)) = find_emergency_access_users(&emer, &mut conn).await else {
+                        continue;
+                    };
 
-                    mail::send_emergency_access_recovery_reminder(
+                    if let Err(e) = mail::send_emergency_access_recovery_reminder(
                         &grantor_user.email,
                         &grantee_user.name,
                         emer.get_type_as_str(),
                         "1", // This notification is only triggered one day before the activation
                     )
                     .await
-                    .expect("Error on sending email");
+                    {
+                        error!("Error sending emergency access reminder email to {}: {e:#?}", grantor_user.email);
+                    }
                 }
             }
         }
@@ -1,6 +1,6 @@
 use std::net::IpAddr;
 
-use chrono::NaiveDateTime;
+use chrono::{NaiveDateTime, Utc};
 use rocket::{form::FromForm, serde::json::Json, Route};
 use serde_json::Value;
 
@@ -8,9 +8,10 @@ use crate::{
     api::{EmptyResult, JsonResult},
     auth::{AdminHeaders, Headers},
     db::{
-        models::{Cipher, CipherId, Event, Membership, MembershipId, OrganizationId, UserId},
+        models::{Cipher, CipherId, Device, Event, EventType, Membership, MembershipId, OrganizationId, User, UserId},
         DbConn, DbPool,
     },
+    mail,
     util::parse_date,
     CONFIG,
 };
@@ -136,7 +137,7 @@ async fn get_user_events(
     })))
 }
 
-fn get_continuation_token(events_json: &[Value]) -> Option<&str> {
+pub fn get_continuation_token(events_json: &[Value]) -> Option<&str> {
     // When the length of the vec equals the max page_size there probably is more data
     // When it is less, then all events are loaded.
     if events_json.len() as i64 == Event::PAGE_SIZE {
@@ -173,14 +174,27 @@ struct EventCollection {
 // https://github.com/bitwarden/server/blob/8a22c0479e987e756ce7412c48a732f9002f0a2d/src/Core/Services/Implementations/EventService.cs
 #[post("/collect", format = "application/json", data = "<data>")]
 async fn post_events_collect(data: Json<Vec<EventCollection>>, headers: Headers, mut conn: DbConn) -> EmptyResult {
+    // Personal vault exports are done by the clients, this event is the only way to know about them.
+    // They count against the same per-user limit as the organization exports, so a hijacked session can't
+    // keep exporting without the user's organization exports being refused and the client getting a 429.
+    let mut export_limit = Ok(());
+    if data.iter().any(|e| e.r#type == EventType::UserClientExportedVault as i32) {
+        notify_vault_export(&headers.user, &headers.device, &headers.ip.ip, None).await;
+        export_limit = crate::ratelimit::check_limit_export(headers.user.uuid.as_ref());
+        if export_limit.is_err() {
+            warn!("User {} exceeded the vault export limit. IP: {}", headers.user.email, headers.ip.ip);
+        }
+    }
+
     if !CONFIG.org_events_enabled() {
-        return Ok(());
+        return export_limit;
     }
 
+    // Only the events which happen on the clients are accepted, and only for the user's own organizations and ciphers
     for event in data.iter() {
         let event_date = parse_date(&event.date);
         match event.r#type {
-            1000..=1099 => {
+            t if t == EventType::UserClientExportedVault as i32 => {
                 _log_user_event(
                     event.r#type,
                     &headers.user.uuid,
@@ -191,8 +205,12 @@ async fn post_events_collect(data: Json<Vec<EventCollection>>, headers: Headers,
                 )
                 .await;
             }
-            1600..=1699 => {
+            t if t == EventType::OrganizationClientExportedVault as i32 => {
                 if let Some(org_id) = &event.organization_id {
+                    if Membership::find_confirmed_by_user_and_org(&headers.user.uuid, org_id, &mut conn).await.is_none()
+                    {
+                        continue;
+                    }
                     _log_event(
                         event.r#type,
                         org_id,
@@ -206,9 +224,12 @@ async fn post_events_collect(data: Json<Vec<EventCollection>>, headers: Headers,
                     .await;
                 }
             }
-            _ => {
+            1107..=1114 | 1117 | 1118 => {
                 if let Some(cipher_uuid) = &event.cipher_id {
                     if let Some(cipher) = Cipher::find_by_uuid(cipher_uuid, &mut conn).await {
+                        if !cipher.is_accessible_to_user(&headers.user.uuid, &mut conn).await {
+                            continue;
+                        }
                         if let Some(org_id) = cipher.organization_uuid {
                             _log_event(
                                 event.r#type,
@@ -225,9 +246,23 @@ async fn post_events_collect(data: Json<Vec<EventCollection>>, headers: Headers,
                     }
                 }
             }
+            _ => debug!("Ignoring event type {} which is not sent by clients", event.r#type),
         }
     }
-    Ok(())
+    export_limit
+}
+
+/// Notifies the user by email that their vault, or the vault of an organization, was exported using their account
+pub async fn notify_vault_export(user: &User, device: &Device, ip: &IpAddr, org_name: Option<&str>) {
+    if !CONFIG.export_notification() || !CONFIG.mail_enabled() {
+        return;
+    }
+
+    if let Err(e) =
+        mail::send_vault_exported(&user.email, &ip.to_string(), &Utc::now().naive_utc(), device, org_name).await
+    {
+        error!("Error sending vault export notification email: {e:#?}");
+    }
 }
 
 pub async fn log_user_event(event_type: i32, user_id: &UserId, device_type: i32, ip: &IpAddr, conn: &mut DbConn) {
@@ -247,6 +282,7 @@ async fn _log_user_event(
 ) {
     let memberships = Membership::find_by_user(user_id, conn).await;
     let mut events: Vec<Event> = Vec::with_capacity(memberships.len() + 1); // We need an event per org and one without an org
+    let geo_location = crate::geoip::lookup(ip).map(|g| g.to_string());
 
     // Upstream saves the event also without any org_id.
     let mut event = Event::new(event_type, event_date);
@@ -254,6 +290,7 @@ async fn _log_user_event(
     event.act_user_uuid = Some(user_id.clone());
     event.device_type = Some(device_type);
     event.ip_address = Some(ip.to_string());
+    event.geo_location = geo_location.clone();
     events.push(event);
 
     // For each org a user is a member of store these events per org
@@ -265,6 +302,7 @@ async fn _log_user_event(
         event.act_user_uuid = Some(user_id.clone());
         event.device_type = Some(device_type);
         event.ip_address = Some(ip.to_string());
+        event.geo_location = geo_location.clone();
         events.push(event);
     }
 
@@ -330,6 +368,7 @@ async fn _log_event(
     event.act_user_uuid = Some(act_user_id.clone());
     event.device_type = Some(device_type);
     event.ip_address = Some(ip.to_string());
+    event.geo_location = crate::geoip::lookup(ip).map(|g| g.to_string());
     event.save(conn).await.unwrap_or(());
 }
 
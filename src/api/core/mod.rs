@@ -1,32 +1,45 @@
 pub mod accounts;
 mod ciphers;
+mod data_export;
+mod device_approvals;
 mod emergency_access;
 mod events;
 mod folders;
+mod org_domains;
+mod org_reports;
 mod organizations;
+pub mod passkeys;
 mod public;
 mod sends;
 pub mod two_factor;
 
-pub use accounts::purge_auth_requests;
-pub use ciphers::{purge_trashed_ciphers, CipherData, CipherSyncData, CipherSyncType};
+pub use accounts::{delete_user_account, purge_auth_requests, purge_scheduled_user_deletions};
+pub use ciphers::{purge_trashed_ciphers, trim_password_histories, CipherData, CipherSyncData, CipherSyncType};
+pub use device_approvals::check_device_approval;
 pub use emergency_access::{emergency_notification_reminder_job, emergency_request_timeout_job};
-pub use events::{event_cleanup_job, log_event, log_user_event};
+pub use events::{event_cleanup_job, log_event, log_user_event, notify_vault_export};
+pub use org_domains::{is_verified_domain_signup_allowed, verify_org_domains_job};
+pub use organizations::{invite_members_bulk, org_export_job, purge_scheduled_org_deletions};
 use reqwest::Method;
 pub use sends::purge_sends;
 
 pub fn routes() -> Vec<Route> {
     let mut eq_domains_routes = routes![get_eq_domains, post_eq_domains, put_eq_domains];
     let mut hibp_routes = routes![hibp_breach];
-    let mut meta_routes = routes![alive, now, version, config, get_api_webauthn];
+    let mut meta_routes = routes![alive, now, version, config, demo_mode, error_codes, error_code];
 
     let mut routes = Vec::new();
     routes.append(&mut accounts::routes());
     routes.append(&mut ciphers::routes());
+    routes.append(&mut data_export::routes());
+    routes.append(&mut device_approvals::routes());
     routes.append(&mut emergency_access::routes());
     routes.append(&mut events::routes());
     routes.append(&mut folders::routes());
+    routes.append(&mut org_domains::routes());
+    routes.append(&mut org_reports::routes());
     routes.append(&mut organizations::routes());
+    routes.append(&mut passkeys::routes());
     routes.append(&mut two_factor::routes());
     routes.append(&mut sends::routes());
     routes.append(&mut public::routes());
@@ -50,10 +63,10 @@ pub fn events_routes() -> Vec<Route> {
 use rocket::{serde::json::Json, serde::json::Value, Catcher, Route};
 
 use crate::{
-    api::{JsonResult, Notify, UpdateType},
+    api::{EmptyResult, JsonResult, Notify, UpdateType},
     auth::Headers,
     db::DbConn,
-    error::Error,
+    error::{ApiErrorCode, Error},
     http_client::make_http_request,
     util::parse_experimental_client_feature_flags,
 };
@@ -179,23 +192,44 @@ pub fn now() -> Json<String> {
     Json(crate::util::format_date(&chrono::Utc::now().naive_utc()))
 }
 
-#[get("/version")]
-fn version() -> Json<&'static str> {
-    Json(crate::VERSION.unwrap_or_default())
+fn error_code_json(code: ApiErrorCode) -> Value {
+    json!({
+        "code": code.id(),
+        "status": code.status(),
+        "description": code.description(),
+        "object": "errorCode",
+    })
 }
 
-#[get("/webauthn")]
-fn get_api_webauthn(_headers: Headers) -> Json<Value> {
-    // Prevent a 404 error, which also causes key-rotation issues
-    // It looks like this is used when login with passkeys is enabled, which Vaultwarden does not (yet) support
-    // An empty list/data also works fine
+// Documentation of the stable codes returned in the `errorCode` field of error responses
+#[get("/error-codes")]
+fn error_codes() -> Json<Value> {
     Json(json!({
+        "data": ApiErrorCode::ALL.iter().map(|c| error_code_json(*c)).collect::<Vec<Value>>(),
         "object": "list",
-        "data": [],
-        "continuationToken": null
+        "continuationToken": null,
     }))
 }
 
+#[get("/error-codes/<code>")]
+fn error_code(code: &str) -> JsonResult {
+    match ApiErrorCode::from_id(code) {
+        Some(code) => Ok(Json(error_code_json(code))),
+        None => err_api!(ApiErrorCode::NotFound, "Unknown error code"),
+    }
+}
+
+// All write requests are rerouted to this endpoint by the `DemoMode` fairing when demo mode is enabled
+#[get("/demo-mode")]
+fn demo_mode() -> EmptyResult {
+    err_api!(ApiErrorCode::ReadOnlyInstance, "This is a read-only demo instance, changes can not be saved")
+}
+
+#[get("/version")]
+fn version() -> Json<&'static str> {
+    Json(crate::VERSION.unwrap_or_default())
+}
+
 #[get("/config")]
 fn config() -> Json<Value> {
     let domain = crate::CONFIG.domain();
@@ -246,6 +280,7 @@ fn api_not_found() -> Json<Value> {
             "code": 404,
             "reason": "Not Found",
             "description": "The requested resource could not be found."
-        }
+        },
+        "errorCode": ApiErrorCode::NotFound.id()
     }))
 }
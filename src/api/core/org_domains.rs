@@ -0,0 +1,289 @@
+//
+// Verified domains of organizations
+// An owner adds a domain and proves its ownership with a DNS TXT record, which is checked on request and
+// periodically by the `org_domain_verification_schedule` job. Users of a verified domain can join the organization.
+//
+use chrono::{TimeDelta, Utc};
+use rocket::{serde::json::Json, Route};
+use serde_json::Value;
+
+use crate::{
+    api::{EmptyResult, JsonResult},
+    auth::OwnerHeaders,
+    db::{
+        models::{
+            Membership, MembershipStatus, OrgDomain, OrgDomainId, OrgPolicy, OrgPolicyErr, Organization,
+            OrganizationId, User,
+        },
+        DbConn, DbPool,
+    },
+    http_client::lookup_txt_records,
+    mail, CONFIG,
+};
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        get_org_domains,
+        get_org_domain,
+        post_org_domain,
+        verify_org_domain,
+        delete_org_domain,
+        post_delete_org_domain,
+        get_org_domain_sso_details,
+    ]
+}
+
+#[get("/organizations/<org_id>/domain")]
+async fn get_org_domains(org_id: OrganizationId, headers: OwnerHeaders, mut conn: DbConn) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+
+    let domains: Vec<Value> = OrgDomain::find_by_org(&org_id, &mut conn).await.iter().map(OrgDomain::to_json).collect();
+
+    Ok(Json(json!({
+        "data": domains,
+        "object": "list",
+        "continuationToken": null,
+    })))
+}
+
+#[get("/organizations/<org_id>/domain/<domain_id>")]
+async fn get_org_domain(
+    org_id: OrganizationId,
+    domain_id: OrgDomainId,
+    headers: OwnerHeaders,
+    mut conn: DbConn,
+) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+
+    match OrgDomain::find_by_uuid_and_org(&domain_id, &org_id, &mut conn).await {
+        Some(domain) => Ok(Json(domain.to_json())),
+        None => err!("Domain not found"),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OrgDomainData {
+    domain_name: String,
+}
+
+#[post("/organizations/<org_id>/domain", data = "<data>")]
+async fn post_org_domain(
+    org_id: OrganizationId,
+    data: Json<OrgDomainData>,
+    headers: OwnerHeaders,
+    mut conn: DbConn,
+) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+
+    let domain_name = data.into_inner().domain_name.trim().trim_end_matches('.').to_lowercase();
+    if !is_valid_domain_name(&domain_name) {
+        err!("Invalid domain name")
+    }
+    if OrgDomain::find_by_org_and_name(&org_id, &domain_name, &mut conn).await.is_some() {
+        err!("The domain name already exists for this organization")
+    }
+    match OrgDomain::find_by_name(&domain_name, &mut conn).await {
+        Some(other) if other.verified_date.is_some() => {
+            err!("The domain name is already claimed by another organization")
+        }
+        Some(_) => err!("The domain name is already added by another organization"),
+        None => (),
+    }
+
+    let mut domain = OrgDomain::new(org_id, domain_name);
+    // Check the domain right away, the TXT record could already be there
+    check_domain(&mut domain, false, &mut conn).await?;
+
+    Ok(Json(domain.to_json()))
+}
+
+#[post("/organizations/<org_id>/domain/<domain_id>/verify")]
+async fn verify_org_domain(
+    org_id: OrganizationId,
+    domain_id: OrgDomainId,
+    headers: OwnerHeaders,
+    mut conn: DbConn,
+) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+
+    let Some(mut domain) = OrgDomain::find_by_uuid_and_org(&domain_id, &org_id, &mut conn).await else {
+        err!("Domain not found")
+    };
+    if !domain.is_verified() {
+        check_domain(&mut domain, false, &mut conn).await?;
+    }
+
+    Ok(Json(domain.to_json()))
+}
+
+#[delete("/organizations/<org_id>/domain/<domain_id>")]
+async fn delete_org_domain(
+    org_id: OrganizationId,
+    domain_id: OrgDomainId,
+    headers: OwnerHeaders,
+    mut conn: DbConn,
+) -> EmptyResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+
+    match OrgDomain::find_by_uuid_and_org(&domain_id, &org_id, &mut conn).await {
+        Some(domain) => domain.delete(&mut conn).await,
+        None => err!("Domain not found"),
+    }
+}
+
+#[post("/organizations/<org_id>/domain/<domain_id>/remove")]
+async fn post_delete_org_domain(
+    org_id: OrganizationId,
+    domain_id: OrgDomainId,
+    headers: OwnerHeaders,
+    conn: DbConn,
+) -> EmptyResult {
+    delete_org_domain(org_id, domain_id, headers, conn).await
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OrgDomainSsoDetailsData {
+    email: String,
+}
+
+// Called by the clients on the SSO login page, SSO is not supported so it is never available.
+// This only tells whether the domain of the email address was claimed by an organization.
+#[post("/organizations/domain/sso/details", data = "<data>")]
+async fn get_org_domain_sso_details(data: Json<OrgDomainSsoDetailsData>, mut conn: DbConn) -> JsonResult {
+    let Some(domain_name) = email_domain(&data.into_inner().email) else {
+        err!("Invalid email address")
+    };
+    let Some(domain) = OrgDomain::find_verified_by_name(&domain_name, &mut conn).await else {
+        err_code!("Claimed org domain not found", 404)
+    };
+
+    Ok(Json(json!({
+        "ssoAvailable": false,
+        "domainName": domain.domain_name,
+        "organizationIdentifier": "",
+        "verifiedDate": domain.verified_date.as_ref().map(crate::util::format_date),
+        "object": "organizationDomainSsoDetails",
+    })))
+}
+
+fn is_valid_domain_name(domain_name: &str) -> bool {
+    domain_name.len() <= 253
+        && domain_name.contains('.')
+        && domain_name.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+fn email_domain(email: &str) -> Option<String> {
+    match email.rsplit_once('@') {
+        Some((local, domain)) if !local.is_empty() && !domain.is_empty() => Some(domain.to_lowercase()),
+        _ => None,
+    }
+}
+
+/// Looks for the TXT record of the domain and marks the domain as verified when it is found.
+/// A domain which has been verified by another organization in the meantime can't be verified anymore.
+async fn check_domain(domain: &mut OrgDomain, from_job: bool, conn: &mut DbConn) -> EmptyResult {
+    let found = match lookup_txt_records(&domain.domain_name).await {
+        Ok(records) => records.iter().any(|record| record.trim() == domain.txt),
+        Err(e) => {
+            warn!("Failed to check the TXT records of organization domain {}: {e:?}", domain.domain_name);
+            false
+        }
+    };
+
+    let now = Utc::now().naive_utc();
+    if found && OrgDomain::find_verified_by_name(&domain.domain_name, conn).await.is_none() {
+        info!("Organization domain {} of organization {} is verified", domain.domain_name, domain.org_uuid);
+        domain.verified_date = Some(now);
+    }
+    domain.last_checked_date = Some(now);
+    if from_job {
+        domain.job_run_count += 1;
+    }
+    domain.save(conn).await
+}
+
+pub async fn verify_org_domains_job(pool: DbPool) {
+    debug!("Checking the DNS TXT records of unverified organization domains");
+    let Ok(mut conn) = pool.get().await else {
+        error!("Failed to get DB connection while checking organization domains");
+        return;
+    };
+
+    let now = Utc::now().naive_utc();
+    for mut domain in OrgDomain::find_pending_verification(&mut conn).await {
+        if domain.next_run_date().is_some_and(|next_run| next_run > now + TimeDelta::minutes(5)) {
+            continue;
+        }
+        if let Err(e) = check_domain(&mut domain, true, &mut conn).await {
+            error!("Error checking organization domain {}: {e:#?}", domain.domain_name);
+        }
+    }
+}
+
+/// Whether the email address belongs to a domain which is verified by an organization, and thus is allowed to sign up.
+pub async fn is_verified_domain_signup_allowed(email: &str, conn: &mut DbConn) -> bool {
+    if !CONFIG.org_domain_auto_join() {
+        return false;
+    }
+    match email_domain(email) {
+        Some(domain_name) => OrgDomain::find_verified_by_name(&domain_name, conn).await.is_some(),
+        None => false,
+    }
+}
+
+/// Invites a new user to the organization which verified the domain of their email address.
+/// This is only done once the user has verified their email address, which proves they own the mailbox.
+pub async fn join_verified_domain_org(user: &User, conn: &mut DbConn) -> EmptyResult {
+    if !CONFIG.org_domain_auto_join() || user.verified_at.is_none() {
+        return Ok(());
+    }
+    let Some(domain_name) = email_domain(&user.email) else {
+        return Ok(());
+    };
+    let Some(domain) = OrgDomain::find_verified_by_name(&domain_name, conn).await else {
+        return Ok(());
+    };
+    let Some(org) = Organization::find_by_uuid(&domain.org_uuid, conn).await else {
+        return Ok(());
+    };
+    if Membership::find_by_user_and_org(&user.uuid, &org.uuid, conn).await.is_some() {
+        return Ok(());
+    }
+
+    // The same checks as for an invitation by an admin, the policies are checked again when accepting it
+    org.check_seats_available(1, conn).await?;
+    // A missing second factor can still be set up before accepting the invitation
+    match OrgPolicy::is_user_allowed(&user.uuid, &org.uuid, false, conn).await {
+        Ok(()) | Err(OrgPolicyErr::TwoFactorMissing) => {}
+        Err(OrgPolicyErr::SingleOrgEnforced) => {
+            err!("The user is a member of an organization which doesn't allow being a member of other organizations")
+        }
+        Err(OrgPolicyErr::SingleOrgOtherMembership) => {
+            err!("The organization doesn't allow its members to be a member of other organizations")
+        }
+    }
+
+    // ORG_DOMAIN_AUTO_JOIN requires email, so the user always has to accept the invitation via the link
+    let mut member = Membership::new(user.uuid.clone(), org.uuid.clone());
+    member.status = MembershipStatus::Invited as i32;
+    member.save(conn).await?;
+    mail::send_invite(user, org.uuid.clone(), member.uuid.clone(), &org.name, None).await
+}
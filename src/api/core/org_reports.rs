@@ -0,0 +1,233 @@
+//
+// Member reports of organizations
+// Owners can list the members who haven't been active for a number of days and the invitations which are pending
+// longer than that, and re-invite or revoke all of them at once.
+//
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use rocket::{serde::json::Json, Route};
+use serde_json::Value;
+
+use crate::{
+    api::{
+        core::organizations::{_reinvite_member, _revoke_member},
+        EmptyResult, JsonResult, Notify,
+    },
+    auth::{AdminHeaders, OwnerHeaders},
+    db::{
+        models::{Membership, MembershipId, MembershipStatus, OrganizationId, User},
+        DbConn,
+    },
+    util::format_date,
+};
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        get_inactive_members,
+        revoke_inactive_members,
+        get_pending_invites,
+        reinvite_pending_invites,
+        revoke_pending_invites,
+    ]
+}
+
+const DEFAULT_INACTIVE_DAYS: u32 = 90;
+const DEFAULT_PENDING_DAYS: u32 = 7;
+
+fn cutoff_date(days: u32) -> NaiveDateTime {
+    Utc::now().naive_utc() - TimeDelta::days(i64::from(days))
+}
+
+/// Accepted and confirmed members who didn't use any of their devices since the cutoff, or never did.
+async fn find_inactive_members(
+    org_id: &OrganizationId,
+    days: u32,
+    conn: &mut DbConn,
+) -> Vec<(Membership, User, Option<NaiveDateTime>)> {
+    let cutoff = cutoff_date(days);
+    let mut inactive = Vec::new();
+    for member in Membership::find_by_org(org_id, conn).await {
+        if member.status < MembershipStatus::Accepted as i32 {
+            continue;
+        }
+        let Some(user) = User::find_by_uuid(&member.user_uuid, conn).await else {
+            continue;
+        };
+        let last_active = user.last_active(conn).await;
+        if last_active.is_none_or(|date| date < cutoff) {
+            inactive.push((member, user, last_active));
+        }
+    }
+    inactive
+}
+
+/// Invitations which were sent before the cutoff. Those of which the date is unknown are older than the tracking of it.
+async fn find_pending_invites(org_id: &OrganizationId, days: u32, conn: &mut DbConn) -> Vec<(Membership, User)> {
+    let cutoff = cutoff_date(days);
+    let mut pending = Vec::new();
+    for member in Membership::find_by_org(org_id, conn).await {
+        if member.status != MembershipStatus::Invited as i32 || member.invited_at.is_some_and(|date| date >= cutoff) {
+            continue;
+        }
+        if let Some(user) = User::find_by_uuid(&member.user_uuid, conn).await {
+            pending.push((member, user));
+        }
+    }
+    pending
+}
+
+fn check_org(org_id: &OrganizationId, headers: &OwnerHeaders) -> EmptyResult {
+    if org_id != &headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+    Ok(())
+}
+
+fn bulk_response(results: Vec<(MembershipId, String)>) -> Value {
+    let data: Vec<Value> = results
+        .into_iter()
+        .map(|(member_id, err_msg)| {
+            json!({
+                "object": "OrganizationBulkConfirmResponseModel",
+                "id": member_id,
+                "error": err_msg
+            })
+        })
+        .collect();
+
+    json!({
+        "data": data,
+        "object": "list",
+        "continuationToken": null
+    })
+}
+
+#[get("/organizations/<org_id>/reports/inactive-members?<days>")]
+async fn get_inactive_members(
+    org_id: OrganizationId,
+    days: Option<u32>,
+    headers: OwnerHeaders,
+    mut conn: DbConn,
+) -> JsonResult {
+    check_org(&org_id, &headers)?;
+
+    let members: Vec<Value> = find_inactive_members(&org_id, days.unwrap_or(DEFAULT_INACTIVE_DAYS), &mut conn)
+        .await
+        .into_iter()
+        .map(|(member, user, last_active)| {
+            json!({
+                "id": member.uuid,
+                "userId": member.user_uuid,
+                "name": user.name,
+                "email": user.email,
+                "type": member.atype,
+                "status": member.status,
+                "lastActive": last_active.as_ref().map(format_date),
+                "object": "inactiveMember",
+            })
+        })
+        .collect();
+
+    Ok(Json(json!({
+        "data": members,
+        "object": "list",
+        "continuationToken": null,
+    })))
+}
+
+#[post("/organizations/<org_id>/reports/inactive-members/revoke?<days>")]
+async fn revoke_inactive_members(
+    org_id: OrganizationId,
+    days: Option<u32>,
+    headers: OwnerHeaders,
+    mut conn: DbConn,
+    nt: Notify<'_>,
+) -> JsonResult {
+    check_org(&org_id, &headers)?;
+    let headers: AdminHeaders = headers.into();
+
+    let mut results = Vec::new();
+    for (member, _, _) in find_inactive_members(&org_id, days.unwrap_or(DEFAULT_INACTIVE_DAYS), &mut conn).await {
+        let err_msg = match _revoke_member(&org_id, &member.uuid, &headers, &mut conn, &nt).await {
+            Ok(_) => String::new(),
+            Err(e) => format!("{e:?}"),
+        };
+        results.push((member.uuid, err_msg));
+    }
+
+    Ok(Json(bulk_response(results)))
+}
+
+#[get("/organizations/<org_id>/reports/pending-invites?<days>")]
+async fn get_pending_invites(
+    org_id: OrganizationId,
+    days: Option<u32>,
+    headers: OwnerHeaders,
+    mut conn: DbConn,
+) -> JsonResult {
+    check_org(&org_id, &headers)?;
+
+    let invites: Vec<Value> = find_pending_invites(&org_id, days.unwrap_or(DEFAULT_PENDING_DAYS), &mut conn)
+        .await
+        .into_iter()
+        .map(|(member, user)| {
+            json!({
+                "id": member.uuid,
+                "userId": member.user_uuid,
+                "email": user.email,
+                "type": member.atype,
+                "invitedDate": member.invited_at.as_ref().map(format_date),
+                "object": "pendingInvite",
+            })
+        })
+        .collect();
+
+    Ok(Json(json!({
+        "data": invites,
+        "object": "list",
+        "continuationToken": null,
+    })))
+}
+
+#[post("/organizations/<org_id>/reports/pending-invites/reinvite?<days>")]
+async fn reinvite_pending_invites(
+    org_id: OrganizationId,
+    days: Option<u32>,
+    headers: OwnerHeaders,
+    mut conn: DbConn,
+) -> JsonResult {
+    check_org(&org_id, &headers)?;
+
+    let mut results = Vec::new();
+    for (member, _) in find_pending_invites(&org_id, days.unwrap_or(DEFAULT_PENDING_DAYS), &mut conn).await {
+        let err_msg = match _reinvite_member(&org_id, &member.uuid, &headers.user.email, &mut conn).await {
+            Ok(_) => String::new(),
+            Err(e) => format!("{e:?}"),
+        };
+        results.push((member.uuid, err_msg));
+    }
+
+    Ok(Json(bulk_response(results)))
+}
+
+#[post("/organizations/<org_id>/reports/pending-invites/revoke?<days>")]
+async fn revoke_pending_invites(
+    org_id: OrganizationId,
+    days: Option<u32>,
+    headers: OwnerHeaders,
+    mut conn: DbConn,
+    nt: Notify<'_>,
+) -> JsonResult {
+    check_org(&org_id, &headers)?;
+    let headers: AdminHeaders = headers.into();
+
+    let mut results = Vec::new();
+    for (member, _) in find_pending_invites(&org_id, days.unwrap_or(DEFAULT_PENDING_DAYS), &mut conn).await {
+        let err_msg = match _revoke_member(&org_id, &member.uuid, &headers, &mut conn, &nt).await {
+            Ok(_) => String::new(),
+            Err(e) => format!("{e:?}"),
+        };
+        results.push((member.uuid, err_msg));
+    }
+
+    Ok(Json(bulk_response(results)))
+}
@@ -1,4 +1,7 @@
+use chrono::{TimeDelta, Utc};
 use num_traits::FromPrimitive;
+use rocket::form::Form;
+use rocket::response::content::RawHtml as Html;
 use rocket::serde::json::Json;
 use rocket::Route;
 use serde_json::Value;
@@ -7,14 +10,19 @@ use std::collections::{HashMap, HashSet};
 use crate::api::admin::FAKE_ADMIN_UUID;
 use crate::{
     api::{
-        core::{log_event, two_factor, CipherSyncData, CipherSyncType},
-        EmptyResult, JsonResult, Notify, PasswordOrOtpData, UpdateType,
+        core::{
+            log_event, log_user_event, notify_vault_export,
+            public::{import_directory, OrgImportData},
+            two_factor, CipherSyncData, CipherSyncType,
+        },
+        link_confirm_page, link_result_page, send_user_webhook, ApiResult, EmptyResult, JsonResult, LinkTokenData,
+        Notify, PasswordOrOtpData, UpdateType, UserWebhookEvent,
     },
     auth::{
-        decode_invite, AdminHeaders, ClientVersion, Headers, ManagerHeaders, ManagerHeadersLoose, OrgMemberHeaders,
-        OwnerHeaders,
+        decode_invite, decode_org_delete, decode_org_delete_cancel, AdminHeaders, ClientVersion, Headers,
+        ManagerHeaders, ManagerHeadersLoose, OrgHeaders, OrgMemberHeaders, OwnerHeaders,
     },
-    db::{models::*, DbConn},
+    db::{models::*, DbConn, DbPool},
     mail,
     util::{convert_json_key_lcase_first, NumberOrString},
     CONFIG,
@@ -26,6 +34,10 @@ pub fn routes() -> Vec<Route> {
         create_organization,
         delete_organization,
         post_delete_organization,
+        post_delete_organization_token,
+        cancel_delete_organization,
+        cancel_delete_organization_page,
+        cancel_delete_organization_token,
         leave_organization,
         get_user_collections,
         get_org_collections,
@@ -33,8 +45,12 @@ pub fn routes() -> Vec<Route> {
         get_org_collection_detail,
         get_collection_users,
         put_collection_users,
+        put_member_collections,
         put_organization,
         post_organization,
+        put_organization_collection_management,
+        get_organization_auto_export,
+        put_organization_auto_export,
         post_organization_collections,
         delete_organization_collection_member,
         post_organization_collection_delete_member,
@@ -48,6 +64,7 @@ pub fn routes() -> Vec<Route> {
         get_org_details,
         get_members,
         send_invite,
+        send_bulk_invite,
         reinvite_member,
         bulk_reinvite_members,
         confirm_invite,
@@ -128,6 +145,20 @@ struct OrganizationUpdateData {
     name: String,
 }
 
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct OrganizationCollectionManagementData {
+    limit_collection_creation: bool,
+    limit_collection_deletion: bool,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct OrganizationAutoExportData {
+    enabled: bool,
+    mail_owners: bool,
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct FullCollectionData {
@@ -224,9 +255,27 @@ async fn delete_organization(
 
     data.validate(&headers.user, true, &mut conn).await?;
 
-    match Organization::find_by_uuid(&org_id, &mut conn).await {
-        None => err!("Organization not found"),
-        Some(org) => org.delete(&mut conn).await,
+    let Some(mut org) = Organization::find_by_uuid(&org_id, &mut conn).await else {
+        err!("Organization not found")
+    };
+    if org.delete_scheduled_at.is_some() {
+        err!("The deletion of this organization is already scheduled")
+    }
+
+    if CONFIG.mail_enabled() {
+        // The deletion only proceeds after one of the owners confirmed it via the emailed link,
+        // a new request replaces the previous one so only the links of the latest mails can be used
+        let requested_at = Utc::now().naive_utc();
+        org.delete_requested_at = Some(requested_at);
+        org.save(&mut conn).await?;
+
+        for email in org_owner_emails(&org_id, &mut conn).await {
+            mail::send_delete_organization(&email, &org.uuid, &org.name, &headers.user.email, &requested_at).await?;
+        }
+        Ok(())
+    } else {
+        // Without mail the request can't be confirmed, the cooling-off delay still applies
+        schedule_org_deletion(org, &mut conn).await
     }
 }
 
@@ -240,6 +289,258 @@ async fn post_delete_organization(
     delete_organization(org_id, data, headers, conn).await
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OrgDeleteRecoverTokenData {
+    token: String,
+}
+
+#[post("/organizations/<org_id>/delete-recover-token", data = "<data>")]
+async fn post_delete_organization_token(
+    org_id: OrganizationId,
+    data: Json<OrgDeleteRecoverTokenData>,
+    mut conn: DbConn,
+) -> EmptyResult {
+    let data: OrgDeleteRecoverTokenData = data.into_inner();
+
+    let Ok(claims) = decode_org_delete(&data.token) else {
+        err!("Invalid claim")
+    };
+    if claims.sub != org_id {
+        err!("Invalid claim")
+    }
+
+    let Some(org) = Organization::find_by_uuid(&org_id, &mut conn).await else {
+        err!("Organization not found")
+    };
+
+    // Another owner could already have confirmed the deletion
+    if org.delete_scheduled_at.is_some() {
+        return Ok(());
+    }
+    // The request is cleared once confirmed, so the token can't schedule the deletion again after it was cancelled
+    let requested_at = org.delete_requested_at.map(|r| r.and_utc().timestamp());
+    if requested_at != Some(claims.requested_at) {
+        err!("Invalid claim", "The deletion request was already confirmed or replaced by a newer one")
+    }
+    schedule_org_deletion(org, &mut conn).await
+}
+
+#[post("/organizations/<org_id>/delete/cancel")]
+async fn cancel_delete_organization(org_id: OrganizationId, headers: OwnerHeaders, mut conn: DbConn) -> EmptyResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+    cancel_org_deletion(&org_id, &headers.user.email, &mut conn).await
+}
+
+#[get("/organizations/<org_id>/delete/cancel?<token>")]
+fn cancel_delete_organization_page(org_id: OrganizationId, token: &str) -> ApiResult<Html<String>> {
+    if !decode_org_delete_cancel(token).is_ok_and(|claims| claims.sub == *org_id) {
+        err!("Invalid claim")
+    }
+    link_confirm_page(
+        "Cancel organization deletion",
+        "The organization is scheduled for deletion. Cancel it to keep the organization and its data.",
+        "Cancel deletion",
+        &format!("/api/organizations/{org_id}/delete/cancel-token"),
+        token,
+    )
+}
+
+// A separate path, the owners cancel a deletion from the web vault with a post to `/delete/cancel`
+#[post("/organizations/<org_id>/delete/cancel-token", data = "<data>")]
+async fn cancel_delete_organization_token(
+    org_id: OrganizationId,
+    data: Form<LinkTokenData>,
+    mut conn: DbConn,
+) -> ApiResult<Html<String>> {
+    let Ok(claims) = decode_org_delete_cancel(&data.token) else {
+        err!("Invalid claim")
+    };
+    if claims.sub != *org_id {
+        err!("Invalid claim")
+    }
+
+    cancel_org_deletion(&org_id, "one of the owners", &mut conn).await?;
+    link_result_page("Deletion cancelled", "The deletion of the organization has been cancelled.")
+}
+
+async fn org_owner_emails(org_id: &OrganizationId, conn: &mut DbConn) -> Vec<String> {
+    let mut emails = Vec::new();
+    for member in Membership::find_by_org_and_type(org_id, MembershipType::Owner, conn).await {
+        if member.status != MembershipStatus::Confirmed as i32 {
+            continue;
+        }
+        if let Some(user) = User::find_by_uuid(&member.user_uuid, conn).await {
+            emails.push(user.email);
+        }
+    }
+    emails
+}
+
+/// The members which are not an owner, and thus don't receive the mails about the deletion which are meant for owners
+async fn org_non_owner_emails(org_id: &OrganizationId, conn: &mut DbConn) -> Vec<String> {
+    let mut emails = Vec::new();
+    for member in Membership::find_by_org(org_id, conn).await {
+        if member.atype == MembershipType::Owner || member.status < MembershipStatus::Accepted as i32 {
+            continue;
+        }
+        if let Some(user) = User::find_by_uuid(&member.user_uuid, conn).await {
+            emails.push(user.email);
+        }
+    }
+    emails
+}
+
+/// Starts the cooling-off period after which the organization is deleted by the `org_deletion_schedule` job.
+async fn schedule_org_deletion(mut org: Organization, conn: &mut DbConn) -> EmptyResult {
+    let delay_hours = CONFIG.org_delete_delay_hours();
+    if delay_hours == 0 {
+        return org.delete(conn).await;
+    }
+
+    let delete_at = Utc::now().naive_utc() + TimeDelta::try_hours(i64::from(delay_hours)).unwrap();
+    org.delete_scheduled_at = Some(delete_at);
+    org.delete_requested_at = None;
+    org.save(conn).await?;
+
+    if CONFIG.mail_enabled() {
+        for email in org_owner_emails(&org.uuid, conn).await {
+            if let Err(e) = mail::send_delete_organization_scheduled(&email, &org.uuid, &org.name, &delete_at).await {
+                error!("Error sending organization deletion scheduled email: {e:#?}");
+            }
+        }
+        // Give the other members the chance to keep a copy of the shared items
+        for email in org_non_owner_emails(&org.uuid, conn).await {
+            if let Err(e) = mail::send_delete_organization_member_notice(&email, &org.name, &delete_at).await {
+                error!("Error sending organization deletion member notice email: {e:#?}");
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn cancel_org_deletion(org_id: &OrganizationId, cancelled_by: &str, conn: &mut DbConn) -> EmptyResult {
+    let Some(mut org) = Organization::find_by_uuid(org_id, conn).await else {
+        err!("Organization not found")
+    };
+    if org.delete_scheduled_at.is_none() {
+        err!("The deletion of this organization is not scheduled")
+    }
+
+    org.delete_scheduled_at = None;
+    org.save(conn).await?;
+
+    if CONFIG.mail_enabled() {
+        let mut emails = org_owner_emails(org_id, conn).await;
+        emails.extend(org_non_owner_emails(org_id, conn).await);
+        for email in emails {
+            if let Err(e) = mail::send_delete_organization_cancelled(&email, &org.name, cancelled_by).await {
+                error!("Error sending organization deletion cancelled email: {e:#?}");
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn purge_scheduled_org_deletions(pool: DbPool) {
+    debug!("Deleting organizations scheduled for deletion");
+    let Ok(mut conn) = pool.get().await else {
+        error!("Failed to get DB connection while deleting scheduled organizations");
+        return;
+    };
+
+    let now = Utc::now().naive_utc();
+    for org in Organization::find_scheduled_for_deletion_before(&now, &mut conn).await {
+        info!("Deleting organization {} ({}) after its deletion delay has passed", org.name, org.uuid);
+        if let Err(e) = org.delete(&mut conn).await {
+            error!("Error deleting scheduled organization: {e:#?}");
+        }
+    }
+}
+
+pub async fn org_export_job(pool: DbPool) {
+    debug!("Exporting the organizations with automatic exports enabled");
+    let Ok(mut conn) = pool.get().await else {
+        error!("Failed to get DB connection while exporting organizations");
+        return;
+    };
+
+    for org in Organization::find_auto_export(&mut conn).await {
+        if let Err(e) = export_organization_to_folder(&org, &mut conn).await {
+            error!("Error exporting organization {} ({}): {e:#?}", org.name, org.uuid);
+        }
+    }
+}
+
+/// Writes the collections and ciphers of the organization to a new file in its export folder and removes
+/// the oldest exports above the retention, and mails it to the confirmed owners when enabled. The data stays encrypted
+/// with the organization key, it's only readable for its members, the same as an export which is requested by an admin.
+async fn export_organization_to_folder(org: &Organization, conn: &mut DbConn) -> EmptyResult {
+    // The permissions in the cipher data are those of an owner, which can see all ciphers
+    let Some(owner) = Membership::find_by_org_and_type(&org.uuid, MembershipType::Owner, conn)
+        .await
+        .into_iter()
+        .find(|m| m.status == MembershipStatus::Confirmed as i32)
+    else {
+        err!("The organization has no confirmed owner")
+    };
+
+    let now = Utc::now();
+    let export = json!({
+        "encrypted": true,
+        "organizationId": org.uuid,
+        "exportDate": crate::util::format_date(&now.naive_utc()),
+        "collections": convert_json_key_lcase_first(_get_org_collections(&org.uuid, conn).await),
+        "ciphers": convert_json_key_lcase_first(_get_org_details(&org.uuid, &CONFIG.domain(), &owner.user_uuid, conn).await),
+    });
+
+    let export = serde_json::to_vec(&export)?;
+
+    let io_permit = crate::util::acquire_io_permit(crate::util::FileStore::Backups).await;
+    let folder = std::path::Path::new(&CONFIG.org_export_folder()).join(org.uuid.as_ref());
+    tokio::fs::create_dir_all(&folder).await?;
+    // With microseconds, a manual run of the job directly after a scheduled one doesn't overwrite the export
+    let file_name = format!("export_{}.json", now.format("%Y%m%d_%H%M%S_%6f"));
+    tokio::fs::write(folder.join(&file_name), &export).await?;
+    info!("Exported organization {} ({}) to {file_name}", org.name, org.uuid);
+
+    // The timestamp in the name makes the alphabetical order the chronological order
+    let mut exports = Vec::new();
+    let mut entries = tokio::fs::read_dir(&folder).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with("export_") && name.ends_with(".json") {
+            exports.push(name);
+        }
+    }
+    exports.sort_unstable();
+    let retention = CONFIG.org_export_retention() as usize;
+    if exports.len() > retention {
+        for name in &exports[..exports.len() - retention] {
+            tokio::fs::remove_file(folder.join(name)).await?;
+        }
+    }
+    drop(io_permit);
+
+    if org.auto_export_mail && CONFIG.mail_enabled() {
+        for member in Membership::find_by_org_and_type(&org.uuid, MembershipType::Owner, conn).await {
+            if member.status != MembershipStatus::Confirmed as i32 {
+                continue;
+            }
+            let Some(user) = User::find_by_uuid(&member.user_uuid, conn).await else {
+                continue;
+            };
+            if let Err(e) = mail::send_org_export(&user.email, &org.name, &file_name, export.clone()).await {
+                error!("Error mailing the export of organization {} to {}: {e:#?}", org.uuid, user.email);
+            }
+        }
+    }
+
+    Ok(())
+}
+
 #[post("/organizations/<org_id>/leave")]
 async fn leave_organization(org_id: OrganizationId, headers: Headers, mut conn: DbConn) -> EmptyResult {
     match Membership::find_by_user_and_org(&headers.user.uuid, &org_id, &mut conn).await {
@@ -324,6 +625,97 @@ async fn post_organization(
     Ok(Json(org.to_json()))
 }
 
+#[put("/organizations/<org_id>/collection-management", data = "<data>")]
+async fn put_organization_collection_management(
+    org_id: OrganizationId,
+    headers: OwnerHeaders,
+    data: Json<OrganizationCollectionManagementData>,
+    mut conn: DbConn,
+) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+    let data: OrganizationCollectionManagementData = data.into_inner();
+
+    let Some(mut org) = Organization::find_by_uuid(&org_id, &mut conn).await else {
+        err!("Organization not found")
+    };
+
+    org.limit_collection_creation = data.limit_collection_creation;
+    org.limit_collection_deletion = data.limit_collection_deletion;
+    org.save(&mut conn).await?;
+
+    log_event(
+        EventType::OrganizationUpdated as i32,
+        org_id.as_ref(),
+        &org_id,
+        &headers.user.uuid,
+        headers.device.atype,
+        &headers.ip.ip,
+        &mut conn,
+    )
+    .await;
+
+    Ok(Json(org.to_json()))
+}
+
+#[get("/organizations/<org_id>/auto-export")]
+async fn get_organization_auto_export(org_id: OrganizationId, headers: OwnerHeaders, mut conn: DbConn) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+    let Some(org) = Organization::find_by_uuid(&org_id, &mut conn).await else {
+        err!("Organization not found")
+    };
+
+    Ok(Json(json!({
+        "enabled": org.auto_export,
+        "mailOwners": org.auto_export_mail,
+        "object": "organizationAutoExport",
+    })))
+}
+
+#[put("/organizations/<org_id>/auto-export", data = "<data>")]
+async fn put_organization_auto_export(
+    org_id: OrganizationId,
+    headers: OwnerHeaders,
+    data: Json<OrganizationAutoExportData>,
+    mut conn: DbConn,
+) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+    let data: OrganizationAutoExportData = data.into_inner();
+    if data.mail_owners && !CONFIG.mail_enabled() {
+        err!("Mailing the exports requires SMTP to be configured")
+    }
+
+    let Some(mut org) = Organization::find_by_uuid(&org_id, &mut conn).await else {
+        err!("Organization not found")
+    };
+
+    org.auto_export = data.enabled;
+    org.auto_export_mail = data.mail_owners;
+    org.save(&mut conn).await?;
+
+    log_event(
+        EventType::OrganizationUpdated as i32,
+        org_id.as_ref(),
+        &org_id,
+        &headers.user.uuid,
+        headers.device.atype,
+        &headers.ip.ip,
+        &mut conn,
+    )
+    .await;
+
+    Ok(Json(json!({
+        "enabled": org.auto_export,
+        "mailOwners": org.auto_export_mail,
+        "object": "organizationAutoExport",
+    })))
+}
+
 // GET /api/collections?writeOnly=false
 #[get("/collections")]
 async fn get_user_collections(headers: Headers, mut conn: DbConn) -> Json<Value> {
@@ -429,7 +821,7 @@ async fn _get_org_collections(org_id: &OrganizationId, conn: &mut DbConn) -> Val
 #[post("/organizations/<org_id>/collections", data = "<data>")]
 async fn post_organization_collections(
     org_id: OrganizationId,
-    headers: ManagerHeadersLoose,
+    headers: OrgHeaders,
     data: Json<FullCollectionData>,
     mut conn: DbConn,
 ) -> JsonResult {
@@ -441,6 +833,31 @@ async fn post_organization_collections(
     let Some(org) = Organization::find_by_uuid(&org_id, &mut conn).await else {
         err!("Can't find organization details")
     };
+    if headers.membership_status != MembershipStatus::Confirmed {
+        err!("You need to be a confirmed member of the organization to create collections")
+    }
+    let is_manager = headers.membership_type >= MembershipType::Manager;
+    if !is_manager && org.limit_collection_creation {
+        err!("Only managers, admins and owners can create collections in this organization")
+    }
+    org.check_collections_available(1, &mut conn).await?;
+
+    // Validate all the ids before the collection is created
+    for group in &data.groups {
+        if Group::find_by_uuid_and_org(&group.id, &org_id, &mut conn).await.is_none() {
+            err!("Group is not part of organization")
+        }
+    }
+    let mut members = Vec::with_capacity(data.users.len());
+    for user in &data.users {
+        let Some(member) = Membership::find_by_uuid_and_org(&user.id, &org_id, &mut conn).await else {
+            err!("User is not part of organization")
+        };
+        // Members with access to all collections don't need explicit access
+        if !member.access_all {
+            members.push((member, user));
+        }
+    }
 
     let collection = Collection::new(org.uuid, data.name, data.external_id);
     collection.save(&mut conn).await?;
@@ -456,21 +873,19 @@ async fn post_organization_collections(
     )
     .await;
 
-    for group in data.groups {
-        CollectionGroup::new(collection.uuid.clone(), group.id, group.read_only, group.hide_passwords, group.manage)
-            .save(&mut conn)
-            .await?;
+    for group in &data.groups {
+        CollectionGroup::new(
+            collection.uuid.clone(),
+            group.id.clone(),
+            group.read_only,
+            group.hide_passwords,
+            group.manage,
+        )
+        .save(&mut conn)
+        .await?;
     }
 
-    for user in data.users {
-        let Some(member) = Membership::find_by_uuid_and_org(&user.id, &org_id, &mut conn).await else {
-            err!("User is not part of organization")
-        };
-
-        if member.access_all {
-            continue;
-        }
-
+    for (member, user) in &members {
         CollectionUser::save(
             &member.user_uuid,
             &collection.uuid,
@@ -484,6 +899,9 @@ async fn post_organization_collections(
 
     if headers.membership.atype == MembershipType::Manager && !headers.membership.access_all {
         CollectionUser::save(&headers.membership.user_uuid, &collection.uuid, false, false, false, &mut conn).await?;
+    } else if !is_manager {
+        // A member which created a collection is allowed to manage it
+        CollectionUser::save(&headers.membership.user_uuid, &collection.uuid, false, false, true, &mut conn).await?;
     }
 
     Ok(Json(collection.to_json_details(&headers.membership.user_uuid, None, &mut conn).await))
@@ -513,55 +931,81 @@ async fn post_bulk_access_collections(
         err!("Can't find organization details")
     };
 
-    for col_id in data.collection_ids {
-        let Some(collection) = Collection::find_by_uuid_and_org(&col_id, &org_id, &mut conn).await else {
+    // A manager can only change the access of the collections it has access to
+    let headers = ManagerHeaders::from_loose(headers, &data.collection_ids, &mut conn).await?;
+
+    // Validate the whole request before changing anything, so an invalid id doesn't leave some collections updated
+    let mut collections = Vec::with_capacity(data.collection_ids.len());
+    for col_id in &data.collection_ids {
+        let Some(collection) = Collection::find_by_uuid_and_org(col_id, &org_id, &mut conn).await else {
             err!("Collection not found")
         };
+        collections.push(collection);
+    }
+    for group in &data.groups {
+        if Group::find_by_uuid_and_org(&group.id, &org_id, &mut conn).await.is_none() {
+            err!("Group is not part of organization")
+        }
+    }
+    let mut members = Vec::with_capacity(data.users.len());
+    for user in &data.users {
+        let Some(member) = Membership::find_by_uuid_and_org(&user.id, &org_id, &mut conn).await else {
+            err!("User is not part of organization")
+        };
+        // Members with access to all collections don't need explicit access
+        if !member.access_all {
+            members.push((member, user));
+        }
+    }
 
-        // update collection modification date
-        collection.save(&mut conn).await?;
+    // All collections are updated in one transaction, a failure leaves all of them unchanged
+    conn.begin_transaction().await?;
+    let result: EmptyResult = async {
+        for collection in collections {
+            // update collection modification date
+            collection.save(&mut conn).await?;
 
-        log_event(
-            EventType::CollectionUpdated as i32,
-            &collection.uuid,
-            &org_id,
-            &headers.user.uuid,
-            headers.device.atype,
-            &headers.ip.ip,
-            &mut conn,
-        )
-        .await;
+            log_event(
+                EventType::CollectionUpdated as i32,
+                &collection.uuid,
+                &org_id,
+                &headers.user.uuid,
+                headers.device.atype,
+                &headers.ip.ip,
+                &mut conn,
+            )
+            .await;
 
-        CollectionGroup::delete_all_by_collection(&col_id, &mut conn).await?;
-        for group in &data.groups {
-            CollectionGroup::new(col_id.clone(), group.id.clone(), group.read_only, group.hide_passwords, group.manage)
+            CollectionGroup::delete_all_by_collection(&collection.uuid, &mut conn).await?;
+            for group in &data.groups {
+                CollectionGroup::new(
+                    collection.uuid.clone(),
+                    group.id.clone(),
+                    group.read_only,
+                    group.hide_passwords,
+                    group.manage,
+                )
                 .save(&mut conn)
                 .await?;
-        }
-
-        CollectionUser::delete_all_by_collection(&col_id, &mut conn).await?;
-        for user in &data.users {
-            let Some(member) = Membership::find_by_uuid_and_org(&user.id, &org_id, &mut conn).await else {
-                err!("User is not part of organization")
-            };
-
-            if member.access_all {
-                continue;
             }
 
-            CollectionUser::save(
-                &member.user_uuid,
-                &col_id,
-                user.read_only,
-                user.hide_passwords,
-                user.manage,
-                &mut conn,
-            )
-            .await?;
+            CollectionUser::delete_all_by_collection(&collection.uuid, &mut conn).await?;
+            for (member, user) in &members {
+                CollectionUser::save(
+                    &member.user_uuid,
+                    &collection.uuid,
+                    user.read_only,
+                    user.hide_passwords,
+                    user.manage,
+                    &mut conn,
+                )
+                .await?;
+            }
         }
+        Ok(())
     }
-
-    Ok(())
+    .await;
+    conn.end_transaction(result).await
 }
 
 #[put("/organizations/<org_id>/collections/<col_id>", data = "<data>")]
@@ -596,11 +1040,25 @@ async fn post_organization_collection_update(
         err!("Collection not found")
     };
 
+    // Validate all the ids before anything is changed
+    for group in &data.groups {
+        if Group::find_by_uuid_and_org(&group.id, &org_id, &mut conn).await.is_none() {
+            err!("Group is not part of organization")
+        }
+    }
+    let mut members = Vec::with_capacity(data.users.len());
+    for user in &data.users {
+        let Some(member) = Membership::find_by_uuid_and_org(&user.id, &org_id, &mut conn).await else {
+            err!("User is not part of organization")
+        };
+        // Members with access to all collections don't need explicit access
+        if !member.access_all {
+            members.push((member, user));
+        }
+    }
+
     collection.name = data.name;
-    collection.external_id = match data.external_id {
-        Some(external_id) if !external_id.trim().is_empty() => Some(external_id),
-        _ => None,
-    };
+    collection.set_external_id(data.external_id);
 
     collection.save(&mut conn).await?;
 
@@ -617,23 +1075,15 @@ async fn post_organization_collection_update(
 
     CollectionGroup::delete_all_by_collection(&col_id, &mut conn).await?;
 
-    for group in data.groups {
-        CollectionGroup::new(col_id.clone(), group.id, group.read_only, group.hide_passwords, group.manage)
+    for group in &data.groups {
+        CollectionGroup::new(col_id.clone(), group.id.clone(), group.read_only, group.hide_passwords, group.manage)
             .save(&mut conn)
             .await?;
     }
 
     CollectionUser::delete_all_by_collection(&col_id, &mut conn).await?;
 
-    for user in data.users {
-        let Some(member) = Membership::find_by_uuid_and_org(&user.id, &org_id, &mut conn).await else {
-            err!("User is not part of organization")
-        };
-
-        if member.access_all {
-            continue;
-        }
-
+    for (member, user) in &members {
         CollectionUser::save(&member.user_uuid, &col_id, user.read_only, user.hide_passwords, user.manage, &mut conn)
             .await?;
     }
@@ -687,6 +1137,16 @@ async fn _delete_organization_collection(
     let Some(collection) = Collection::find_by_uuid_and_org(col_id, org_id, conn).await else {
         err!("Collection not found", "Collection does not exist or does not belong to this organization")
     };
+    // Members who only have the manage permission on the collection can't delete it when the organization limits this
+    if let Some(org) = Organization::find_by_uuid(org_id, conn).await {
+        if org.limit_collection_deletion
+            && !Membership::find_confirmed_by_user_and_org(&headers.user.uuid, org_id, conn)
+                .await
+                .is_some_and(|m| m.atype >= MembershipType::Manager)
+        {
+            err!("Only managers, admins and owners can delete collections of this organization")
+        }
+    }
     log_event(
         EventType::CollectionDeleted as i32,
         &collection.uuid,
@@ -869,21 +1329,85 @@ async fn put_collection_users(
         err!("Collection not found in Organization")
     }
 
-    // Delete all the user-collections
-    CollectionUser::delete_all_by_collection(&col_id, &mut conn).await?;
-
-    // And then add all the received ones (except if the user has access_all)
+    // Check all the received users before changing anything (users with access_all don't need explicit access)
+    let mut members = Vec::with_capacity(data.len());
     for d in data.iter() {
         let Some(user) = Membership::find_by_uuid_and_org(&d.id, &org_id, &mut conn).await else {
             err!("User is not part of organization")
         };
 
-        if user.access_all {
-            continue;
+        if !user.access_all {
+            members.push((user, d));
+        }
+    }
+
+    conn.begin_transaction().await?;
+    let result: EmptyResult = async {
+        // Delete all the user-collections
+        CollectionUser::delete_all_by_collection(&col_id, &mut conn).await?;
+
+        // And then add all the received ones
+        for (user, d) in members {
+            CollectionUser::save(&user.user_uuid, &col_id, d.read_only, d.hide_passwords, d.manage, &mut conn).await?;
+        }
+        Ok(())
+    }
+    .await;
+    conn.end_transaction(result).await
+}
+
+/// Gives a member access to many collections at once, the access to other collections is kept.
+#[put("/organizations/<org_id>/users/<member_id>/collections", data = "<data>")]
+async fn put_member_collections(
+    org_id: OrganizationId,
+    member_id: MembershipId,
+    data: Json<Vec<CollectionData>>,
+    headers: ManagerHeadersLoose,
+    mut conn: DbConn,
+) -> EmptyResult {
+    if org_id != headers.membership.org_uuid {
+        err!("Organization not found", "Organization id's do not match");
+    }
+    let data: Vec<CollectionData> = data.into_inner();
+
+    // A manager can only give access to the collections it has access to
+    let collection_ids: Vec<CollectionId> = data.iter().map(|c| c.id.clone()).collect();
+    let headers = ManagerHeaders::from_loose(headers, &collection_ids, &mut conn).await?;
+
+    let Some(member) = Membership::find_by_uuid_and_org(&member_id, &org_id, &mut conn).await else {
+        err!("User is not part of organization")
+    };
+    for col_id in &collection_ids {
+        if Collection::find_by_uuid_and_org(col_id, &org_id, &mut conn).await.is_none() {
+            err!("Collection not found in Organization")
         }
+    }
+    // Members with access to all collections don't need explicit access
+    if member.access_all {
+        return Ok(());
+    }
 
-        CollectionUser::save(&user.user_uuid, &col_id, d.read_only, d.hide_passwords, d.manage, &mut conn).await?;
+    conn.begin_transaction().await?;
+    let result: EmptyResult = async {
+        for col in &data {
+            CollectionUser::save(&member.user_uuid, &col.id, col.read_only, col.hide_passwords, col.manage, &mut conn)
+                .await?;
+        }
+        Ok(())
     }
+    .await;
+    conn.end_transaction(result).await?;
+
+    log_event(
+        EventType::OrganizationUserUpdated as i32,
+        &member.uuid,
+        &org_id,
+        &headers.user.uuid,
+        headers.device.atype,
+        &headers.ip.ip,
+        &mut conn,
+    )
+    .await;
 
     Ok(())
 }
@@ -1038,112 +1562,370 @@ async fn send_invite(
         data.access_all = true;
     }
 
-    let mut user_created: bool = false;
-    for email in data.emails.iter() {
+    let Some(org) = Organization::find_by_uuid(&org_id, &mut conn).await else {
+        err!("Organization not found")
+    };
+    org.check_seats_available(data.emails.len() as i64, &mut conn).await?;
+
+    for email in data.emails.iter() {
+        let mut user_created: bool = false;
+        let mut member_status = MembershipStatus::Invited as i32;
+        let user = match User::find_by_mail(email, &mut conn).await {
+            None => {
+                // Do not reveal that the user has no account, the invitation is just not sent
+                if CONFIG.anti_enumeration() && !(CONFIG.invitations_allowed() && CONFIG.is_email_domain_allowed(email))
+                {
+                    warn!("Organization invitation of unknown user {email} is not allowed and was ignored");
+                    continue;
+                }
+
+                if !CONFIG.invitations_allowed() {
+                    err!(format!("User does not exist: {email}"))
+                }
+
+                if !CONFIG.is_email_domain_allowed(email) {
+                    err!(format!("The email domain of {email} is not allowed by the signup domain whitelist"))
+                }
+
+                if !CONFIG.mail_enabled() {
+                    Invitation::new(email).save(&mut conn).await?;
+                }
+
+                let mut new_user = User::new(email.clone());
+                new_user.save(&mut conn).await?;
+                user_created = true;
+                new_user
+            }
+            Some(user) => {
+                if Membership::find_by_user_and_org(&user.uuid, &org_id, &mut conn).await.is_some() {
+                    err!(format!("User already in organization: {email}"))
+                } else {
+                    // automatically accept existing users if mail is disabled
+                    if !CONFIG.mail_enabled() && !user.password_hash.is_empty() {
+                        member_status = MembershipStatus::Accepted as i32;
+                    }
+                    user
+                }
+            }
+        };
+
+        let mut new_member = Membership::new(user.uuid.clone(), org_id.clone());
+        let access_all = data.access_all;
+        new_member.access_all = access_all;
+        new_member.atype = new_type;
+        new_member.status = member_status;
+        new_member.save(&mut conn).await?;
+
+        if CONFIG.mail_enabled() {
+            if let Err(e) = mail::send_invite(
+                &user,
+                org_id.clone(),
+                new_member.uuid.clone(),
+                &org.name,
+                Some(headers.user.email.clone()),
+            )
+            .await
+            {
+                // Upon error delete the user, invite and org member records when needed
+                if user_created {
+                    user.delete(&mut conn).await?;
+                } else {
+                    new_member.delete(&mut conn).await?;
+                }
+
+                err!(format!("Error sending invite: {e:?} "));
+            }
+        }
+        if user_created {
+            send_user_webhook(UserWebhookEvent::Invited, &user.uuid, &user.email);
+        }
+
+        log_event(
+            EventType::OrganizationUserInvited as i32,
+            &new_member.uuid,
+            &org_id,
+            &headers.user.uuid,
+            headers.device.atype,
+            &headers.ip.ip,
+            &mut conn,
+        )
+        .await;
+
+        // If no accessAll, add the collections received
+        if !access_all {
+            for col in data.collections.iter().flatten() {
+                match Collection::find_by_uuid_and_org(&col.id, &org_id, &mut conn).await {
+                    None => err!("Collection not found in Organization"),
+                    Some(collection) => {
+                        CollectionUser::save(
+                            &user.uuid,
+                            &collection.uuid,
+                            col.read_only,
+                            col.hide_passwords,
+                            col.manage,
+                            &mut conn,
+                        )
+                        .await?;
+                    }
+                }
+            }
+        }
+
+        for group_id in data.groups.iter() {
+            let mut group_entry = GroupUser::new(group_id.clone(), new_member.uuid.clone());
+            group_entry.save(&mut conn).await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BulkInviteData {
+    #[serde(default)]
+    emails: Vec<String>,
+    // Content of an uploaded CSV file, the email address is taken from the first column of each line
+    csv: Option<String>,
+    r#type: NumberOrString,
+    #[serde(default)]
+    access_all: bool,
+}
+
+#[post("/organizations/<org_id>/users/invite/bulk", data = "<data>")]
+async fn send_bulk_invite(
+    org_id: OrganizationId,
+    data: Json<BulkInviteData>,
+    headers: AdminHeaders,
+    mut conn: DbConn,
+) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+    let data: BulkInviteData = data.into_inner();
+
+    // Custom roles can't be configured for a whole list, they are invited as managers like in send_invite
+    let new_type = match MembershipType::from_str(&data.r#type.into_string()) {
+        Some(new_type) => new_type,
+        None => err!("Invalid type"),
+    };
+    if new_type != MembershipType::User && headers.membership_type != MembershipType::Owner {
+        err!("Only Owners can invite Managers, Admins or Owners")
+    }
+
+    let Some(org) = Organization::find_by_uuid(&org_id, &mut conn).await else {
+        err!("Organization not found")
+    };
+
+    let mut emails = data.emails;
+    if let Some(csv) = &data.csv {
+        emails.extend(parse_invite_csv(csv));
+    }
+
+    let results = invite_members_bulk(
+        &org,
+        emails,
+        new_type as i32,
+        data.access_all,
+        Some(headers.user.email.clone()),
+        &mut conn,
+    )
+    .await;
+
+    for member_id in results.iter().filter_map(|r| r.member_id.as_ref()) {
+        log_event(
+            EventType::OrganizationUserInvited as i32,
+            member_id,
+            &org_id,
+            &headers.user.uuid,
+            headers.device.atype,
+            &headers.ip.ip,
+            &mut conn,
+        )
+        .await;
+    }
+
+    Ok(Json(json!({
+        "data": results,
+        "object": "list",
+        "continuationToken": null,
+    })))
+}
+
+/// Takes the email addresses out of CSV content, one per line from the first column.
+/// A first line without an email address is treated as the header.
+fn parse_invite_csv(csv: &str) -> Vec<String> {
+    let mut emails = Vec::new();
+    for (i, line) in csv.lines().enumerate() {
+        let field = line.split([',', ';', '\t']).next().unwrap_or_default().trim().trim_matches('"').trim();
+        if field.is_empty() || (i == 0 && !field.contains('@')) {
+            continue;
+        }
+        emails.push(field.to_string());
+    }
+    emails
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkInviteResult {
+    pub email: String,
+    pub status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    // Only set when a membership was created
+    #[serde(skip)]
+    pub member_id: Option<MembershipId>,
+}
+
+impl BulkInviteResult {
+    fn new(email: &str, status: &'static str, message: Option<String>) -> Self {
+        Self {
+            email: email.to_string(),
+            status,
+            message,
+            member_id: None,
+        }
+    }
+}
+
+/// Invites a list of email addresses to the organization and reports the result of every address.
+/// All memberships are created first, the invitation mails are then sent together through the bulk mail class.
+/// An address which fails is reported and skipped, it doesn't stop the others.
+pub async fn invite_members_bulk(
+    org: &Organization,
+    emails: Vec<String>,
+    new_type: i32,
+    access_all: bool,
+    invited_by_email: Option<String>,
+    conn: &mut DbConn,
+) -> Vec<BulkInviteResult> {
+    let mut results: Vec<BulkInviteResult> = Vec::with_capacity(emails.len());
+    // The users and memberships which still need an invitation mail, and whether the user was created for it
+    let mut pending: Vec<(usize, User, Membership, bool)> = Vec::new();
+    // The users created for the invitation, these are reported to the user webhook once invited
+    let mut created_users: Vec<(usize, UserId)> = Vec::new();
+    let mut seen = HashSet::new();
+
+    for email in emails {
+        let email = email.trim().to_lowercase();
+        if !crate::util::is_valid_email(&email) {
+            results.push(BulkInviteResult::new(&email, "invalid", Some(String::from("Not a valid email address"))));
+            continue;
+        }
+        if !seen.insert(email.clone()) {
+            results.push(BulkInviteResult::new(&email, "duplicate", None));
+            continue;
+        }
+
         let mut member_status = MembershipStatus::Invited as i32;
-        let user = match User::find_by_mail(email, &mut conn).await {
+        let mut user_created = false;
+        let user = match User::find_by_mail(&email, conn).await {
             None => {
+                // Do not reveal that the user has no account, the invitation is just not sent
+                if CONFIG.anti_enumeration()
+                    && !(CONFIG.invitations_allowed() && CONFIG.is_email_domain_allowed(&email))
+                {
+                    warn!("Organization invitation of unknown user {email} is not allowed and was ignored");
+                    results.push(BulkInviteResult::new(&email, "invited", None));
+                    continue;
+                }
                 if !CONFIG.invitations_allowed() {
-                    err!(format!("User does not exist: {email}"))
+                    results.push(BulkInviteResult::new(&email, "invalid", Some(String::from("User does not exist"))));
+                    continue;
                 }
-
-                if !CONFIG.is_email_domain_allowed(email) {
-                    err!("Email domain not eligible for invitations")
+                if !CONFIG.is_email_domain_allowed(&email) {
+                    results.push(BulkInviteResult::new(
+                        &email,
+                        "invalid",
+                        Some(String::from("The email domain is not allowed by the signup domain whitelist")),
+                    ));
+                    continue;
+                }
+                if let Err(e) = org.check_seats_available(1, conn).await {
+                    results.push(BulkInviteResult::new(&email, "error", Some(e.to_string())));
+                    continue;
                 }
 
                 if !CONFIG.mail_enabled() {
-                    Invitation::new(email).save(&mut conn).await?;
+                    if let Err(e) = Invitation::new(&email).save(conn).await {
+                        results.push(BulkInviteResult::new(&email, "error", Some(e.to_string())));
+                        continue;
+                    }
+                }
+                let new_user = User::new(email.clone());
+                if let Err(e) = new_user.save(conn).await {
+                    results.push(BulkInviteResult::new(&email, "error", Some(e.to_string())));
+                    continue;
                 }
-
-                let mut new_user = User::new(email.clone());
-                new_user.save(&mut conn).await?;
                 user_created = true;
                 new_user
             }
             Some(user) => {
-                if Membership::find_by_user_and_org(&user.uuid, &org_id, &mut conn).await.is_some() {
-                    err!(format!("User already in organization: {email}"))
-                } else {
-                    // automatically accept existing users if mail is disabled
-                    if !CONFIG.mail_enabled() && !user.password_hash.is_empty() {
-                        member_status = MembershipStatus::Accepted as i32;
-                    }
-                    user
+                if Membership::find_by_user_and_org(&user.uuid, &org.uuid, conn).await.is_some() {
+                    results.push(BulkInviteResult::new(&email, "alreadyMember", None));
+                    continue;
                 }
+                if let Err(e) = org.check_seats_available(1, conn).await {
+                    results.push(BulkInviteResult::new(&email, "error", Some(e.to_string())));
+                    continue;
+                }
+                // automatically accept existing users if mail is disabled
+                if !CONFIG.mail_enabled() && !user.password_hash.is_empty() {
+                    member_status = MembershipStatus::Accepted as i32;
+                }
+                user
             }
         };
 
-        let mut new_member = Membership::new(user.uuid.clone(), org_id.clone());
-        let access_all = data.access_all;
+        let mut new_member = Membership::new(user.uuid.clone(), org.uuid.clone());
         new_member.access_all = access_all;
         new_member.atype = new_type;
         new_member.status = member_status;
-        new_member.save(&mut conn).await?;
+        if let Err(e) = new_member.save(conn).await {
+            results.push(BulkInviteResult::new(&email, "error", Some(e.to_string())));
+            continue;
+        }
 
+        let mut result = BulkInviteResult::new(&email, "invited", None);
+        result.member_id = Some(new_member.uuid.clone());
+        if user_created {
+            created_users.push((results.len(), user.uuid.clone()));
+        }
         if CONFIG.mail_enabled() {
-            let org_name = match Organization::find_by_uuid(&org_id, &mut conn).await {
-                Some(org) => org.name,
-                None => err!("Error looking up organization"),
-            };
-
-            if let Err(e) = mail::send_invite(
-                &user,
-                org_id.clone(),
-                new_member.uuid.clone(),
-                &org_name,
-                Some(headers.user.email.clone()),
-            )
-            .await
-            {
-                // Upon error delete the user, invite and org member records when needed
-                if user_created {
-                    user.delete(&mut conn).await?;
-                } else {
-                    new_member.delete(&mut conn).await?;
-                }
-
-                err!(format!("Error sending invite: {e:?} "));
-            }
+            pending.push((results.len(), user, new_member, user_created));
         }
+        results.push(result);
+    }
 
-        log_event(
-            EventType::OrganizationUserInvited as i32,
-            &new_member.uuid,
-            &org_id,
-            &headers.user.uuid,
-            headers.device.atype,
-            &headers.ip.ip,
-            &mut conn,
-        )
-        .await;
+    let mails = pending.iter().map(|(_, user, member, _)| {
+        mail::send_invite(user, org.uuid.clone(), member.uuid.clone(), &org.name, invited_by_email.clone())
+    });
+    let mail_results = futures::future::join_all(mails).await;
 
-        // If no accessAll, add the collections received
-        if !access_all {
-            for col in data.collections.iter().flatten() {
-                match Collection::find_by_uuid_and_org(&col.id, &org_id, &mut conn).await {
-                    None => err!("Collection not found in Organization"),
-                    Some(collection) => {
-                        CollectionUser::save(
-                            &user.uuid,
-                            &collection.uuid,
-                            col.read_only,
-                            col.hide_passwords,
-                            col.manage,
-                            &mut conn,
-                        )
-                        .await?;
-                    }
-                }
-            }
+    for ((idx, user, member, user_created), mail_result) in pending.into_iter().zip(mail_results) {
+        let Err(e) = mail_result else {
+            continue;
+        };
+        results[idx] = BulkInviteResult::new(&user.email, "error", Some(format!("Error sending invite: {e}")));
+        // Upon error delete the user, invite and org member records when needed
+        let cleanup = if user_created {
+            user.delete(conn).await
+        } else {
+            member.delete(conn).await
+        };
+        if let Err(cleanup_err) = cleanup {
+            error!("Error removing the membership of {} after a failed invite: {cleanup_err:#?}", results[idx].email);
         }
+    }
 
-        for group_id in data.groups.iter() {
-            let mut group_entry = GroupUser::new(group_id.clone(), new_member.uuid.clone());
-            group_entry.save(&mut conn).await?;
+    for (idx, user_id) in created_users {
+        if results[idx].status == "invited" {
+            send_user_webhook(UserWebhookEvent::Invited, &user_id, &results[idx].email);
         }
     }
 
-    Ok(())
+    results
 }
 
 #[post("/organizations/<org_id>/users/reinvite", data = "<data>")]
@@ -1191,7 +1973,7 @@ async fn reinvite_member(
     _reinvite_member(&org_id, &member_id, &headers.user.email, &mut conn).await
 }
 
-async fn _reinvite_member(
+pub async fn _reinvite_member(
     org_id: &OrganizationId,
     member_id: &MembershipId,
     invited_by_email: &str,
@@ -1218,14 +2000,19 @@ async fn _reinvite_member(
         None => err!("Error looking up organization."),
     };
 
+    let mut member = member;
     if CONFIG.mail_enabled() {
-        mail::send_invite(&user, org_id.clone(), member.uuid, &org_name, Some(invited_by_email.to_string())).await?;
+        mail::send_invite(&user, org_id.clone(), member.uuid.clone(), &org_name, Some(invited_by_email.to_string()))
+            .await?;
+        member.invited_at = Some(Utc::now().naive_utc());
+        member.save(conn).await?;
     } else if user.password_hash.is_empty() {
         let invitation = Invitation::new(&user.email);
         invitation.save(conn).await?;
+        member.invited_at = Some(Utc::now().naive_utc());
+        member.save(conn).await?;
     } else {
         Invitation::take(&user.email, conn).await;
-        let mut member = member;
         member.status = MembershipStatus::Accepted as i32;
         member.save(conn).await?;
     }
@@ -1262,6 +2049,10 @@ async fn accept_invite(
         err!("Error accepting the invitation", "Claim does not match the member_id")
     }
 
+    if CONFIG.org_require_verified_email() && headers.user.verified_at.is_none() {
+        err!("You need to verify your email address before you can join an organization")
+    }
+
     let member = &claims.member_id;
     let org = &claims.org_id;
 
@@ -1297,6 +2088,9 @@ async fn accept_invite(
                 Err(OrgPolicyErr::SingleOrgEnforced) => {
                     err!("You cannot join this organization because you are a member of an organization which forbids it");
                 }
+                Err(OrgPolicyErr::SingleOrgOtherMembership) => {
+                    err!("You cannot join this organization because it forbids its members from being a member of any other organization. Leave your other organizations first");
+                }
             }
         }
 
@@ -1421,6 +2215,13 @@ async fn _confirm_invite(
         err!("User in invalid state")
     }
 
+    if CONFIG.org_require_verified_email() {
+        match User::find_by_uuid(&member_to_confirm.user_uuid, conn).await {
+            Some(user) if user.verified_at.is_some() => (),
+            _ => err!("You cannot confirm this user because they have not verified their email address"),
+        }
+    }
+
     // This check is also done at accept_invite, _confirm_invite, _activate_member, edit_member, admin::update_membership_type
     // It returns different error messages per function.
     if member_to_confirm.atype < MembershipType::Admin {
@@ -1436,6 +2237,9 @@ async fn _confirm_invite(
             Err(OrgPolicyErr::SingleOrgEnforced) => {
                 err!("You cannot confirm this user because they are a member of an organization which forbids it");
             }
+            Err(OrgPolicyErr::SingleOrgOtherMembership) => {
+                err!("You cannot confirm this user because this organization forbids its members from being a member of any other organization");
+            }
         }
     }
 
@@ -1614,6 +2418,9 @@ async fn edit_member(
             Err(OrgPolicyErr::SingleOrgEnforced) => {
                 err!("You cannot modify this user to this type because they are a member of an organization which forbids it");
             }
+            Err(OrgPolicyErr::SingleOrgOtherMembership) => {
+                err!("You cannot modify this user to this type because this organization forbids its members from being a member of any other organization");
+            }
         }
     }
 
@@ -1831,7 +2638,6 @@ async fn post_org_import(
     data: Json<ImportData>,
     headers: AdminHeaders,
     mut conn: DbConn,
-    nt: Notify<'_>,
 ) -> EmptyResult {
     let data: ImportData = data.into_inner();
     let org_id = query.organization_id;
@@ -1844,6 +2650,12 @@ async fn post_org_import(
 
     let existing_collections: HashSet<Option<CollectionId>> =
         Collection::find_by_organization(&org_id, &mut conn).await.into_iter().map(|c| Some(c.uuid)).collect();
+    let Some(org) = Organization::find_by_uuid(&org_id, &mut conn).await else {
+        err!("Organization not found")
+    };
+    let new_collections = data.collections.iter().filter(|c| !existing_collections.contains(&c.id)).count();
+    org.check_collections_available(new_collections as i64, &mut conn).await?;
+
     let mut collections: Vec<CollectionId> = Vec::with_capacity(data.collections.len());
     for col in data.collections {
         let collection_uuid = if existing_collections.contains(&col.id) {
@@ -1871,7 +2683,7 @@ async fn post_org_import(
         // Always clear folder_id's via an organization import
         cipher_data.folder_id = None;
         let mut cipher = Cipher::new(cipher_data.r#type, cipher_data.name.clone());
-        update_cipher_from_data(&mut cipher, cipher_data, &headers, None, &mut conn, &nt, UpdateType::None).await.ok();
+        update_cipher_from_data(&mut cipher, cipher_data, &headers, None, &mut conn, None, UpdateType::None).await.ok();
         ciphers.push(cipher.uuid);
     }
 
@@ -1952,7 +2764,7 @@ async fn list_policies(org_id: OrganizationId, headers: AdminHeaders, mut conn:
         err!("Organization not found", "Organization id's do not match");
     }
     let policies = OrgPolicy::find_by_org(&org_id, &mut conn).await;
-    let policies_json: Vec<Value> = policies.iter().map(OrgPolicy::to_json).collect();
+    let policies_json: Vec<Value> = policies.iter().filter(|p| p.is_client_visible()).map(OrgPolicy::to_json).collect();
 
     Ok(Json(json!({
         "data": policies_json,
@@ -1976,7 +2788,7 @@ async fn list_policies_token(org_id: OrganizationId, token: &str, mut conn: DbCo
 
     // TODO: We receive the invite token as ?token=<>, validate it contains the org id
     let policies = OrgPolicy::find_by_org(&org_id, &mut conn).await;
-    let policies_json: Vec<Value> = policies.iter().map(OrgPolicy::to_json).collect();
+    let policies_json: Vec<Value> = policies.iter().filter(|p| p.is_client_visible()).map(OrgPolicy::to_json).collect();
 
     Ok(Json(json!({
         "data": policies_json,
@@ -2027,15 +2839,43 @@ async fn put_policy(
         err!("Invalid or unsupported policy type")
     };
 
+    let policy = save_org_policy(
+        &org_id,
+        pol_type_enum,
+        data.enabled,
+        data.data,
+        &headers.user.uuid,
+        headers.device.atype,
+        &headers.ip.ip,
+        &mut conn,
+    )
+    .await?;
+
+    Ok(Json(policy.to_json()))
+}
+
+/// Enables or disables a policy of the organization and applies the side effects of enabling it,
+/// used by the web-vault and by the public API.
+#[allow(clippy::too_many_arguments)]
+pub async fn save_org_policy(
+    org_id: &OrganizationId,
+    pol_type_enum: OrgPolicyType,
+    enabled: bool,
+    data: Option<Value>,
+    act_user_id: &UserId,
+    device_type: i32,
+    ip: &std::net::IpAddr,
+    conn: &mut DbConn,
+) -> ApiResult<OrgPolicy> {
     // Bitwarden only allows the Reset Password policy when Single Org policy is enabled
     // Vaultwarden encouraged to use multiple orgs instead of groups because groups were not available in the past
     // Now that groups are available we can enforce this option when wanted.
     // We put this behind a config option to prevent breaking current installation.
     // Maybe we want to enable this by default in the future, but currently it is disabled by default.
     if CONFIG.enforce_single_org_with_reset_pw_policy() {
-        if pol_type_enum == OrgPolicyType::ResetPassword && data.enabled {
+        if pol_type_enum == OrgPolicyType::ResetPassword && enabled {
             let single_org_policy_enabled =
-                match OrgPolicy::find_by_org_and_type(&org_id, OrgPolicyType::SingleOrg, &mut conn).await {
+                match OrgPolicy::find_by_org_and_type(org_id, OrgPolicyType::SingleOrg, conn).await {
                     Some(p) => p.enabled,
                     None => false,
                 };
@@ -2046,9 +2886,9 @@ async fn put_policy(
         }
 
         // Also prevent the Single Org Policy to be disabled if the Reset Password policy is enabled
-        if pol_type_enum == OrgPolicyType::SingleOrg && !data.enabled {
+        if pol_type_enum == OrgPolicyType::SingleOrg && !enabled {
             let reset_pw_policy_enabled =
-                match OrgPolicy::find_by_org_and_type(&org_id, OrgPolicyType::ResetPassword, &mut conn).await {
+                match OrgPolicy::find_by_org_and_type(org_id, OrgPolicyType::ResetPassword, conn).await {
                     Some(p) => p.enabled,
                     None => false,
                 };
@@ -2060,31 +2900,24 @@ async fn put_policy(
     }
 
     // When enabling the TwoFactorAuthentication policy, revoke all members that do not have 2FA
-    if pol_type_enum == OrgPolicyType::TwoFactorAuthentication && data.enabled {
-        two_factor::enforce_2fa_policy_for_org(
-            &org_id,
-            &headers.user.uuid,
-            headers.device.atype,
-            &headers.ip.ip,
-            &mut conn,
-        )
-        .await?;
+    if pol_type_enum == OrgPolicyType::TwoFactorAuthentication && enabled {
+        two_factor::enforce_2fa_policy_for_org(org_id, act_user_id, device_type, ip, conn).await?;
     }
 
     // When enabling the SingleOrg policy, remove this org's members that are members of other orgs
-    if pol_type_enum == OrgPolicyType::SingleOrg && data.enabled {
-        for member in Membership::find_by_org(&org_id, &mut conn).await.into_iter() {
+    if pol_type_enum == OrgPolicyType::SingleOrg && enabled {
+        for member in Membership::find_by_org(org_id, conn).await.into_iter() {
             // Policy only applies to non-Owner/non-Admin members who have accepted joining the org
             // Exclude invited and revoked users when checking for this policy.
             // Those users will not be allowed to accept or be activated because of the policy checks done there.
             // We check if the count is larger then 1, because it includes this organization also.
             if member.atype < MembershipType::Admin
                 && member.status != MembershipStatus::Invited as i32
-                && Membership::count_accepted_and_confirmed_by_user(&member.user_uuid, &mut conn).await > 1
+                && Membership::count_accepted_and_confirmed_by_user(&member.user_uuid, conn).await > 1
             {
                 if CONFIG.mail_enabled() {
-                    let org = Organization::find_by_uuid(&member.org_uuid, &mut conn).await.unwrap();
-                    let user = User::find_by_uuid(&member.user_uuid, &mut conn).await.unwrap();
+                    let org = Organization::find_by_uuid(&member.org_uuid, conn).await.unwrap();
+                    let user = User::find_by_uuid(&member.user_uuid, conn).await.unwrap();
 
                     mail::send_single_org_removed_from_org(&user.email, &org.name).await?;
                 }
@@ -2092,40 +2925,31 @@ async fn put_policy(
                 log_event(
                     EventType::OrganizationUserRemoved as i32,
                     &member.uuid,
-                    &org_id,
-                    &headers.user.uuid,
-                    headers.device.atype,
-                    &headers.ip.ip,
-                    &mut conn,
+                    org_id,
+                    act_user_id,
+                    device_type,
+                    ip,
+                    conn,
                 )
                 .await;
 
-                member.delete(&mut conn).await?;
+                member.delete(conn).await?;
             }
         }
     }
 
-    let mut policy = match OrgPolicy::find_by_org_and_type(&org_id, pol_type_enum, &mut conn).await {
+    let mut policy = match OrgPolicy::find_by_org_and_type(org_id, pol_type_enum, conn).await {
         Some(p) => p,
         None => OrgPolicy::new(org_id.clone(), pol_type_enum, "{}".to_string()),
     };
 
-    policy.enabled = data.enabled;
-    policy.data = serde_json::to_string(&data.data)?;
-    policy.save(&mut conn).await?;
+    policy.enabled = enabled;
+    policy.data = serde_json::to_string(&data)?;
+    policy.save(conn).await?;
 
-    log_event(
-        EventType::PolicyUpdated as i32,
-        policy.uuid.as_ref(),
-        &org_id,
-        &headers.user.uuid,
-        headers.device.atype,
-        &headers.ip.ip,
-        &mut conn,
-    )
-    .await;
+    log_event(EventType::PolicyUpdated as i32, policy.uuid.as_ref(), org_id, act_user_id, device_type, ip, conn).await;
 
-    Ok(Json(policy.to_json()))
+    Ok(policy)
 }
 
 #[allow(unused_variables)]
@@ -2191,143 +3015,20 @@ fn _empty_data_json() -> Value {
     })
 }
 
-#[derive(Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-struct OrgImportGroupData {
-    #[allow(dead_code)]
-    name: String, // "GroupName"
-    #[allow(dead_code)]
-    external_id: String, // "cn=GroupName,ou=Groups,dc=example,dc=com"
-    #[allow(dead_code)]
-    users: Vec<String>, // ["uid=user,ou=People,dc=example,dc=com"]
-}
-
-#[derive(Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-struct OrgImportUserData {
-    email: String, // "user@maildomain.net"
-    #[allow(dead_code)]
-    external_id: String, // "uid=user,ou=People,dc=example,dc=com"
-    deleted: bool,
-}
-
-#[derive(Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-struct OrgImportData {
-    #[allow(dead_code)]
-    groups: Vec<OrgImportGroupData>,
-    overwrite_existing: bool,
-    users: Vec<OrgImportUserData>,
-}
-
-/// This function seems to be deprected
-/// It is only used with older directory connectors
-/// TODO: Cleanup Tech debt
+// Used by the Directory Connector when it is logged in with the account of an admin instead of the organization API key
 #[post("/organizations/<org_id>/import", data = "<data>")]
-async fn import(org_id: OrganizationId, data: Json<OrgImportData>, headers: Headers, mut conn: DbConn) -> EmptyResult {
-    let data = data.into_inner();
-
-    // TODO: Currently we aren't storing the externalId's anywhere, so we also don't have a way
-    // to differentiate between auto-imported users and manually added ones.
-    // This means that this endpoint can end up removing users that were added manually by an admin,
-    // as opposed to upstream which only removes auto-imported users.
-
-    // User needs to be admin or owner to use the Directory Connector
-    match Membership::find_by_user_and_org(&headers.user.uuid, &org_id, &mut conn).await {
-        Some(member) if member.atype >= MembershipType::Admin => { /* Okay, nothing to do */ }
-        Some(_) => err!("User has insufficient permissions to use Directory Connector"),
-        None => err!("User not part of organization"),
-    };
-
-    for user_data in &data.users {
-        if user_data.deleted {
-            // If user is marked for deletion and it exists, delete it
-            if let Some(member) = Membership::find_by_email_and_org(&user_data.email, &org_id, &mut conn).await {
-                log_event(
-                    EventType::OrganizationUserRemoved as i32,
-                    &member.uuid,
-                    &org_id,
-                    &headers.user.uuid,
-                    headers.device.atype,
-                    &headers.ip.ip,
-                    &mut conn,
-                )
-                .await;
-
-                member.delete(&mut conn).await?;
-            }
-
-        // If user is not part of the organization, but it exists
-        } else if Membership::find_by_email_and_org(&user_data.email, &org_id, &mut conn).await.is_none() {
-            if let Some(user) = User::find_by_mail(&user_data.email, &mut conn).await {
-                let member_status = if CONFIG.mail_enabled() {
-                    MembershipStatus::Invited as i32
-                } else {
-                    MembershipStatus::Accepted as i32 // Automatically mark user as accepted if no email invites
-                };
-
-                let mut new_member = Membership::new(user.uuid.clone(), org_id.clone());
-                new_member.access_all = false;
-                new_member.atype = MembershipType::User as i32;
-                new_member.status = member_status;
-
-                if CONFIG.mail_enabled() {
-                    let org_name = match Organization::find_by_uuid(&org_id, &mut conn).await {
-                        Some(org) => org.name,
-                        None => err!("Error looking up organization"),
-                    };
-
-                    mail::send_invite(
-                        &user,
-                        org_id.clone(),
-                        new_member.uuid.clone(),
-                        &org_name,
-                        Some(headers.user.email.clone()),
-                    )
-                    .await?;
-                }
-
-                // Save the member after sending an email
-                // If sending fails the member will not be saved to the database, and will not result in the admin needing to reinvite the users manually
-                new_member.save(&mut conn).await?;
-
-                log_event(
-                    EventType::OrganizationUserInvited as i32,
-                    &new_member.uuid,
-                    &org_id,
-                    &headers.user.uuid,
-                    headers.device.atype,
-                    &headers.ip.ip,
-                    &mut conn,
-                )
-                .await;
-            }
-        }
-    }
-
-    // If this flag is enabled, any user that isn't provided in the Users list will be removed (by default they will be kept unless they have Deleted == true)
-    if data.overwrite_existing {
-        for member in Membership::find_by_org_and_type(&org_id, MembershipType::User, &mut conn).await {
-            if let Some(user_email) = User::find_by_uuid(&member.user_uuid, &mut conn).await.map(|u| u.email) {
-                if !data.users.iter().any(|u| u.email == user_email) {
-                    log_event(
-                        EventType::OrganizationUserRemoved as i32,
-                        &member.uuid,
-                        &org_id,
-                        &headers.user.uuid,
-                        headers.device.atype,
-                        &headers.ip.ip,
-                        &mut conn,
-                    )
-                    .await;
-
-                    member.delete(&mut conn).await?;
-                }
-            }
-        }
+async fn import(
+    org_id: OrganizationId,
+    data: Json<OrgImportData>,
+    headers: AdminHeaders,
+    mut conn: DbConn,
+) -> EmptyResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
     }
 
-    Ok(())
+    let headers: Headers = headers.into();
+    import_directory(&org_id, data.into_inner(), Some(&headers), &mut conn).await
 }
 
 // Pre web-vault v2022.9.x endpoint
@@ -2337,8 +3038,9 @@ async fn deactivate_member(
     member_id: MembershipId,
     headers: AdminHeaders,
     mut conn: DbConn,
+    nt: Notify<'_>,
 ) -> EmptyResult {
-    _revoke_member(&org_id, &member_id, &headers, &mut conn).await
+    _revoke_member(&org_id, &member_id, &headers, &mut conn, &nt).await
 }
 
 #[derive(Deserialize, Debug)]
@@ -2354,8 +3056,9 @@ async fn bulk_deactivate_members(
     data: Json<BulkRevokeMembershipIds>,
     headers: AdminHeaders,
     conn: DbConn,
+    nt: Notify<'_>,
 ) -> JsonResult {
-    bulk_revoke_members(org_id, data, headers, conn).await
+    bulk_revoke_members(org_id, data, headers, conn, nt).await
 }
 
 #[put("/organizations/<org_id>/users/<member_id>/revoke")]
@@ -2364,8 +3067,9 @@ async fn revoke_member(
     member_id: MembershipId,
     headers: AdminHeaders,
     mut conn: DbConn,
+    nt: Notify<'_>,
 ) -> EmptyResult {
-    _revoke_member(&org_id, &member_id, &headers, &mut conn).await
+    _revoke_member(&org_id, &member_id, &headers, &mut conn, &nt).await
 }
 
 #[put("/organizations/<org_id>/users/revoke", data = "<data>")]
@@ -2374,6 +3078,7 @@ async fn bulk_revoke_members(
     data: Json<BulkRevokeMembershipIds>,
     headers: AdminHeaders,
     mut conn: DbConn,
+    nt: Notify<'_>,
 ) -> JsonResult {
     if org_id != headers.org_id {
         err!("Organization not found", "Organization id's do not match");
@@ -2384,7 +3089,7 @@ async fn bulk_revoke_members(
     match data.ids {
         Some(members) => {
             for member_id in members {
-                let err_msg = match _revoke_member(&org_id, &member_id, &headers, &mut conn).await {
+                let err_msg = match _revoke_member(&org_id, &member_id, &headers, &mut conn, &nt).await {
                     Ok(_) => String::new(),
                     Err(e) => format!("{e:?}"),
                 };
@@ -2408,11 +3113,12 @@ async fn bulk_revoke_members(
     })))
 }
 
-async fn _revoke_member(
+pub async fn _revoke_member(
     org_id: &OrganizationId,
     member_id: &MembershipId,
     headers: &AdminHeaders,
     conn: &mut DbConn,
+    nt: &Notify<'_>,
 ) -> EmptyResult {
     match Membership::find_by_uuid_and_org(member_id, org_id, conn).await {
         Some(mut member) if member.status > MembershipStatus::Revoked as i32 => {
@@ -2441,6 +3147,10 @@ async fn _revoke_member(
                 conn,
             )
             .await;
+
+            if let Some(user) = User::find_by_uuid(&member.user_uuid, conn).await {
+                nt.send_user_update(UpdateType::SyncOrgKeys, &user).await;
+            }
         }
         Some(_) => err!("User is already revoked"),
         None => err!("User not found in organization"),
@@ -2455,8 +3165,9 @@ async fn activate_member(
     member_id: MembershipId,
     headers: AdminHeaders,
     mut conn: DbConn,
+    nt: Notify<'_>,
 ) -> EmptyResult {
-    _restore_member(&org_id, &member_id, &headers, &mut conn).await
+    _restore_member(&org_id, &member_id, &headers, &mut conn, &nt).await
 }
 
 // Pre web-vault v2022.9.x endpoint
@@ -2466,8 +3177,9 @@ async fn bulk_activate_members(
     data: Json<BulkMembershipIds>,
     headers: AdminHeaders,
     conn: DbConn,
+    nt: Notify<'_>,
 ) -> JsonResult {
-    bulk_restore_members(org_id, data, headers, conn).await
+    bulk_restore_members(org_id, data, headers, conn, nt).await
 }
 
 #[put("/organizations/<org_id>/users/<member_id>/restore")]
@@ -2476,8 +3188,9 @@ async fn restore_member(
     member_id: MembershipId,
     headers: AdminHeaders,
     mut conn: DbConn,
+    nt: Notify<'_>,
 ) -> EmptyResult {
-    _restore_member(&org_id, &member_id, &headers, &mut conn).await
+    _restore_member(&org_id, &member_id, &headers, &mut conn, &nt).await
 }
 
 #[put("/organizations/<org_id>/users/restore", data = "<data>")]
@@ -2486,6 +3199,7 @@ async fn bulk_restore_members(
     data: Json<BulkMembershipIds>,
     headers: AdminHeaders,
     mut conn: DbConn,
+    nt: Notify<'_>,
 ) -> JsonResult {
     if org_id != headers.org_id {
         err!("Organization not found", "Organization id's do not match");
@@ -2494,7 +3208,7 @@ async fn bulk_restore_members(
 
     let mut bulk_response = Vec::new();
     for member_id in data.ids {
-        let err_msg = match _restore_member(&org_id, &member_id, &headers, &mut conn).await {
+        let err_msg = match _restore_member(&org_id, &member_id, &headers, &mut conn, &nt).await {
             Ok(_) => String::new(),
             Err(e) => format!("{e:?}"),
         };
@@ -2520,6 +3234,7 @@ async fn _restore_member(
     member_id: &MembershipId,
     headers: &AdminHeaders,
     conn: &mut DbConn,
+    nt: &Notify<'_>,
 ) -> EmptyResult {
     match Membership::find_by_uuid_and_org(member_id, org_id, conn).await {
         Some(mut member) if member.status < MembershipStatus::Accepted as i32 => {
@@ -2545,9 +3260,16 @@ async fn _restore_member(
                     Err(OrgPolicyErr::SingleOrgEnforced) => {
                         err!("You cannot restore this user because they are a member of an organization which forbids it");
                     }
+                    Err(OrgPolicyErr::SingleOrgOtherMembership) => {
+                        err!("You cannot restore this user because this organization forbids its members from being a member of any other organization");
+                    }
                 }
             }
 
+            if let Some(org) = Organization::find_by_uuid(org_id, conn).await {
+                org.check_seats_available(1, conn).await?;
+            }
+
             member.restore();
             member.save(conn).await?;
 
@@ -2561,6 +3283,10 @@ async fn _restore_member(
                 conn,
             )
             .await;
+
+            if let Some(user) = User::find_by_uuid(&member.user_uuid, conn).await {
+                nt.send_user_update(UpdateType::SyncOrgKeys, &user).await;
+            }
         }
         Some(_) => err!("User is already active"),
         None => err!("User not found in organization"),
@@ -2619,8 +3345,11 @@ impl GroupRequest {
     pub fn update_group(&self, mut group: Group) -> Group {
         group.name.clone_from(&self.name);
         group.access_all = self.access_all;
-        // Group Updates do not support changing the external_id
-        // These input fields are in a disabled state, and can only be updated/added via ldap_import
+        // The external_id input field is disabled in the web-vault, it's mostly set by the Directory Connector.
+        // Keep it when a client doesn't send it, so an update doesn't unlink the group from the directory.
+        if self.external_id.is_some() {
+            group.set_external_id(self.external_id.clone());
+        }
 
         group
     }
@@ -2664,6 +3393,7 @@ async fn post_groups(
     }
 
     let group_request = data.into_inner();
+    check_group_request(&group_request, &org_id, &mut conn).await?;
     let group = group_request.to_group(&org_id);
 
     log_event(
@@ -2697,6 +3427,7 @@ async fn put_group(
     };
 
     let group_request = data.into_inner();
+    check_group_request(&group_request, &org_id, &mut conn).await?;
     let updated_group = group_request.update_group(group);
 
     CollectionGroup::delete_all_by_group(&group_id, &mut conn).await?;
@@ -2716,6 +3447,22 @@ async fn put_group(
     add_update_group(updated_group, group_request.collections, group_request.users, org_id, &headers, &mut conn).await
 }
 
+/// Checks that the collections and members of a group request belong to the organization.
+/// This runs before the existing assignments of the group are removed, so an invalid request changes nothing.
+async fn check_group_request(group_request: &GroupRequest, org_id: &OrganizationId, conn: &mut DbConn) -> EmptyResult {
+    for col_selection in &group_request.collections {
+        if Collection::find_by_uuid_and_org(&col_selection.id, org_id, conn).await.is_none() {
+            err!("Collection is not part of organization")
+        }
+    }
+    for assigned_member in &group_request.users {
+        if Membership::find_by_uuid_and_org(assigned_member, org_id, conn).await.is_none() {
+            err!("User is not part of organization")
+        }
+    }
+    Ok(())
+}
+
 async fn add_update_group(
     mut group: Group,
     collections: Vec<CollectionData>,
@@ -2903,9 +3650,15 @@ async fn put_group_members(
         err!("Group could not be found!", "Group uuid is invalid or does not belong to the organization")
     };
 
+    let assigned_members = data.into_inner();
+    for assigned_member in &assigned_members {
+        if Membership::find_by_uuid_and_org(assigned_member, &org_id, &mut conn).await.is_none() {
+            err!("User is not part of organization")
+        }
+    }
+
     GroupUser::delete_all_by_group(&group_id, &mut conn).await?;
 
-    let assigned_members = data.into_inner();
     for assigned_member in assigned_members {
         let mut user_entry = GroupUser::new(group_id.clone(), assigned_member.clone());
         user_entry.save(&mut conn).await?;
@@ -2985,9 +3738,15 @@ async fn put_user_groups(
         err!("User could not be found or does not belong to the organization.");
     }
 
+    let assigned_group_ids = data.into_inner();
+    for assigned_group_id in &assigned_group_ids.group_ids {
+        if Group::find_by_uuid_and_org(assigned_group_id, &org_id, &mut conn).await.is_none() {
+            err!("Group is not part of organization")
+        }
+    }
+
     GroupUser::delete_all_by_member(&member_id, &mut conn).await?;
 
-    let assigned_group_ids = data.into_inner();
     for assigned_group_id in assigned_group_ids.group_ids {
         let mut group_user = GroupUser::new(assigned_group_id.clone(), member_id.clone());
         group_user.save(&mut conn).await?;
@@ -3142,8 +3901,13 @@ async fn put_reset_password(
 
     let mut user = user;
     user.set_password(reset_request.new_master_password_hash.as_str(), Some(reset_request.key), true, None);
+    // The password was chosen by an admin, the user has to replace it at the next login
+    user.force_password_reset = true;
     user.save(&mut conn).await?;
 
+    // Sessions which were started with the previous password should not be able to renew themselves
+    Device::revoke_refresh_tokens_by_user(&user.uuid, &mut conn).await?;
+
     nt.send_logout(&user, None).await;
 
     log_event(
@@ -3273,7 +4037,7 @@ async fn put_reset_password_enrollment(
         EventType::OrganizationUserResetPasswordWithdraw as i32
     };
 
-    log_event(log_id, &member_id, &org_id, &headers.user.uuid, headers.device.atype, &headers.ip.ip, &mut conn).await;
+    log_event(log_id, &member.uuid, &org_id, &headers.user.uuid, headers.device.atype, &headers.ip.ip, &mut conn).await;
 
     Ok(())
 }
@@ -3295,6 +4059,11 @@ async fn get_org_export(
     if org_id != headers.org_id {
         err!("Organization not found", "Organization id's do not match");
     }
+    crate::ratelimit::check_limit_export(headers.user.uuid.as_ref())?;
+    if CONFIG.export_require_fresh_verification() {
+        two_factor::protected_actions::check_fresh_verification(&headers.user.uuid, &mut conn).await?;
+    }
+
     // Since version v2023.1.0 the format of the export is different.
     // Also, this endpoint was created since v2022.9.0.
     // Therefore, we will check for any version smaller then v2023.1.0 and return a different response.
@@ -3308,9 +4077,9 @@ async fn get_org_export(
     };
 
     // Also both main keys here need to be lowercase, else the export will fail.
-    if use_list_response_model {
+    let export = if use_list_response_model {
         // Backwards compatible pre v2023.1.0 response
-        Ok(Json(json!({
+        json!({
             "collections": {
                 "data": convert_json_key_lcase_first(_get_org_collections(&org_id, &mut conn).await),
                 "object": "list",
@@ -3321,14 +4090,28 @@ async fn get_org_export(
                 "object": "list",
                 "continuationToken": null,
             }
-        })))
+        })
     } else {
         // v2023.1.0 and newer response
-        Ok(Json(json!({
+        json!({
             "collections": convert_json_key_lcase_first(_get_org_collections(&org_id, &mut conn).await),
             "ciphers": convert_json_key_lcase_first(_get_org_details(&org_id, &headers.host, &headers.user.uuid, &mut conn).await),
-        })))
-    }
+        })
+    };
+
+    // Keep track of the export in the security history of the user
+    log_user_event(
+        EventType::UserClientExportedVault as i32,
+        &headers.user.uuid,
+        headers.device.atype,
+        &headers.ip.ip,
+        &mut conn,
+    )
+    .await;
+    let org_name = Organization::find_by_uuid(&org_id, &mut conn).await.map(|o| o.name);
+    notify_vault_export(&headers.user, &headers.device, &headers.ip.ip, org_name.as_deref()).await;
+
+    Ok(Json(export))
 }
 
 async fn _api_key(
@@ -3338,6 +4121,14 @@ async fn _api_key(
     headers: AdminHeaders,
     mut conn: DbConn,
 ) -> JsonResult {
+    if org_id != &headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+    // The API key gives full access to the public API, which includes managing the members
+    if headers.membership_type != MembershipType::Owner {
+        err!("Only owners can view or rotate the organization API key")
+    }
+
     let data: PasswordOrOtpData = data.into_inner();
     let user = headers.user;
 
@@ -0,0 +1,412 @@
+use data_encoding::BASE64URL_NOPAD;
+use rocket::serde::json::Json;
+use rocket::Route;
+use serde_json::Value;
+use webauthn_rs::{proto::*, AuthenticationState, RegistrationState};
+
+use crate::{
+    api::{
+        core::two_factor::webauthn::{
+            relying_party_id, PublicKeyCredentialCopy, RegisterPublicKeyCredentialCopy, WebauthnConfig,
+        },
+        EmptyResult, JsonResult, PasswordOrOtpData,
+    },
+    auth::{decode_webauthn, encode_jwt, generate_webauthn_claims, Headers},
+    crypto,
+    db::{
+        models::{TwoFactor, TwoFactorType, User, UserId},
+        DbConn,
+    },
+    error::Error,
+    util::get_uuid,
+    CONFIG,
+};
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        get_passkeys,
+        passkey_attestation_options,
+        passkey_assertion_options,
+        create_passkey,
+        update_passkey,
+        delete_passkey
+    ]
+}
+
+// The same limit as the official server, the clients do not offer to create more
+const MAX_PASSKEYS: usize = 5;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PasskeyCredential {
+    pub id: String,
+    pub name: String,
+    pub credential: Credential,
+
+    // The keys used to decrypt the vault with the PRF extension, see `UserDecryptionOptions.WebAuthnPrfOption`
+    pub supports_prf: bool,
+    pub encrypted_user_key: Option<String>,
+    pub encrypted_public_key: Option<String>,
+    pub encrypted_private_key: Option<String>,
+}
+
+impl PasskeyCredential {
+    fn prf_status(&self) -> i32 {
+        match (self.supports_prf, &self.encrypted_user_key) {
+            (true, Some(_)) => 0, // Enabled
+            (true, None) => 1,    // Supported
+            (false, _) => 2,      // Unsupported
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "name": self.name,
+            "prfStatus": self.prf_status(),
+            "encryptedUserKey": self.encrypted_user_key,
+            "encryptedPublicKey": self.encrypted_public_key,
+            "object": "webauthnCredential",
+        })
+    }
+
+    /// Returns the decryption option for the login response, when this passkey can decrypt the vault.
+    pub fn prf_decryption_option(&self) -> Option<Value> {
+        match (&self.encrypted_user_key, &self.encrypted_private_key) {
+            (Some(user_key), Some(private_key)) => Some(json!({
+                "EncryptedPrivateKey": private_key,
+                "EncryptedUserKey": user_key,
+            })),
+            _ => None,
+        }
+    }
+}
+
+pub async fn get_passkey_credentials(user_id: &UserId, conn: &mut DbConn) -> Result<Vec<PasskeyCredential>, Error> {
+    let type_ = TwoFactorType::PasskeyCredentials as i32;
+    match TwoFactor::find_by_user_and_type(user_id, type_, conn).await {
+        Some(tf) => Ok(serde_json::from_str(&tf.data)?),
+        None => Ok(Vec::new()),
+    }
+}
+
+pub async fn save_passkey_credentials(
+    user_id: &UserId,
+    passkeys: &[PasskeyCredential],
+    conn: &mut DbConn,
+) -> EmptyResult {
+    if passkeys.is_empty() {
+        if let Some(tf) =
+            TwoFactor::find_by_user_and_type(user_id, TwoFactorType::PasskeyCredentials as i32, conn).await
+        {
+            tf.delete(conn).await?;
+        }
+        return Ok(());
+    }
+
+    TwoFactor::new(user_id.clone(), TwoFactorType::PasskeyCredentials, serde_json::to_string(passkeys)?)
+        .save(conn)
+        .await
+}
+
+fn check_domain_set() -> EmptyResult {
+    if !CONFIG.domain_set() {
+        err!("`DOMAIN` environment variable is not set. Passkey login disabled")
+    }
+    Ok(())
+}
+
+#[get("/webauthn")]
+async fn get_passkeys(headers: Headers, mut conn: DbConn) -> JsonResult {
+    let passkeys = get_passkey_credentials(&headers.user.uuid, &mut conn).await?;
+    let passkeys_json: Vec<Value> = passkeys.iter().map(PasskeyCredential::to_json).collect();
+
+    Ok(Json(json!({
+        "object": "list",
+        "data": passkeys_json,
+        "continuationToken": null
+    })))
+}
+
+#[post("/webauthn/attestation-options", data = "<data>")]
+async fn passkey_attestation_options(data: Json<PasswordOrOtpData>, headers: Headers, mut conn: DbConn) -> JsonResult {
+    check_domain_set()?;
+    let user = headers.user;
+    data.into_inner().validate(&user, false, &mut conn).await?;
+
+    let passkeys = get_passkey_credentials(&user.uuid, &mut conn).await?;
+    if passkeys.len() >= MAX_PASSKEYS {
+        err!(format!("A maximum of {MAX_PASSKEYS} passkeys can be registered"))
+    }
+    // Prevent registering the same authenticator twice
+    let registered = passkeys.into_iter().map(|p| p.credential.cred_id).collect();
+
+    let (challenge, state) = WebauthnConfig::load().generate_challenge_register_options(
+        user.uuid.as_bytes().to_vec(),
+        user.email,
+        user.name,
+        Some(registered),
+        Some(UserVerificationPolicy::Required),
+        None,
+    )?;
+
+    // Passkeys have to be discoverable, the login does not know the user beforehand
+    let mut options = serde_json::to_value(challenge.public_key)?;
+    options["authenticatorSelection"] = json!({
+        "requireResidentKey": true,
+        "residentKey": "required",
+        "userVerification": "required",
+    });
+
+    let token = encode_jwt(&generate_webauthn_claims(user.uuid.to_string(), serde_json::to_string(&state)?));
+    Ok(Json(json!({
+        "options": options,
+        "token": token,
+        "object": "webauthnCredentialCreateOptions"
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CreatePasskeyData {
+    device_response: RegisterPublicKeyCredentialCopy,
+    name: String,
+    token: String,
+    #[serde(default)]
+    supports_prf: bool,
+    encrypted_user_key: Option<String>,
+    encrypted_public_key: Option<String>,
+    encrypted_private_key: Option<String>,
+}
+
+#[post("/webauthn", data = "<data>")]
+async fn create_passkey(data: Json<CreatePasskeyData>, headers: Headers, mut conn: DbConn) -> EmptyResult {
+    let data: CreatePasskeyData = data.into_inner();
+    let user = headers.user;
+
+    let claims = decode_webauthn(&data.token)?;
+    if claims.sub != *user.uuid {
+        err!("The token does not belong to this user")
+    }
+    let state: RegistrationState = serde_json::from_str(&claims.state)?;
+
+    let (credential, _data) =
+        WebauthnConfig::load().register_credential(&data.device_response.into(), &state, |_| Ok(false))?;
+
+    let mut passkeys = get_passkey_credentials(&user.uuid, &mut conn).await?;
+    if passkeys.len() >= MAX_PASSKEYS {
+        err!(format!("A maximum of {MAX_PASSKEYS} passkeys can be registered"))
+    }
+    if passkeys.iter().any(|p| p.credential.cred_id == credential.cred_id) {
+        err!("This passkey is already registered")
+    }
+
+    passkeys.push(PasskeyCredential {
+        id: get_uuid(),
+        name: data.name,
+        credential,
+        supports_prf: data.supports_prf,
+        encrypted_user_key: data.encrypted_user_key,
+        encrypted_public_key: data.encrypted_public_key,
+        encrypted_private_key: data.encrypted_private_key,
+    });
+    save_passkey_credentials(&user.uuid, &passkeys, &mut conn).await
+}
+
+/// Used by the clients to enable vault encryption for a passkey which supports the PRF extension.
+#[post("/webauthn/assertion-options", data = "<data>")]
+async fn passkey_assertion_options(data: Json<PasswordOrOtpData>, headers: Headers, mut conn: DbConn) -> JsonResult {
+    check_domain_set()?;
+    let user = headers.user;
+    data.into_inner().validate(&user, false, &mut conn).await?;
+
+    let creds: Vec<Credential> =
+        get_passkey_credentials(&user.uuid, &mut conn).await?.into_iter().map(|p| p.credential).collect();
+    if creds.is_empty() {
+        err!("No passkeys registered")
+    }
+
+    let (response, state) = WebauthnConfig::load().generate_challenge_authenticate_options(creds, None)?;
+
+    let token = encode_jwt(&generate_webauthn_claims(user.uuid.to_string(), serde_json::to_string(&state)?));
+    Ok(Json(json!({
+        "options": response.public_key,
+        "token": token,
+        "object": "webAuthnLoginAssertionOptions"
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UpdatePasskeyData {
+    device_response: PublicKeyCredentialCopy,
+    token: String,
+    encrypted_user_key: String,
+    encrypted_public_key: String,
+    encrypted_private_key: String,
+}
+
+#[put("/webauthn", data = "<data>")]
+async fn update_passkey(data: Json<UpdatePasskeyData>, headers: Headers, mut conn: DbConn) -> EmptyResult {
+    let data: UpdatePasskeyData = data.into_inner();
+    let user = headers.user;
+
+    let claims = decode_webauthn(&data.token)?;
+    if claims.sub != *user.uuid {
+        err!("The token does not belong to this user")
+    }
+    let state: AuthenticationState = serde_json::from_str(&claims.state)?;
+
+    let rsp: PublicKeyCredential = data.device_response.into();
+    let (cred_id, auth_data) = WebauthnConfig::load().authenticate_credential(&rsp, &state)?;
+
+    let mut passkeys = get_passkey_credentials(&user.uuid, &mut conn).await?;
+    let Some(passkey) = passkeys.iter_mut().find(|p| &p.credential.cred_id == cred_id) else {
+        err!("Passkey not found")
+    };
+    if !passkey.supports_prf {
+        err!("This passkey does not support encryption")
+    }
+
+    passkey.credential.counter = auth_data.counter;
+    passkey.encrypted_user_key = Some(data.encrypted_user_key);
+    passkey.encrypted_public_key = Some(data.encrypted_public_key);
+    passkey.encrypted_private_key = Some(data.encrypted_private_key);
+    save_passkey_credentials(&user.uuid, &passkeys, &mut conn).await
+}
+
+#[post("/webauthn/<id>/delete", data = "<data>")]
+async fn delete_passkey(id: &str, data: Json<PasswordOrOtpData>, headers: Headers, mut conn: DbConn) -> EmptyResult {
+    let user = headers.user;
+    data.into_inner().validate(&user, true, &mut conn).await?;
+
+    let mut passkeys = get_passkey_credentials(&user.uuid, &mut conn).await?;
+    let Some(pos) = passkeys.iter().position(|p| p.id == id) else {
+        err!("Passkey not found")
+    };
+    passkeys.remove(pos);
+    save_passkey_credentials(&user.uuid, &passkeys, &mut conn).await
+}
+
+/// Returns the options to log in with any passkey, the token holds the challenge to verify the login with.
+pub fn generate_passkey_login_options() -> JsonResult {
+    check_domain_set()?;
+
+    let challenge = crypto::encode_random_bytes::<32>(BASE64URL_NOPAD);
+    let token = encode_jwt(&generate_webauthn_claims(String::new(), challenge.clone()));
+
+    Ok(Json(json!({
+        "options": {
+            "challenge": challenge,
+            "timeout": 60000,
+            "rpId": relying_party_id(),
+            "allowCredentials": [],
+            "userVerification": "required",
+        },
+        "token": token,
+        "object": "webAuthnLoginAssertionOptions"
+    })))
+}
+
+/// Verifies a passkey login, and returns the user together with the passkey that was used.
+pub async fn validate_passkey_login(
+    token: &str,
+    device_response: &str,
+    conn: &mut DbConn,
+) -> Result<(User, PasskeyCredential), Error> {
+    check_domain_set()?;
+
+    let claims = decode_webauthn(token)?;
+    if !claims.sub.is_empty() {
+        err!("Invalid passkey login token")
+    }
+
+    let rsp: PublicKeyCredentialCopy = serde_json::from_str(device_response)?;
+    let rsp: PublicKeyCredential = rsp.into();
+
+    // The user handle is the id of the user, set when the passkey was created
+    let Some(user_id) = rsp.response.user_handle.as_ref().and_then(|h| String::from_utf8(h.0.clone()).ok()) else {
+        err!("The passkey did not provide a valid user handle")
+    };
+    let user_id = UserId::from(user_id);
+    let Some(user) = User::find_by_uuid(&user_id, conn).await else {
+        err!("Passkey not registered")
+    };
+
+    let mut passkeys = get_passkey_credentials(&user.uuid, conn).await?;
+    let Some(pos) = passkeys.iter().position(|p| p.credential.cred_id == rsp.raw_id.0) else {
+        err!("Passkey not registered")
+    };
+
+    // The challenge was generated without knowing the user, so create the state for this credential and
+    // replace its challenge with the one that was handed out with the login options.
+    // webauthn-rs serializes the challenge as its raw bytes, not as the base64url string the clients get.
+    let Ok(challenge) = BASE64URL_NOPAD.decode(claims.state.as_bytes()) else {
+        err!("Invalid passkey login token")
+    };
+    let (_, state) =
+        WebauthnConfig::load().generate_challenge_authenticate_options(vec![passkeys[pos].credential.clone()], None)?;
+    let mut state = serde_json::to_value(state)?;
+    state["challenge"] = serde_json::to_value(challenge)?;
+    let state: AuthenticationState = serde_json::from_value(state)?;
+
+    let (_, auth_data) = WebauthnConfig::load().authenticate_credential(&rsp, &state)?;
+    // Without user verification a passkey would be a single factor which can be used by anyone holding it
+    if !auth_data.user_verified {
+        err!("User verification is required to log in with a passkey")
+    }
+
+    // Authenticators which always report a counter of 0 can't be protected against replays by the counter,
+    // so every challenge can only be used once
+    conn.begin_transaction().await?;
+    let mut result = consume_passkey_login_challenge(&user.uuid, claims.state, claims.exp, conn).await;
+    if result.is_ok() {
+        passkeys[pos].credential.counter = auth_data.counter;
+        result = save_passkey_credentials(&user.uuid, &passkeys, conn).await;
+    }
+    conn.end_transaction(result).await?;
+
+    let passkey = passkeys.swap_remove(pos);
+    Ok((user, passkey))
+}
+
+/// Records the challenge of a passkey login of the user, failing if it was already used.
+/// The challenges are kept until their login token expires, after which they are rejected anyway.
+async fn consume_passkey_login_challenge(
+    user_id: &UserId,
+    challenge: String,
+    exp: i64,
+    conn: &mut DbConn,
+) -> EmptyResult {
+    let type_ = TwoFactorType::PasskeyLoginChallenges as i32;
+    let existing = TwoFactor::find_by_user_and_type(user_id, type_, conn).await;
+    let mut consumed: Vec<(String, i64)> = match &existing {
+        Some(tf) => serde_json::from_str(&tf.data).unwrap_or_default(),
+        None => Vec::new(),
+    };
+
+    let now = chrono::Utc::now().timestamp();
+    consumed.retain(|(_, consumed_exp)| *consumed_exp >= now);
+    if consumed.iter().any(|(c, _)| crypto::ct_eq(c, &challenge)) {
+        err!("This passkey login was already used")
+    }
+    consumed.push((challenge, exp));
+    let data = serde_json::to_string(&consumed)?;
+
+    // Only save when no other login recorded a challenge since it was read, otherwise two logins replaying
+    // the same challenge at the same time could both pass the check above
+    let saved = match existing {
+        Some(mut tf) => {
+            let old_data = std::mem::replace(&mut tf.data, data);
+            tf.save_if_unchanged(Some(&old_data), conn).await?
+        }
+        None => {
+            TwoFactor::new(user_id.clone(), TwoFactorType::PasskeyLoginChallenges, data)
+                .save_if_unchanged(None, conn)
+                .await?
+        }
+    };
+    if !saved {
+        err!("Another passkey login happened at the same time, please try again")
+    }
+    Ok(())
+}
@@ -1,21 +1,56 @@
-use chrono::Utc;
+use chrono::{DateTime, NaiveDateTime, TimeDelta, Utc};
+use num_traits::FromPrimitive;
 use rocket::{
+    form::FromForm,
     request::{FromRequest, Outcome},
     serde::json::Json,
     Request, Route,
 };
+use serde_json::Value;
 
 use std::collections::HashSet;
 
 use crate::{
-    api::EmptyResult,
-    auth,
+    api::{
+        core::{
+            events::get_continuation_token,
+            invite_members_bulk, log_event,
+            organizations::{_reinvite_member, save_org_policy},
+        },
+        send_user_webhook, ApiResult, EmptyResult, JsonResult, Notify, UpdateType, UserWebhookEvent,
+    },
+    auth::{self, ClientIp, Headers},
     db::{models::*, DbConn},
     mail, CONFIG,
 };
 
 pub fn routes() -> Vec<Route> {
-    routes![ldap_import]
+    routes![
+        ldap_import,
+        list_members,
+        get_member,
+        post_member,
+        put_member,
+        delete_member,
+        reinvite_member,
+        get_member_group_ids,
+        put_member_group_ids,
+        list_groups,
+        get_group,
+        post_group,
+        put_group,
+        delete_group,
+        get_group_member_ids,
+        put_group_member_ids,
+        list_collections,
+        get_collection,
+        put_collection,
+        delete_collection,
+        list_policies,
+        get_policy,
+        put_policy,
+        list_events,
+    ]
 }
 
 #[derive(Deserialize)]
@@ -23,6 +58,8 @@ pub fn routes() -> Vec<Route> {
 struct OrgImportGroupData {
     name: String,
     external_id: String,
+    // Older Directory Connector versions call this `users`
+    #[serde(alias = "users")]
     member_external_ids: Vec<String>,
 }
 
@@ -36,8 +73,9 @@ struct OrgImportUserData {
 
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct OrgImportData {
+pub struct OrgImportData {
     groups: Vec<OrgImportGroupData>,
+    #[serde(alias = "users")]
     members: Vec<OrgImportUserData>,
     overwrite_existing: bool,
     // largeImport: bool, // For now this will not be used, upstream uses this to prevent syncs of more then 2000 users or groups without the flag set.
@@ -45,23 +83,37 @@ struct OrgImportData {
 
 #[post("/public/organization/import", data = "<data>")]
 async fn ldap_import(data: Json<OrgImportData>, token: PublicToken, mut conn: DbConn) -> EmptyResult {
+    import_directory(&token.org_id, data.into_inner(), None, &mut conn).await
+}
+
+/// Syncs the members and groups of an organization with a directory, used by the Directory Connector.
+/// Members are matched by email and linked to the directory by their external id. Only members which have an
+/// external id are removed when `overwrite_existing` is set, members which were added manually are kept.
+/// `headers` are those of the admin which runs the import, if it doesn't use the organization API key.
+pub async fn import_directory(
+    org_id: &OrganizationId,
+    data: OrgImportData,
+    headers: Option<&Headers>,
+    conn: &mut DbConn,
+) -> EmptyResult {
     // Most of the logic for this function can be found here
     // https://github.com/bitwarden/server/blob/fd892b2ff4547648a276734fb2b14a8abae2c6f5/src/Core/Services/Implementations/OrganizationService.cs#L1797
 
-    let org_id = token.0;
-    let data = data.into_inner();
+    let org_id = org_id.clone();
+    let Some(org) = Organization::find_by_uuid(&org_id, conn).await else {
+        err!("Organization not found")
+    };
 
     for user_data in &data.members {
         let mut user_created: bool = false;
         if user_data.deleted {
             // If user is marked for deletion and it exists, revoke it
-            if let Some(mut member) = Membership::find_by_email_and_org(&user_data.email, &org_id, &mut conn).await {
+            if let Some(mut member) = Membership::find_by_email_and_org(&user_data.email, &org_id, conn).await {
                 // Only revoke a user if it is not the last confirmed owner
                 let revoked = if member.atype == MembershipType::Owner
                     && member.status == MembershipStatus::Confirmed as i32
                 {
-                    if Membership::count_confirmed_by_org_and_type(&org_id, MembershipType::Owner, &mut conn).await <= 1
-                    {
+                    if Membership::count_confirmed_by_org_and_type(&org_id, MembershipType::Owner, conn).await <= 1 {
                         warn!("Can't revoke the last owner");
                         false
                     } else {
@@ -73,27 +125,58 @@ async fn ldap_import(data: Json<OrgImportData>, token: PublicToken, mut conn: Db
 
                 let ext_modified = member.set_external_id(Some(user_data.external_id.clone()));
                 if revoked || ext_modified {
-                    member.save(&mut conn).await?;
+                    member.save(conn).await?;
+                }
+                if revoked {
+                    log_import_event(EventType::OrganizationUserRevoked, &member, headers, conn).await;
                 }
             }
         // If user is part of the organization, restore it
-        } else if let Some(mut member) = Membership::find_by_email_and_org(&user_data.email, &org_id, &mut conn).await {
-            let restored = member.restore();
+        } else if let Some(mut member) = Membership::find_by_email_and_org(&user_data.email, &org_id, conn).await {
+            let restored = if member.status == MembershipStatus::Revoked as i32
+                && org.check_seats_available(1, conn).await.is_err()
+            {
+                warn!("Can't restore {}, the organization has no free seats", user_data.email);
+                false
+            } else {
+                member.restore()
+            };
             let ext_modified = member.set_external_id(Some(user_data.external_id.clone()));
             if restored || ext_modified {
-                member.save(&mut conn).await?;
+                member.save(conn).await?;
+            }
+            if restored {
+                log_import_event(EventType::OrganizationUserRestored, &member, headers, conn).await;
             }
         } else {
             // If user is not part of the organization
-            let user = match User::find_by_mail(&user_data.email, &mut conn).await {
-                Some(user) => user, // exists in vaultwarden
+            org.check_seats_available(1, conn).await?;
+            let user = match User::find_by_mail(&user_data.email, conn).await {
+                // exists in vaultwarden, the first directory to sync the user sets its external id
+                Some(mut user) => {
+                    if user.external_id.is_none() {
+                        user.external_id = Some(user_data.external_id.clone());
+                        user.save(conn).await?;
+                    }
+                    user
+                }
                 None => {
-                    // User does not exist yet
+                    // User does not exist yet, the same restrictions as for an invitation apply
+                    if !CONFIG.invitations_allowed() {
+                        warn!("Can't import {}, invitations of new users are disabled", user_data.email);
+                        continue;
+                    }
+                    if !CONFIG.is_email_domain_allowed(&user_data.email) {
+                        warn!("Can't import {}, its email domain is not allowed", user_data.email);
+                        continue;
+                    }
+
                     let mut new_user = User::new(user_data.email.clone());
-                    new_user.save(&mut conn).await?;
+                    new_user.external_id = Some(user_data.external_id.clone());
+                    new_user.save(conn).await?;
 
                     if !CONFIG.mail_enabled() {
-                        Invitation::new(&new_user.email).save(&mut conn).await?;
+                        Invitation::new(&new_user.email).save(conn).await?;
                     }
                     user_created = true;
                     new_user
@@ -111,34 +194,38 @@ async fn ldap_import(data: Json<OrgImportData>, token: PublicToken, mut conn: Db
             new_member.atype = MembershipType::User as i32;
             new_member.status = member_status;
 
-            new_member.save(&mut conn).await?;
+            new_member.save(conn).await?;
 
             if CONFIG.mail_enabled() {
-                let (org_name, org_email) = match Organization::find_by_uuid(&org_id, &mut conn).await {
-                    Some(org) => (org.name, org.billing_email),
-                    None => err!("Error looking up organization"),
+                let invited_by = match headers {
+                    Some(headers) => headers.user.email.clone(),
+                    None => org.billing_email.clone(),
                 };
 
                 if let Err(e) =
-                    mail::send_invite(&user, org_id.clone(), new_member.uuid.clone(), &org_name, Some(org_email)).await
+                    mail::send_invite(&user, org_id.clone(), new_member.uuid.clone(), &org.name, Some(invited_by)).await
                 {
                     // Upon error delete the user, invite and org member records when needed
                     if user_created {
-                        user.delete(&mut conn).await?;
+                        user.delete(conn).await?;
                     } else {
-                        new_member.delete(&mut conn).await?;
+                        new_member.delete(conn).await?;
                     }
 
                     err!(format!("Error sending invite: {e:?} "));
                 }
             }
+            if user_created {
+                send_user_webhook(UserWebhookEvent::Invited, &user.uuid, &user.email);
+            }
+
+            log_import_event(EventType::OrganizationUserInvited, &new_member, headers, conn).await;
         }
     }
 
     if CONFIG.org_groups_enabled() {
         for group_data in &data.groups {
-            let group_uuid = match Group::find_by_external_id_and_org(&group_data.external_id, &org_id, &mut conn).await
-            {
+            let group_uuid = match Group::find_by_external_id_and_org(&group_data.external_id, &org_id, conn).await {
                 Some(group) => group.uuid,
                 None => {
                     let mut group = Group::new(
@@ -147,17 +234,17 @@ async fn ldap_import(data: Json<OrgImportData>, token: PublicToken, mut conn: Db
                         false,
                         Some(group_data.external_id.clone()),
                     );
-                    group.save(&mut conn).await?;
+                    group.save(conn).await?;
                     group.uuid
                 }
             };
 
-            GroupUser::delete_all_by_group(&group_uuid, &mut conn).await?;
+            GroupUser::delete_all_by_group(&group_uuid, conn).await?;
 
             for ext_id in &group_data.member_external_ids {
-                if let Some(member) = Membership::find_by_external_id_and_org(ext_id, &org_id, &mut conn).await {
+                if let Some(member) = Membership::find_by_external_id_and_org(ext_id, &org_id, conn).await {
                     let mut group_user = GroupUser::new(group_uuid.clone(), member.uuid.clone());
-                    group_user.save(&mut conn).await?;
+                    group_user.save(conn).await?;
                 }
             }
         }
@@ -169,19 +256,19 @@ async fn ldap_import(data: Json<OrgImportData>, token: PublicToken, mut conn: Db
     if data.overwrite_existing {
         // Generate a HashSet to quickly verify if a member is listed or not.
         let sync_members: HashSet<String> = data.members.into_iter().map(|m| m.external_id).collect();
-        for member in Membership::find_by_org(&org_id, &mut conn).await {
+        for member in Membership::find_by_org(&org_id, conn).await {
             if let Some(ref user_external_id) = member.external_id {
                 if !sync_members.contains(user_external_id) {
                     if member.atype == MembershipType::Owner && member.status == MembershipStatus::Confirmed as i32 {
                         // Removing owner, check that there is at least one other confirmed owner
-                        if Membership::count_confirmed_by_org_and_type(&org_id, MembershipType::Owner, &mut conn).await
-                            <= 1
+                        if Membership::count_confirmed_by_org_and_type(&org_id, MembershipType::Owner, conn).await <= 1
                         {
                             warn!("Can't delete the last owner");
                             continue;
                         }
                     }
-                    member.delete(&mut conn).await?;
+                    log_import_event(EventType::OrganizationUserRemoved, &member, headers, conn).await;
+                    member.delete(conn).await?;
                 }
             }
         }
@@ -190,7 +277,739 @@ async fn ldap_import(data: Json<OrgImportData>, token: PublicToken, mut conn: Db
     Ok(())
 }
 
-pub struct PublicToken(OrganizationId);
+async fn log_import_event(event_type: EventType, member: &Membership, headers: Option<&Headers>, conn: &mut DbConn) {
+    if let Some(headers) = headers {
+        log_event(
+            event_type as i32,
+            &member.uuid,
+            &member.org_uuid,
+            &headers.user.uuid,
+            headers.device.atype,
+            &headers.ip.ip,
+            conn,
+        )
+        .await;
+    }
+}
+
+//
+// Public API, compatible with https://bitwarden.com/help/api/
+// All endpoints are authenticated with the organization API key and act on that organization only.
+//
+
+// Changes done with the organization API key are not done by a user, they are logged like those of the admin panel
+const PUBLIC_API_ACTING_USER: &str = "vaultwarden-public-api-0000000000000";
+const PUBLIC_API_DEVICE_TYPE: i32 = 14; // Use UnknownBrowser type
+
+async fn log_public_event(event_type: EventType, source_uuid: &str, token: &PublicToken, conn: &mut DbConn) {
+    log_event(
+        event_type as i32,
+        source_uuid,
+        &token.org_id,
+        &PUBLIC_API_ACTING_USER.into(),
+        PUBLIC_API_DEVICE_TYPE,
+        &token.ip.ip,
+        conn,
+    )
+    .await;
+}
+
+fn public_list(data: Vec<Value>) -> Value {
+    json!({
+        "object": "list",
+        "data": data,
+        "continuationToken": null,
+    })
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PublicAssociationData {
+    id: String,
+    #[serde(default)]
+    read_only: bool,
+    #[serde(default)]
+    hide_passwords: bool,
+    #[serde(default)]
+    manage: bool,
+}
+
+/// Checks that all the collections belong to the organization before any of them is assigned
+async fn check_org_collections(
+    org_id: &OrganizationId,
+    collections: &[PublicAssociationData],
+    conn: &mut DbConn,
+) -> EmptyResult {
+    for col in collections {
+        let col_id: CollectionId = col.id.clone().into();
+        if Collection::find_by_uuid_and_org(&col_id, org_id, conn).await.is_none() {
+            err!("Collection not found in Organization")
+        }
+    }
+    Ok(())
+}
+
+async fn check_org_groups(org_id: &OrganizationId, group_ids: &[GroupId], conn: &mut DbConn) -> EmptyResult {
+    if !group_ids.is_empty() && !CONFIG.org_groups_enabled() {
+        err!("Group support is disabled");
+    }
+    for group_id in group_ids {
+        if Group::find_by_uuid_and_org(group_id, org_id, conn).await.is_none() {
+            err!("Group not found in Organization")
+        }
+    }
+    Ok(())
+}
+
+//
+// Members
+//
+
+async fn member_to_json_public(member: &Membership, conn: &mut DbConn) -> Value {
+    let user = User::find_by_uuid(&member.user_uuid, conn).await;
+    let twofactor_enabled = !TwoFactor::find_by_user(&member.user_uuid, conn).await.is_empty();
+    let collections: Vec<Value> =
+        CollectionUser::find_by_organization_and_user_uuid(&member.org_uuid, &member.user_uuid, conn)
+            .await
+            .iter()
+            .map(|cu| {
+                json!({
+                    "id": cu.collection_uuid,
+                    "readOnly": cu.read_only,
+                    "hidePasswords": cu.hide_passwords,
+                    "manage": cu.manage,
+                })
+            })
+            .collect();
+
+    // Revoked members have a lower status, but the API knows only one revoked status
+    let status = if member.status < MembershipStatus::Revoked as i32 {
+        MembershipStatus::Revoked as i32
+    } else {
+        member.status
+    };
+    let name = match &user {
+        Some(user) if member.get_unrevoked_status() >= MembershipStatus::Accepted as i32 => Some(user.name.clone()),
+        _ => None,
+    };
+
+    json!({
+        "id": member.uuid,
+        "userId": member.user_uuid,
+        "name": name,
+        "email": user.map(|u| u.email),
+        "type": member.atype,
+        "accessAll": member.access_all,
+        "externalId": member.external_id,
+        "resetPasswordEnrolled": member.reset_password_key.is_some(),
+        "status": status,
+        "twoFactorEnabled": twofactor_enabled,
+        "collections": collections,
+        "object": "member",
+    })
+}
+
+async fn find_member(member_id: &MembershipId, token: &PublicToken, conn: &mut DbConn) -> ApiResult<Membership> {
+    match Membership::find_by_uuid_and_org(member_id, &token.org_id, conn).await {
+        Some(member) => Ok(member),
+        None => err_code!("Member not found", 404),
+    }
+}
+
+/// Replaces the collections of a member, the collections have to be checked with `check_org_collections` first
+async fn set_member_collections(
+    member: &Membership,
+    collections: &[PublicAssociationData],
+    conn: &mut DbConn,
+) -> EmptyResult {
+    for cu in CollectionUser::find_by_organization_and_user_uuid(&member.org_uuid, &member.user_uuid, conn).await {
+        cu.delete(conn).await?;
+    }
+    for col in collections {
+        CollectionUser::save(
+            &member.user_uuid,
+            &col.id.clone().into(),
+            col.read_only,
+            col.hide_passwords,
+            col.manage,
+            conn,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Replaces the groups of a member, the groups have to be checked with `check_org_groups` first
+async fn set_member_groups(member: &Membership, group_ids: &[GroupId], conn: &mut DbConn) -> EmptyResult {
+    GroupUser::delete_all_by_member(&member.uuid, conn).await?;
+    for group_id in group_ids {
+        GroupUser::new(group_id.clone(), member.uuid.clone()).save(conn).await?;
+    }
+    Ok(())
+}
+
+#[get("/public/members")]
+async fn list_members(token: PublicToken, mut conn: DbConn) -> JsonResult {
+    let mut members_json = Vec::new();
+    for member in Membership::find_by_org(&token.org_id, &mut conn).await {
+        members_json.push(member_to_json_public(&member, &mut conn).await);
+    }
+    Ok(Json(public_list(members_json)))
+}
+
+#[get("/public/members/<member_id>")]
+async fn get_member(member_id: MembershipId, token: PublicToken, mut conn: DbConn) -> JsonResult {
+    let member = find_member(&member_id, &token, &mut conn).await?;
+    Ok(Json(member_to_json_public(&member, &mut conn).await))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PublicMemberData {
+    email: Option<String>,
+    r#type: i32,
+    #[serde(default)]
+    access_all: bool,
+    external_id: Option<String>,
+    collections: Option<Vec<PublicAssociationData>>,
+    groups: Option<Vec<GroupId>>,
+}
+
+impl PublicMemberData {
+    fn membership_type(&self) -> ApiResult<MembershipType> {
+        // Custom (4) is treated as a manager, like in the web-vault
+        match MembershipType::from_str(&self.r#type.to_string()) {
+            Some(new_type) => Ok(new_type),
+            None => err!("Invalid type"),
+        }
+    }
+}
+
+#[post("/public/members", data = "<data>")]
+async fn post_member(data: Json<PublicMemberData>, token: PublicToken, mut conn: DbConn) -> JsonResult {
+    let data: PublicMemberData = data.into_inner();
+    let new_type = data.membership_type()?;
+    let Some(email) = data.email.clone() else {
+        err!("An email address is required")
+    };
+    check_org_collections(&token.org_id, data.collections.as_deref().unwrap_or_default(), &mut conn).await?;
+    check_org_groups(&token.org_id, data.groups.as_deref().unwrap_or_default(), &mut conn).await?;
+
+    let Some(org) = Organization::find_by_uuid(&token.org_id, &mut conn).await else {
+        err!("Organization not found")
+    };
+
+    let result = invite_members_bulk(
+        &org,
+        vec![email],
+        new_type as i32,
+        data.access_all,
+        Some(org.billing_email.clone()),
+        &mut conn,
+    )
+    .await
+    .pop();
+    let Some(member_id) = result.as_ref().and_then(|r| r.member_id.clone()) else {
+        let msg = result.and_then(|r| r.message).unwrap_or_else(|| String::from("The member could not be invited"));
+        err!(msg)
+    };
+    let mut member = find_member(&member_id, &token, &mut conn).await?;
+
+    if member.set_external_id(data.external_id) {
+        member.save(&mut conn).await?;
+    }
+    if !data.access_all {
+        set_member_collections(&member, data.collections.as_deref().unwrap_or_default(), &mut conn).await?;
+    }
+    set_member_groups(&member, data.groups.as_deref().unwrap_or_default(), &mut conn).await?;
+
+    log_public_event(EventType::OrganizationUserInvited, &member.uuid, &token, &mut conn).await;
+
+    Ok(Json(member_to_json_public(&member, &mut conn).await))
+}
+
+#[put("/public/members/<member_id>", data = "<data>")]
+async fn put_member(
+    member_id: MembershipId,
+    data: Json<PublicMemberData>,
+    token: PublicToken,
+    mut conn: DbConn,
+) -> JsonResult {
+    let data: PublicMemberData = data.into_inner();
+    let new_type = data.membership_type()?;
+    let mut member = find_member(&member_id, &token, &mut conn).await?;
+    check_org_collections(&token.org_id, data.collections.as_deref().unwrap_or_default(), &mut conn).await?;
+    if let Some(group_ids) = &data.groups {
+        check_org_groups(&token.org_id, group_ids, &mut conn).await?;
+    }
+
+    if member.atype == MembershipType::Owner
+        && new_type != MembershipType::Owner
+        && member.status == MembershipStatus::Confirmed as i32
+        && Membership::count_confirmed_by_org_and_type(&token.org_id, MembershipType::Owner, &mut conn).await <= 1
+    {
+        err!("Can't delete the last owner")
+    }
+    if new_type < MembershipType::Admin
+        && OrgPolicy::is_user_allowed(&member.user_uuid, &token.org_id, true, &mut conn).await.is_err()
+    {
+        err!("You cannot modify this member to this type because of the policies of the organization")
+    }
+
+    member.atype = new_type as i32;
+    member.access_all = data.access_all;
+    // Keep the external id when it isn't sent, so an update doesn't unlink the member from the directory
+    if data.external_id.is_some() {
+        member.set_external_id(data.external_id);
+    }
+    member.save(&mut conn).await?;
+
+    if data.access_all {
+        set_member_collections(&member, &[], &mut conn).await?;
+    } else if let Some(collections) = &data.collections {
+        set_member_collections(&member, collections, &mut conn).await?;
+    }
+    if let Some(group_ids) = &data.groups {
+        set_member_groups(&member, group_ids, &mut conn).await?;
+    }
+
+    log_public_event(EventType::OrganizationUserUpdated, &member.uuid, &token, &mut conn).await;
+
+    Ok(Json(member_to_json_public(&member, &mut conn).await))
+}
+
+#[delete("/public/members/<member_id>")]
+async fn delete_member(member_id: MembershipId, token: PublicToken, mut conn: DbConn, nt: Notify<'_>) -> EmptyResult {
+    let member = find_member(&member_id, &token, &mut conn).await?;
+
+    if member.atype == MembershipType::Owner
+        && member.status == MembershipStatus::Confirmed as i32
+        && Membership::count_confirmed_by_org_and_type(&token.org_id, MembershipType::Owner, &mut conn).await <= 1
+    {
+        err!("Can't delete the last owner")
+    }
+
+    log_public_event(EventType::OrganizationUserRemoved, &member.uuid, &token, &mut conn).await;
+
+    if let Some(user) = User::find_by_uuid(&member.user_uuid, &mut conn).await {
+        nt.send_user_update(UpdateType::SyncOrgKeys, &user).await;
+    }
+
+    member.delete(&mut conn).await
+}
+
+#[post("/public/members/<member_id>/reinvite")]
+async fn reinvite_member(member_id: MembershipId, token: PublicToken, mut conn: DbConn) -> EmptyResult {
+    let Some(org) = Organization::find_by_uuid(&token.org_id, &mut conn).await else {
+        err!("Organization not found")
+    };
+    _reinvite_member(&token.org_id, &member_id, &org.billing_email, &mut conn).await
+}
+
+#[get("/public/members/<member_id>/group-ids")]
+async fn get_member_group_ids(member_id: MembershipId, token: PublicToken, mut conn: DbConn) -> JsonResult {
+    let member = find_member(&member_id, &token, &mut conn).await?;
+    let group_ids: Vec<GroupId> = if CONFIG.org_groups_enabled() {
+        GroupUser::find_by_member(&member.uuid, &mut conn).await.into_iter().map(|gu| gu.groups_uuid).collect()
+    } else {
+        Vec::new()
+    };
+    Ok(Json(json!(group_ids)))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PublicGroupIdsData {
+    group_ids: Vec<GroupId>,
+}
+
+#[put("/public/members/<member_id>/group-ids", data = "<data>")]
+async fn put_member_group_ids(
+    member_id: MembershipId,
+    data: Json<PublicGroupIdsData>,
+    token: PublicToken,
+    mut conn: DbConn,
+) -> EmptyResult {
+    let data: PublicGroupIdsData = data.into_inner();
+    let member = find_member(&member_id, &token, &mut conn).await?;
+    check_org_groups(&token.org_id, &data.group_ids, &mut conn).await?;
+
+    set_member_groups(&member, &data.group_ids, &mut conn).await?;
+    log_public_event(EventType::OrganizationUserUpdatedGroups, &member.uuid, &token, &mut conn).await;
+    Ok(())
+}
+
+//
+// Groups
+//
+
+async fn group_to_json_public(group: &Group, conn: &mut DbConn) -> Value {
+    let collections: Vec<Value> = CollectionGroup::find_by_group(&group.uuid, conn)
+        .await
+        .iter()
+        .map(|cg| {
+            json!({
+                "id": cg.collections_uuid,
+                "readOnly": cg.read_only,
+                "hidePasswords": cg.hide_passwords,
+                "manage": cg.manage,
+            })
+        })
+        .collect();
+
+    json!({
+        "id": group.uuid,
+        "name": group.name,
+        "accessAll": group.access_all,
+        "externalId": group.external_id,
+        "collections": collections,
+        "object": "group",
+    })
+}
+
+async fn find_group(group_id: &GroupId, token: &PublicToken, conn: &mut DbConn) -> ApiResult<Group> {
+    if !CONFIG.org_groups_enabled() {
+        err!("Group support is disabled");
+    }
+    match Group::find_by_uuid_and_org(group_id, &token.org_id, conn).await {
+        Some(group) => Ok(group),
+        None => err_code!("Group not found", 404),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PublicGroupData {
+    name: String,
+    #[serde(default)]
+    access_all: bool,
+    external_id: Option<String>,
+    #[serde(default)]
+    collections: Vec<PublicAssociationData>,
+}
+
+/// Replaces the collections of a group, the collections have to be checked with `check_org_collections` first
+async fn set_group_collections(group: &Group, collections: &[PublicAssociationData], conn: &mut DbConn) -> EmptyResult {
+    CollectionGroup::delete_all_by_group(&group.uuid, conn).await?;
+    for col in collections {
+        CollectionGroup::new(col.id.clone().into(), group.uuid.clone(), col.read_only, col.hide_passwords, col.manage)
+            .save(conn)
+            .await?;
+    }
+    Ok(())
+}
+
+#[get("/public/groups")]
+async fn list_groups(token: PublicToken, mut conn: DbConn) -> JsonResult {
+    let mut groups_json = Vec::new();
+    if CONFIG.org_groups_enabled() {
+        for group in Group::find_by_organization(&token.org_id, &mut conn).await {
+            groups_json.push(group_to_json_public(&group, &mut conn).await);
+        }
+    }
+    Ok(Json(public_list(groups_json)))
+}
+
+#[get("/public/groups/<group_id>")]
+async fn get_group(group_id: GroupId, token: PublicToken, mut conn: DbConn) -> JsonResult {
+    let group = find_group(&group_id, &token, &mut conn).await?;
+    Ok(Json(group_to_json_public(&group, &mut conn).await))
+}
+
+#[post("/public/groups", data = "<data>")]
+async fn post_group(data: Json<PublicGroupData>, token: PublicToken, mut conn: DbConn) -> JsonResult {
+    if !CONFIG.org_groups_enabled() {
+        err!("Group support is disabled");
+    }
+    let data: PublicGroupData = data.into_inner();
+    check_org_collections(&token.org_id, &data.collections, &mut conn).await?;
+
+    let mut group = Group::new(token.org_id.clone(), data.name, data.access_all, data.external_id);
+    group.save(&mut conn).await?;
+    set_group_collections(&group, &data.collections, &mut conn).await?;
+
+    log_public_event(EventType::GroupCreated, &group.uuid, &token, &mut conn).await;
+
+    Ok(Json(group_to_json_public(&group, &mut conn).await))
+}
+
+#[put("/public/groups/<group_id>", data = "<data>")]
+async fn put_group(group_id: GroupId, data: Json<PublicGroupData>, token: PublicToken, mut conn: DbConn) -> JsonResult {
+    let mut group = find_group(&group_id, &token, &mut conn).await?;
+    let data: PublicGroupData = data.into_inner();
+    check_org_collections(&token.org_id, &data.collections, &mut conn).await?;
+
+    group.name = data.name;
+    group.access_all = data.access_all;
+    // Keep the external id when it isn't sent, so an update doesn't unlink the group from the directory
+    if data.external_id.is_some() {
+        group.set_external_id(data.external_id);
+    }
+    group.save(&mut conn).await?;
+    set_group_collections(&group, &data.collections, &mut conn).await?;
+
+    log_public_event(EventType::GroupUpdated, &group.uuid, &token, &mut conn).await;
+
+    Ok(Json(group_to_json_public(&group, &mut conn).await))
+}
+
+#[delete("/public/groups/<group_id>")]
+async fn delete_group(group_id: GroupId, token: PublicToken, mut conn: DbConn) -> EmptyResult {
+    let group = find_group(&group_id, &token, &mut conn).await?;
+    log_public_event(EventType::GroupDeleted, &group.uuid, &token, &mut conn).await;
+    group.delete(&mut conn).await
+}
+
+#[get("/public/groups/<group_id>/member-ids")]
+async fn get_group_member_ids(group_id: GroupId, token: PublicToken, mut conn: DbConn) -> JsonResult {
+    let group = find_group(&group_id, &token, &mut conn).await?;
+    let member_ids: Vec<MembershipId> = GroupUser::find_by_group(&group.uuid, &mut conn)
+        .await
+        .into_iter()
+        .map(|gu| gu.users_organizations_uuid)
+        .collect();
+    Ok(Json(json!(member_ids)))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PublicMemberIdsData {
+    member_ids: Vec<MembershipId>,
+}
+
+#[put("/public/groups/<group_id>/member-ids", data = "<data>")]
+async fn put_group_member_ids(
+    group_id: GroupId,
+    data: Json<PublicMemberIdsData>,
+    token: PublicToken,
+    mut conn: DbConn,
+) -> EmptyResult {
+    let group = find_group(&group_id, &token, &mut conn).await?;
+    let data: PublicMemberIdsData = data.into_inner();
+    for member_id in &data.member_ids {
+        find_member(member_id, &token, &mut conn).await?;
+    }
+
+    GroupUser::delete_all_by_group(&group.uuid, &mut conn).await?;
+    for member_id in data.member_ids {
+        GroupUser::new(group.uuid.clone(), member_id.clone()).save(&mut conn).await?;
+        log_public_event(EventType::OrganizationUserUpdatedGroups, &member_id, &token, &mut conn).await;
+    }
+    Ok(())
+}
+
+//
+// Collections
+// Collection names are encrypted with the organization key, so collections can't be created with the public API
+//
+
+async fn collection_to_json_public(collection: &Collection, conn: &mut DbConn) -> Value {
+    let groups: Vec<Value> = if CONFIG.org_groups_enabled() {
+        CollectionGroup::find_by_collection(&collection.uuid, conn)
+            .await
+            .iter()
+            .map(CollectionGroup::to_json_details_for_group)
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    json!({
+        "id": collection.uuid,
+        "externalId": collection.external_id,
+        "groups": groups,
+        "object": "collection",
+    })
+}
+
+async fn find_collection(col_id: &CollectionId, token: &PublicToken, conn: &mut DbConn) -> ApiResult<Collection> {
+    match Collection::find_by_uuid_and_org(col_id, &token.org_id, conn).await {
+        Some(collection) => Ok(collection),
+        None => err_code!("Collection not found", 404),
+    }
+}
+
+#[get("/public/collections")]
+async fn list_collections(token: PublicToken, mut conn: DbConn) -> JsonResult {
+    let mut collections_json = Vec::new();
+    for collection in Collection::find_by_organization(&token.org_id, &mut conn).await {
+        collections_json.push(collection_to_json_public(&collection, &mut conn).await);
+    }
+    Ok(Json(public_list(collections_json)))
+}
+
+#[get("/public/collections/<col_id>")]
+async fn get_collection(col_id: CollectionId, token: PublicToken, mut conn: DbConn) -> JsonResult {
+    let collection = find_collection(&col_id, &token, &mut conn).await?;
+    Ok(Json(collection_to_json_public(&collection, &mut conn).await))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PublicCollectionData {
+    external_id: Option<String>,
+    groups: Option<Vec<PublicAssociationData>>,
+}
+
+#[put("/public/collections/<col_id>", data = "<data>")]
+async fn put_collection(
+    col_id: CollectionId,
+    data: Json<PublicCollectionData>,
+    token: PublicToken,
+    mut conn: DbConn,
+) -> JsonResult {
+    let mut collection = find_collection(&col_id, &token, &mut conn).await?;
+    let data: PublicCollectionData = data.into_inner();
+    if let Some(groups) = &data.groups {
+        let group_ids: Vec<GroupId> = groups.iter().map(|g| g.id.clone().into()).collect();
+        check_org_groups(&token.org_id, &group_ids, &mut conn).await?;
+    }
+
+    // Keep the external id when it isn't sent, so an update doesn't unlink the collection from the directory
+    if data.external_id.is_some() {
+        collection.set_external_id(data.external_id);
+    }
+    collection.save(&mut conn).await?;
+
+    if let Some(groups) = data.groups {
+        CollectionGroup::delete_all_by_collection(&collection.uuid, &mut conn).await?;
+        for group in groups {
+            CollectionGroup::new(
+                collection.uuid.clone(),
+                group.id.into(),
+                group.read_only,
+                group.hide_passwords,
+                group.manage,
+            )
+            .save(&mut conn)
+            .await?;
+        }
+    }
+
+    log_public_event(EventType::CollectionUpdated, &collection.uuid, &token, &mut conn).await;
+
+    Ok(Json(collection_to_json_public(&collection, &mut conn).await))
+}
+
+#[delete("/public/collections/<col_id>")]
+async fn delete_collection(col_id: CollectionId, token: PublicToken, mut conn: DbConn) -> EmptyResult {
+    let collection = find_collection(&col_id, &token, &mut conn).await?;
+    log_public_event(EventType::CollectionDeleted, &collection.uuid, &token, &mut conn).await;
+    collection.delete(&mut conn).await
+}
+
+//
+// Policies
+//
+
+#[get("/public/policies")]
+async fn list_policies(token: PublicToken, mut conn: DbConn) -> JsonResult {
+    let policies: Vec<Value> =
+        OrgPolicy::find_by_org(&token.org_id, &mut conn).await.iter().map(OrgPolicy::to_json).collect();
+    Ok(Json(public_list(policies)))
+}
+
+#[get("/public/policies/<pol_type>")]
+async fn get_policy(pol_type: i32, token: PublicToken, mut conn: DbConn) -> JsonResult {
+    let Some(pol_type_enum) = OrgPolicyType::from_i32(pol_type) else {
+        err!("Invalid or unsupported policy type")
+    };
+    let policy = match OrgPolicy::find_by_org_and_type(&token.org_id, pol_type_enum, &mut conn).await {
+        Some(p) => p,
+        None => OrgPolicy::new(token.org_id.clone(), pol_type_enum, "null".to_string()),
+    };
+    Ok(Json(policy.to_json()))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PublicPolicyData {
+    enabled: bool,
+    data: Option<Value>,
+}
+
+#[put("/public/policies/<pol_type>", data = "<data>")]
+async fn put_policy(pol_type: i32, data: Json<PublicPolicyData>, token: PublicToken, mut conn: DbConn) -> JsonResult {
+    let data: PublicPolicyData = data.into_inner();
+    let Some(pol_type_enum) = OrgPolicyType::from_i32(pol_type) else {
+        err!("Invalid or unsupported policy type")
+    };
+
+    let policy = save_org_policy(
+        &token.org_id,
+        pol_type_enum,
+        data.enabled,
+        data.data,
+        &PUBLIC_API_ACTING_USER.into(),
+        PUBLIC_API_DEVICE_TYPE,
+        &token.ip.ip,
+        &mut conn,
+    )
+    .await?;
+
+    Ok(Json(policy.to_json()))
+}
+
+//
+// Events
+//
+
+#[derive(FromForm)]
+struct PublicEventFilter {
+    start: Option<String>,
+    end: Option<String>,
+    #[field(name = "actingUserId")]
+    acting_user_id: Option<UserId>,
+    #[field(name = "itemId")]
+    item_id: Option<CipherId>,
+    #[field(name = "continuationToken")]
+    continuation_token: Option<String>,
+}
+
+fn parse_event_date(date: Option<&str>) -> ApiResult<Option<NaiveDateTime>> {
+    match date.map(DateTime::parse_from_rfc3339) {
+        Some(Ok(date)) => Ok(Some(date.naive_utc())),
+        Some(Err(_)) => err!("Invalid date"),
+        None => Ok(None),
+    }
+}
+
+// Without a range the events of the last 30 days are returned, like upstream
+#[get("/public/events?<filter..>")]
+async fn list_events(filter: PublicEventFilter, token: PublicToken, mut conn: DbConn) -> JsonResult {
+    if !CONFIG.org_events_enabled() {
+        return Ok(Json(public_list(Vec::new())));
+    }
+
+    let end_date = match parse_event_date(filter.continuation_token.as_deref())? {
+        Some(before_date) => before_date,
+        None => parse_event_date(filter.end.as_deref())?.unwrap_or_else(|| Utc::now().naive_utc()),
+    };
+    let start_date = parse_event_date(filter.start.as_deref())?.unwrap_or_else(|| end_date - TimeDelta::days(30));
+
+    let events = Event::find_by_organization_uuid(&token.org_id, &start_date, &end_date, &mut conn).await;
+    // The continuation token depends on the size of the page, so it is taken before the events are filtered
+    let events_json: Vec<Value> = events.iter().map(Event::to_json_public).collect();
+    let continuation_token = get_continuation_token(&events_json).map(String::from);
+
+    let events_json: Vec<Value> = events
+        .iter()
+        .filter(|e| filter.acting_user_id.is_none() || e.act_user_uuid == filter.acting_user_id)
+        .filter(|e| filter.item_id.is_none() || e.cipher_uuid == filter.item_id)
+        .map(Event::to_json_public)
+        .collect();
+
+    Ok(Json(json!({
+        "object": "list",
+        "data": events_json,
+        "continuationToken": continuation_token,
+    })))
+}
+
+pub struct PublicToken {
+    org_id: OrganizationId,
+    ip: ClientIp,
+}
 
 #[rocket::async_trait]
 impl<'r> FromRequest<'r> for PublicToken {
@@ -243,7 +1062,18 @@ impl<'r> FromRequest<'r> for PublicToken {
         if org_api_key.uuid != claims.sub {
             err_handler!("Token not issued for this client");
         }
+        // Tokens issued before the API key was rotated are not valid anymore
+        if claims.nbf < org_api_key.revision_date.and_utc().timestamp() {
+            err_handler!("Token issued before the API key was rotated");
+        }
+
+        let Outcome::Success(ip) = ClientIp::from_request(request).await else {
+            err_handler!("Error getting Client IP")
+        };
 
-        Outcome::Success(PublicToken(claims.client_sub))
+        Outcome::Success(PublicToken {
+            org_id: claims.client_sub,
+            ip,
+        })
     }
 }
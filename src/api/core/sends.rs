@@ -1,9 +1,6 @@
-use std::path::Path;
-
 use chrono::{DateTime, TimeDelta, Utc};
 use num_traits::ToPrimitive;
 use rocket::form::Form;
-use rocket::fs::NamedFile;
 use rocket::fs::TempFile;
 use rocket::serde::json::Json;
 use serde_json::Value;
@@ -12,15 +9,13 @@ use crate::{
     api::{ApiResult, EmptyResult, JsonResult, Notify, UpdateType},
     auth::{ClientIp, Headers, Host},
     db::{models::*, DbConn, DbPool},
+    storage::{FileBackend, FileResponse, FileStorage},
     util::NumberOrString,
     CONFIG,
 };
 
 const SEND_INACCESSIBLE_MSG: &str = "Send does not exist or is no longer available";
 
-// The max file size allowed by Bitwarden clients and add an extra 5% to avoid issues
-const SIZE_525_MB: i64 = 550_502_400;
-
 pub fn routes() -> Vec<rocket::Route> {
     routes![
         get_sends,
@@ -34,6 +29,7 @@ pub fn routes() -> Vec<rocket::Route> {
         put_remove_password,
         download_send,
         post_send_file_v2,
+        get_send_file_v2_upload,
         post_send_file_v2_data
     ]
 }
@@ -42,11 +38,39 @@ pub async fn purge_sends(pool: DbPool) {
     debug!("Purging sends");
     if let Ok(mut conn) = pool.get().await {
         Send::purge(&mut conn).await;
+        purge_orphaned_send_files(&mut conn).await;
     } else {
         error!("Failed to get DB connection while purging sends")
     }
 }
 
+// A file is written before its Send is saved, so only files which are older than this are orphans
+const ORPHANED_SEND_FILE_MIN_AGE: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+/// Removes the files of Sends which don't exist anymore, left behind when removing them failed.
+async fn purge_orphaned_send_files(conn: &mut DbConn) {
+    let storage = FileStorage::sends();
+    let folders = match storage.list_folders().await {
+        Ok(folders) => folders,
+        Err(e) => {
+            warn!("Failed to list the send files: {e:#?}");
+            return;
+        }
+    };
+    for (name, modified) in folders {
+        let is_old = modified.elapsed().is_ok_and(|age| age > ORPHANED_SEND_FILE_MIN_AGE);
+        if !is_old || Send::find_by_uuid(&SendId::from(name.clone()), conn).await.is_some() {
+            continue;
+        }
+
+        info!("Removing the files of send {name}, it doesn't exist anymore");
+        let _io_permit = crate::util::acquire_io_permit(crate::util::FileStore::Sends).await;
+        if let Err(e) = storage.delete_folder(&name).await {
+            warn!("Failed to remove the files of send {name}: {e:#?}");
+        }
+    }
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SendData {
@@ -88,6 +112,18 @@ async fn enforce_disable_send_policy(headers: &Headers, conn: &mut DbConn) -> Em
     Ok(())
 }
 
+/// Sends can't be accessed anymore when their owner isn't allowed to use Sends,
+/// either because of the `sends_allowed` config setting or the Disable Send policy.
+async fn is_send_disabled(send: &Send, conn: &mut DbConn) -> bool {
+    if !CONFIG.sends_allowed() {
+        return true;
+    }
+    match &send.user_uuid {
+        Some(user_id) => OrgPolicy::is_applicable_to_user(user_id, OrgPolicyType::DisableSend, None, conn).await,
+        None => false,
+    }
+}
+
 /// Enforces the `DisableHideEmail` option of the `Send Options` policy.
 /// A non-owner/admin user belonging to an org with this option enabled isn't
 /// allowed to hide their email address from the recipient of a Bitwarden Send,
@@ -106,6 +142,53 @@ async fn enforce_disable_hide_email_policy(data: &SendData, headers: &Headers, c
     Ok(())
 }
 
+/// The date checks of upstream's SendRequestModel. Like upstream, a minute of margin is kept
+/// so a Send can't expire while it is being saved.
+fn validate_send_dates(data: &SendData, creation: bool) -> EmptyResult {
+    let now_plus_1_minute = Utc::now() + TimeDelta::minutes(1);
+
+    if creation && data.expiration_date.is_some_and(|d| d <= now_plus_1_minute) {
+        err!("You cannot create a Send that is already expired. Adjust the expiration date and try again.");
+    }
+    if data.deletion_date <= now_plus_1_minute {
+        err!("You cannot have a Send with a deletion date in the past. Adjust the Deletion Date to a value greater than the current date and time and try again.");
+    }
+    if data.deletion_date > now_plus_1_minute + TimeDelta::days(31) {
+        err!(
+            "You cannot have a Send with a deletion date that far into the future. Adjust the Deletion Date to a value less than 31 days from now and try again."
+        );
+    }
+    Ok(())
+}
+
+/// Checks the size of a new Send file against the file size limit and what is left of the storage limit of the user
+async fn check_send_file_size(size: i64, user_id: &UserId, conn: &mut DbConn) -> EmptyResult {
+    let file_size_limit = CONFIG.send_file_size_limit() * 1024;
+    if size > file_size_limit {
+        err!(format!("The file of a Send can't be larger than {}", crate::util::get_display_size(file_size_limit)));
+    }
+
+    match CONFIG.user_send_limit() {
+        Some(0) => err!("File uploads are disabled"),
+        Some(limit_kb) => {
+            let Some(already_used) = Send::size_by_user(user_id, conn).await else {
+                err!("Existing sends overflow")
+            };
+            let Some(left) = limit_kb.checked_mul(1024).and_then(|l| l.checked_sub(already_used)) else {
+                err!("Send size overflow");
+            };
+            if left <= 0 {
+                err!("Send storage limit reached! Delete some sends to free up space")
+            }
+            if size > left {
+                err!("Send storage limit exceeded with this file");
+            }
+        }
+        None => {}
+    }
+    Ok(())
+}
+
 fn create_send(data: SendData, user_id: UserId) -> ApiResult<Send> {
     let data_val = if data.r#type == SendType::Text as i32 {
         data.text
@@ -122,11 +205,7 @@ fn create_send(data: SendData, user_id: UserId) -> ApiResult<Send> {
         err!("Send data not provided");
     };
 
-    if data.deletion_date > Utc::now() + TimeDelta::try_days(31).unwrap() {
-        err!(
-            "You cannot have a Send with a deletion date that far into the future. Adjust the Deletion Date to a value less than 31 days from now and try again."
-        );
-    }
+    validate_send_dates(&data, true)?;
 
     let mut send = Send::new(data.r#type, data.name, data_str, data.key, data.deletion_date.naive_utc());
     send.user_uuid = Some(user_id);
@@ -223,26 +302,7 @@ async fn post_send_file(data: Form<UploadData<'_>>, headers: Headers, mut conn:
 
     enforce_disable_hide_email_policy(&model, &headers, &mut conn).await?;
 
-    let size_limit = match CONFIG.user_send_limit() {
-        Some(0) => err!("File uploads are disabled"),
-        Some(limit_kb) => {
-            let Some(already_used) = Send::size_by_user(&headers.user.uuid, &mut conn).await else {
-                err!("Existing sends overflow")
-            };
-            let Some(left) = limit_kb.checked_mul(1024).and_then(|l| l.checked_sub(already_used)) else {
-                err!("Send size overflow");
-            };
-            if left <= 0 {
-                err!("Send storage limit reached! Delete some sends to free up space")
-            }
-            i64::clamp(left, 0, SIZE_525_MB)
-        }
-        None => SIZE_525_MB,
-    };
-
-    if size > size_limit {
-        err!("Send storage limit exceeded with this file");
-    }
+    check_send_file_size(size, &headers.user.uuid, &mut conn).await?;
 
     let mut send = create_send(model, headers.user.uuid)?;
     if send.atype != SendType::File as i32 {
@@ -250,13 +310,9 @@ async fn post_send_file(data: Form<UploadData<'_>>, headers: Headers, mut conn:
     }
 
     let file_id = crate::crypto::generate_send_file_id();
-    let folder_path = tokio::fs::canonicalize(&CONFIG.sends_folder()).await?.join(&send.uuid);
-    let file_path = folder_path.join(&file_id);
-    tokio::fs::create_dir_all(&folder_path).await?;
-
-    if let Err(_err) = data.persist_to(&file_path).await {
-        data.move_copy_to(file_path).await?
-    }
+    let io_permit = crate::util::acquire_io_permit(crate::util::FileStore::Sends).await;
+    FileStorage::sends().save(&format!("{}/{file_id}", send.uuid), &mut data).await?;
+    drop(io_permit);
 
     let mut data_value: Value = serde_json::from_str(&send.data)?;
     if let Some(o) = data_value.as_object_mut() {
@@ -301,26 +357,7 @@ async fn post_send_file_v2(data: Json<SendData>, headers: Headers, mut conn: DbC
         err!("Send size can't be negative")
     }
 
-    let size_limit = match CONFIG.user_send_limit() {
-        Some(0) => err!("File uploads are disabled"),
-        Some(limit_kb) => {
-            let Some(already_used) = Send::size_by_user(&headers.user.uuid, &mut conn).await else {
-                err!("Existing sends overflow")
-            };
-            let Some(left) = limit_kb.checked_mul(1024).and_then(|l| l.checked_sub(already_used)) else {
-                err!("Send size overflow");
-            };
-            if left <= 0 {
-                err!("Send storage limit reached! Delete some sends to free up space")
-            }
-            i64::clamp(left, 0, SIZE_525_MB)
-        }
-        None => SIZE_525_MB,
-    };
-
-    if file_length > size_limit {
-        err!("Send storage limit exceeded with this file");
-    }
+    check_send_file_size(file_length, &headers.user.uuid, &mut conn).await?;
 
     let mut send = create_send(data, headers.user.uuid)?;
 
@@ -351,6 +388,38 @@ pub struct SendFileData {
     fileName: String,
 }
 
+// Called by the clients to get the upload url again, when the upload of a file created with post_send_file_v2 failed
+// Upstream: https://github.com/bitwarden/server/blob/66f95d1c443490b653e5a15d32977e2f5a3f9e32/src/Api/Tools/Controllers/SendsController.cs
+#[get("/sends/<send_id>/file/<file_id>")]
+async fn get_send_file_v2_upload(
+    send_id: SendId,
+    file_id: SendFileId,
+    headers: Headers,
+    mut conn: DbConn,
+) -> JsonResult {
+    let Some(send) = Send::find_by_uuid_and_user(&send_id, &headers.user.uuid, &mut conn).await else {
+        err!("Send not found", "Invalid send uuid or does not belong to user")
+    };
+
+    if send.atype != SendType::File as i32 {
+        err!("Send is not a file type send.");
+    }
+
+    let Ok(send_data) = serde_json::from_str::<SendFileData>(&send.data) else {
+        err!("Unable to decode send data as json.")
+    };
+    if file_id != send_data.id {
+        err!("Send file does not match send data.", format!("Expected id {} got {file_id}", send_data.id));
+    }
+
+    Ok(Json(json!({
+        "fileUploadType": 0, // 0 == Direct | 1 == Azure
+        "object": "send-fileUpload",
+        "url": format!("/sends/{}/file/{}", send.uuid, file_id),
+        "sendResponse": send.to_json()
+    })))
+}
+
 // https://github.com/bitwarden/server/blob/66f95d1c443490b653e5a15d32977e2f5a3f9e32/src/Api/Tools/Controllers/SendsController.cs#L250
 #[post("/sends/<send_id>/file/<file_id>", format = "multipart/form-data", data = "<data>")]
 async fn post_send_file_v2_data(
@@ -406,19 +475,17 @@ async fn post_send_file_v2_data(
         err!("Send file size does not match.", format!("Expected a file size of {} got {size}", send_data.size));
     }
 
-    let folder_path = tokio::fs::canonicalize(&CONFIG.sends_folder()).await?.join(send_id);
-    let file_path = folder_path.join(file_id);
+    let storage = FileStorage::sends();
+    let file_path = format!("{send_id}/{file_id}");
 
     // Check if the file already exists, if that is the case do not overwrite it
-    if tokio::fs::metadata(&file_path).await.is_ok() {
-        err!("Send file has already been uploaded.", format!("File {file_path:?} already exists"))
+    if storage.exists(&file_path).await? {
+        err!("Send file has already been uploaded.", format!("File {file_path} already exists"))
     }
 
-    tokio::fs::create_dir_all(&folder_path).await?;
-
-    if let Err(_err) = data.data.persist_to(&file_path).await {
-        data.data.move_copy_to(file_path).await?
-    }
+    let io_permit = crate::util::acquire_io_permit(crate::util::FileStore::Sends).await;
+    storage.save(&file_path, &mut data.data).await?;
+    drop(io_permit);
 
     nt.send_send_update(
         UpdateType::SyncSendCreate,
@@ -438,17 +505,12 @@ pub struct SendAccessData {
     pub password: Option<String>,
 }
 
-#[post("/sends/access/<access_id>", data = "<data>")]
-async fn post_access(
-    access_id: &str,
-    data: Json<SendAccessData>,
-    mut conn: DbConn,
-    ip: ClientIp,
-    nt: Notify<'_>,
-) -> JsonResult {
-    let Some(mut send) = Send::find_by_access_id(access_id, &mut conn).await else {
+/// Checks whether the Send can still be accessed, and its password when it has one.
+/// Wrong passwords are backed off per Send and IP address, so someone else knowing the link can't lock the recipients out.
+async fn check_send_access(send: &Send, password: Option<String>, ip: &ClientIp, conn: &mut DbConn) -> EmptyResult {
+    if is_send_disabled(send, conn).await {
         err_code!(SEND_INACCESSIBLE_MSG, 404)
-    };
+    }
 
     if let Some(max_access_count) = send.max_access_count {
         if send.access_count >= max_access_count {
@@ -471,12 +533,38 @@ async fn post_access(
     }
 
     if send.password_hash.is_some() {
-        match data.into_inner().password {
-            Some(ref p) if send.check_password(p) => { /* Nothing to do here */ }
-            Some(_) => err!("Invalid password", format!("IP: {}.", ip.ip)),
+        let backoff_key = crate::ratelimit::send_password_backoff_key(&send.uuid, &ip.ip);
+        crate::ratelimit::check_backoff(
+            &backoff_key,
+            "Too many failed password attempts for this Send. Try again later.",
+            conn,
+        )
+        .await?;
+        match password {
+            Some(ref p) if send.check_password(p) => crate::ratelimit::reset_failures(&backoff_key, conn).await?,
+            Some(_) => {
+                crate::ratelimit::register_failure(&backoff_key, conn).await?;
+                err!("Invalid password", format!("IP: {}.", ip.ip))
+            }
             None => err_code!("Password not provided", format!("IP: {}.", ip.ip), 401),
         }
     }
+    Ok(())
+}
+
+#[post("/sends/access/<access_id>", data = "<data>")]
+async fn post_access(
+    access_id: &str,
+    data: Json<SendAccessData>,
+    mut conn: DbConn,
+    ip: ClientIp,
+    nt: Notify<'_>,
+) -> JsonResult {
+    let Some(mut send) = Send::find_by_access_id(access_id, &mut conn).await else {
+        err_code!(SEND_INACCESSIBLE_MSG, 404)
+    };
+
+    check_send_access(&send, data.into_inner().password, &ip, &mut conn).await?;
 
     // Files are incremented during the download
     if send.atype == SendType::Text as i32 {
@@ -503,6 +591,7 @@ async fn post_access_file(
     file_id: SendFileId,
     data: Json<SendAccessData>,
     host: Host,
+    ip: ClientIp,
     mut conn: DbConn,
     nt: Notify<'_>,
 ) -> JsonResult {
@@ -510,33 +599,7 @@ async fn post_access_file(
         err_code!(SEND_INACCESSIBLE_MSG, 404)
     };
 
-    if let Some(max_access_count) = send.max_access_count {
-        if send.access_count >= max_access_count {
-            err_code!(SEND_INACCESSIBLE_MSG, 404)
-        }
-    }
-
-    if let Some(expiration) = send.expiration_date {
-        if Utc::now().naive_utc() >= expiration {
-            err_code!(SEND_INACCESSIBLE_MSG, 404)
-        }
-    }
-
-    if Utc::now().naive_utc() >= send.deletion_date {
-        err_code!(SEND_INACCESSIBLE_MSG, 404)
-    }
-
-    if send.disabled {
-        err_code!(SEND_INACCESSIBLE_MSG, 404)
-    }
-
-    if send.password_hash.is_some() {
-        match data.into_inner().password {
-            Some(ref p) if send.check_password(p) => { /* Nothing to do here */ }
-            Some(_) => err!("Invalid password."),
-            None => err_code!("Password not provided", 401),
-        }
-    }
+    check_send_access(&send, data.into_inner().password, &ip, &mut conn).await?;
 
     send.access_count += 1;
 
@@ -551,20 +614,23 @@ async fn post_access_file(
     )
     .await;
 
-    let token_claims = crate::auth::generate_send_claims(&send_id, &file_id);
-    let token = crate::auth::encode_jwt(&token_claims);
+    let url = FileStorage::sends().presigned_url(&format!("{send_id}/{file_id}")).unwrap_or_else(|| {
+        let token_claims = crate::auth::generate_send_claims(&send_id, &file_id);
+        let token = crate::auth::encode_jwt(&token_claims);
+        format!("{}/api/sends/{}/{}?t={}", &host.host, send_id, file_id, token)
+    });
     Ok(Json(json!({
         "object": "send-fileDownload",
         "id": file_id,
-        "url": format!("{}/api/sends/{}/{}?t={}", &host.host, send_id, file_id, token)
+        "url": url
     })))
 }
 
 #[get("/sends/<send_id>/<file_id>?<t>")]
-async fn download_send(send_id: SendId, file_id: SendFileId, t: &str) -> Option<NamedFile> {
+async fn download_send(send_id: SendId, file_id: SendFileId, t: &str) -> Option<FileResponse> {
     if let Ok(claims) = crate::auth::decode_send(t) {
         if claims.sub == format!("{send_id}/{file_id}") {
-            return NamedFile::open(Path::new(&CONFIG.sends_folder()).join(send_id).join(file_id)).await.ok();
+            return FileStorage::sends().download(&claims.sub).await;
         }
     }
     None
@@ -582,6 +648,8 @@ async fn put_send(
 
     let data: SendData = data.into_inner();
     enforce_disable_hide_email_policy(&data, &headers, &mut conn).await?;
+    // Not checked by update_send_from_data, the key rotation also re-saves Sends which are already past their dates
+    validate_send_dates(&data, false)?;
 
     let Some(mut send) = Send::find_by_uuid_and_user(&send_id, &headers.user.uuid, &mut conn).await else {
         err!("Send not found", "Send send_id is invalid or does not belong to user")
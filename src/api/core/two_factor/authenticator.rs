@@ -10,6 +10,7 @@ use crate::{
         models::{EventType, TwoFactor, TwoFactorType, UserId},
         DbConn,
     },
+    error::ApiErrorCode,
     util::NumberOrString,
 };
 
@@ -127,16 +128,17 @@ pub async fn validate_totp_code(
         _ => TwoFactor::new(user_id.clone(), TwoFactorType::Authenticator, secret.to_string()),
     };
 
-    // The amount of steps back and forward in time
-    // Also check if we need to disable time drifted TOTP codes.
-    // If that is the case, we set the steps to 0 so only the current TOTP is valid.
-    let steps = i64::from(!CONFIG.authenticator_disable_time_drift());
+    // The amount of steps back and forward in time which are accepted.
+    // If this is set to 0 only the current TOTP is valid.
+    let steps = i64::from(CONFIG.authenticator_time_drift_steps());
 
     // Get the current system time in UNIX Epoch (UTC)
     let current_time = chrono::Utc::now();
     let current_timestamp = current_time.timestamp();
 
-    for step in -steps..=steps {
+    // Check the current step first and then move outwards, so the least drifted step wins when a code matches multiple steps.
+    let step_offsets = std::iter::once(0).chain((1..=steps).flat_map(|s| [-s, s]));
+    for step in step_offsets {
         let time_step = current_timestamp / 30i64 + step;
 
         // We need to calculate the time offsite and cast it as an u64.
@@ -144,19 +146,12 @@ pub async fn validate_totp_code(
         let time = (current_timestamp + step * 30i64) as u64;
         let generated = totp_custom::<Sha1>(30, 6, &decoded_secret, time);
 
-        // Check the given code equals the generated and if the time_step is larger then the one last used.
-        if generated == totp_code && time_step > twofactor.last_used {
-            // If the step does not equals 0 the time is drifted either server or client side.
-            if step != 0 {
-                warn!("TOTP Time drift detected. The step offset is {}", step);
-            }
-
-            // Save the last used time step so only totp time steps higher then this one are allowed.
-            // This will also save a newly created twofactor if the code is correct.
-            twofactor.last_used = time_step;
-            twofactor.save(conn).await?;
-            return Ok(());
-        } else if generated == totp_code && time_step <= twofactor.last_used {
+        if !crypto::ct_eq(&generated, totp_code) {
+            continue;
+        }
+
+        // Prevent replays, only time steps larger then the last used one are allowed.
+        if time_step <= twofactor.last_used {
             warn!("This TOTP or a TOTP code within {} steps back or forward has already been used!", steps);
             err!(
                 format!("Invalid TOTP code! Server time: {} IP: {}", current_time.format("%F %T UTC"), ip.ip),
@@ -165,6 +160,17 @@ pub async fn validate_totp_code(
                 }
             );
         }
+
+        // If the step does not equals 0 the time is drifted either server or client side.
+        if step != 0 {
+            warn!("TOTP Time drift detected. The step offset is {}", step);
+        }
+
+        // Save the last used time step so only totp time steps higher then this one are allowed.
+        // This will also save a newly created twofactor if the code is correct.
+        twofactor.last_used = time_step;
+        twofactor.save(conn).await?;
+        return Ok(());
     }
 
     // Else no valid code received, deny access
@@ -190,7 +196,7 @@ async fn disable_authenticator(data: Json<DisableAuthenticatorData>, headers: He
     let type_ = data.r#type.into_i32()?;
 
     if !user.check_valid_password(&data.master_password_hash) {
-        err!("Invalid password");
+        err_api!(ApiErrorCode::InvalidPassword, "Invalid password");
     }
 
     if let Some(twofactor) = TwoFactor::find_by_user_and_type(&user.uuid, type_, &mut conn).await {
@@ -63,9 +63,12 @@ pub async fn send_token(user_id: &UserId, conn: &mut DbConn) -> EmptyResult {
     let type_ = TwoFactorType::Email as i32;
     let mut twofactor = TwoFactor::find_by_user_and_type(user_id, type_, conn).await.map_res("Two factor not found")?;
 
-    let generated_token = crypto::generate_email_token(CONFIG.email_token_size());
-
     let mut twofactor_data = EmailTokenData::from_json(&twofactor.data)?;
+    if twofactor_data.resend_cooldown_active() {
+        err!("A token was sent recently. Please wait before requesting a new one.")
+    }
+
+    let generated_token = crypto::generate_email_token(CONFIG.email_token_size());
     twofactor_data.set_token(generated_token);
     twofactor.data = twofactor_data.to_json();
     twofactor.save(conn).await?;
@@ -125,14 +128,22 @@ async fn send_email(data: Json<SendEmailData>, headers: Headers, mut conn: DbCon
         err!("Email 2FA is disabled")
     }
 
-    let type_ = TwoFactorType::Email as i32;
+    let address = data.email.trim().to_lowercase();
+    if !crate::util::is_valid_email(&address) {
+        err!("Invalid email address")
+    }
 
-    if let Some(tf) = TwoFactor::find_by_user_and_type(&user.uuid, type_, &mut conn).await {
-        tf.delete(&mut conn).await?;
+    if let Some(challenge) =
+        TwoFactor::find_by_user_and_type(&user.uuid, TwoFactorType::EmailVerificationChallenge as i32, &mut conn).await
+    {
+        if EmailTokenData::from_json(&challenge.data)?.resend_cooldown_active() {
+            err!("A token was sent recently. Please wait before requesting a new one.")
+        }
     }
 
+    // An already enabled email 2FA keeps working with the current address until the new address is verified
     let generated_token = crypto::generate_email_token(CONFIG.email_token_size());
-    let twofactor_data = EmailTokenData::new(data.email, generated_token);
+    let twofactor_data = EmailTokenData::new(address, generated_token);
 
     // Uses EmailVerificationChallenge as type to show that it's not verified yet.
     let twofactor = TwoFactor::new(user.uuid, TwoFactorType::EmailVerificationChallenge, twofactor_data.to_json());
@@ -180,6 +191,15 @@ async fn email(data: Json<EmailData>, headers: Headers, mut conn: DbConn) -> Jso
         err!("Token is invalid")
     }
 
+    if !email_data.email.eq_ignore_ascii_case(data.email.trim()) {
+        err!("The email address does not match the address the token was sent to")
+    }
+
+    // Replace the previously verified address, if any
+    if let Some(tf) = TwoFactor::find_by_user_and_type(&user.uuid, TwoFactorType::Email as i32, &mut conn).await {
+        tf.delete(&mut conn).await?;
+    }
+
     email_data.reset_token();
     twofactor.atype = TwoFactorType::Email as i32;
     twofactor.data = email_data.to_json();
@@ -269,9 +289,18 @@ impl EmailTokenData {
         }
     }
 
+    /// Replaces any previously issued token, which invalidates it, and resets the attempt counter.
     pub fn set_token(&mut self, token: String) {
         self.last_token = Some(token);
         self.token_sent = Utc::now().timestamp();
+        self.attempts = 0;
+    }
+
+    /// Returns true when a token was issued less than `EMAIL_TOKEN_RESEND_COOLDOWN` seconds ago.
+    /// The cooldown never outlasts the token, otherwise no valid token could be requested for a while.
+    pub fn resend_cooldown_active(&self) -> bool {
+        let cooldown = CONFIG.email_token_resend_cooldown().min(CONFIG.email_expiration_time()) as i64;
+        cooldown > 0 && self.last_token.is_some() && Utc::now().timestamp() - self.token_sent < cooldown
     }
 
     pub fn reset_token(&mut self) {
@@ -7,11 +7,12 @@ use serde_json::Value;
 use crate::{
     api::{
         core::{log_event, log_user_event},
-        EmptyResult, JsonResult, PasswordOrOtpData,
+        ApiResult, EmptyResult, JsonResult, PasswordOrOtpData,
     },
     auth::{ClientHeaders, Headers},
     crypto,
     db::{models::*, DbConn, DbPool},
+    error::{ApiErrorCode, Error, ErrorEvent},
     mail,
     util::NumberOrString,
     CONFIG,
@@ -29,9 +30,11 @@ pub fn routes() -> Vec<Route> {
     let mut routes = routes![
         get_twofactor,
         get_recover,
+        regenerate_recover,
         recover,
         disable_twofactor,
         disable_twofactor_put,
+        revoke_remembered_devices,
         get_device_verification_settings,
     ];
 
@@ -64,10 +67,43 @@ async fn get_recover(data: Json<PasswordOrOtpData>, headers: Headers, mut conn:
 
     data.validate(&user, true, &mut conn).await?;
 
-    Ok(Json(json!({
-        "code": user.totp_recover,
+    let codes = RecoveryCodesData::find_by_user(&user.uuid, &mut conn).await;
+    Ok(Json(recover_json(&user, codes)))
+}
+
+#[post("/two-factor/get-recover/regenerate", data = "<data>")]
+async fn regenerate_recover(data: Json<PasswordOrOtpData>, headers: Headers, mut conn: DbConn) -> JsonResult {
+    let data: PasswordOrOtpData = data.into_inner();
+    let mut user = headers.user;
+
+    data.validate(&user, true, &mut conn).await?;
+
+    if TwoFactor::find_by_user(&user.uuid, &mut conn).await.is_empty() {
+        err!("Two-step login is not enabled for this account")
+    }
+
+    // The new set replaces any previous codes, including a legacy single recovery code
+    let codes = RecoveryCodesData::generate();
+    codes.save(&user.uuid, &mut conn).await?;
+    if user.totp_recover.is_some() {
+        user.totp_recover = None;
+        user.save(&mut conn).await?;
+    }
+
+    Ok(Json(recover_json(&user, Some(codes))))
+}
+
+fn recover_json(user: &User, codes: Option<RecoveryCodesData>) -> Value {
+    let codes = codes.map(|c| c.codes).unwrap_or_default();
+    // Official clients only know about a single code, so also provide the first remaining one there
+    let code = codes.first().cloned().or_else(|| user.totp_recover.clone());
+
+    json!({
+        "code": code,
+        "codes": codes,
+        "remaining": codes.len(),
         "object": "twoFactorRecover"
-    })))
+    })
 }
 
 #[derive(Deserialize)]
@@ -95,12 +131,12 @@ async fn recover(data: Json<RecoverTwoFactor>, client_headers: ClientHeaders, mu
     }
 
     // Check if recovery code is correct
-    if !user.check_valid_recovery_code(&data.recovery_code) {
+    if !consume_recovery_code(&user.uuid, &data.recovery_code, &mut conn).await? {
         err!("Recovery code is incorrect. Try again.")
     }
 
     // Remove all twofactors from the user
-    TwoFactor::delete_all_by_user(&user.uuid, &mut conn).await?;
+    TwoFactor::delete_two_step_login_by_user(&user.uuid, &mut conn).await?;
     enforce_2fa_policy(&user, &user.uuid, client_headers.device_type, &client_headers.ip.ip, &mut conn).await?;
 
     log_user_event(
@@ -119,13 +155,77 @@ async fn recover(data: Json<RecoverTwoFactor>, client_headers: ClientHeaders, mu
 }
 
 async fn _generate_recover_code(user: &mut User, conn: &mut DbConn) {
-    if user.totp_recover.is_none() {
-        let totp_recover = crypto::encode_random_bytes::<20>(BASE32);
-        user.totp_recover = Some(totp_recover);
-        user.save(conn).await.ok();
+    if user.totp_recover.is_none() && RecoveryCodesData::find_by_user(&user.uuid, conn).await.is_none() {
+        RecoveryCodesData::generate().save(&user.uuid, conn).await.ok();
+    }
+}
+
+/// Number of single-use recovery codes generated for a user.
+const RECOVERY_CODE_COUNT: usize = 10;
+
+#[derive(Deserialize, Serialize)]
+struct RecoveryCodesData {
+    codes: Vec<String>,
+}
+
+impl RecoveryCodesData {
+    fn generate() -> Self {
+        Self {
+            codes: (0..RECOVERY_CODE_COUNT).map(|_| crypto::encode_random_bytes::<10>(BASE32)).collect(),
+        }
+    }
+
+    async fn find_by_user(user_id: &UserId, conn: &mut DbConn) -> Option<Self> {
+        let twofactor = TwoFactor::find_by_user_and_type(user_id, TwoFactorType::RecoveryCodes as i32, conn).await?;
+        serde_json::from_str(&twofactor.data).ok()
+    }
+
+    async fn save(&self, user_id: &UserId, conn: &mut DbConn) -> EmptyResult {
+        let data = serde_json::to_string(self)?;
+        match TwoFactor::find_by_user_and_type(user_id, TwoFactorType::RecoveryCodes as i32, conn).await {
+            Some(mut twofactor) => {
+                twofactor.data = data;
+                twofactor.save(conn).await
+            }
+            None => TwoFactor::new(user_id.clone(), TwoFactorType::RecoveryCodes, data).save(conn).await,
+        }
     }
 }
 
+/// Checks the given recovery code and, if valid, removes it so it can't be used again.
+/// Also accepts the legacy single recovery code of users who haven't regenerated their codes yet.
+pub async fn consume_recovery_code(user_id: &UserId, recovery_code: &str, conn: &mut DbConn) -> ApiResult<bool> {
+    let recovery_code = recovery_code.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_lowercase();
+    if recovery_code.is_empty() {
+        return Ok(false);
+    }
+
+    if let Some(mut codes) = RecoveryCodesData::find_by_user(user_id, conn).await {
+        if let Some(pos) = codes.codes.iter().position(|c| crypto::ct_eq(&recovery_code, c.to_lowercase())) {
+            codes.codes.remove(pos);
+            codes.save(user_id, conn).await?;
+            return Ok(true);
+        }
+    }
+
+    if let Some(mut user) = User::find_by_uuid(user_id, conn).await {
+        if user.check_valid_recovery_code(&recovery_code) {
+            user.totp_recover = None;
+            user.save(conn).await?;
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// Forgets all devices on which the user chose to remember the second factor,
+/// so every device has to complete a two-step login again.
+#[post("/two-factor/revoke-remembered-devices")]
+async fn revoke_remembered_devices(headers: Headers, mut conn: DbConn) -> EmptyResult {
+    Device::clear_twofactor_remember_by_user(&headers.user.uuid, &mut conn).await
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct DisableTwoFactorData {
@@ -242,6 +342,92 @@ pub async fn enforce_2fa_policy_for_org(
     Ok(())
 }
 
+#[derive(Default, Deserialize, Serialize)]
+struct TwoFactorLockoutData {
+    attempts: u32,
+    locked_until: i64,
+}
+
+/// Returns an error if the second factor of the user is temporarily locked because of too many failed attempts.
+pub async fn check_twofactor_lockout(user_id: &UserId, conn: &mut DbConn) -> EmptyResult {
+    if CONFIG.twofactor_lockout_attempts() == 0 {
+        return Ok(());
+    }
+
+    if let Some(twofactor) = TwoFactor::find_by_user_and_type(user_id, TwoFactorType::LoginLockout as i32, conn).await {
+        let data: TwoFactorLockoutData = serde_json::from_str(&twofactor.data).unwrap_or_default();
+        let remaining = data.locked_until - Utc::now().timestamp();
+        if remaining > 0 {
+            let msg = "Two-step login is temporarily locked because of too many failed attempts. Try again later.";
+            error!("{msg}");
+            // Answered like the other rate limits, with a 429 and the time after which it can be tried again
+            return Err(Error::new(msg, msg)
+                .with_api_code(ApiErrorCode::TwoFactorLocked)
+                .with_retry_after(remaining as u64)
+                .with_event(ErrorEvent {
+                    event: EventType::UserFailedLogIn2fa,
+                }));
+        }
+    }
+
+    Ok(())
+}
+
+/// Registers a failed two-step login attempt, and locks the second factor once the configured limit is reached.
+pub async fn register_twofactor_failure(
+    user: &User,
+    device: &Device,
+    ip: &std::net::IpAddr,
+    conn: &mut DbConn,
+) -> EmptyResult {
+    let max_attempts = CONFIG.twofactor_lockout_attempts();
+    if max_attempts == 0 {
+        return Ok(());
+    }
+
+    let mut twofactor = TwoFactor::find_by_user_and_type(&user.uuid, TwoFactorType::LoginLockout as i32, conn)
+        .await
+        .unwrap_or_else(|| TwoFactor::new(user.uuid.clone(), TwoFactorType::LoginLockout, String::new()));
+    let mut data: TwoFactorLockoutData = serde_json::from_str(&twofactor.data).unwrap_or_default();
+
+    data.attempts += 1;
+    let locked = data.attempts >= max_attempts;
+    if locked {
+        data.attempts = 0;
+        data.locked_until = Utc::now().timestamp() + CONFIG.twofactor_lockout_duration();
+    }
+
+    twofactor.data = serde_json::to_string(&data)?;
+    twofactor.save(conn).await?;
+
+    if locked {
+        warn!("Two-step login of user {} locked after {max_attempts} failed attempts. IP: {ip}", user.email);
+        if CONFIG.mail_enabled() {
+            if let Err(e) = mail::send_twofactor_lockout(
+                &user.email,
+                &ip.to_string(),
+                &Utc::now().naive_utc(),
+                &device.name,
+                &DeviceType::from_i32(device.atype).to_string(),
+            )
+            .await
+            {
+                error!("Error sending two-step login lockout email: {e:#?}");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Clears the failed two-step login attempts after a successful login.
+pub async fn reset_twofactor_failures(user_id: &UserId, conn: &mut DbConn) -> EmptyResult {
+    match TwoFactor::find_by_user_and_type(user_id, TwoFactorType::LoginLockout as i32, conn).await {
+        Some(twofactor) => twofactor.delete(conn).await,
+        None => Ok(()),
+    }
+}
+
 pub async fn send_incomplete_2fa_notifications(pool: DbPool) {
     debug!("Sending notifications for incomplete 2FA logins");
 
@@ -9,7 +9,7 @@ use crate::{
         models::{TwoFactor, TwoFactorType, UserId},
         DbConn,
     },
-    error::{Error, MapResult},
+    error::{ApiErrorCode, Error, MapResult},
     mail, CONFIG,
 };
 
@@ -17,6 +17,28 @@ pub fn routes() -> Vec<Route> {
     routes![request_otp, verify_otp]
 }
 
+/// Remembers that the user just verified themselves, which allows them to perform protected actions for a short while.
+/// The time is kept in the database, so it survives a restart and applies to all the instances using it.
+pub async fn mark_fresh_verification(user_id: &UserId, conn: &mut DbConn) -> EmptyResult {
+    let mut twofactor = TwoFactor::find_by_user_and_type(user_id, TwoFactorType::FreshVerification as i32, conn)
+        .await
+        .unwrap_or_else(|| TwoFactor::new(user_id.clone(), TwoFactorType::FreshVerification, String::new()));
+    twofactor.last_used = Utc::now().timestamp();
+    twofactor.save(conn).await
+}
+
+pub async fn check_fresh_verification(user_id: &UserId, conn: &mut DbConn) -> EmptyResult {
+    let verified_at = TwoFactor::find_by_user_and_type(user_id, TwoFactorType::FreshVerification as i32, conn)
+        .await
+        .map_or(0, |twofactor| twofactor.last_used);
+    let window = i64::try_from(CONFIG.protected_action_window_secs()).unwrap_or(i64::MAX);
+    if Utc::now().timestamp().saturating_sub(verified_at) < window {
+        Ok(())
+    } else {
+        err_api!(ApiErrorCode::VerificationRequired, "This action requires you to verify your identity again")
+    }
+}
+
 /// Data stored in the TwoFactor table in the db
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ProtectedActionData {
@@ -118,7 +140,7 @@ pub async fn validate_protected_action_otp(
     // We use the 6, which should be more then enough for invalid attempts and multiple valid checks
     if pa_data.attempts > 6 {
         pa.delete(conn).await?;
-        err!("Token has expired")
+        err_api!(ApiErrorCode::InvalidToken, "Token has expired")
     }
 
     // Check if the token has expired (Using the email 2fa expiration time)
@@ -127,17 +149,17 @@ pub async fn validate_protected_action_otp(
     let max_time = CONFIG.email_expiration_time() as i64;
     if date + TimeDelta::try_seconds(max_time).unwrap() < Utc::now().naive_utc() {
         pa.delete(conn).await?;
-        err!("Token has expired")
+        err_api!(ApiErrorCode::InvalidToken, "Token has expired")
     }
 
     if !crypto::ct_eq(&pa_data.token, otp) {
         pa.save(conn).await?;
-        err!("Token is invalid")
+        err_api!(ApiErrorCode::InvalidToken, "Token is invalid")
     }
 
     if delete_if_valid {
         pa.delete(conn).await?;
     }
 
-    Ok(())
+    mark_fresh_verification(user_id, conn).await
 }
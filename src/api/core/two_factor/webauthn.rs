@@ -14,7 +14,7 @@ use crate::{
         models::{EventType, TwoFactor, TwoFactorType, UserId},
         DbConn,
     },
-    error::Error,
+    error::{ApiErrorCode, Error},
     util::NumberOrString,
     CONFIG,
 };
@@ -45,18 +45,23 @@ pub struct U2FRegistration {
     pub migrated: Option<bool>,
 }
 
-struct WebauthnConfig {
+pub struct WebauthnConfig {
     url: String,
     origin: Url,
     rpid: String,
 }
 
+/// The relying party id is the domain Vaultwarden is served on, without scheme, port and path.
+pub fn relying_party_id() -> String {
+    Url::parse(&CONFIG.domain()).map(|u| u.domain().map(str::to_owned)).ok().flatten().unwrap_or_default()
+}
+
 impl WebauthnConfig {
-    fn load() -> Webauthn<Self> {
+    pub fn load() -> Webauthn<Self> {
         let domain = CONFIG.domain();
         let domain_origin = CONFIG.domain_origin();
         Webauthn::new(Self {
-            rpid: Url::parse(&domain).map(|u| u.domain().map(str::to_owned)).ok().flatten().unwrap_or_default(),
+            rpid: relying_party_id(),
             url: domain,
             origin: Url::parse(&domain_origin).unwrap(),
         })
@@ -168,7 +173,7 @@ struct EnableWebauthnData {
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct RegisterPublicKeyCredentialCopy {
+pub struct RegisterPublicKeyCredentialCopy {
     pub id: String,
     pub raw_id: Base64UrlSafeData,
     pub response: AuthenticatorAttestationResponseRawCopy,
@@ -306,7 +311,7 @@ struct DeleteU2FData {
 async fn delete_webauthn(data: Json<DeleteU2FData>, headers: Headers, mut conn: DbConn) -> JsonResult {
     let id = data.id.into_i32()?;
     if !headers.user.check_valid_password(&data.master_password_hash) {
-        err!("Invalid password");
+        err_api!(ApiErrorCode::InvalidPassword, "Invalid password");
     }
 
     let Some(mut tf) =
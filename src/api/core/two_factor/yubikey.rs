@@ -75,8 +75,12 @@ async fn verify_yubikey_otp(otp: String) -> EmptyResult {
 
     let config = Config::default().set_client_id(yubico_id).set_key(yubico_secret);
 
+    // Custom servers replace the official YubiCloud servers, which allows air-gapped deployments
     match CONFIG.yubico_server() {
-        Some(server) => verify_async(otp, config.set_api_hosts(vec![server])).await,
+        Some(servers) => {
+            let api_hosts = servers.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect();
+            verify_async(otp, config.set_api_hosts(api_hosts)).await
+        }
         None => verify_async(otp, config).await,
     }
     .map_res("Failed to verify OTP")
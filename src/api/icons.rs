@@ -167,6 +167,7 @@ async fn get_icon(domain: &str) -> Option<(Vec<u8>, String)> {
     }
 
     if let Some(icon) = get_cached_icon(&path).await {
+        crate::usage_stats::record_icon(true);
         let icon_type = match get_icon_type(&icon) {
             Some(x) => x,
             _ => "x-icon",
@@ -178,6 +179,8 @@ async fn get_icon(domain: &str) -> Option<(Vec<u8>, String)> {
         return None;
     }
 
+    crate::usage_stats::record_icon(false);
+
     // Get the icon, or None in case of error
     match download_icon(domain).await {
         Ok((icon, icon_type)) => {
@@ -569,6 +572,7 @@ async fn download_icon(domain: &str) -> Result<(Bytes, Option<&str>), Error> {
 }
 
 async fn save_icon(path: &str, icon: &[u8]) {
+    let _io_permit = crate::util::acquire_io_permit(crate::util::FileStore::Icons).await;
     match File::create(path).await {
         Ok(mut f) => {
             f.write_all(icon).await.expect("Error writing icon file");
@@ -10,21 +10,24 @@ use serde_json::Value;
 use crate::{
     api::{
         core::{
-            accounts::{PreloginData, RegisterData, _prelogin, _register},
-            log_user_event,
-            two_factor::{authenticator, duo, duo_oidc, email, enforce_2fa_policy, webauthn, yubikey},
+            accounts::{_prelogin, _register, record_login_ip, PreloginData, RegisterData},
+            check_device_approval, is_verified_domain_signup_allowed, log_user_event, passkeys,
+            two_factor::{
+                authenticator, check_twofactor_lockout, consume_recovery_code, duo, duo_oidc, email,
+                enforce_2fa_policy, register_twofactor_failure, reset_twofactor_failures, webauthn, yubikey,
+            },
         },
         push::register_push_device,
         ApiResult, EmptyResult, JsonResult,
     },
     auth::{generate_organization_api_key_login_claims, ClientHeaders, ClientIp},
     db::{models::*, DbConn},
-    error::MapResult,
+    error::{ErrorEvent, MapResult},
     mail, util, CONFIG,
 };
 
 pub fn routes() -> Vec<Route> {
-    routes![login, prelogin, identity_register, register_verification_email, register_finish]
+    routes![login, prelogin, identity_register, register_verification_email, register_finish, passkey_login_options]
 }
 
 #[post("/connect/token", data = "<data>")]
@@ -61,6 +64,18 @@ async fn login(data: Form<ConnectData>, client_header: ClientHeaders, mut conn:
 
             _api_key_login(data, &mut user_id, &mut conn, &client_header.ip).await
         }
+        "webauthn" => {
+            _check_is_some(&data.client_id, "client_id cannot be blank")?;
+            _check_is_some(&data.scope, "scope cannot be blank")?;
+            _check_is_some(&data.token, "token cannot be blank")?;
+            _check_is_some(&data.device_response, "device_response cannot be blank")?;
+
+            _check_is_some(&data.device_identifier, "device_identifier cannot be blank")?;
+            _check_is_some(&data.device_name, "device_name cannot be blank")?;
+            _check_is_some(&data.device_type, "device_type cannot be blank")?;
+
+            _passkey_login(data, &mut user_id, &mut conn, &client_header.ip).await
+        }
         t => err!("Invalid type", t),
     };
 
@@ -99,19 +114,52 @@ async fn _refresh_login(data: ConnectData, conn: &mut DbConn) -> JsonResult {
     let token = data.refresh_token.unwrap();
 
     // Get device by refresh token
-    let mut device = Device::find_by_refresh_token(&token, conn).await.map_res("Invalid refresh token")?;
+    // Revoked sessions have an empty refresh token
+    if token.is_empty() {
+        err!("Invalid refresh token")
+    }
+    let (mut device, rotate) = match Device::find_by_refresh_token(&token, conn).await {
+        Some(device) => (device, CONFIG.refresh_token_rotation()),
+        None => {
+            let Some((family, _)) = token.split_once('.') else {
+                err!("Invalid refresh token")
+            };
+            let Some(mut device) = Device::find_by_refresh_token_family(family, conn).await else {
+                err!("Invalid refresh token")
+            };
+            // The token replaced by a concurrent refresh, hand out the current one again instead of rotating twice
+            if !device.check_previous_refresh_token(&token) {
+                // A stale token of a known session was either stolen or replayed, revoke the whole session
+                warn!("Reuse of a rotated refresh token of device {}, revoking its session", device.uuid);
+                device.revoke_refresh_token();
+                device.save(conn).await?;
+                err!("Invalid refresh token")
+            }
+            (device, false)
+        }
+    };
 
     let scope = "api offline_access";
     let scope_vec = vec!["api".into(), "offline_access".into()];
 
     // Common
     let user = User::find_by_uuid(&device.user_uuid, conn).await.unwrap();
+    if user.delete_scheduled_at.is_some() {
+        err!("This account has been deleted")
+    }
+    // Disabling an account removes its devices, but a refresh can race with it
+    if !user.enabled {
+        err!("This user has been disabled")
+    }
     // ---
     // Disabled this variable, it was used to generate the JWT
     // Because this might get used in the future, and is add by the Bitwarden Server, lets keep it, but then commented out
     // See: https://github.com/dani-garcia/vaultwarden/issues/4156
     // ---
     // let members = Membership::find_confirmed_by_user(&user.uuid, conn).await;
+    if rotate {
+        device.rotate_refresh_token();
+    }
     let (access_token, expires_in) = device.refresh_tokens(&user, scope_vec);
     device.save(conn).await?;
 
@@ -127,7 +175,7 @@ async fn _refresh_login(data: ConnectData, conn: &mut DbConn) -> JsonResult {
     Ok(Json(result))
 }
 
-#[derive(Default, Deserialize, Serialize)]
+#[derive(Default, Deserialize, Serialize, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 struct MasterPasswordPolicy {
     min_complexity: u8,
@@ -139,6 +187,69 @@ struct MasterPasswordPolicy {
     enforce_on_login: bool,
 }
 
+impl MasterPasswordPolicy {
+    /// The instance wide policy configured by the administrator, if it has any requirement.
+    fn from_config() -> Option<Self> {
+        let policy = Self {
+            min_complexity: CONFIG.master_password_min_complexity(),
+            min_length: CONFIG.master_password_min_length(),
+            require_lower: CONFIG.master_password_require_lower(),
+            require_upper: CONFIG.master_password_require_upper(),
+            require_numbers: CONFIG.master_password_require_numbers(),
+            require_special: CONFIG.master_password_require_special(),
+            enforce_on_login: CONFIG.master_password_enforce_on_login(),
+        };
+        (policy != Self::default()).then_some(policy)
+    }
+
+    fn merge(self, other: Self) -> Self {
+        Self {
+            min_complexity: self.min_complexity.max(other.min_complexity),
+            min_length: self.min_length.max(other.min_length),
+            require_lower: self.require_lower || other.require_lower,
+            require_upper: self.require_upper || other.require_upper,
+            require_numbers: self.require_numbers || other.require_numbers,
+            require_special: self.require_special || other.require_special,
+            enforce_on_login: self.enforce_on_login || other.enforce_on_login,
+        }
+    }
+}
+
+/// Merges the given organization policies with the instance wide policy into the policy object the clients expect.
+/// The server only receives a hash of the master password, so the clients are the ones enforcing the requirements.
+fn master_password_policy_json(org_policies: Vec<MasterPasswordPolicy>) -> Value {
+    let merged =
+        org_policies.into_iter().chain(MasterPasswordPolicy::from_config()).reduce(MasterPasswordPolicy::merge);
+    let mut mpp_json = match merged {
+        Some(policy) => json!(policy),
+        None => json!({}),
+    };
+    mpp_json["object"] = json!("masterPasswordPolicy");
+    mpp_json
+}
+
+/// The master password policy which applies to the user, including the policies of the organizations of the user.
+/// Returned on login, so the clients can ask for a compliant password when the policy is enforced on login.
+async fn user_master_password_policy_json(user: &User, conn: &mut DbConn) -> Value {
+    // Fetch all valid Master Password Policies and merge them into one with all true's and larges numbers as one policy
+    let master_password_policies: Vec<MasterPasswordPolicy> =
+        OrgPolicy::find_accepted_and_confirmed_by_user_and_active_policy(
+            &user.uuid,
+            OrgPolicyType::MasterPassword,
+            conn,
+        )
+        .await
+        .into_iter()
+        .filter_map(|p| serde_json::from_str(&p.data).ok())
+        .collect();
+    master_password_policy_json(master_password_policies)
+}
+
+/// The instance wide master password policy, returned during prelogin and registration so the clients can show it.
+pub fn instance_master_password_policy_json() -> Value {
+    master_password_policy_json(Vec::new())
+}
+
 async fn _password_login(
     data: ConnectData,
     user_id: &mut Option<UserId>,
@@ -150,7 +261,6 @@ async fn _password_login(
     if scope != "api offline_access" {
         err!("Scope not supported")
     }
-    let scope_vec = vec!["api".into(), "offline_access".into()];
 
     // Ratelimit the login
     crate::ratelimit::check_limit_login(&ip.ip)?;
@@ -158,21 +268,24 @@ async fn _password_login(
     // Get the user
     let username = data.username.as_ref().unwrap().trim();
     let Some(mut user) = User::find_by_mail(username, conn).await else {
+        if CONFIG.anti_enumeration() {
+            // Back off and spend the same time as for the password of an existing account
+            let backoff_key = crate::ratelimit::unknown_account_backoff_key(username, &ip.ip);
+            crate::ratelimit::check_backoff(&backoff_key, "Too many failed login attempts. Try again later.", conn)
+                .await?;
+            let password = data.password.as_deref().unwrap_or_default();
+            crate::crypto::hash_password(password.as_bytes(), &[0u8; 64], CONFIG.password_iterations() as u32);
+            crate::ratelimit::register_failure(&backoff_key, conn).await?;
+        }
         err!("Username or password is incorrect. Try again", format!("IP: {}. Username: {}.", ip.ip, username))
     };
 
     // Set the user_id here to be passed back used for event logging.
     *user_id = Some(user.uuid.clone());
 
-    // Check if the user is disabled
-    if !user.enabled {
-        err!(
-            "This user has been disabled",
-            format!("IP: {}. Username: {}.", ip.ip, username),
-            ErrorEvent {
-                event: EventType::UserFailedLogIn
-            }
-        )
+    // When hiding which accounts exist, the state of the account is only reported after a correct password
+    if !CONFIG.anti_enumeration() {
+        check_account_state(&user, username, ip)?;
     }
 
     let password = data.password.as_ref().unwrap();
@@ -189,12 +302,9 @@ async fn _password_login(
             )
         };
 
-        let expiration_time = auth_request.creation_date + chrono::Duration::minutes(5);
-        let request_expired = Utc::now().naive_utc() >= expiration_time;
-
         if auth_request.user_uuid != user.uuid
             || !auth_request.approved.unwrap_or(false)
-            || request_expired
+            || auth_request.is_expired()
             || ip.ip.to_string() != auth_request.request_ip
             || !auth_request.check_access_code(password)
         {
@@ -206,18 +316,34 @@ async fn _password_login(
                 }
             )
         }
-    } else if !user.check_valid_password(password) {
-        err!(
-            "Username or password is incorrect. Try again",
-            format!("IP: {}. Username: {}.", ip.ip, username),
-            ErrorEvent {
-                event: EventType::UserFailedLogIn,
-            }
-        )
+    } else {
+        // Don't even check the password while the account is backed off because of failed attempts,
+        // an approved auth request is not affected as it already required a logged in device
+        check_login_backoff(&user, ip, conn).await?;
+        if !user.check_valid_password(password) {
+            register_login_failure(&user, ip, conn).await?;
+            err!(
+                "Username or password is incorrect. Try again",
+                format!("IP: {}. Username: {}.", ip.ip, username),
+                ErrorEvent {
+                    event: EventType::UserFailedLogIn,
+                }
+            )
+        }
     }
 
-    // Change the KDF Iterations (only when not logging in with an auth request)
-    if data.auth_request.is_none() && user.password_iterations != CONFIG.password_iterations() {
+    if CONFIG.anti_enumeration() {
+        check_account_state(&user, username, ip)?;
+    }
+
+    reset_login_failures(&user, ip, conn).await?;
+
+    // When a directory is configured, the user also needs to be allowed to log in there
+    check_ldap_directory(&user, ip).await?;
+
+    // Change the KDF Iterations, or replace an imported SHA512 hash (only when not logging in with an auth request)
+    if data.auth_request.is_none() && (user.password_iterations != CONFIG.password_iterations() || user.password_sha512)
+    {
         user.password_iterations = CONFIG.password_iterations();
         user.set_password(password, None, false, None);
 
@@ -226,46 +352,138 @@ async fn _password_login(
         }
     }
 
-    let now = Utc::now().naive_utc();
+    check_email_verified(&mut user, username, ip, conn).await?;
 
-    if user.verified_at.is_none() && CONFIG.mail_enabled() && CONFIG.signups_verify() {
-        if user.last_verifying_at.is_none()
-            || now.signed_duration_since(user.last_verifying_at.unwrap()).num_seconds()
-                > CONFIG.signups_verify_resend_time() as i64
-        {
-            let resend_limit = CONFIG.signups_verify_resend_limit() as i32;
-            if resend_limit == 0 || user.login_verify_count < resend_limit {
-                // We want to send another email verification if we require signups to verify
-                // their email address, and we haven't sent them a reminder in a while...
-                user.last_verifying_at = Some(now);
-                user.login_verify_count += 1;
-
-                if let Err(e) = user.save(conn).await {
-                    error!("Error updating user: {:#?}", e);
-                }
+    let (mut device, new_device) = get_device(&data, conn, &user).await;
 
-                if let Err(e) = mail::send_verify_email(&user.email, &user.uuid).await {
-                    error!("Error auto-sending email verification email: {:#?}", e);
-                }
+    let twofactor_token = twofactor_auth(&user, &data, &mut device, ip, conn).await?;
+
+    // A login with an approved auth request was already approved by an existing device
+    check_login_device(&user, &mut device, new_device, data.auth_request.is_none(), ip, conn).await?;
+
+    let mut result = login_result(&user, &mut device, scope, conn).await?;
+
+    if let Some(token) = twofactor_token {
+        result["TwoFactorToken"] = Value::String(token);
+    }
+
+    info!("User {} logged in successfully. IP: {}", username, ip.ip);
+    Ok(Json(result))
+}
+
+async fn _passkey_login(
+    data: ConnectData,
+    user_id: &mut Option<UserId>,
+    conn: &mut DbConn,
+    ip: &ClientIp,
+) -> JsonResult {
+    // Validate scope
+    let scope = data.scope.as_ref().unwrap();
+    if scope != "api offline_access" {
+        err!("Scope not supported")
+    }
+
+    // Ratelimit the login
+    crate::ratelimit::check_limit_login(&ip.ip)?;
+
+    let (mut user, passkey) =
+        passkeys::validate_passkey_login(data.token.as_ref().unwrap(), data.device_response.as_ref().unwrap(), conn)
+            .await?;
+
+    // Set the user_id here to be passed back used for event logging.
+    *user_id = Some(user.uuid.clone());
+
+    // A passkey does not lift the backoff caused by failed password attempts
+    check_login_backoff(&user, ip, conn).await?;
+
+    let username = user.email.clone();
+    check_account_state(&user, &username, ip)?;
+
+    // The same checks as after a password login
+    check_ldap_directory(&user, ip).await?;
+
+    // A passkey proves the account, but not the ownership of its email address
+    check_email_verified(&mut user, &username, ip, conn).await?;
+
+    let (mut device, new_device) = get_device(&data, conn, &user).await;
+
+    // Note that a passkey login bypasses 2FA, the passkey itself is verified with user verification.
+    check_login_device(&user, &mut device, new_device, true, ip, conn).await?;
+
+    let mut result = login_result(&user, &mut device, scope, conn).await?;
+
+    // Allows the client to decrypt the vault with the PRF extension, without asking for the master password
+    if let Some(prf_option) = passkey.prf_decryption_option() {
+        result["UserDecryptionOptions"]["WebAuthnPrfOption"] = prf_option;
+    }
+
+    info!("User {} logged in successfully with a passkey. IP: {}", username, ip.ip);
+    Ok(Json(result))
+}
+
+/// Fails the login as long as the email address of the user needs to be verified,
+/// resending the verification email from time to time.
+async fn check_email_verified(user: &mut User, username: &str, ip: &ClientIp, conn: &mut DbConn) -> EmptyResult {
+    if user.verified_at.is_some() || !CONFIG.mail_enabled() || !CONFIG.signups_verify() {
+        return Ok(());
+    }
+
+    let now = Utc::now().naive_utc();
+    if user.last_verifying_at.is_none()
+        || now.signed_duration_since(user.last_verifying_at.unwrap()).num_seconds()
+            > CONFIG.signups_verify_resend_time() as i64
+    {
+        let resend_limit = CONFIG.signups_verify_resend_limit() as i32;
+        if resend_limit == 0 || user.login_verify_count < resend_limit {
+            // We want to send another email verification if we require signups to verify
+            // their email address, and we haven't sent them a reminder in a while...
+            user.last_verifying_at = Some(now);
+            user.login_verify_count += 1;
+
+            if let Err(e) = user.save(conn).await {
+                error!("Error updating user: {:#?}", e);
             }
-        }
 
-        // We still want the login to fail until they actually verified the email address
-        err!(
-            "Please verify your email before trying again.",
-            format!("IP: {}. Username: {}.", ip.ip, username),
-            ErrorEvent {
-                event: EventType::UserFailedLogIn
+            if let Err(e) = mail::send_verify_email(&user.email, &user.uuid).await {
+                error!("Error auto-sending email verification email: {:#?}", e);
             }
-        )
+        }
     }
 
-    let (mut device, new_device) = get_device(&data, conn, &user).await;
+    // We still want the login to fail until they actually verified the email address
+    err!(
+        "Please verify your email before trying again.",
+        format!("IP: {}. Username: {}.", ip.ip, username),
+        ErrorEvent {
+            event: EventType::UserFailedLogIn
+        }
+    )
+}
 
-    let twofactor_token = twofactor_auth(&user, &data, &mut device, ip, conn).await?;
+/// The device checks of an authenticated login, these have to run after the second factor,
+/// so the errors do not reveal that the first factor was correct.
+async fn check_login_device(
+    user: &User,
+    device: &mut Device,
+    new_device: bool,
+    require_approval: bool,
+    ip: &ClientIp,
+    conn: &mut DbConn,
+) -> EmptyResult {
+    OrgPolicy::check_device_enrollment(&user.uuid, device, new_device, conn).await.map_err(|e| {
+        e.with_event(ErrorEvent {
+            event: EventType::UserFailedLogIn,
+        })
+    })?;
+
+    if new_device && require_approval {
+        check_device_approval(user, device, ip, conn).await?;
+    }
+    record_login_ip(&user.uuid, ip, conn).await?;
 
     if CONFIG.mail_enabled() && new_device {
-        if let Err(e) = mail::send_new_device_logged_in(&user.email, &ip.ip.to_string(), &now, &device).await {
+        let now = Utc::now().naive_utc();
+        if let Err(e) = mail::send_new_device_logged_in(&user.email, &ip.ip.to_string(), &now, device).await {
             error!("Error sending new device email: {:#?}", e);
 
             if CONFIG.require_device_email() {
@@ -281,8 +499,14 @@ async fn _password_login(
 
     // register push device
     if !new_device {
-        register_push_device(&mut device, conn).await?;
+        register_push_device(device, conn).await?;
     }
+    Ok(())
+}
+
+/// Starts a new session on the device and returns the tokens and keys of the user.
+async fn login_result(user: &User, device: &mut Device, scope: &str, conn: &mut DbConn) -> ApiResult<Value> {
+    let scope_vec = vec!["api".into(), "offline_access".into()];
 
     // Common
     // ---
@@ -291,38 +515,14 @@ async fn _password_login(
     // See: https://github.com/dani-garcia/vaultwarden/issues/4156
     // ---
     // let members = Membership::find_confirmed_by_user(&user.uuid, conn).await;
-    let (access_token, expires_in) = device.refresh_tokens(&user, scope_vec);
+    // Every login starts a new session, which invalidates the previous refresh token of the device
+    if CONFIG.refresh_token_rotation() {
+        device.start_refresh_token_family();
+    }
+    let (access_token, expires_in) = device.refresh_tokens(user, scope_vec);
     device.save(conn).await?;
 
-    // Fetch all valid Master Password Policies and merge them into one with all true's and larges numbers as one policy
-    let master_password_policies: Vec<MasterPasswordPolicy> =
-        OrgPolicy::find_accepted_and_confirmed_by_user_and_active_policy(
-            &user.uuid,
-            OrgPolicyType::MasterPassword,
-            conn,
-        )
-        .await
-        .into_iter()
-        .filter_map(|p| serde_json::from_str(&p.data).ok())
-        .collect();
-
-    let master_password_policy = if !master_password_policies.is_empty() {
-        let mut mpp_json = json!(master_password_policies.into_iter().reduce(|acc, policy| {
-            MasterPasswordPolicy {
-                min_complexity: acc.min_complexity.max(policy.min_complexity),
-                min_length: acc.min_length.max(policy.min_length),
-                require_lower: acc.require_lower || policy.require_lower,
-                require_upper: acc.require_upper || policy.require_upper,
-                require_numbers: acc.require_numbers || policy.require_numbers,
-                require_special: acc.require_special || policy.require_special,
-                enforce_on_login: acc.enforce_on_login || policy.enforce_on_login,
-            }
-        }));
-        mpp_json["object"] = json!("masterPasswordPolicy");
-        mpp_json
-    } else {
-        json!({"object": "masterPasswordPolicy"})
-    };
+    let master_password_policy = user_master_password_policy_json(user, conn).await;
 
     let mut result = json!({
         "access_token": access_token,
@@ -338,7 +538,7 @@ async fn _password_login(
         "KdfMemory": user.client_kdf_memory,
         "KdfParallelism": user.client_kdf_parallelism,
         "ResetMasterPassword": false, // TODO: Same as above
-        "ForcePasswordReset": false,
+        "ForcePasswordReset": user.force_password_reset,
         "MasterPasswordPolicy": master_password_policy,
 
         "scope": scope,
@@ -348,12 +548,105 @@ async fn _password_login(
         },
     });
 
-    if let Some(token) = twofactor_token {
-        result["TwoFactorToken"] = Value::String(token);
+    if let Some(trusted_device_option) = trusted_device_option(user, device, conn).await {
+        result["UserDecryptionOptions"]["TrustedDeviceOption"] = trusted_device_option;
     }
 
-    info!("User {} logged in successfully. IP: {}", username, ip.ip);
-    Ok(Json(result))
+    Ok(result)
+}
+
+/// Returns the keys of a trusted device, allowing the client to unlock the vault with its device key.
+/// Approvals by organization admins are not supported, only the other devices of the user can approve a login.
+async fn trusted_device_option(user: &User, device: &Device, conn: &mut DbConn) -> Option<Value> {
+    if !device.is_trusted() {
+        return None;
+    }
+
+    let has_login_approving_device =
+        Device::find_by_user(&user.uuid, conn).await.iter().any(|d| d.uuid != device.uuid && !d.is_cli());
+
+    Some(json!({
+        "HasAdminApproval": false,
+        "HasLoginApprovingDevice": has_login_approving_device,
+        "HasManageResetPasswordPermission": false,
+        "EncryptedPrivateKey": device.encrypted_private_key,
+        "EncryptedUserKey": device.encrypted_user_key,
+    }))
+}
+
+fn check_account_state(user: &User, username: &str, ip: &ClientIp) -> EmptyResult {
+    // Check if the user is disabled
+    if !user.enabled {
+        err!(
+            "This user has been disabled",
+            format!("IP: {}. Username: {}.", ip.ip, username),
+            ErrorEvent {
+                event: EventType::UserFailedLogIn
+            }
+        )
+    }
+
+    // Accounts which are scheduled for deletion can only be restored via the emailed link
+    if user.delete_scheduled_at.is_some() {
+        err!(
+            "This account has been deleted",
+            format!("IP: {}. Username: {}.", ip.ip, username),
+            ErrorEvent {
+                event: EventType::UserFailedLogIn
+            }
+        )
+    }
+    Ok(())
+}
+
+/// Returns an error with a `Retry-After` header while the logins of the account from this IP address,
+/// or from all IP addresses together, are backed off.
+async fn check_login_backoff(user: &User, ip: &ClientIp, conn: &mut DbConn) -> EmptyResult {
+    crate::ratelimit::check_account_backoff(
+        &user.uuid,
+        &ip.ip,
+        "Too many failed login attempts. Try again later.",
+        conn,
+    )
+    .await
+    .map_err(|e| {
+        e.with_event(ErrorEvent {
+            event: EventType::UserFailedLogIn,
+        })
+    })
+}
+
+async fn register_login_failure(user: &User, ip: &ClientIp, conn: &mut DbConn) -> EmptyResult {
+    crate::ratelimit::register_account_failure(&user.uuid, &ip.ip, conn).await
+}
+
+async fn reset_login_failures(user: &User, ip: &ClientIp, conn: &mut DbConn) -> EmptyResult {
+    crate::ratelimit::reset_account_failures(&user.uuid, &ip.ip, conn).await
+}
+
+/// Checks if the directory, when configured, still allows the user to log in.
+async fn check_ldap_directory(user: &User, ip: &ClientIp) -> EmptyResult {
+    if !crate::ldap::is_enabled() {
+        return Ok(());
+    }
+
+    match crate::ldap::user_allowed(&user.email).await {
+        Ok(true) => Ok(()),
+        Ok(false) => err!(
+            "Username or password is incorrect. Try again",
+            format!("IP: {}. Username: {}. Not allowed by the LDAP directory.", ip.ip, user.email),
+            ErrorEvent {
+                event: EventType::UserFailedLogIn,
+            }
+        ),
+        Err(e) => err!(
+            "Unable to reach the directory server. Please contact your administrator.",
+            format!("IP: {}. Username: {}. LDAP lookup failed: {e}", ip.ip, user.email),
+            ErrorEvent {
+                event: EventType::UserFailedLogIn,
+            }
+        ),
+    }
 }
 
 async fn _api_key_login(
@@ -403,9 +696,22 @@ async fn _user_api_key_login(
         )
     }
 
+    // Accounts which are scheduled for deletion can only be restored via the emailed link
+    if user.delete_scheduled_at.is_some() {
+        err!(
+            "This account has been deleted (API key login)",
+            format!("IP: {}. Username: {}.", ip.ip, user.email),
+            ErrorEvent {
+                event: EventType::UserFailedLogIn
+            }
+        )
+    }
+
     // Check API key. Note that API key logins bypass 2FA.
+    check_login_backoff(&user, ip, conn).await?;
     let client_secret = data.client_secret.as_ref().unwrap();
     if !user.check_valid_api_key(client_secret) {
+        register_login_failure(&user, ip, conn).await?;
         err!(
             "Incorrect client_secret",
             format!("IP: {}. Username: {}.", ip.ip, user.email),
@@ -415,8 +721,23 @@ async fn _user_api_key_login(
         )
     }
 
+    reset_login_failures(&user, ip, conn).await?;
+
+    // API keys are not allowed to bypass a configured directory
+    check_ldap_directory(&user, ip).await?;
+
     let (mut device, new_device) = get_device(&data, conn, &user).await;
 
+    OrgPolicy::check_device_enrollment(&user.uuid, &device, new_device, conn).await.map_err(|e| {
+        e.with_event(ErrorEvent {
+            event: EventType::UserFailedLogIn,
+        })
+    })?;
+
+    if new_device {
+        check_device_approval(&user, &device, ip, conn).await?;
+    }
+
     if CONFIG.mail_enabled() && new_device {
         let now = Utc::now().naive_utc();
         if let Err(e) = mail::send_new_device_logged_in(&user.email, &ip.ip.to_string(), &now, &device).await {
@@ -544,65 +865,92 @@ async fn twofactor_auth(
 
     let selected_twofactor = twofactors.into_iter().find(|tf| tf.atype == selected_id && tf.enabled);
 
-    use crate::crypto::ct_eq;
-
     let selected_data = _selected_data(selected_twofactor);
     let mut remember = data.two_factor_remember.unwrap_or(0);
 
-    match TwoFactorType::from_i32(selected_id) {
-        Some(TwoFactorType::Authenticator) => {
-            authenticator::validate_totp_code_str(&user.uuid, twofactor_code, &selected_data?, ip, conn).await?
-        }
-        Some(TwoFactorType::Webauthn) => webauthn::validate_webauthn_login(&user.uuid, twofactor_code, conn).await?,
-        Some(TwoFactorType::YubiKey) => yubikey::validate_yubikey_login(twofactor_code, &selected_data?).await?,
-        Some(TwoFactorType::Duo) => {
-            match CONFIG.duo_use_iframe() {
-                true => {
-                    // Legacy iframe prompt flow
-                    duo::validate_duo_login(&user.email, twofactor_code, conn).await?
+    check_twofactor_lockout(&user.uuid, conn).await?;
+
+    let result: EmptyResult = async {
+        match TwoFactorType::from_i32(selected_id) {
+            Some(TwoFactorType::Authenticator) => {
+                authenticator::validate_totp_code_str(&user.uuid, twofactor_code, &selected_data?, ip, conn).await?
+            }
+            Some(TwoFactorType::Webauthn) => {
+                webauthn::validate_webauthn_login(&user.uuid, twofactor_code, conn).await?
+            }
+            Some(TwoFactorType::YubiKey) => yubikey::validate_yubikey_login(twofactor_code, &selected_data?).await?,
+            Some(TwoFactorType::Duo) => {
+                match CONFIG.duo_use_iframe() {
+                    true => {
+                        // Legacy iframe prompt flow
+                        duo::validate_duo_login(&user.email, twofactor_code, conn).await?
+                    }
+                    false => {
+                        // OIDC based flow
+                        duo_oidc::validate_duo_login(
+                            &user.email,
+                            twofactor_code,
+                            data.client_id.as_ref().unwrap(),
+                            data.device_identifier.as_ref().unwrap(),
+                            conn,
+                        )
+                        .await?
+                    }
                 }
-                false => {
-                    // OIDC based flow
-                    duo_oidc::validate_duo_login(
-                        &user.email,
-                        twofactor_code,
-                        data.client_id.as_ref().unwrap(),
-                        data.device_identifier.as_ref().unwrap(),
-                        conn,
+            }
+            Some(TwoFactorType::Email) => {
+                email::validate_email_code_str(&user.uuid, twofactor_code, &selected_data?, conn).await?
+            }
+
+            Some(TwoFactorType::RecoveryCode) => {
+                if !consume_recovery_code(&user.uuid, twofactor_code, conn).await? {
+                    err!(
+                        "Invalid recovery code",
+                        ErrorEvent {
+                            event: EventType::UserFailedLogIn2fa
+                        }
                     )
-                    .await?
                 }
             }
-        }
-        Some(TwoFactorType::Email) => {
-            email::validate_email_code_str(&user.uuid, twofactor_code, &selected_data?, conn).await?
-        }
 
-        Some(TwoFactorType::Remember) => {
-            match device.twofactor_remember {
-                Some(ref code) if !CONFIG.disable_2fa_remember() && ct_eq(code, twofactor_code) => {
+            Some(TwoFactorType::Remember) => {
+                if !CONFIG.disable_2fa_remember() && device.check_twofactor_remember(twofactor_code) {
                     remember = 1; // Make sure we also return the token here, otherwise it will only remember the first time
-                }
-                _ => {
+                } else {
                     err_json!(
                         _json_err_twofactor(&twofactor_ids, &user.uuid, data, conn).await?,
                         "2FA Remember token not provided"
                     )
                 }
             }
+            _ => err!(
+                "Invalid two factor provider",
+                ErrorEvent {
+                    event: EventType::UserFailedLogIn2fa
+                }
+            ),
         }
-        _ => err!(
-            "Invalid two factor provider",
-            ErrorEvent {
-                event: EventType::UserFailedLogIn2fa
-            }
-        ),
+        Ok(())
+    }
+    .await;
+
+    // A wrong or expired remember token is not a guess of the second factor, so it doesn't count as a failure
+    if let Err(e) = result {
+        if selected_id != TwoFactorType::Remember as i32 {
+            register_twofactor_failure(user, device, &ip.ip, conn).await?;
+        }
+        return Err(e);
     }
+    reset_twofactor_failures(&user.uuid, conn).await?;
 
     TwoFactorIncomplete::mark_complete(&user.uuid, &device.uuid, conn).await?;
 
     if !CONFIG.disable_2fa_remember() && remember == 1 {
-        Ok(Some(device.refresh_twofactor_remember()))
+        if selected_id == TwoFactorType::Remember as i32 {
+            Ok(Some(device.rotate_twofactor_remember()))
+        } else {
+            Ok(Some(device.refresh_twofactor_remember()))
+        }
     } else {
         device.delete_twofactor_remember();
         Ok(None)
@@ -689,12 +1037,13 @@ async fn _json_err_twofactor(
                     err!("No twofactor email registered")
                 };
 
-                // Send email immediately if email is the only 2FA option
-                if providers.len() == 1 {
+                let email_data = email::EmailTokenData::from_json(&twofactor.data)?;
+
+                // Send email immediately if email is the only 2FA option, unless a token was sent very recently
+                if providers.len() == 1 && !email_data.resend_cooldown_active() {
                     email::send_token(user_id, conn).await?
                 }
 
-                let email_data = email::EmailTokenData::from_json(&twofactor.data)?;
                 result["TwoFactorProviders2"][provider.to_string()] = json!({
                     "Email": email::obscure_email(&email_data.email),
                 })
@@ -712,6 +1061,11 @@ async fn prelogin(data: Json<PreloginData>, conn: DbConn) -> Json<Value> {
     _prelogin(data, conn).await
 }
 
+#[get("/accounts/webauthn/assertion-options")]
+fn passkey_login_options() -> JsonResult {
+    passkeys::generate_passkey_login_options()
+}
+
 #[post("/accounts/register", data = "<data>")]
 async fn identity_register(data: Json<RegisterData>, conn: DbConn) -> JsonResult {
     _register(data, false, conn).await
@@ -723,6 +1077,7 @@ struct RegisterVerificationData {
     email: String,
     name: Option<String>,
     // receiveMarketingEmails: bool,
+    captcha_response: Option<String>,
 }
 
 #[derive(rocket::Responder)]
@@ -738,13 +1093,19 @@ async fn register_verification_email(
 ) -> ApiResult<RegisterVerificationResponse> {
     let data = data.into_inner();
 
-    if !CONFIG.is_signup_allowed(&data.email) {
-        err!("Registration not allowed or user already exists")
+    if !CONFIG.is_signup_allowed(&data.email) && !is_verified_domain_signup_allowed(&data.email, &mut conn).await {
+        err!(CONFIG.signup_denied_reason(&data.email))
     }
 
+    crate::captcha::verify(data.captcha_response.as_deref()).await?;
+
     let should_send_mail = CONFIG.mail_enabled() && CONFIG.signups_verify();
 
-    if User::find_by_mail(&data.email, &mut conn).await.is_some() {
+    // An account for the same mailbox (ignoring plus addresses when enabled) is handled like an existing account
+    let existing_user = User::find_by_mail(&data.email, &mut conn).await.is_some()
+        || (CONFIG.signups_normalize_plus_addresses()
+            && User::find_by_normalized_mail(&data.email, &mut conn).await.is_some());
+    if existing_user {
         if should_send_mail {
             // There is still a timing side channel here in that the code
             // paths that send mail take noticeably longer than ones that
@@ -831,6 +1192,13 @@ struct ConnectData {
     two_factor_remember: Option<i32>,
     #[field(name = uncased("authrequest"))]
     auth_request: Option<AuthRequestId>,
+
+    // Needed for passkey login
+    #[field(name = uncased("token"))]
+    token: Option<String>,
+    #[field(name = uncased("device_response"))]
+    #[field(name = uncased("deviceresponse"))]
+    device_response: Option<String>,
 }
 
 fn _check_is_some<T>(value: &Option<T>, msg: &str) -> EmptyResult {
@@ -5,40 +5,83 @@ mod identity;
 mod notifications;
 mod push;
 mod web;
+mod webhook;
 
-use rocket::serde::json::Json;
+use rocket::{response::content::RawHtml as Html, serde::json::Json};
 use serde_json::Value;
 
 pub use crate::api::{
     admin::catchers as admin_catchers,
     admin::routes as admin_routes,
     core::catchers as core_catchers,
+    core::org_export_job,
     core::purge_auth_requests,
+    core::purge_scheduled_org_deletions,
+    core::purge_scheduled_user_deletions,
     core::purge_sends,
     core::purge_trashed_ciphers,
     core::routes as core_routes,
+    core::trim_password_histories,
     core::two_factor::send_incomplete_2fa_notifications,
+    core::verify_org_domains_job,
     core::{emergency_notification_reminder_job, emergency_request_timeout_job},
     core::{event_cleanup_job, events_routes as core_events_routes},
     icons::routes as icons_routes,
-    identity::routes as identity_routes,
+    identity::{instance_master_password_policy_json, routes as identity_routes},
     notifications::routes as notifications_routes,
-    notifications::{AnonymousNotify, Notify, UpdateType, WS_ANONYMOUS_SUBSCRIPTIONS, WS_USERS},
+    notifications::{
+        prune_stalled_ws_connections, AnonymousNotify, Notify, UpdateType, WS_ANONYMOUS_SUBSCRIPTIONS, WS_USERS,
+    },
     push::{
         push_cipher_update, push_folder_update, push_logout, push_send_update, push_user_update, register_push_device,
-        unregister_push_device,
+        reset_push_token, unregister_push_device,
     },
     web::catchers as web_catchers,
     web::routes as web_routes,
     web::static_files,
+    webhook::{send_user_webhook, UserWebhookEvent},
 };
 use crate::db::{models::User, DbConn};
+use crate::error::ApiErrorCode;
 
 // Type aliases for API methods results
 type ApiResult<T> = Result<T, crate::error::Error>;
 pub type JsonResult = ApiResult<Json<Value>>;
 pub type EmptyResult = ApiResult<()>;
 
+// The links in the emails only show a page, the action is done by posting the token from it.
+// Mail scanners and link previews open these links, which would otherwise act on behalf of the user.
+#[derive(FromForm)]
+struct LinkTokenData {
+    token: String,
+}
+
+/// The page of an emailed link, with a button which posts the token to `action` (a path below the domain path)
+fn link_confirm_page(title: &str, message: &str, button: &str, action: &str, token: &str) -> ApiResult<Html<String>> {
+    let json = json!({
+        "urlpath": crate::CONFIG.domain_path(),
+        "title": title,
+        "message": message,
+        "form": {
+            "action": action,
+            "token": token,
+            "button": button,
+        },
+    });
+    Ok(Html(crate::CONFIG.render_template("link_action", &json)?))
+}
+
+/// The page shown after the action of an emailed link is done
+fn link_result_page(title: &str, message: &str) -> ApiResult<Html<String>> {
+    let json = json!({
+        "urlpath": crate::CONFIG.domain_path(),
+        "title": title,
+        "message": message,
+        "form": null,
+    });
+    Ok(Html(crate::CONFIG.render_template("link_action", &json)?))
+}
+
 // Common structs representing JSON data received
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -57,8 +100,9 @@ impl PasswordOrOtpData {
         match (self.master_password_hash.as_deref(), self.otp.as_deref()) {
             (Some(pw_hash), None) => {
                 if !user.check_valid_password(pw_hash) {
-                    err!("Invalid password");
+                    err_api!(ApiErrorCode::InvalidPassword, "Invalid password");
                 }
+                crate::api::core::two_factor::protected_actions::mark_fresh_verification(&user.uuid, conn).await?;
             }
             (None, Some(otp)) => {
                 validate_protected_action_otp(otp, &user.uuid, delete_if_valid, conn).await?;
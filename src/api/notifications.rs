@@ -13,7 +13,7 @@ use crate::{
         models::{AuthRequestId, Cipher, CollectionId, DeviceId, Folder, Send as DbSend, User, UserId},
         DbConn,
     },
-    Error, CONFIG,
+    watchdog, Error, CONFIG,
 };
 
 use once_cell::sync::Lazy;
@@ -35,8 +35,23 @@ use super::{
     push_send_update, push_user_update,
 };
 
+// A client which does not read its messages should not be able to block the sending of updates
+const WS_SEND_TIMEOUT: Duration = Duration::from_secs(5);
+
 static NOTIFICATIONS_DISABLED: Lazy<bool> = Lazy::new(|| !CONFIG.enable_websocket() && !CONFIG.push_enabled());
 
+/// Removes all connections which are closed or of which the message queue is full.
+/// Dropping the sender ends the connection once the client has read the remaining messages.
+/// Used by the watchdog to restart the hub when sending updates keeps failing.
+pub fn prune_stalled_ws_connections() {
+    let is_stalled = |sender: &Sender<Message>| sender.is_closed() || sender.capacity() == 0;
+    WS_USERS.map.retain(|_, senders| {
+        senders.retain(|(_, sender)| !is_stalled(sender));
+        !senders.is_empty()
+    });
+    WS_ANONYMOUS_SUBSCRIPTIONS.map.retain(|_, sender| !is_stalled(sender));
+}
+
 pub fn routes() -> Vec<Route> {
     if CONFIG.enable_websocket() {
         routes![websockets_hub, anonymous_websockets_hub]
@@ -331,8 +346,13 @@ impl WebSocketUsers {
     async fn send_update(&self, user_id: &UserId, data: &[u8]) {
         if let Some(user) = self.map.get(user_id.as_ref()).map(|v| v.clone()) {
             for (_, sender) in user.iter() {
-                if let Err(e) = sender.send(Message::binary(data)).await {
-                    error!("Error sending WS update {e}");
+                match tokio::time::timeout(WS_SEND_TIMEOUT, sender.send(Message::binary(data))).await {
+                    Ok(Ok(())) => watchdog::heartbeat(watchdog::Component::WebSocket),
+                    Ok(Err(e)) => error!("Error sending WS update {e}"),
+                    Err(_) => {
+                        error!("Timeout sending WS update");
+                        watchdog::report_failure(watchdog::Component::WebSocket, "Timeout sending WS update");
+                    }
                 }
             }
         }
@@ -551,8 +571,13 @@ pub struct AnonymousWebSocketSubscriptions {
 impl AnonymousWebSocketSubscriptions {
     async fn send_update(&self, token: &str, data: &[u8]) {
         if let Some(sender) = self.map.get(token).map(|v| v.clone()) {
-            if let Err(e) = sender.send(Message::binary(data)).await {
-                error!("Error sending WS update {e}");
+            match tokio::time::timeout(WS_SEND_TIMEOUT, sender.send(Message::binary(data))).await {
+                Ok(Ok(())) => watchdog::heartbeat(watchdog::Component::WebSocket),
+                Ok(Err(e)) => error!("Error sending WS update {e}"),
+                Err(_) => {
+                    error!("Timeout sending WS update");
+                    watchdog::report_failure(watchdog::Component::WebSocket, "Timeout sending WS update");
+                }
             }
         }
     }
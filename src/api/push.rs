@@ -10,7 +10,7 @@ use crate::{
     db::models::{AuthRequestId, Cipher, Device, DeviceId, Folder, Send, User, UserId},
     http_client::make_http_request,
     util::format_date,
-    CONFIG,
+    watchdog, CONFIG,
 };
 
 use once_cell::sync::Lazy;
@@ -28,13 +28,22 @@ struct LocalAuthPushToken {
     valid_until: Instant,
 }
 
-async fn get_auth_push_token() -> ApiResult<String> {
-    static PUSH_TOKEN: Lazy<RwLock<LocalAuthPushToken>> = Lazy::new(|| {
-        RwLock::new(LocalAuthPushToken {
-            access_token: String::new(),
-            valid_until: Instant::now(),
-        })
+static PUSH_TOKEN: Lazy<RwLock<LocalAuthPushToken>> = Lazy::new(|| {
+    RwLock::new(LocalAuthPushToken {
+        access_token: String::new(),
+        valid_until: Instant::now(),
+    })
+});
+
+/// Invalidates the cached push token, so the next notification authenticates with the relay again.
+/// Used by the watchdog to restart the push dispatcher when sending notifications keeps failing.
+pub fn reset_push_token() {
+    tokio::spawn(async {
+        PUSH_TOKEN.write().await.valid_until = Instant::now();
     });
+}
+
+async fn get_auth_push_token() -> ApiResult<String> {
     let push_token = PUSH_TOKEN.read().await;
 
     if push_token.valid_until.saturating_duration_since(Instant::now()).as_secs() > 0 {
@@ -258,6 +267,7 @@ async fn send_to_push_relay(notification_data: Value) {
         Ok(s) => s,
         Err(e) => {
             debug!("Could not get the auth push token: {}", e);
+            watchdog::report_failure(watchdog::Component::Push, "Could not get the auth push token");
             return;
         }
     };
@@ -272,7 +282,7 @@ async fn send_to_push_relay(notification_data: Value) {
         }
     };
 
-    if let Err(e) = req
+    match req
         .header(ACCEPT, "application/json")
         .header(CONTENT_TYPE, "application/json")
         .header(AUTHORIZATION, &auth_header)
@@ -280,7 +290,11 @@ async fn send_to_push_relay(notification_data: Value) {
         .send()
         .await
     {
-        error!("An error occurred while sending a send update to the push relay: {}", e);
+        Ok(_) => watchdog::heartbeat(watchdog::Component::Push),
+        Err(e) => {
+            error!("An error occurred while sending a send update to the push relay: {}", e);
+            watchdog::report_failure(watchdog::Component::Push, &format!("Error sending to the push relay: {e}"));
+        }
     };
 }
 
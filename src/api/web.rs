@@ -14,6 +14,7 @@ use crate::{
     auth::decode_file_download,
     db::models::{AttachmentId, CipherId},
     error::Error,
+    storage::{FileResponse, FileStorage},
     util::Cached,
     CONFIG,
 };
@@ -160,7 +161,7 @@ async fn web_files(p: PathBuf) -> Cached<Option<NamedFile>> {
 }
 
 #[get("/attachments/<cipher_id>/<file_id>?<token>")]
-async fn attachments(cipher_id: CipherId, file_id: AttachmentId, token: String) -> Option<NamedFile> {
+async fn attachments(cipher_id: CipherId, file_id: AttachmentId, token: String) -> Option<FileResponse> {
     let Ok(claims) = decode_file_download(&token) else {
         return None;
     };
@@ -168,7 +169,7 @@ async fn attachments(cipher_id: CipherId, file_id: AttachmentId, token: String)
         return None;
     }
 
-    NamedFile::open(Path::new(&CONFIG.attachments_folder()).join(cipher_id.as_ref()).join(file_id.as_ref())).await.ok()
+    FileStorage::attachments().download(&format!("{cipher_id}/{file_id}")).await
 }
 
 // We use DbConn here to let the alive healthcheck also verify the database connection.
@@ -0,0 +1,74 @@
+use chrono::Utc;
+use reqwest::{header::CONTENT_TYPE, Method};
+
+use crate::{db::models::UserId, http_client::make_internal_http_request, util::format_date, CONFIG};
+
+/// Events which can be sent to the configured `USER_WEBHOOK_URL`.
+#[derive(Clone, Copy)]
+pub enum UserWebhookEvent {
+    // An account was created for an address which was invited, the user still needs to finish the registration
+    Invited,
+    Registered,
+    EmailVerified,
+    Deleted,
+}
+
+impl UserWebhookEvent {
+    fn name(self) -> &'static str {
+        match self {
+            UserWebhookEvent::Invited => "invited",
+            UserWebhookEvent::Registered => "registered",
+            UserWebhookEvent::EmailVerified => "email_verified",
+            UserWebhookEvent::Deleted => "deleted",
+        }
+    }
+
+    fn is_enabled(self) -> bool {
+        CONFIG.user_webhook_events().split(',').any(|e| e.trim() == self.name())
+    }
+}
+
+/// Notifies the external provisioning webhook, if configured, about a change of a user account.
+/// The request is sent in the background, so it never delays or fails the API call which triggered it.
+pub fn send_user_webhook(event: UserWebhookEvent, user_id: &UserId, email: &str) {
+    let Some(url) = CONFIG.user_webhook_url() else {
+        return;
+    };
+    if !event.is_enabled() {
+        return;
+    }
+
+    let body = json!({
+        "event": event.name(),
+        "userId": user_id,
+        "email": email,
+        "date": format_date(&Utc::now().naive_utc()),
+    })
+    .to_string();
+
+    tokio::task::spawn(_send_user_webhook(url, user_id.clone(), body));
+}
+
+async fn _send_user_webhook(url: String, user_id: UserId, body: String) {
+    // The receiving system is configured by the admin and often runs on the internal network
+    let req = match make_internal_http_request(Method::POST, &url) {
+        Ok(r) => r,
+        Err(e) => {
+            error!("An error occurred while sending the user webhook for {user_id}: {e}");
+            return;
+        }
+    };
+
+    let req = match CONFIG.user_webhook_secret() {
+        Some(secret) => req.header("X-Webhook-Signature", crate::crypto::hmac_sign_sha256(&secret, &body)),
+        None => req,
+    };
+
+    match req.header(CONTENT_TYPE, "application/json").body(body).send().await {
+        Ok(res) if !res.status().is_success() => {
+            error!("The user webhook for {user_id} returned an unexpected status: {}", res.status());
+        }
+        Ok(_) => {}
+        Err(e) => error!("An error occurred while sending the user webhook for {user_id}: {e}"),
+    }
+}
@@ -1,6 +1,6 @@
 // JWT Handling
 //
-use chrono::{TimeDelta, Utc};
+use chrono::{NaiveDateTime, TimeDelta, Utc};
 use jsonwebtoken::{errors::ErrorKind, Algorithm, DecodingKey, EncodingKey, Header};
 use num_traits::FromPrimitive;
 use once_cell::sync::{Lazy, OnceCell};
@@ -30,15 +30,24 @@ static JWT_INVITE_ISSUER: Lazy<String> = Lazy::new(|| format!("{}|invite", CONFI
 static JWT_EMERGENCY_ACCESS_INVITE_ISSUER: Lazy<String> =
     Lazy::new(|| format!("{}|emergencyaccessinvite", CONFIG.domain_origin()));
 static JWT_DELETE_ISSUER: Lazy<String> = Lazy::new(|| format!("{}|delete", CONFIG.domain_origin()));
+static JWT_ORG_DELETE_ISSUER: Lazy<String> = Lazy::new(|| format!("{}|orgdelete", CONFIG.domain_origin()));
+static JWT_USER_DELETE_CANCEL_ISSUER: Lazy<String> =
+    Lazy::new(|| format!("{}|userdeletecancel", CONFIG.domain_origin()));
+static JWT_ORG_DELETE_CANCEL_ISSUER: Lazy<String> = Lazy::new(|| format!("{}|orgdeletecancel", CONFIG.domain_origin()));
 static JWT_VERIFYEMAIL_ISSUER: Lazy<String> = Lazy::new(|| format!("{}|verifyemail", CONFIG.domain_origin()));
 static JWT_ADMIN_ISSUER: Lazy<String> = Lazy::new(|| format!("{}|admin", CONFIG.domain_origin()));
 static JWT_SEND_ISSUER: Lazy<String> = Lazy::new(|| format!("{}|send", CONFIG.domain_origin()));
 static JWT_ORG_API_KEY_ISSUER: Lazy<String> = Lazy::new(|| format!("{}|api.organization", CONFIG.domain_origin()));
 static JWT_FILE_DOWNLOAD_ISSUER: Lazy<String> = Lazy::new(|| format!("{}|file_download", CONFIG.domain_origin()));
 static JWT_REGISTER_VERIFY_ISSUER: Lazy<String> = Lazy::new(|| format!("{}|register_verify", CONFIG.domain_origin()));
+static JWT_WEBAUTHN_ISSUER: Lazy<String> = Lazy::new(|| format!("{}|webauthn", CONFIG.domain_origin()));
+static JWT_EMAIL_CHANGE_ISSUER: Lazy<String> = Lazy::new(|| format!("{}|emailchange", CONFIG.domain_origin()));
+static JWT_DEVICE_APPROVAL_ISSUER: Lazy<String> = Lazy::new(|| format!("{}|deviceapproval", CONFIG.domain_origin()));
 
 static PRIVATE_RSA_KEY: OnceCell<EncodingKey> = OnceCell::new();
 static PUBLIC_RSA_KEY: OnceCell<DecodingKey> = OnceCell::new();
+// Derived from the private key, for values which need to be stable across restarts without being predictable
+static KEY_DERIVED_SECRET: OnceCell<String> = OnceCell::new();
 
 pub fn initialize_keys() -> Result<(), Error> {
     fn read_key(create_if_missing: bool) -> Result<(Rsa<openssl::pkey::Private>, Vec<u8>), Error> {
@@ -81,9 +90,17 @@ pub fn initialize_keys() -> Result<(), Error> {
     if PUBLIC_RSA_KEY.set(dec).is_err() {
         err!("PUBLIC_RSA_KEY must only be initialized once")
     }
+    let secret = data_encoding::HEXLOWER.encode(ring::digest::digest(&ring::digest::SHA256, &priv_key_buffer).as_ref());
+    if KEY_DERIVED_SECRET.set(secret).is_err() {
+        err!("KEY_DERIVED_SECRET must only be initialized once")
+    }
     Ok(())
 }
 
+pub fn key_derived_secret() -> &'static str {
+    KEY_DERIVED_SECRET.wait()
+}
+
 pub fn encode_jwt<T: Serialize>(claims: &T) -> String {
     match jsonwebtoken::encode(&JWT_HEADER, claims, PRIVATE_RSA_KEY.wait()) {
         Ok(token) => token,
@@ -126,6 +143,18 @@ pub fn decode_delete(token: &str) -> Result<BasicJwtClaims, Error> {
     decode_jwt(token, JWT_DELETE_ISSUER.to_string())
 }
 
+pub fn decode_org_delete(token: &str) -> Result<OrgDeleteJwtClaims, Error> {
+    decode_jwt(token, JWT_ORG_DELETE_ISSUER.to_string())
+}
+
+pub fn decode_user_delete_cancel(token: &str) -> Result<BasicJwtClaims, Error> {
+    decode_jwt(token, JWT_USER_DELETE_CANCEL_ISSUER.to_string())
+}
+
+pub fn decode_org_delete_cancel(token: &str) -> Result<BasicJwtClaims, Error> {
+    decode_jwt(token, JWT_ORG_DELETE_CANCEL_ISSUER.to_string())
+}
+
 pub fn decode_verify_email(token: &str) -> Result<BasicJwtClaims, Error> {
     decode_jwt(token, JWT_VERIFYEMAIL_ISSUER.to_string())
 }
@@ -150,6 +179,18 @@ pub fn decode_register_verify(token: &str) -> Result<RegisterVerifyClaims, Error
     decode_jwt(token, JWT_REGISTER_VERIFY_ISSUER.to_string())
 }
 
+pub fn decode_webauthn(token: &str) -> Result<WebauthnJwtClaims, Error> {
+    decode_jwt(token, JWT_WEBAUTHN_ISSUER.to_string())
+}
+
+pub fn decode_email_change(token: &str) -> Result<EmailChangeJwtClaims, Error> {
+    decode_jwt(token, JWT_EMAIL_CHANGE_ISSUER.to_string())
+}
+
+pub fn decode_device_approval(token: &str) -> Result<DeviceApprovalJwtClaims, Error> {
+    decode_jwt(token, JWT_DEVICE_APPROVAL_ISSUER.to_string())
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LoginJwtClaims {
     // Not before
@@ -347,6 +388,91 @@ pub fn generate_register_verify_claims(email: String, name: Option<String>, veri
     }
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WebauthnJwtClaims {
+    // Not before
+    pub nbf: i64,
+    // Expiration time
+    pub exp: i64,
+    // Issuer
+    pub iss: String,
+    // Subject, the user the ceremony was started for, empty for a passkey login
+    pub sub: String,
+
+    // The serialized WebAuthn ceremony state, or the challenge of a passkey login
+    pub state: String,
+}
+
+pub fn generate_webauthn_claims(sub: String, state: String) -> WebauthnJwtClaims {
+    let time_now = Utc::now();
+    WebauthnJwtClaims {
+        nbf: time_now.timestamp(),
+        exp: (time_now + TimeDelta::try_minutes(5).unwrap()).timestamp(),
+        iss: JWT_WEBAUTHN_ISSUER.to_string(),
+        sub,
+        state,
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmailChangeJwtClaims {
+    // Not before
+    pub nbf: i64,
+    // Expiration time
+    pub exp: i64,
+    // Issuer
+    pub iss: String,
+    // Subject
+    pub sub: UserId,
+
+    // The requested new address and the time of the request, to only confirm this specific request
+    pub new_email: String,
+    pub requested_at: i64,
+}
+
+pub fn generate_email_change_claims(
+    user_id: UserId,
+    new_email: String,
+    requested_at: &NaiveDateTime,
+) -> EmailChangeJwtClaims {
+    let time_now = Utc::now();
+    let expire_hours = i64::from(CONFIG.email_change_expiration_hours());
+    EmailChangeJwtClaims {
+        nbf: time_now.timestamp(),
+        exp: (time_now + TimeDelta::try_hours(expire_hours).unwrap()).timestamp(),
+        iss: JWT_EMAIL_CHANGE_ISSUER.to_string(),
+        sub: user_id,
+        new_email,
+        requested_at: requested_at.and_utc().timestamp(),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeviceApprovalJwtClaims {
+    // Not before
+    pub nbf: i64,
+    // Expiration time
+    pub exp: i64,
+    // Issuer
+    pub iss: String,
+    // Subject
+    pub sub: UserId,
+
+    pub device_id: DeviceId,
+}
+
+pub fn generate_device_approval_claims(user_id: UserId, device_id: DeviceId) -> DeviceApprovalJwtClaims {
+    let time_now = Utc::now();
+    let expire_minutes = i64::from(CONFIG.new_device_approval_expiration_minutes());
+    DeviceApprovalJwtClaims {
+        nbf: time_now.timestamp(),
+        exp: (time_now + TimeDelta::try_minutes(expire_minutes).unwrap()).timestamp(),
+        iss: JWT_DEVICE_APPROVAL_ISSUER.to_string(),
+        sub: user_id,
+        device_id,
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct BasicJwtClaims {
     // Not before
@@ -370,6 +496,55 @@ pub fn generate_delete_claims(uuid: String) -> BasicJwtClaims {
     }
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OrgDeleteJwtClaims {
+    // Not before
+    pub nbf: i64,
+    // Expiration time
+    pub exp: i64,
+    // Issuer
+    pub iss: String,
+    // Subject
+    pub sub: OrganizationId,
+
+    // The time of the deletion request, to only confirm this specific request once
+    pub requested_at: i64,
+}
+
+pub fn generate_org_delete_claims(org_id: &OrganizationId, requested_at: &NaiveDateTime) -> OrgDeleteJwtClaims {
+    let time_now = Utc::now();
+    let expire_hours = i64::from(CONFIG.invitation_expiration_hours());
+    OrgDeleteJwtClaims {
+        nbf: time_now.timestamp(),
+        exp: (time_now + TimeDelta::try_hours(expire_hours).unwrap()).timestamp(),
+        iss: JWT_ORG_DELETE_ISSUER.to_string(),
+        sub: org_id.clone(),
+        requested_at: requested_at.and_utc().timestamp(),
+    }
+}
+
+/// The cancel token stays valid until the scheduled deletion has happened.
+pub fn generate_org_delete_cancel_claims(org_id: &OrganizationId, valid_until: &NaiveDateTime) -> BasicJwtClaims {
+    let time_now = Utc::now();
+    BasicJwtClaims {
+        nbf: time_now.timestamp(),
+        exp: valid_until.and_utc().timestamp(),
+        iss: JWT_ORG_DELETE_CANCEL_ISSUER.to_string(),
+        sub: org_id.to_string(),
+    }
+}
+
+/// The restore token stays valid until the account has been permanently deleted.
+pub fn generate_user_delete_cancel_claims(user_id: &UserId, valid_until: &NaiveDateTime) -> BasicJwtClaims {
+    let time_now = Utc::now();
+    BasicJwtClaims {
+        nbf: time_now.timestamp(),
+        exp: valid_until.and_utc().timestamp(),
+        iss: JWT_USER_DELETE_CANCEL_ISSUER.to_string(),
+        sub: user_id.to_string(),
+    }
+}
+
 pub fn generate_verify_email_claims(user_id: UserId) -> BasicJwtClaims {
     let time_now = Utc::now();
     let expire_hours = i64::from(CONFIG.invitation_expiration_hours());
@@ -560,6 +735,8 @@ impl<'r> FromRequest<'r> for Headers {
             }
         }
 
+        crate::usage_stats::record_active_user(&user.uuid);
+
         Outcome::Success(Headers {
             host,
             device,
@@ -719,8 +896,8 @@ fn get_col_id(request: &Request<'_>) -> Option<CollectionId> {
     None
 }
 
-/// The ManagerHeaders are used to check if you are at least a Manager
-/// and have access to the specific collection provided via the <col_id>/collections/collectionId.
+/// The ManagerHeaders are used to check if you are at least a Manager and have access to the specific collection
+/// provided via the <col_id>/collections/collectionId, or are a member with the manage permission on that collection.
 /// This does strict checking on the collection_id, ManagerHeadersLoose does not.
 pub struct ManagerHeaders {
     pub host: String,
@@ -736,7 +913,7 @@ impl<'r> FromRequest<'r> for ManagerHeaders {
 
     async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
         let headers = try_outcome!(OrgHeaders::from_request(request).await);
-        if headers.is_confirmed_and_manager() {
+        if headers.membership_status == MembershipStatus::Confirmed {
             match get_col_id(request) {
                 Some(col_id) => {
                     let mut conn = match DbConn::from_request(request).await {
@@ -744,7 +921,13 @@ impl<'r> FromRequest<'r> for ManagerHeaders {
                         _ => err_handler!("Error getting DB"),
                     };
 
-                    if !Collection::can_access_collection(&headers.membership, &col_id, &mut conn).await {
+                    // Members which are not a manager can still manage the collections they have the manage permission for
+                    let can_manage = if headers.is_confirmed_and_manager() {
+                        Collection::can_access_collection(&headers.membership, &col_id, &mut conn).await
+                    } else {
+                        Collection::can_manage_collection(&headers.membership, &col_id, &mut conn).await
+                    };
+                    if !can_manage {
                         err_handler!("The current user isn't a manager for this collection")
                     }
                 }
@@ -842,6 +1025,7 @@ impl ManagerHeaders {
 }
 
 pub struct OwnerHeaders {
+    pub host: String,
     pub device: Device,
     pub user: User,
     pub ip: ClientIp,
@@ -856,6 +1040,7 @@ impl<'r> FromRequest<'r> for OwnerHeaders {
         let headers = try_outcome!(OrgHeaders::from_request(request).await);
         if headers.is_confirmed_and_owner() {
             Outcome::Success(Self {
+                host: headers.host,
                 device: headers.device,
                 user: headers.user,
                 ip: headers.ip,
@@ -867,6 +1052,19 @@ impl<'r> FromRequest<'r> for OwnerHeaders {
     }
 }
 
+impl From<OwnerHeaders> for AdminHeaders {
+    fn from(h: OwnerHeaders) -> AdminHeaders {
+        AdminHeaders {
+            host: h.host,
+            device: h.device,
+            user: h.user,
+            membership_type: MembershipType::Owner,
+            ip: h.ip,
+            org_id: h.org_id,
+        }
+    }
+}
+
 pub struct OrgMemberHeaders {
     pub host: String,
     pub user: User,
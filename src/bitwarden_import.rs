@@ -0,0 +1,500 @@
+//
+// Importer for the database of an official Bitwarden server
+//
+// The tables of the official (MSSQL) database are read from JSON files, one file per table named after the table,
+// for example `User.json`. These can be created with `sqlcmd`, the `-y 0` option prevents the output from being cut:
+//   sqlcmd -S localhost -U sa -d vault -y 0 -Q "SET NOCOUNT ON; SELECT * FROM dbo.[User] FOR JSON PATH" -o User.json
+// A SQL backup (`.bak`) has to be restored into an MSSQL instance first, to be able to export the tables.
+//
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use chrono::NaiveDateTime;
+use data_encoding::BASE64;
+use num_traits::FromPrimitive;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+
+use crate::{
+    db::{models::*, DbConn},
+    error::{Error, MapResult},
+    storage::{FileBackend, FileStorage},
+};
+
+// The tables which are imported, all other tables (events, devices, sends, ...) are skipped
+const TABLES: [&str; 8] = [
+    "User",
+    "Organization",
+    "OrganizationUser",
+    "Collection",
+    "CollectionUser",
+    "Folder",
+    "Cipher",
+    "CollectionCipher",
+];
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct BwUser {
+    id: String,
+    name: Option<String>,
+    email: String,
+    #[serde(default)]
+    email_verified: bool,
+    master_password: Option<String>,
+    master_password_hint: Option<String>,
+    security_stamp: Option<String>,
+    key: Option<String>,
+    public_key: Option<String>,
+    private_key: Option<String>,
+    #[serde(default)]
+    kdf: i32,
+    kdf_iterations: Option<i32>,
+    kdf_memory: Option<i32>,
+    kdf_parallelism: Option<i32>,
+    api_key: Option<String>,
+    avatar_color: Option<String>,
+    equivalent_domains: Option<String>,
+    excluded_global_equivalent_domains: Option<String>,
+    creation_date: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct BwOrganization {
+    id: String,
+    name: String,
+    billing_email: Option<String>,
+    private_key: Option<String>,
+    public_key: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct BwOrganizationUser {
+    id: String,
+    organization_id: String,
+    user_id: Option<String>,
+    email: Option<String>,
+    key: Option<String>,
+    status: i32,
+    r#type: i32,
+    #[serde(default)]
+    access_all: bool,
+    external_id: Option<String>,
+    reset_password_key: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct BwCollection {
+    id: String,
+    organization_id: String,
+    name: String,
+    external_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct BwCollectionUser {
+    collection_id: String,
+    organization_user_id: String,
+    #[serde(default)]
+    read_only: bool,
+    #[serde(default)]
+    hide_passwords: bool,
+    #[serde(default)]
+    manage: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct BwFolder {
+    id: String,
+    user_id: String,
+    name: String,
+    creation_date: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct BwCipher {
+    id: String,
+    user_id: Option<String>,
+    organization_id: Option<String>,
+    r#type: i32,
+    data: String,
+    favorites: Option<String>,
+    folders: Option<String>,
+    attachments: Option<String>,
+    creation_date: Option<String>,
+    deleted_date: Option<String>,
+    reprompt: Option<i32>,
+    key: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct BwCollectionCipher {
+    collection_id: String,
+    cipher_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct BwAttachment {
+    file_name: String,
+    key: Option<String>,
+    size: Value, // Stored as a string by some versions
+}
+
+#[derive(Default)]
+pub struct ImportSummary {
+    pub users: usize,
+    pub organizations: usize,
+    pub memberships: usize,
+    pub collections: usize,
+    pub folders: usize,
+    pub ciphers: usize,
+    pub attachments: usize,
+    pub warnings: Vec<String>,
+    /// Users of which the master password hash could not be converted, they can't log in with their password
+    pub unconverted_users: Vec<String>,
+}
+
+/// The official server uses uppercase GUIDs, Vaultwarden lowercase UUIDs.
+fn id(guid: &str) -> String {
+    guid.to_lowercase()
+}
+
+fn parse_date(date: Option<&String>) -> Option<NaiveDateTime> {
+    date.and_then(|d| NaiveDateTime::parse_from_str(d, "%Y-%m-%dT%H:%M:%S%.f").ok())
+}
+
+fn read_table<T: DeserializeOwned>(folder: &Path, table: &str) -> Result<Vec<T>, Error> {
+    let path = folder.join(format!("{table}.json"));
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    // `sqlcmd` splits long output over multiple lines, newlines within values are always escaped in JSON
+    let content = std::fs::read_to_string(&path).map_res(&format!("Unable to read {}", path.display()))?;
+    let content: String = content.lines().map(str::trim_end).collect();
+    if content.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    serde_json::from_str(&content).map_res(&format!("Unable to parse {}", path.display()))
+}
+
+/// Converts an ASP.NET Core Identity (V3) password hash as used by the official server. These are PBKDF2 hashes of the
+/// master password hash, just like the ones of Vaultwarden, so users can keep logging in with their password.
+/// Returns the hash, the salt, the iterations and whether HMAC-SHA512 is used instead of HMAC-SHA256.
+fn convert_password_hash(hash: &str) -> Option<(Vec<u8>, Vec<u8>, i32, bool)> {
+    let bytes = BASE64.decode(hash.as_bytes()).ok()?;
+    // Format marker, followed by the PRF, the iteration count and the salt length as big endian u32 values
+    if bytes.len() < 13 || bytes[0] != 0x01 {
+        return None;
+    }
+    let read_u32 = |i: usize| u32::from_be_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+    let (prf, iterations, salt_len) = (read_u32(1), read_u32(5), read_u32(9) as usize);
+
+    // HMAC-SHA256 (1) is used up to .NET 6, HMAC-SHA512 (2) since .NET 7. HMAC-SHA1 (0) is not supported
+    let sha512 = match prf {
+        1 => false,
+        2 => true,
+        _ => return None,
+    };
+    if iterations == 0 || bytes.len() <= 13 + salt_len {
+        return None;
+    }
+    let salt = bytes[13..13 + salt_len].to_vec();
+    let password_hash = bytes[13 + salt_len..].to_vec();
+    Some((password_hash, salt, i32::try_from(iterations).ok()?, sha512))
+}
+
+/// Removes a key from the cipher data, the casing differs between versions of the official server.
+fn take_key(data: &mut Map<String, Value>, key: &str) -> Option<Value> {
+    let found = data.keys().find(|k| k.eq_ignore_ascii_case(key))?.clone();
+    data.remove(&found).filter(|v| !v.is_null())
+}
+
+/// Imports the exported tables from `folder`, and copies the attachment files from `attachments_folder` when given.
+/// To prevent mixing up existing data, this is only allowed on an empty database.
+/// The import runs in a single transaction, so a failed import leaves the database empty and can be run again.
+/// The attachment files are only copied once the transaction is committed.
+pub async fn import(
+    folder: &Path,
+    attachments_folder: Option<&Path>,
+    conn: &mut DbConn,
+) -> Result<ImportSummary, Error> {
+    if !User::get_all(conn).await.is_empty() {
+        err_silent!("The database already contains users, the import is only possible into an empty database")
+    }
+    if !TABLES.iter().any(|t| folder.join(format!("{t}.json")).exists()) {
+        err_silent!(format!("No exported tables found in {}", folder.display()))
+    }
+
+    conn.begin_transaction().await?;
+    let result = import_tables(folder, attachments_folder, conn).await;
+    let (mut summary, files) = conn.end_transaction(result).await?;
+
+    for (attachment_id, path, source) in files {
+        if let Err(e) = FileStorage::attachments().copy_from(&path, &source).await {
+            summary.attachments -= 1;
+            summary.warnings.push(format!("Attachment file {attachment_id} could not be copied: {e}"));
+        }
+    }
+    Ok(summary)
+}
+
+/// An attachment file to copy from the export once the import is committed: the attachment id, its path and the source file
+type PendingFile = (AttachmentId, String, PathBuf);
+
+async fn import_tables(
+    folder: &Path,
+    attachments_folder: Option<&Path>,
+    conn: &mut DbConn,
+) -> Result<(ImportSummary, Vec<PendingFile>), Error> {
+    let mut summary = ImportSummary::default();
+    let mut files = Vec::new();
+
+    // Users
+    let mut user_ids: HashMap<String, UserId> = HashMap::new();
+    for bw_user in read_table::<BwUser>(folder, "User")? {
+        let mut user = User::new(bw_user.email);
+        user.uuid = UserId::from(id(&bw_user.id));
+        if let Some(name) = bw_user.name {
+            user.name = name;
+        }
+        if let Some(created_at) = parse_date(bw_user.creation_date.as_ref()) {
+            user.created_at = created_at;
+        }
+        if bw_user.email_verified {
+            user.verified_at = Some(user.created_at);
+        }
+
+        match bw_user.master_password.as_deref().map(convert_password_hash) {
+            Some(Some((password_hash, salt, iterations, sha512))) => {
+                user.password_hash = password_hash;
+                user.salt = salt;
+                user.password_iterations = iterations;
+                user.password_sha512 = sha512;
+            }
+            Some(None) => summary.unconverted_users.push(user.email.clone()),
+            None => {}
+        }
+
+        user.password_hint = bw_user.master_password_hint;
+        user.akey = bw_user.key.unwrap_or_default();
+        user.public_key = bw_user.public_key;
+        user.private_key = bw_user.private_key;
+        user.client_kdf_type = bw_user.kdf;
+        user.client_kdf_iter = bw_user.kdf_iterations.unwrap_or(User::CLIENT_KDF_ITER_DEFAULT);
+        user.client_kdf_memory = bw_user.kdf_memory;
+        user.client_kdf_parallelism = bw_user.kdf_parallelism;
+        user.api_key = bw_user.api_key;
+        user.avatar_color = bw_user.avatar_color;
+        if let Some(security_stamp) = bw_user.security_stamp {
+            user.security_stamp = security_stamp;
+        }
+        if let Some(domains) = bw_user.equivalent_domains {
+            user.equivalent_domains = domains;
+        }
+        if let Some(excluded) = bw_user.excluded_global_equivalent_domains {
+            user.excluded_globals = excluded;
+        }
+
+        user.save(conn).await?;
+        user_ids.insert(user.email.clone(), user.uuid.clone());
+        summary.users += 1;
+    }
+
+    // Organizations
+    for bw_org in read_table::<BwOrganization>(folder, "Organization")? {
+        let mut org = Organization::new(
+            bw_org.name,
+            bw_org.billing_email.unwrap_or_default(),
+            bw_org.private_key,
+            bw_org.public_key,
+        );
+        org.uuid = OrganizationId::from(id(&bw_org.id));
+        org.save(conn).await?;
+        summary.organizations += 1;
+    }
+
+    // Memberships, invited users without an account get an invited user just like a regular invite creates
+    let mut member_users: HashMap<String, UserId> = HashMap::new();
+    for bw_member in read_table::<BwOrganizationUser>(folder, "OrganizationUser")? {
+        let user_id = match (bw_member.user_id, bw_member.email) {
+            (Some(user_id), _) => UserId::from(id(&user_id)),
+            (None, Some(email)) => match user_ids.get(&email.to_lowercase()) {
+                Some(user_id) => user_id.clone(),
+                None => {
+                    let mut invited = User::new(email);
+                    invited.save(conn).await?;
+                    user_ids.insert(invited.email.clone(), invited.uuid.clone());
+                    invited.uuid
+                }
+            },
+            (None, None) => {
+                summary.warnings.push(format!("Skipped membership {} without a user", bw_member.id));
+                continue;
+            }
+        };
+
+        let mut member = Membership::new(user_id.clone(), OrganizationId::from(id(&bw_member.organization_id)));
+        member.uuid = MembershipId::from(id(&bw_member.id));
+        member.akey = bw_member.key.unwrap_or_default();
+        member.access_all = bw_member.access_all;
+        member.external_id = bw_member.external_id;
+        member.reset_password_key = bw_member.reset_password_key;
+        member.atype = match MembershipType::from_i32(bw_member.r#type) {
+            Some(atype) => atype as i32,
+            None => {
+                // Custom roles are not supported, fall back to the least privileged type
+                summary.warnings.push(format!("Custom role of membership {} imported as a regular user", member.uuid));
+                MembershipType::User as i32
+            }
+        };
+        if bw_member.status < MembershipStatus::Invited as i32 {
+            member.status = MembershipStatus::Confirmed as i32;
+            member.revoke();
+        } else {
+            member.status = bw_member.status;
+        }
+
+        member.save(conn).await?;
+        member_users.insert(member.uuid.to_string(), user_id);
+        summary.memberships += 1;
+    }
+
+    // Collections and their members
+    for bw_collection in read_table::<BwCollection>(folder, "Collection")? {
+        let mut collection = Collection::new(
+            OrganizationId::from(id(&bw_collection.organization_id)),
+            bw_collection.name,
+            bw_collection.external_id,
+        );
+        collection.uuid = CollectionId::from(id(&bw_collection.id));
+        collection.save(conn).await?;
+        summary.collections += 1;
+    }
+
+    for bw_collection_user in read_table::<BwCollectionUser>(folder, "CollectionUser")? {
+        let Some(user_id) = member_users.get(&id(&bw_collection_user.organization_user_id)) else {
+            continue;
+        };
+        CollectionUser::save(
+            user_id,
+            &CollectionId::from(id(&bw_collection_user.collection_id)),
+            bw_collection_user.read_only,
+            bw_collection_user.hide_passwords,
+            bw_collection_user.manage,
+            conn,
+        )
+        .await?;
+    }
+
+    // Folders
+    for bw_folder in read_table::<BwFolder>(folder, "Folder")? {
+        let mut new_folder = Folder::new(UserId::from(id(&bw_folder.user_id)), bw_folder.name);
+        new_folder.uuid = FolderId::from(id(&bw_folder.id));
+        if let Some(created_at) = parse_date(bw_folder.creation_date.as_ref()) {
+            new_folder.created_at = created_at;
+        }
+        new_folder.save(conn).await?;
+        summary.folders += 1;
+    }
+
+    // Ciphers, including the folders, favorites and attachments of every user
+    for bw_cipher in read_table::<BwCipher>(folder, "Cipher")? {
+        let cipher_id = id(&bw_cipher.id);
+        let Ok(Value::Object(mut data)) = serde_json::from_str::<Value>(&bw_cipher.data) else {
+            summary.warnings.push(format!("Skipped cipher {cipher_id} with invalid data"));
+            continue;
+        };
+        let Some(Value::String(name)) = take_key(&mut data, "Name") else {
+            summary.warnings.push(format!("Skipped cipher {cipher_id} without a name"));
+            continue;
+        };
+
+        let mut cipher = Cipher::new(bw_cipher.r#type, name);
+        cipher.uuid = CipherId::from(cipher_id.clone());
+        cipher.user_uuid = bw_cipher.user_id.as_deref().map(|u| UserId::from(id(u)));
+        cipher.organization_uuid = bw_cipher.organization_id.as_deref().map(|o| OrganizationId::from(id(o)));
+        cipher.key = bw_cipher.key;
+        cipher.notes = take_key(&mut data, "Notes").and_then(|n| n.as_str().map(String::from));
+        cipher.fields = take_key(&mut data, "Fields").map(|f| f.to_string());
+        cipher.password_history = take_key(&mut data, "PasswordHistory").map(|p| p.to_string());
+        cipher.data = Value::Object(data).to_string();
+        cipher.reprompt = bw_cipher.reprompt;
+        cipher.deleted_at = parse_date(bw_cipher.deleted_date.as_ref());
+        if let Some(created_at) = parse_date(bw_cipher.creation_date.as_ref()) {
+            cipher.created_at = created_at;
+        }
+        cipher.save(conn).await?;
+        summary.ciphers += 1;
+
+        // Both are stored as a JSON object with the id of the user as key
+        let folders: HashMap<String, String> =
+            bw_cipher.folders.and_then(|f| serde_json::from_str(&f).ok()).unwrap_or_default();
+        for folder_id in folders.values() {
+            FolderCipher::new(FolderId::from(id(folder_id)), cipher.uuid.clone()).save(conn).await?;
+        }
+        let favorites: HashMap<String, bool> =
+            bw_cipher.favorites.and_then(|f| serde_json::from_str(&f).ok()).unwrap_or_default();
+        for (user_id, favorite) in favorites {
+            if favorite {
+                Favorite::set_favorite(true, &cipher.uuid, &UserId::from(id(&user_id)), conn).await?;
+            }
+        }
+
+        let attachments: HashMap<String, BwAttachment> =
+            bw_cipher.attachments.and_then(|a| serde_json::from_str(&a).ok()).unwrap_or_default();
+        for (attachment_id, bw_attachment) in attachments {
+            let size = match &bw_attachment.size {
+                Value::String(s) => s.parse().unwrap_or_default(),
+                v => v.as_i64().unwrap_or_default(),
+            };
+            let attachment = Attachment::new(
+                AttachmentId(attachment_id.clone()),
+                cipher.uuid.clone(),
+                bw_attachment.file_name,
+                size,
+                bw_attachment.key,
+            );
+
+            let Some(source_folder) = attachments_folder else {
+                summary.warnings.push(format!("Skipped attachment {attachment_id}, no attachments folder given"));
+                continue;
+            };
+            // The official server stores the files in a folder per cipher
+            let source: PathBuf = [&bw_cipher.id, &cipher_id]
+                .iter()
+                .map(|c| source_folder.join(c).join(&attachment_id))
+                .find(|p| p.exists())
+                .unwrap_or_default();
+            if !source.is_file() {
+                summary.warnings.push(format!("Attachment file {attachment_id} of cipher {cipher_id} not found"));
+                continue;
+            }
+
+            attachment.save(conn).await?;
+            files.push((attachment.id.clone(), attachment.get_file_path(), source));
+            summary.attachments += 1;
+        }
+    }
+
+    for bw_collection_cipher in read_table::<BwCollectionCipher>(folder, "CollectionCipher")? {
+        CollectionCipher::save(
+            &CipherId::from(id(&bw_collection_cipher.cipher_id)),
+            &CollectionId::from(id(&bw_collection_cipher.collection_id)),
+            conn,
+        )
+        .await?;
+    }
+
+    Ok((summary, files))
+}
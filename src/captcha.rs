@@ -0,0 +1,64 @@
+//
+// CAPTCHA verification of the registration and password hint requests, using hCaptcha or Cloudflare Turnstile
+// The clients send the response token of the widget as `captchaResponse`. When it is missing, the error contains the
+// site key (`HCaptcha_SiteKey` as used by the Bitwarden clients), so a client can render the widget and try again.
+//
+use reqwest::Method;
+use serde_json::Value;
+
+use crate::{
+    error::{ApiErrorCode, Error},
+    http_client::make_http_request,
+    CONFIG,
+};
+
+const HCAPTCHA_VERIFY_URL: &str = "https://api.hcaptcha.com/siteverify";
+const TURNSTILE_VERIFY_URL: &str = "https://challenges.cloudflare.com/turnstile/v0/siteverify";
+
+fn captcha_error(msg: &str, site_key: &str) -> Error {
+    let json = json!({
+        "message": msg,
+        "validationErrors": {"": [ msg ]},
+        "errorModel": {
+            "message": msg,
+            "object": "error"
+        },
+        "HCaptcha_SiteKey": site_key,
+        "captchaProvider": CONFIG.captcha_provider(),
+        "object": "error"
+    });
+    Error::from((msg, json)).with_api_code(ApiErrorCode::CaptchaRequired)
+}
+
+/// Verifies the CAPTCHA response token with the configured provider, does nothing when no provider is configured.
+pub async fn verify(response: Option<&str>) -> Result<(), Error> {
+    let (Some(provider), Some(site_key), Some(secret)) =
+        (CONFIG.captcha_provider(), CONFIG.captcha_site_key(), CONFIG.captcha_secret())
+    else {
+        return Ok(());
+    };
+
+    let Some(response) = response.map(str::trim).filter(|r| !r.is_empty()) else {
+        return Err(captcha_error("Please complete the CAPTCHA and try again.", &site_key));
+    };
+
+    let url = match provider.as_str() {
+        "turnstile" => TURNSTILE_VERIFY_URL,
+        _ => HCAPTCHA_VERIFY_URL,
+    };
+    let params = [("secret", secret.as_str()), ("response", response), ("sitekey", site_key.as_str())];
+
+    let result: Value = match make_http_request(Method::POST, url)?.form(&params).send().await {
+        Ok(res) => match res.error_for_status() {
+            Ok(res) => res.json().await?,
+            Err(e) => err!(format!("Unable to verify the CAPTCHA with {provider}: {e}")),
+        },
+        Err(e) => err!(format!("Unable to verify the CAPTCHA with {provider}: {e}")),
+    };
+
+    if result["success"].as_bool() != Some(true) {
+        error!("CAPTCHA verification failed: {}", result["error-codes"]);
+        return Err(captcha_error("The CAPTCHA could not be verified, please try again.", &site_key));
+    }
+    Ok(())
+}
@@ -382,6 +382,16 @@ make_config! {
         attachments_folder:     String, false,  auto,   |c| format!("{}/{}", c.data_folder, "attachments");
         /// Sends folder
         sends_folder:           String, false,  auto,   |c| format!("{}/{}", c.data_folder, "sends");
+        /// Backup folder |> Where database backups are written to, defaults to the folder of the database
+        backup_folder:          String, false,  option;
+        /// Organization export folder |> Where the automatic organization exports are written to
+        org_export_folder:      String, false,  auto,   |c| format!("{}/{}", c.data_folder, "org_exports");
+        /// Attachments IO concurrency |> Maximum number of simultaneous file operations on the attachments folder, including the downloads. 0 means unlimited
+        attachments_io_concurrency: usize, false, def,  0;
+        /// Sends IO concurrency |> Maximum number of simultaneous file operations on the sends folder, including the downloads. 0 means unlimited
+        sends_io_concurrency:   usize,  false,  def,    0;
+        /// Icon cache IO concurrency |> Maximum number of simultaneous file operations on the icon cache folder. 0 means unlimited
+        icons_io_concurrency:   usize,  false,  def,    0;
         /// Temp folder |> Used for storing temporary file uploads
         tmp_folder:             String, false,  auto,   |c| format!("{}/{}", c.data_folder, "tmp");
         /// Templates folder
@@ -411,12 +421,15 @@ make_config! {
         /// Job scheduler poll interval |> How often the job scheduler thread checks for jobs to run.
         /// Set to 0 to globally disable scheduled jobs.
         job_poll_interval_ms:   u64,    false,  def,    30_000;
-        /// Send purge schedule |> Cron schedule of the job that checks for Sends past their deletion date.
+        /// Send purge schedule |> Cron schedule of the job that checks for Sends past their deletion date, and for files of Sends which do not exist anymore.
         /// Defaults to hourly. Set blank to disable this job.
         send_purge_schedule:    String, false,  def,    "0 5 * * * *".to_string();
         /// Trash purge schedule |> Cron schedule of the job that checks for trashed items to delete permanently.
         /// Defaults to daily. Set blank to disable this job.
         trash_purge_schedule:   String, false,  def,    "0 5 0 * * *".to_string();
+        /// Password history trim schedule |> Cron schedule of the job that removes the password history entries beyond the password history limit.
+        /// Defaults to daily. Set blank to disable this job.
+        password_history_trim_schedule: String, false, def, "0 15 0 * * *".to_string();
         /// Incomplete 2FA login schedule |> Cron schedule of the job that checks for incomplete 2FA logins.
         /// Defaults to once every minute. Set blank to disable this job.
         incomplete_2fa_schedule: String, false,  def,   "30 * * * * *".to_string();
@@ -435,6 +448,31 @@ make_config! {
         /// Duo Auth context cleanup schedule |> Cron schedule of the job that cleans expired Duo contexts from the database. Does nothing if Duo MFA is disabled or set to use the legacy iframe prompt.
         /// Defaults to once every minute. Set blank to disable this job.
         duo_context_purge_schedule:   String, false,  def,    "30 * * * * *".to_string();
+        /// Organization deletion schedule |> Cron schedule of the job that deletes organizations of which the deletion delay has passed.
+        /// Defaults to hourly. (20 minutes after the hour) Set blank to disable this job.
+        org_deletion_schedule:   String, false,  def,    "0 20 * * * *".to_string();
+        /// User deletion schedule |> Cron schedule of the job that deletes user accounts of which the deletion delay has passed.
+        /// Defaults to hourly. (25 minutes after the hour) Set blank to disable this job.
+        user_deletion_schedule:  String, false,  def,    "0 25 * * * *".to_string();
+        /// Organization domain verification schedule |> Cron schedule of the job that checks the DNS TXT records of the unverified organization domains.
+        /// Every domain is checked every 12 hours, until it's verified or 72 hours have passed. Defaults to hourly. (35 minutes after the hour) Set blank to disable this job.
+        org_domain_verification_schedule: String, false, def, "0 35 * * * *".to_string();
+        /// Organization export schedule |> Cron schedule of the job that exports the vault of the organizations which have automatic exports enabled.
+        /// Owners can also have the exports mailed to them. The export stays encrypted with the organization key. Defaults to daily. (40 minutes after 3 AM) Set blank to disable this job.
+        org_export_schedule:    String, false,  def,    "0 40 3 * * *".to_string();
+        /// Organization export retention |> Number of automatic exports which are kept per organization, older ones are deleted
+        org_export_retention:   u32,    true,   def,    7;
+        /// Watchdog interval |> How often (in seconds) the watchdog checks the job scheduler, mail delivery, WebSocket hub and push dispatcher.
+        /// Set to 0 to disable the watchdog.
+        watchdog_interval_secs: u64,    false,  def,    60;
+        /// Watchdog stall timeout |> Number of seconds after which a component which stopped responding is considered stalled and restarted
+        watchdog_stall_secs:    u64,    false,  def,    300;
+        /// Watchdog failure threshold |> Number of consecutive failures after which a component is restarted
+        watchdog_failure_threshold: u32, false, def,    5;
+        /// Usage statistics |> Collect anonymized usage statistics (daily active users, syncs, mails and icon cache hits) per day, shown in the admin panel and saved to `usage_stats.json` in the data folder. Nothing is sent anywhere
+        usage_stats_enabled:    bool,   true,   def,    false;
+        /// Usage statistics retention |> Number of days for which the usage statistics are kept
+        usage_stats_days:       u32,    true,   def,    30;
     },
 
     /// General settings
@@ -452,7 +490,7 @@ make_config! {
         web_vault_enabled:      bool,   false,  def,    true;
 
         /// Allow Sends |> Controls whether users are allowed to create Bitwarden Sends.
-        /// This setting applies globally to all users. To control this on a per-org basis instead, use the "Disable Send" org policy.
+        /// This setting applies globally to all users, existing Sends can not be accessed while it is disabled. To control this on a per-org basis instead, use the "Disable Send" org policy.
         sends_allowed:          bool,   true,   def,    true;
 
         /// HIBP Api Key |> HaveIBeenPwned API Key, request it here: https://haveibeenpwned.com/API/Key
@@ -460,16 +498,22 @@ make_config! {
 
         /// Per-user attachment storage limit (KB) |> Max kilobytes of attachment storage allowed per user. When this limit is reached, the user will not be allowed to upload further attachments.
         user_attachment_limit:  i64,    true,   option;
-        /// Per-organization attachment storage limit (KB) |> Max kilobytes of attachment storage allowed per org. When this limit is reached, org members will not be allowed to upload further attachments for ciphers owned by that org.
+        /// Per-organization attachment storage limit (KB) |> Max kilobytes of attachment storage allowed per org. When this limit is reached, org members will not be allowed to upload further attachments for ciphers owned by that org. A different limit can be set for an org on the admin panel.
         org_attachment_limit:   i64,    true,   option;
         /// Per-user send storage limit (KB) |> Max kilobytes of sends storage allowed per user. When this limit is reached, the user will not be allowed to upload further sends.
         user_send_limit:   i64,    true,   option;
+        /// Max send file size (KB) |> Max kilobytes of the file of a send. The default is the maximum the Bitwarden clients allow (500 MB) with some margin. Only read at startup, as it also sets the upload limit of the server.
+        send_file_size_limit:   i64,    false,  def,    537_600;
 
         /// Trash auto-delete days |> Number of days to wait before auto-deleting a trashed item.
         /// If unset, trashed items are not auto-deleted. This setting applies globally, so make
         /// sure to inform all users of any changes to this setting.
         trash_auto_delete_days: i64,    true,   option;
 
+        /// Password history limit |> Max number of password history entries kept per item, older entries are removed
+        /// when the item is saved and by the password history trim job. If unset, the complete history is kept.
+        password_history_limit: u32,    true,   option;
+
         /// Incomplete 2FA time limit |> Number of minutes to wait before a 2FA-enabled login is
         /// considered incomplete, resulting in an email notification. An incomplete 2FA login is one
         /// where the correct master password was provided but the required 2FA step was not completed,
@@ -491,13 +535,31 @@ make_config! {
         signups_verify_resend_time: u64, true,  def,    3_600;
         /// If signups require email verification, limit how many emails are automatically sent when login is attempted (0 means no limit)
         signups_verify_resend_limit: u32, true, def,    6;
+        /// Require a verified email for organizations |> Users need to have verified their email address before they can accept
+        /// an organization invitation or be confirmed as a member. Requires email to be configured
+        org_require_verified_email: bool, true, def,    false;
+        /// Join organizations via verified domains |> Users can sign up with an email address of a domain which is verified by an organization,
+        /// even if signups are disabled, and are invited to that organization once they verified their email. Requires signups verify and email
+        org_domain_auto_join:   bool,   true,   def,    false;
+        /// Hardened anti-enumeration mode |> Makes the responses and their timing for existing and unknown accounts indistinguishable.
+        /// Logins always check a password and report the account state only after a correct password, prelogin returns stable KDF
+        /// settings for unknown emails, registering an existing account answers like a successful registration and invitations of unknown users which are not allowed are silently ignored
+        anti_enumeration:       bool,   true,   def,    false;
         /// Email domain whitelist |> Allow signups only from this list of comma-separated domains, even when signups are otherwise disabled
         signups_domains_whitelist: String, true, def,   String::new();
+        /// Normalize plus addresses |> Treat `user+tag@example.org` as `user@example.org` for signups, so no second account can be created for the same mailbox
+        signups_normalize_plus_addresses: bool, true, def, false;
         /// Enable event logging |> Enables event logging for organizations.
         org_events_enabled:     bool,   false,  def,    false;
         /// Org creation users |> Allow org creation only by this list of comma-separated user emails.
         /// Blank or 'all' means all users can create orgs; 'none' means no users can create orgs.
         org_creation_users:     String, true,   def,    String::new();
+        /// Organization deletion delay (in hours) |> The number of hours between the confirmation of an organization deletion and the actual deletion.
+        /// During this period any owner of the organization can cancel the deletion. Set to 0 to delete directly after the confirmation.
+        org_delete_delay_hours: u32,    true,   def,    72;
+        /// User deletion delay (in days) |> The number of days a deleted account is retained before it is permanently deleted.
+        /// During this period the account can't be used, but the user can restore it via the link in the notification email. Set to 0 to delete accounts directly.
+        user_delete_delay_days: u32,    true,   def,    0;
         /// Allow invitations |> Controls whether users can be invited by organization admins, even when signups are otherwise disabled
         invitations_allowed:    bool,   true,   def,    true;
         /// Invitation token expiration time (in hours) |> The number of hours after which an organization invite token, emergency access invite token,
@@ -507,24 +569,78 @@ make_config! {
         emergency_access_allowed:    bool,   true,   def,    true;
         /// Allow email change |> Controls whether users can change their email. This setting applies globally to all users.
         email_change_allowed:    bool,   true,   def,    true;
+        /// Confirm email change from the current address |> When mail is enabled, an email change also needs to be confirmed via a link sent to the current address, so a hijacked session alone can't change it
+        email_change_confirm_current: bool, true, def,   true;
+        /// Email change expiration time |> Number of hours after which a pending email change expires
+        email_change_expiration_hours: u32, true, def,   24;
         /// Password iterations |> Number of server-side passwords hashing iterations for the password hash.
         /// The default for new users. If changed, it will be updated during login for existing users.
         password_iterations:    i32,    true,   def,    600_000;
+        /// Minimum PBKDF2 KDF iterations |> The minimum number of client side PBKDF2 iterations accepted on registration and KDF changes
+        kdf_min_pbkdf2_iterations: i32, true,   def,    100_000;
+        /// Minimum Argon2 KDF iterations |> The minimum number of client side Argon2id iterations accepted on registration and KDF changes
+        kdf_min_argon2_iterations: i32, true,   def,    1;
+        /// Minimum Argon2 KDF memory |> The minimum client side Argon2id memory in MB accepted on registration and KDF changes
+        kdf_min_argon2_memory:  i32,    true,   def,    15;
+        /// Minimum Argon2 KDF parallelism |> The minimum client side Argon2id parallelism accepted on registration and KDF changes
+        kdf_min_argon2_parallelism: i32, true,  def,    1;
         /// Allow password hints |> Controls whether users can set or show password hints. This setting applies globally to all users.
         password_hints_allowed: bool,   true,   def,    true;
         /// Show password hint (Know the risks!) |> Controls whether a password hint should be shown directly in the web page
         /// if SMTP service is not configured and password hints are allowed. Not recommended for publicly-accessible instances
         /// because this provides unauthenticated access to potentially sensitive data.
         show_password_hint:     bool,   true,   def,    false;
+        /// Send password hints by email |> Controls whether a password hint is sent by email when SMTP is configured.
+        /// When disabled, hints are only available if `show_password_hint` is enabled
+        password_hints_email:   bool,   true,   def,    true;
+        /// Password hints only from known IPs |> Only provide a password hint when the request comes from an IP address from which
+        /// the account recently logged in successfully. Only the logins after enabling this setting are known
+        password_hints_require_known_ip: bool, true, def, false;
+        /// CAPTCHA provider |> Require a CAPTCHA for registrations and password hint requests, either `hcaptcha` or `turnstile`.
+        /// The client needs to send the response of the widget as `captchaResponse`
+        captcha_provider:       String, true,   option;
+        /// CAPTCHA site key |> The public site key of the CAPTCHA widget
+        captcha_site_key:       String, true,   option;
+        /// CAPTCHA secret |> The secret key used to verify the CAPTCHA responses
+        captcha_secret:         Pass,   true,   option;
+        /// Master password minimum length |> Minimum length of the master password, 0 to disable. The clients enforce this since the server only receives a hash of the master password
+        master_password_min_length: u32, true,  def,    0;
+        /// Master password minimum complexity |> Minimum strength score (0-4) of the master password, 0 to disable
+        master_password_min_complexity: u8, true, def,  0;
+        /// Master password requires lowercase letters
+        master_password_require_lower: bool, true, def, false;
+        /// Master password requires uppercase letters
+        master_password_require_upper: bool, true, def, false;
+        /// Master password requires numbers
+        master_password_require_numbers: bool, true, def, false;
+        /// Master password requires special characters
+        master_password_require_special: bool, true, def, false;
+        /// Enforce master password policy on login |> Require existing users with a non compliant master password to change it when logging in
+        master_password_enforce_on_login: bool, true, def, false;
 
         /// Admin token/Argon2 PHC |> The plain text token or Argon2 PHC string used to authenticate in this very same page. Changing it here will not deauthorize the current session!
         admin_token:            Pass,   true,   option;
+        /// Admin TOTP secret |> Base32 encoded secret of the authenticator app which is required as a second factor to log in to this page.
+        /// Use the setup in the "Admin Two-step Login" section to enable it, clear this value to disable it again. Also required when the admin token is disabled.
+        admin_totp_secret:      Pass,   true,   option;
 
         /// Invitation organization name |> Name shown in the invitation emails that don't come from a specific organization
         invitation_org_name:    String, true,   def,    "Vaultwarden".to_string();
 
         /// Events days retain |> Number of days to retain events stored in the database. If unset, events are kept indefinitely.
         events_days_retain:     i64,    false,   option;
+
+        /// Demo mode |> Makes the whole instance read-only. Logging in and reading data still works, but all changes are rejected.
+        /// Useful to host a public demo instance which never needs to be cleaned up. The background jobs are disabled,
+        /// and a demo account with example items is created when it does not exist yet.
+        demo_mode:              bool,   false,  def,    false;
+        /// Demo account email |> The email address visitors of the demo log in with
+        demo_account_email:     String, false,  def,    "demo@example.com".to_string();
+        /// Demo account password |> The master password visitors of the demo log in with, it is shown publicly in the example items
+        demo_account_password:  String, false,  def,    "vaultwarden-demo".to_string();
+        /// Demo seed database |> SQLite database which is copied to the database location when it does not exist yet while demo mode is enabled.
+        /// A backup created via the admin panel of an instance containing example data can be used for this.
+        demo_seed_database:     String, false,  option;
     },
 
     /// Advanced settings
@@ -573,17 +689,58 @@ make_config! {
         /// Disable Two-Factor remember |> Enabling this would force the users to use a second factor to login every time.
         /// Note that the checkbox would still be present, but ignored.
         disable_2fa_remember:   bool,   true,   def,    false;
-
-        /// Disable authenticator time drifted codes to be valid |> Enabling this only allows the current TOTP code to be valid
-        /// TOTP codes of the previous and next 30 seconds will be invalid.
-        authenticator_disable_time_drift: bool, true, def, false;
+        /// Two-Factor remember duration |> Number of days a device stays remembered after a successful two-step login,
+        /// after which the second factor is requested again
+        twofactor_remember_days: i64,   true,   def,    30;
+        /// Rotate refresh tokens |> Issue a new refresh token on every use. When an already used refresh token is presented again,
+        /// all the refresh tokens of that login session are revoked and the client has to log in again
+        refresh_token_rotation: bool,   true,   def,    true;
+        /// Refresh token grace period |> Number of seconds the refresh token replaced by a rotation is still accepted,
+        /// so a concurrent refresh or a retry after a lost response is not mistaken for a replay. Set to 0 to disable
+        refresh_token_grace_seconds: i64, true, def,    30;
+
+        /// [Deprecated] Disable authenticator time drifted codes to be valid |> Use `authenticator_time_drift_steps` set to 0 instead
+        authenticator_disable_time_drift: bool, false, def, false;
+        /// Authenticator time drift window |> Number of 30 second time steps before and after the current one for which a TOTP code is still accepted.
+        /// Set to 0 to only allow the current TOTP code. A used code, or any code older than it, can never be used again.
+        authenticator_time_drift_steps: u8, true, auto, |c| u8::from(!c.authenticator_disable_time_drift);
+
+        /// Two-Factor lockout attempts |> Number of consecutive failed two-step login attempts after which the second factor of an account is temporarily locked.
+        /// The user is notified by email when this happens. Set to 0 to disable the lockout.
+        twofactor_lockout_attempts: u32, true, def, 10;
+        /// Two-Factor lockout duration |> Number of seconds the second factor stays locked after too many failed attempts
+        twofactor_lockout_duration: i64, true, def, 900;
+
+        /// Admin alert email |> When set, incidents of background components detected by the watchdog are sent to this address
+        admin_alert_email:      String, true,   option;
+
+        /// User webhook URL |> When set, a JSON POST request is sent to this URL when a user is invited, registers, verifies their email or is deleted.
+        /// The URL may point to the internal network, `HTTP_REQUEST_BLOCK_NON_GLOBAL_IPS` and `HTTP_REQUEST_BLOCK_REGEX` don't apply to it.
+        /// Useful to keep external systems (billing, directories, portals) in sync with this instance.
+        user_webhook_url:       String, true,   option;
+        /// User webhook secret |> When set, the request body is signed with HMAC-SHA256 using this secret and the hex signature is sent in the `X-Webhook-Signature` header
+        user_webhook_secret:    Pass,   true,   option;
+        /// User webhook events |> Comma separated list of the events to send: `invited`, `registered`, `email_verified` and `deleted`
+        user_webhook_events:    String, true,   def,    "invited,registered,email_verified,deleted".to_string();
 
         /// Customize the enabled feature flags on the clients |> This is a comma separated list of feature flags to enable.
         experimental_client_feature_flags: String, false, def, "fido2-vault-credentials".to_string();
 
+        /// GeoIP City database path |> Path to a local City MMDB file (MaxMind GeoLite2/GeoIP2 or compatible).
+        /// When set, security emails and events are enriched with the city and country of the IP address. No external lookups are made.
+        geoip_city_db_path:     String, false,  option;
+        /// GeoIP ASN database path |> Path to a local ASN MMDB file (MaxMind GeoLite2/GeoIP2 or compatible).
+        /// When set, security emails and events are enriched with the autonomous system of the IP address. No external lookups are made.
+        geoip_asn_db_path:      String, false,  option;
+
         /// Require new device emails |> When a user logs in an email is required to be sent.
         /// If sending the email fails the login attempt will fail.
         require_device_email:   bool,   true,   def,     false;
+        /// Require new device approval |> Logins from devices which were never used by the user are held until the user approves them via an emailed link.
+        /// Logging in with an existing device ("Log in with device") counts as approval. Requires email to be configured.
+        new_device_approval:    bool,   true,   def,     false;
+        /// New device approval expiration (in minutes) |> The number of minutes during which a pending device can be approved and used to log in
+        new_device_approval_expiration_minutes: u32, true, def, 60;
 
         /// Reload templates (Dev) |> When this is set to true, the templates get reloaded with every request.
         /// ONLY use this during development, as it can slow down the server
@@ -599,6 +756,10 @@ make_config! {
         /// Log level |> Valid values are "trace", "debug", "info", "warn", "error" and "off"
         /// For a specific module append it as a comma separated value "info,path::to::module=debug"
         log_level:              String, false,  def,    "info".to_string();
+        /// Redact personal data from logs |> Replace email addresses and tokens in the log output. Not applied when the log level is debug or trace
+        log_redaction:          bool,   false,  def,    false;
+        /// Redact IP addresses from logs |> Also replace IP addresses when the log redaction is enabled
+        log_redact_ips:         bool,   false,  def,    true;
 
         /// Enable DB WAL |> Turning this off might lead to worse performance, but might help if using vaultwarden on some exotic filesystems,
         /// that do not support WAL. Please make sure you read project wiki on the topic before changing this setting.
@@ -635,6 +796,33 @@ make_config! {
         /// Max burst size for admin login requests |> Allow a burst of requests of up to this size, while maintaining the average indicated by `admin_ratelimit_seconds`
         admin_ratelimit_max_burst:     u32, false, def, 3;
 
+        /// Login backoff attempts |> Number of consecutive failed logins of an account or the admin panel from an IP address after which further attempts are delayed.
+        /// Every further failure doubles the delay. Set to 0 to disable the backoff.
+        login_backoff_attempts:        u32, true, def, 5;
+        /// Login backoff attempts per account |> Number of failed logins of an account from all IP addresses together after which further attempts are delayed.
+        /// Should be well above the attempts per IP address, as the failures from other addresses also delay the logins of the owner. Set to 0 to only back off per IP address.
+        login_backoff_account_attempts: u32, true, def, 50;
+        /// Login backoff base delay |> Number of seconds a login is delayed after reaching the number of failed attempts
+        login_backoff_base_seconds:    u64, true, def, 30;
+        /// Login backoff max delay |> Maximum number of seconds a login is delayed, also the time after which the failed attempts are forgotten
+        login_backoff_max_seconds:     u64, true, def, 900;
+
+        /// Seconds between vault exports |> Number of seconds, on average, between vault exports by the same user before rate limiting kicks in. Counts the organization exports and the personal exports reported by the clients
+        export_ratelimit_seconds:      u64, false, def, 600;
+        /// Max burst size for vault exports |> Allow a burst of exports of up to this size, while maintaining the average indicated by `export_ratelimit_seconds`
+        export_ratelimit_max_burst:    u32, false, def, 3;
+        /// Seconds between password hint requests |> Number of seconds, on average, between password hint requests from the same IP address before rate limiting kicks in
+        password_hint_ratelimit_seconds: u64, false, def, 300;
+        /// Max burst size for password hint requests |> Allow a burst of requests of up to this size, while maintaining the average indicated by `password_hint_ratelimit_seconds`
+        password_hint_ratelimit_max_burst: u32, false, def, 3;
+        /// Protected action window |> Number of seconds after verifying the master password or a protected action OTP during which protected actions are allowed
+        protected_action_window_secs:  u64, true, def, 300;
+        /// Require fresh verification for exports |> Organization vault exports are only allowed shortly after the user verified their master password or a protected action OTP.
+        /// Current clients do this before every export, older clients or scripts might not.
+        export_require_fresh_verification: bool, true, def, false;
+        /// Notify on vault export |> Send an email to the user every time their vault, or an organization vault, is exported using their account
+        export_notification:           bool, true, def, true;
+
         /// Admin session lifetime |> Set the lifetime of admin sessions to this value (in minutes).
         admin_session_lifetime:        i64, true,  def, 20;
 
@@ -647,6 +835,10 @@ make_config! {
         /// Generated max_note_size value to prevent if..else matching during every check
         _max_note_size:                usize, false, generated, |c| if c.increase_note_size_limit {100_000} else {10_000};
 
+        /// Max JSON request size |> The maximum size in MB of a JSON request body, this limits the size of vault imports.
+        /// The vault is encrypted by the client, so importing from other password managers can't be done by the server.
+        json_size_limit:               u64,   false, def, 20;
+
         /// Enforce Single Org with Reset Password Policy |> Enforce that the Single Org policy is enabled before setting the Reset Password policy
         /// Bitwarden enforces this by default. In Vaultwarden we encouraged to use multiple organizations because groups were not available.
         /// Setting this to true will enforce the Single Org Policy to be enabled before you can enable the Reset Password policy.
@@ -661,8 +853,10 @@ make_config! {
         yubico_client_id:       String, true,   option;
         /// Secret Key
         yubico_secret_key:      Pass,   true,   option;
-        /// Server
+        /// Server |> Comma-separated list of YubiCloud compatible validation URLs, e.g. of self-hosted yubikey-val instances. When set, the official YubiCloud servers are not used
         yubico_server:          String, true,   option;
+        /// Allow HTTP servers |> Allow validation URLs using plain HTTP, only use this for servers within a trusted network
+        yubico_server_allow_http: bool, true,   def,     false;
     },
 
     /// Global Duo settings (Note that users can override them)
@@ -681,6 +875,48 @@ make_config! {
         _duo_akey:              Pass,   false,  option;
     },
 
+    /// LDAP directory settings
+    ldap: _enable_ldap {
+        /// Enabled
+        _enable_ldap:           bool,   true,   def,     true;
+        /// Server URL |> The directory server, e.g. ldaps://ldap.example.com or ldap://ldap.example.com:389. When set, password logins require an existing directory entry
+        ldap_url:               String, true,   option;
+        /// Use StartTLS |> Upgrade a plain ldap:// connection using StartTLS
+        ldap_starttls:          bool,   true,   def,     false;
+        /// Accept invalid certificates |> Only use this for servers within a trusted network
+        ldap_tls_insecure:      bool,   true,   def,     false;
+        /// Bind DN |> The service account used to look up users. Leave empty to use an anonymous bind
+        ldap_bind_dn:           String, true,   option;
+        /// Bind password
+        ldap_bind_password:     Pass,   true,   option;
+        /// User DN template |> DN of the directory entry for a user, {email} and {username} (the part before the @) are replaced, e.g. uid={username},ou=people,dc=example,dc=com
+        ldap_user_dn_template:  String, true,   option;
+        /// User filter |> Filter the user entry has to match to be allowed to log in, e.g. to exclude disabled accounts
+        ldap_user_filter:       String, true,   def,     "(objectClass=*)".to_string();
+        /// Timeout |> Number of seconds to wait for the directory server
+        ldap_timeout:           u64,    true,   def,     10;
+    },
+
+    /// S3 storage settings
+    s3: _enable_s3 {
+        /// Enabled
+        _enable_s3:             bool,   false,  def,     true;
+        /// Endpoint |> The S3 compatible endpoint, e.g. https://s3.eu-central-1.amazonaws.com. When set, attachments and Send files are stored in the bucket instead of the local folders
+        s3_endpoint:            String, false,  option;
+        /// Region
+        s3_region:              String, false,  def,     "us-east-1".to_string();
+        /// Bucket
+        s3_bucket:              String, false,  option;
+        /// Access key id
+        s3_access_key_id:       String, false,  option;
+        /// Secret access key
+        s3_secret_access_key:   Pass,   false,  option;
+        /// Path-style addressing |> Address the bucket in the path (https://endpoint/bucket) instead of the host (https://bucket.endpoint), needed by most self-hosted servers
+        s3_path_style:          bool,   false,  def,     false;
+        /// Download URL expiration |> Number of seconds the presigned URLs the clients download the files from stay valid
+        s3_presign_expiration:  u64,    false,  def,     300;
+    },
+
     /// SMTP Email Settings
     smtp: _enable_smtp {
         /// Enabled
@@ -711,6 +947,10 @@ make_config! {
         smtp_auth_mechanism:           String, true,   option;
         /// SMTP connection timeout |> Number of seconds when to stop trying to connect to the SMTP server
         smtp_timeout:                  u64,    true,   def,     15;
+        /// Priority mail concurrency |> Maximum number of security critical mails (tokens, login alerts) sent at the same time
+        smtp_priority_concurrency:     usize,  false,  def,     8;
+        /// Bulk mail concurrency |> Maximum number of bulk mails (invites, welcome mails, reminders) sent at the same time, these always wait for queued priority mail
+        smtp_bulk_concurrency:         usize,  false,  def,     2;
         /// Server name sent during HELO |> By default this value should be is on the machine's hostname, but might need to be changed in case it trips some anti-spam filters
         helo_name:                     String, true,   option;
         /// Embed images as email attachments.
@@ -733,6 +973,8 @@ make_config! {
         email_token_size:       u8,     true,   def,      6;
         /// Token expiration time |> Maximum time in seconds a token is valid. The time the user has to open email client and copy token.
         email_expiration_time:  u64,    true,   def,      600;
+        /// Token resend cooldown |> Minimum time in seconds before a new token can be requested. Issuing a new token invalidates the previous one. Ends when the token expires. Set to 0 to disable.
+        email_token_resend_cooldown: u64, true,  def,      60;
         /// Maximum attempts |> Maximum attempts before an email token is reset and a new email will need to be sent
         email_attempts_limit:   u64,    true,   def,      3;
         /// Setup email 2FA at signup |> Setup email 2FA provider on registration regardless of any organization policy
@@ -758,6 +1000,19 @@ fn validate_config(cfg: &ConfigItems) -> Result<(), Error> {
         err!("PASSWORD_ITERATIONS should be at least 100000 or higher. The default is 600000!");
     }
 
+    if cfg.kdf_min_pbkdf2_iterations < 100_000 {
+        err!("`KDF_MIN_PBKDF2_ITERATIONS` should be at least 100000")
+    }
+    if cfg.kdf_min_argon2_iterations < 1 {
+        err!("`KDF_MIN_ARGON2_ITERATIONS` should be at least 1")
+    }
+    if !(15..=1024).contains(&cfg.kdf_min_argon2_memory) {
+        err!("`KDF_MIN_ARGON2_MEMORY` should be between 15 and 1024")
+    }
+    if !(1..=16).contains(&cfg.kdf_min_argon2_parallelism) {
+        err!("`KDF_MIN_ARGON2_PARALLELISM` should be between 1 and 16")
+    }
+
     let limit = 256;
     if cfg.database_max_conns < 1 || cfg.database_max_conns > limit {
         err!(format!("`DATABASE_MAX_CONNS` contains an invalid value. Ensure it is between 1 and {limit}.",));
@@ -769,6 +1024,15 @@ fn validate_config(cfg: &ConfigItems) -> Result<(), Error> {
         }
     }
 
+    for (name, path) in [("GEOIP_CITY_DB_PATH", &cfg.geoip_city_db_path), ("GEOIP_ASN_DB_PATH", &cfg.geoip_asn_db_path)]
+    {
+        if let Some(path) = path {
+            if !std::path::Path::new(path).is_file() {
+                err!(format!("`{name}` is set but `{path}` does not exist or is not a file"));
+            }
+        }
+    }
+
     let dom = cfg.domain.to_lowercase();
     if !dom.starts_with("http://") && !dom.starts_with("https://") {
         err!(
@@ -787,6 +1051,18 @@ fn validate_config(cfg: &ConfigItems) -> Result<(), Error> {
     if !whitelist.is_empty() && whitelist.split(',').any(|d| d.trim().is_empty()) {
         err!("`SIGNUPS_DOMAINS_WHITELIST` contains empty tokens");
     }
+    if whitelist.split(',').any(|d| d.trim().trim_start_matches("*.").contains('*')) {
+        err!("`SIGNUPS_DOMAINS_WHITELIST` only supports a wildcard at the start of a domain, like `*.example.org`");
+    }
+
+    if let Some(ref provider) = cfg.captcha_provider {
+        if !matches!(provider.as_str(), "hcaptcha" | "turnstile") {
+            err!("`CAPTCHA_PROVIDER` must be either `hcaptcha` or `turnstile`")
+        }
+        if cfg.captcha_site_key.is_none() || cfg.captcha_secret.is_none() {
+            err!("`CAPTCHA_PROVIDER` requires both `CAPTCHA_SITE_KEY` and `CAPTCHA_SECRET` to be set")
+        }
+    }
 
     let org_creation_users = cfg.org_creation_users.trim().to_lowercase();
     if !(org_creation_users.is_empty() || org_creation_users == "all" || org_creation_users == "none")
@@ -875,6 +1151,18 @@ fn validate_config(cfg: &ConfigItems) -> Result<(), Error> {
         }
     }
 
+    if !(1i64..=MAX_FILESIZE_KB).contains(&cfg.send_file_size_limit) {
+        err!("`SEND_FILE_SIZE_LIMIT` is out of bounds");
+    }
+
+    if !(1..=512).contains(&cfg.json_size_limit) {
+        err!("`JSON_SIZE_LIMIT` must be between 1 and 512 MB");
+    }
+
+    if cfg.trash_auto_delete_days.is_some_and(|days| days < 0) {
+        err!("`TRASH_AUTO_DELETE_DAYS` can't be negative")
+    }
+
     if cfg._enable_duo
         && (cfg.duo_host.is_some() || cfg.duo_ikey.is_some() || cfg.duo_skey.is_some())
         && !(cfg.duo_host.is_some() && cfg.duo_ikey.is_some() && cfg.duo_skey.is_some())
@@ -888,13 +1176,91 @@ fn validate_config(cfg: &ConfigItems) -> Result<(), Error> {
         }
 
         if let Some(yubico_server) = &cfg.yubico_server {
-            let yubico_server = yubico_server.to_lowercase();
-            if !yubico_server.starts_with("https://") {
-                err!("`YUBICO_SERVER` must be a valid URL and start with 'https://'. Either unset this variable or provide a valid URL.")
+            // Empty entries, like the one after a trailing comma, are skipped
+            let servers: Vec<&str> = yubico_server.split(',').map(str::trim).filter(|s| !s.is_empty()).collect();
+            if servers.is_empty() {
+                err!(
+                    "`YUBICO_SERVER` must contain at least one URL. Either unset this variable or provide a valid URL."
+                )
+            }
+            for server in servers {
+                let server = server.to_lowercase();
+                let allowed_scheme =
+                    server.starts_with("https://") || (cfg.yubico_server_allow_http && server.starts_with("http://"));
+                if !allowed_scheme || Url::parse(&server).is_err() {
+                    err!(format!(
+                        "`YUBICO_SERVER` entry '{server}' must be a valid URL and start with 'https://' (or 'http://' when `YUBICO_SERVER_ALLOW_HTTP` is enabled). Either unset this variable or provide a valid URL."
+                    ))
+                }
             }
         }
     }
 
+    if cfg.master_password_min_complexity > 4 {
+        err!("`MASTER_PASSWORD_MIN_COMPLEXITY` must be between 0 and 4")
+    }
+
+    if cfg._enable_ldap {
+        if let Some(ldap_url) = &cfg.ldap_url {
+            let ldap_url = ldap_url.to_lowercase();
+            if !(ldap_url.starts_with("ldap://") || ldap_url.starts_with("ldaps://")) || Url::parse(&ldap_url).is_err()
+            {
+                err!("`LDAP_URL` must be a valid URL and start with 'ldap://' or 'ldaps://'")
+            }
+            if cfg.ldap_starttls && ldap_url.starts_with("ldaps://") {
+                err!("`LDAP_STARTTLS` can't be used together with an 'ldaps://' `LDAP_URL`")
+            }
+
+            match &cfg.ldap_user_dn_template {
+                Some(template) if template.contains("{email}") || template.contains("{username}") => (),
+                _ => err!(
+                    "`LDAP_USER_DN_TEMPLATE` needs to be set and contain {email} or {username} when `LDAP_URL` is set"
+                ),
+            }
+
+            if cfg.ldap_bind_dn.is_some() != cfg.ldap_bind_password.is_some() {
+                err!("Both `LDAP_BIND_DN` and `LDAP_BIND_PASSWORD` must be set, or neither for an anonymous bind")
+            }
+
+            let filter = cfg.ldap_user_filter.trim();
+            if !(filter.starts_with('(') && filter.ends_with(')')) {
+                err!("`LDAP_USER_FILTER` must be a valid LDAP filter enclosed in parentheses")
+            }
+
+            if cfg.ldap_timeout == 0 {
+                err!("`LDAP_TIMEOUT` must be larger than 0")
+            }
+        }
+    }
+
+    if cfg._enable_s3 && cfg.s3_endpoint.is_some() {
+        match cfg.s3_endpoint.as_deref().map(Url::parse) {
+            Some(Ok(url))
+                if matches!(url.scheme(), "http" | "https")
+                    && url.host_str().is_some()
+                    && url.path() == "/"
+                    && url.query().is_none() => {}
+            _ => err!("`S3_ENDPOINT` must be a valid URL without a path, starting with 'http://' or 'https://'"),
+        }
+        // The requests to the endpoint are blocked like any other outgoing request
+        let endpoint_ip =
+            Url::parse(cfg.s3_endpoint.as_deref().unwrap_or_default()).ok().and_then(|url| match url.host() {
+                Some(url::Host::Ipv4(ip)) => Some(std::net::IpAddr::V4(ip)),
+                Some(url::Host::Ipv6(ip)) => Some(std::net::IpAddr::V6(ip)),
+                _ => None,
+            });
+        if cfg.http_request_block_non_global_ips && endpoint_ip.is_some_and(|ip| !crate::util::is_global(ip)) {
+            err!("`S3_ENDPOINT` is not a global IP address, disable `HTTP_REQUEST_BLOCK_NON_GLOBAL_IPS` to use it")
+        }
+        if cfg.s3_bucket.is_none() || cfg.s3_access_key_id.is_none() || cfg.s3_secret_access_key.is_none() {
+            err!("`S3_BUCKET`, `S3_ACCESS_KEY_ID` and `S3_SECRET_ACCESS_KEY` need to be set when `S3_ENDPOINT` is set")
+        }
+        // Presigned URLs can't be valid for longer than a week
+        if cfg.s3_presign_expiration == 0 || cfg.s3_presign_expiration > 604_800 {
+            err!("`S3_PRESIGN_EXPIRATION` must be between 1 and 604800 seconds")
+        }
+    }
+
     if cfg._enable_smtp {
         match cfg.smtp_security.as_str() {
             "off" | "starttls" | "force_tls" => (),
@@ -955,6 +1321,11 @@ fn validate_config(cfg: &ConfigItems) -> Result<(), Error> {
         }
     }
 
+    if cfg._enable_email_2fa && cfg.email_token_resend_cooldown >= cfg.email_expiration_time {
+        println!("[WARNING] `EMAIL_TOKEN_RESEND_COOLDOWN` is not lower than `EMAIL_EXPIRATION_TIME`.");
+        println!("[WARNING] The cooldown ends when the token expires, so a new token can be requested then.");
+    }
+
     if cfg._enable_email_2fa && !(cfg.smtp_host.is_some() || cfg.use_sendmail) {
         err!("To enable email 2FA, a mail transport must be configured")
     }
@@ -966,6 +1337,58 @@ fn validate_config(cfg: &ConfigItems) -> Result<(), Error> {
         err!("To use email 2FA as automatic fallback, email 2fa has to be enabled!");
     }
 
+    if let Some(ref url) = cfg.user_webhook_url {
+        match Url::parse(url) {
+            Ok(u) if u.scheme() == "http" || u.scheme() == "https" => {}
+            _ => err!("`USER_WEBHOOK_URL` must be a valid http(s) URL"),
+        }
+    }
+    for event in cfg.user_webhook_events.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        if !["invited", "registered", "email_verified", "deleted"].contains(&event) {
+            err!(format!("`USER_WEBHOOK_EVENTS` contains an unknown event: `{event}`"))
+        }
+    }
+
+    if cfg.twofactor_remember_days < 1 || cfg.twofactor_remember_days > 365 {
+        err!("`TWOFACTOR_REMEMBER_DAYS` must be between 1 and 365")
+    }
+
+    if cfg.refresh_token_grace_seconds < 0 || cfg.refresh_token_grace_seconds > 300 {
+        err!("`REFRESH_TOKEN_GRACE_SECONDS` must be between 0 and 300")
+    }
+
+    if cfg.new_device_approval && !(cfg._enable_smtp && (cfg.smtp_host.is_some() || cfg.use_sendmail)) {
+        err!("`NEW_DEVICE_APPROVAL` requires email to be configured")
+    }
+
+    if cfg.org_domain_auto_join
+        && !(cfg.signups_verify && cfg._enable_smtp && (cfg.smtp_host.is_some() || cfg.use_sendmail))
+    {
+        err!("`ORG_DOMAIN_AUTO_JOIN` requires `SIGNUPS_VERIFY` to be enabled and email to be configured")
+    }
+
+    if cfg.org_require_verified_email && !(cfg._enable_smtp && (cfg.smtp_host.is_some() || cfg.use_sendmail)) {
+        err!("`ORG_REQUIRE_VERIFIED_EMAIL` requires email to be configured")
+    }
+
+    if cfg.new_device_approval_expiration_minutes < 1 {
+        err!("`NEW_DEVICE_APPROVAL_EXPIRATION_MINUTES` must be at least 1")
+    }
+
+    if cfg.login_backoff_attempts > 0
+        && (cfg.login_backoff_base_seconds < 1 || cfg.login_backoff_max_seconds < cfg.login_backoff_base_seconds)
+    {
+        err!("`LOGIN_BACKOFF_BASE_SECONDS` must be at least 1 and not larger than `LOGIN_BACKOFF_MAX_SECONDS`")
+    }
+
+    if cfg.twofactor_lockout_attempts > 0 && cfg.twofactor_lockout_duration < 1 {
+        err!("`TWOFACTOR_LOCKOUT_DURATION` must be at least 1 second")
+    }
+
+    if cfg.authenticator_time_drift_steps > 10 {
+        err!("`AUTHENTICATOR_TIME_DRIFT_STEPS` can not be larger than 10 (5 minutes)")
+    }
+
     // Check if the HTTP request block regex is valid
     if let Some(ref r) = cfg.http_request_block_regex {
         let validate_regex = regex::Regex::new(r);
@@ -1010,6 +1433,11 @@ fn validate_config(cfg: &ConfigItems) -> Result<(), Error> {
         err!("`TRASH_PURGE_SCHEDULE` is not a valid cron expression")
     }
 
+    if !cfg.password_history_trim_schedule.is_empty() && cfg.password_history_trim_schedule.parse::<Schedule>().is_err()
+    {
+        err!("`PASSWORD_HISTORY_TRIM_SCHEDULE` is not a valid cron expression")
+    }
+
     if !cfg.incomplete_2fa_schedule.is_empty() && cfg.incomplete_2fa_schedule.parse::<Schedule>().is_err() {
         err!("`INCOMPLETE_2FA_SCHEDULE` is not a valid cron expression")
     }
@@ -1026,6 +1454,61 @@ fn validate_config(cfg: &ConfigItems) -> Result<(), Error> {
         err!("`EMERGENCY_REQUEST_TIMEOUT_SCHEDULE` is not a valid cron expression")
     }
 
+    if !cfg.org_deletion_schedule.is_empty() && cfg.org_deletion_schedule.parse::<Schedule>().is_err() {
+        err!("`ORG_DELETION_SCHEDULE` is not a valid cron expression")
+    }
+
+    if !cfg.user_deletion_schedule.is_empty() && cfg.user_deletion_schedule.parse::<Schedule>().is_err() {
+        err!("`USER_DELETION_SCHEDULE` is not a valid cron expression")
+    }
+
+    if !cfg.org_domain_verification_schedule.is_empty()
+        && cfg.org_domain_verification_schedule.parse::<Schedule>().is_err()
+    {
+        err!("`ORG_DOMAIN_VERIFICATION_SCHEDULE` is not a valid cron expression")
+    }
+
+    if !cfg.org_export_schedule.is_empty() && cfg.org_export_schedule.parse::<Schedule>().is_err() {
+        err!("`ORG_EXPORT_SCHEDULE` is not a valid cron expression")
+    }
+
+    if cfg.org_export_retention < 1 {
+        err!("`ORG_EXPORT_RETENTION` must be at least 1")
+    }
+
+    if let Some(ref secret) = cfg.admin_totp_secret {
+        if data_encoding::BASE32.decode(secret.as_bytes()).is_err() {
+            err!("`ADMIN_TOTP_SECRET` is not a valid base32 encoded secret")
+        }
+    }
+
+    if cfg.demo_mode && !is_valid_email(&cfg.demo_account_email) {
+        err!(format!("`DEMO_ACCOUNT_EMAIL` '{}' is not a valid email address", cfg.demo_account_email))
+    }
+
+    if let Some(ref seed) = cfg.demo_seed_database {
+        if !cfg.demo_mode {
+            err!("`DEMO_SEED_DATABASE` can only be used when `DEMO_MODE` is enabled")
+        }
+        if !std::path::Path::new(seed).is_file() {
+            err!(format!("`DEMO_SEED_DATABASE` file '{seed}' does not exist"))
+        }
+    }
+
+    if cfg.watchdog_stall_secs < 10 {
+        err!("`WATCHDOG_STALL_SECS` should be at least 10 seconds")
+    }
+
+    if cfg.watchdog_failure_threshold < 1 {
+        err!("`WATCHDOG_FAILURE_THRESHOLD` should be at least 1")
+    }
+
+    if let Some(ref address) = cfg.admin_alert_email {
+        if !address.contains('@') {
+            err!("`ADMIN_ALERT_EMAIL` is not a valid email address")
+        }
+    }
+
     if !cfg.event_cleanup_schedule.is_empty() && cfg.event_cleanup_schedule.parse::<Schedule>().is_err() {
         err!("`EVENT_CLEANUP_SCHEDULE` is not a valid cron expression")
     }
@@ -1132,6 +1615,15 @@ fn smtp_convert_deprecated_ssl_options(smtp_ssl: Option<bool>, smtp_explicit_tls
     "starttls".to_string()
 }
 
+/// Tests whether the lowercase `email_domain` is in the comma separated `whitelist`.
+/// Entries starting with `*.` match all the subdomains of the domain, but not the domain itself.
+fn is_domain_whitelisted(whitelist: &str, email_domain: &str) -> bool {
+    whitelist.split(',').map(str::trim).any(|d| match d.strip_prefix("*.") {
+        Some(parent) => email_domain.strip_suffix(parent).is_some_and(|sub| sub.len() > 1 && sub.ends_with('.')),
+        None => d == email_domain,
+    })
+}
+
 impl Config {
     pub fn load() -> Result<Self, Error> {
         // Loading from env and file
@@ -1199,7 +1691,7 @@ impl Config {
         Ok(())
     }
 
-    fn update_config_partial(&self, other: ConfigBuilder) -> Result<(), Error> {
+    pub fn update_config_partial(&self, other: ConfigBuilder) -> Result<(), Error> {
         let builder = {
             let usr = &self.inner.read().unwrap()._usr;
             let mut _overrides = Vec::new();
@@ -1211,6 +1703,7 @@ impl Config {
     /// Tests whether an email's domain is allowed. A domain is allowed if it
     /// is in signups_domains_whitelist, or if no whitelist is set (so there
     /// are no domain restrictions in effect).
+    /// Entries starting with `*.` match all the subdomains of the domain, but not the domain itself.
     pub fn is_email_domain_allowed(&self, email: &str) -> bool {
         let e: Vec<&str> = email.rsplitn(2, '@').collect();
         if e.len() != 2 || e[0].is_empty() || e[1].is_empty() {
@@ -1220,7 +1713,21 @@ impl Config {
         let email_domain = e[0].to_lowercase();
         let whitelist = self.signups_domains_whitelist();
 
-        whitelist.is_empty() || whitelist.split(',').any(|d| d.trim() == email_domain)
+        whitelist.is_empty() || is_domain_whitelisted(&whitelist, &email_domain)
+    }
+
+    /// The reason shown when a signup is not allowed, only call this when `is_signup_allowed` returned false.
+    /// It doesn't reveal whether an account exists, only how the signups are restricted.
+    pub fn signup_denied_reason(&self, email: &str) -> String {
+        if self.signups_domains_whitelist().is_empty() {
+            return String::from(
+                "Registration is not allowed on this server, you need an invitation to create an account",
+            );
+        }
+        match email.rsplit_once('@') {
+            Some((_, domain)) => format!("Registration is not allowed for email addresses of the domain {domain}"),
+            None => String::from("Registration is not allowed for this email address"),
+        }
     }
 
     /// Tests whether signup is allowed for an email address, taking into
@@ -1270,6 +1777,17 @@ impl Config {
         Ok(())
     }
 
+    /// Returns the settings saved through the admin panel, without the values coming from the environment.
+    pub fn user_config(&self) -> ConfigBuilder {
+        self.inner.read().unwrap()._usr.clone()
+    }
+
+    /// Reloads the templates from the templates folder, for example after custom templates have been restored.
+    pub fn reload_template_folder(&self) {
+        let templates = load_templates(self.templates_folder());
+        self.inner.write().unwrap().templates = templates;
+    }
+
     pub fn private_rsa_key(&self) -> String {
         format!("{}.pem", self.rsa_key_filename())
     }
@@ -1372,7 +1890,14 @@ where
 
     reg!("email/admin_reset_password", ".html");
     reg!("email/change_email", ".html");
+    reg!("email/change_email_confirm_current", ".html");
     reg!("email/delete_account", ".html");
+    reg!("email/delete_organization", ".html");
+    reg!("email/delete_organization_scheduled", ".html");
+    reg!("email/delete_organization_member_notice", ".html");
+    reg!("email/delete_account_scheduled", ".html");
+    reg!("email/delete_organization_cancelled", ".html");
+    reg!("email/org_export", ".html");
     reg!("email/emergency_access_invite_accepted", ".html");
     reg!("email/emergency_access_invite_confirmed", ".html");
     reg!("email/emergency_access_recovery_approved", ".html");
@@ -1381,9 +1906,13 @@ where
     reg!("email/emergency_access_recovery_reminder", ".html");
     reg!("email/emergency_access_recovery_timed_out", ".html");
     reg!("email/incomplete_2fa_login", ".html");
+    reg!("email/twofactor_lockout", ".html");
+    reg!("email/admin_alert", ".html");
+    reg!("email/vault_exported", ".html");
     reg!("email/invite_accepted", ".html");
     reg!("email/invite_confirmed", ".html");
     reg!("email/new_device_logged_in", ".html");
+    reg!("email/new_device_approval", ".html");
     reg!("email/protected_action", ".html");
     reg!("email/pw_hint_none", ".html");
     reg!("email/pw_hint_some", ".html");
@@ -1404,8 +1933,10 @@ where
     reg!("admin/users");
     reg!("admin/organizations");
     reg!("admin/diagnostics");
+    reg!("admin/data");
 
     reg!("404");
+    reg!("link_action");
 
     reg!(@withfallback "scss/vaultwarden.scss");
     reg!("scss/user.vaultwarden.scss");
@@ -1491,3 +2022,27 @@ handlebars::handlebars_helper!(webver: | web_vault_version: String |
 handlebars::handlebars_helper!(vwver: | vw_version: String |
     semver::VersionReq::parse(&vw_version).expect("Invalid Vaultwarden version compare string").matches(&VW_VERSION)
 );
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_domain_whitelist_exact() {
+        assert!(is_domain_whitelisted("example.org", "example.org"));
+        assert!(is_domain_whitelisted("example.com, example.org", "example.org"));
+        assert!(!is_domain_whitelisted("example.org", "sub.example.org"));
+        assert!(!is_domain_whitelisted("example.org", "example.com"));
+    }
+
+    #[test]
+    fn test_domain_whitelist_wildcard() {
+        assert!(is_domain_whitelisted("*.example.org", "sub.example.org"));
+        assert!(is_domain_whitelisted("*.example.org", "a.b.example.org"));
+        // The wildcard doesn't match the domain itself, nor domains which only end with the same text
+        assert!(!is_domain_whitelisted("*.example.org", "example.org"));
+        assert!(!is_domain_whitelisted("*.example.org", "badexample.org"));
+        assert!(!is_domain_whitelisted("*.example.org", ".example.org"));
+        assert!(is_domain_whitelisted("example.org,*.example.org", "example.org"));
+    }
+}
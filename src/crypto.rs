@@ -23,6 +23,12 @@ pub fn verify_password_hash(secret: &[u8], salt: &[u8], previous: &[u8], iterati
     pbkdf2::verify(DIGEST_ALG, iterations, salt, secret, previous).is_ok()
 }
 
+/// Only used for password hashes imported from an official server, new hashes always use `DIGEST_ALG`.
+pub fn verify_password_hash_sha512(secret: &[u8], salt: &[u8], previous: &[u8], iterations: u32) -> bool {
+    let iterations = NonZeroU32::new(iterations).expect("Iterations can't be zero");
+    pbkdf2::verify(pbkdf2::PBKDF2_HMAC_SHA512, iterations, salt, secret, previous).is_ok()
+}
+
 //
 // HMAC
 //
@@ -33,6 +39,13 @@ pub fn hmac_sign(key: &str, data: &str) -> String {
     HEXLOWER.encode(signature.as_ref())
 }
 
+pub fn hmac_sign_sha256(key: &str, data: &str) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, key.as_bytes());
+    let signature = hmac::sign(&key, data.as_bytes());
+
+    HEXLOWER.encode(signature.as_ref())
+}
+
 //
 // Random values
 //
@@ -113,3 +126,54 @@ pub fn ct_eq<T: AsRef<[u8]>, U: AsRef<[u8]>>(a: T, b: U) -> bool {
     use subtle::ConstantTimeEq;
     a.as_ref().ct_eq(b.as_ref()).into()
 }
+
+//
+// Passphrase based encryption
+//
+
+/// Data encrypted with a key derived from a passphrase, see `encrypt_with_passphrase`.
+pub struct PassphraseEncrypted {
+    pub iterations: u32,
+    pub salt: Vec<u8>,
+    pub nonce: Vec<u8>,
+    pub data: Vec<u8>,
+}
+
+/// Encrypts `plaintext` with AES-256-GCM using a key derived from `passphrase` via PBKDF2-SHA256.
+pub fn encrypt_with_passphrase(passphrase: &[u8], plaintext: &[u8], iterations: u32) -> PassphraseEncrypted {
+    use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM};
+
+    let salt = get_random_bytes::<32>().to_vec();
+    let nonce = get_random_bytes::<12>();
+    let key = hash_password(passphrase, &salt, iterations);
+
+    let key = LessSafeKey::new(UnboundKey::new(&AES_256_GCM, &key).expect("Invalid AES key length"));
+    let mut data = plaintext.to_vec();
+    key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut data)
+        .expect("Error encrypting data");
+
+    PassphraseEncrypted {
+        iterations,
+        salt,
+        nonce: nonce.to_vec(),
+        data,
+    }
+}
+
+/// Decrypts data produced by `encrypt_with_passphrase`.
+/// Returns `None` if the passphrase is wrong or the data was tampered with.
+pub fn decrypt_with_passphrase(passphrase: &[u8], encrypted: &PassphraseEncrypted) -> Option<Vec<u8>> {
+    use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM};
+
+    if encrypted.iterations == 0 {
+        return None;
+    }
+    let nonce = Nonce::try_assume_unique_for_key(&encrypted.nonce).ok()?;
+    let key = hash_password(passphrase, &encrypted.salt, encrypted.iterations);
+
+    let key = LessSafeKey::new(UnboundKey::new(&AES_256_GCM, &key).ok()?);
+    let mut data = encrypted.data.clone();
+    let plaintext = key.open_in_place(nonce, Aad::empty(), &mut data).ok()?;
+
+    Some(plaintext.to_vec())
+}
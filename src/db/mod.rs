@@ -60,6 +60,7 @@ macro_rules! generate_connections {
         pub struct DbConn {
             conn: Arc<Mutex<Option<DbConnInner>>>,
             permit: Option<OwnedSemaphorePermit>,
+            in_transaction: bool,
         }
 
         #[allow(non_camel_case_types)]
@@ -96,6 +97,7 @@ macro_rules! generate_connections {
             fn drop(&mut self) {
                 let conn = Arc::clone(&self.conn);
                 let permit = self.permit.take();
+                let in_transaction = self.in_transaction;
 
                 // Since connection can't be on the stack in an async fn during an
                 // await, we have to spawn a new blocking-safe thread...
@@ -103,7 +105,18 @@ macro_rules! generate_connections {
                     // And then re-enter the runtime to wait on the async mutex, but in a blocking fashion.
                     let mut conn = tokio::runtime::Handle::current().block_on(conn.lock_owned());
 
-                    if let Some(conn) = conn.take() {
+                    if let Some(mut conn) = conn.take() {
+                        // A transaction is still open when the request was cancelled, it must not end up in the pool
+                        if in_transaction {
+                            match &mut conn { $(
+                                #[cfg($name)]
+                                DbConnInner::$name(c) => {
+                                    if let Err(e) = c.batch_execute("ROLLBACK") {
+                                        error!("Error rolling back an unfinished transaction: {e:?}");
+                                    }
+                                },
+                            )+ }
+                        }
                         drop(conn);
                     }
 
@@ -113,6 +126,46 @@ macro_rules! generate_connections {
             }
         }
 
+        impl DbConn {
+            async fn batch_execute(&self, query: &'static str) -> Result<(), Error> {
+                let mut conn = Arc::clone(&self.conn).lock_owned().await;
+                match conn.as_mut().expect("internal invariant broken: self.connection is Some") { $(
+                    #[cfg($name)]
+                    DbConnInner::$name(c) => tokio::task::block_in_place(move || c.batch_execute(query)),
+                )+ }
+                .map_res("Error running a transaction statement")
+            }
+
+            /// Starts a transaction on this connection, which lasts until `end_transaction` is called.
+            /// A transaction which is still open when the connection is dropped is rolled back.
+            pub async fn begin_transaction(&mut self) -> Result<(), Error> {
+                if self.in_transaction {
+                    err!("A transaction is already running on this connection")
+                }
+                self.batch_execute("BEGIN").await?;
+                self.in_transaction = true;
+                Ok(())
+            }
+
+            /// Commits the running transaction if `result` is `Ok`, otherwise it is rolled back. Returns `result`.
+            pub async fn end_transaction<T>(&mut self, result: Result<T, Error>) -> Result<T, Error> {
+                let end = if result.is_ok() {
+                    "COMMIT"
+                } else {
+                    "ROLLBACK"
+                };
+                if let Err(e) = self.batch_execute(end).await {
+                    // Make sure the transaction doesn't stay open after a failed commit
+                    if self.batch_execute("ROLLBACK").await.is_ok() {
+                        self.in_transaction = false;
+                    }
+                    return Err(e);
+                }
+                self.in_transaction = false;
+                result
+            }
+        }
+
         impl Drop for DbPool {
             fn drop(&mut self) {
                 let pool = self.pool.take();
@@ -168,7 +221,8 @@ macro_rules! generate_connections {
 
                         Ok(DbConn {
                             conn: Arc::new(Mutex::new(Some(DbConnInner::$name(c)))),
-                            permit: Some(permit)
+                            permit: Some(permit),
+                            in_transaction: false,
                         })
                     },
                 )+ }
@@ -367,6 +421,7 @@ pub mod models;
 /// Creates a back-up of the sqlite database
 /// MySQL/MariaDB and PostgreSQL are not supported.
 pub async fn backup_database(conn: &mut DbConn) -> Result<String, Error> {
+    let _io_permit = crate::util::acquire_io_permit(crate::util::FileStore::Backups).await;
     db_run! {@raw conn:
         postgresql, mysql {
             let _ = conn;
@@ -374,7 +429,10 @@ pub async fn backup_database(conn: &mut DbConn) -> Result<String, Error> {
         }
         sqlite {
             let db_url = CONFIG.database_url();
-            let db_path = std::path::Path::new(&db_url).parent().unwrap();
+            let db_path = match CONFIG.backup_folder() {
+                Some(folder) => std::path::PathBuf::from(folder),
+                None => std::path::Path::new(&db_url).parent().unwrap().to_path_buf(),
+            };
             let backup_file = db_path
                 .join(format!("db_{}.sqlite3", chrono::Utc::now().format("%Y%m%d_%H%M%S")))
                 .to_string_lossy()
@@ -1,11 +1,9 @@
-use std::io::ErrorKind;
-
 use bigdecimal::{BigDecimal, ToPrimitive};
 use derive_more::{AsRef, Deref, Display};
 use serde_json::Value;
 
 use super::{CipherId, OrganizationId, UserId};
-use crate::CONFIG;
+use crate::storage::{FileBackend, FileStorage};
 use macros::IdFromParam;
 
 db_object! {
@@ -40,11 +38,15 @@ impl Attachment {
         }
     }
 
+    /// The path of the file within the attachments storage
     pub fn get_file_path(&self) -> String {
-        format!("{}/{}/{}", CONFIG.attachments_folder(), self.cipher_uuid, self.id)
+        format!("{}/{}", self.cipher_uuid, self.id)
     }
 
     pub fn get_url(&self, host: &str) -> String {
+        if let Some(url) = FileStorage::attachments().presigned_url(&self.get_file_path()) {
+            return url;
+        }
         let token = encode_jwt(&generate_file_download_claims(self.cipher_uuid.clone(), self.id.clone()));
         format!("{}/attachments/{}/{}?token={}", host, self.cipher_uuid, self.id, token)
     }
@@ -65,7 +67,7 @@ impl Attachment {
 use crate::auth::{encode_jwt, generate_file_download_claims};
 use crate::db::DbConn;
 
-use crate::api::EmptyResult;
+use crate::api::{ApiResult, EmptyResult};
 use crate::error::MapResult;
 
 /// Database methods
@@ -103,36 +105,40 @@ impl Attachment {
     }
 
     pub async fn delete(&self, conn: &mut DbConn) -> EmptyResult {
+        self.delete_record(conn).await?;
+        Self::delete_files(&[self.get_file_path()]).await
+    }
+
+    async fn delete_record(&self, conn: &mut DbConn) -> EmptyResult {
         db_run! { conn: {
-            let _: () = crate::util::retry(
+            crate::util::retry(
                 || diesel::delete(attachments::table.filter(attachments::id.eq(&self.id))).execute(conn),
                 10,
             )
-            .map_res("Error deleting attachment")?;
-
-            let file_path = &self.get_file_path();
-
-            match std::fs::remove_file(file_path) {
-                // Ignore "file not found" errors. This can happen when the
-                // upstream caller has already cleaned up the file as part of
-                // its own error handling.
-                Err(e) if e.kind() == ErrorKind::NotFound => {
-                    debug!("File '{}' already deleted.", file_path);
-                    Ok(())
-                }
-                Err(e) => Err(e.into()),
-                _ => Ok(()),
-            }
+            .map_res("Error deleting attachment")
         }}
     }
 
-    pub async fn delete_all_by_cipher(cipher_uuid: &CipherId, conn: &mut DbConn) -> EmptyResult {
-        for attachment in Attachment::find_by_cipher(cipher_uuid, conn).await {
-            attachment.delete(conn).await?;
+    /// Removes attachment files from the storage, use this for the paths returned by `delete_records_by_cipher`.
+    pub async fn delete_files(paths: &[String]) -> EmptyResult {
+        let _io_permit = crate::util::acquire_io_permit(crate::util::FileStore::Attachments).await;
+        for path in paths {
+            FileStorage::attachments().delete(path).await?;
         }
         Ok(())
     }
 
+    /// Deletes the attachments of the cipher from the database, but keeps their files, so a transaction can still be
+    /// rolled back. Returns the paths of the files, which have to be removed once the deletion is committed.
+    pub async fn delete_records_by_cipher(cipher_uuid: &CipherId, conn: &mut DbConn) -> ApiResult<Vec<String>> {
+        let mut paths = Vec::new();
+        for attachment in Attachment::find_by_cipher(cipher_uuid, conn).await {
+            attachment.delete_record(conn).await?;
+            paths.push(attachment.get_file_path());
+        }
+        Ok(paths)
+    }
+
     pub async fn find_by_id(id: &AttachmentId, conn: &mut DbConn) -> Option<Self> {
         db_run! { conn: {
             attachments::table
@@ -1,10 +1,14 @@
+use super::DeviceType;
 use super::{DeviceId, OrganizationId, UserId};
-use crate::{crypto::ct_eq, util::format_date};
-use chrono::{NaiveDateTime, Utc};
+use crate::{crypto::ct_eq, util::format_date, CONFIG};
+use chrono::{NaiveDateTime, TimeDelta, Utc};
 use derive_more::{AsRef, Deref, Display, From};
 use macros::UuidFromParam;
 use serde_json::Value;
 
+// After this number of minutes the clients reject the request
+pub const AUTH_REQUEST_EXPIRATION_MINUTES: i64 = 5;
+
 db_object! {
     #[derive(Debug, Identifiable, Queryable, Insertable, AsChangeset, Deserialize, Serialize)]
     #[diesel(table_name = auth_requests)]
@@ -66,6 +70,31 @@ impl AuthRequest {
         }
     }
 
+    pub fn is_expired(&self) -> bool {
+        self.creation_date + TimeDelta::try_minutes(AUTH_REQUEST_EXPIRATION_MINUTES).unwrap() <= Utc::now().naive_utc()
+    }
+
+    pub fn to_json(&self) -> Value {
+        let country =
+            self.request_ip.parse().ok().and_then(|ip| crate::geoip::lookup(&ip)).and_then(|info| info.country);
+        json!({
+            "id": self.uuid,
+            "publicKey": self.public_key,
+            "requestDeviceType": DeviceType::from_i32(self.device_type).to_string(),
+            "requestDeviceTypeValue": self.device_type,
+            "requestDeviceIdentifier": self.request_device_identifier,
+            "requestIpAddress": self.request_ip,
+            "requestCountryName": country,
+            "key": self.enc_key,
+            "masterPasswordHash": self.master_password_hash,
+            "creationDate": format_date(&self.creation_date),
+            "responseDate": self.response_date.as_ref().map(format_date),
+            "requestApproved": self.approved,
+            "origin": CONFIG.domain_origin(),
+            "object": "auth-request"
+        })
+    }
+
     pub fn to_json_for_pending_device(&self) -> Value {
         json!({
             "id": self.uuid,
@@ -177,7 +206,7 @@ impl AuthRequest {
     }
 
     pub async fn purge_expired_auth_requests(conn: &mut DbConn) {
-        let expiry_time = Utc::now().naive_utc() - chrono::TimeDelta::try_minutes(5).unwrap(); //after 5 minutes, clients reject the request
+        let expiry_time = Utc::now().naive_utc() - TimeDelta::try_minutes(AUTH_REQUEST_EXPIRATION_MINUTES).unwrap();
         for auth_request in Self::find_created_before(&expiry_time, conn).await {
             auth_request.delete(conn).await.ok();
         }
@@ -1,6 +1,6 @@
 use crate::util::LowerCase;
 use crate::CONFIG;
-use chrono::{NaiveDateTime, TimeDelta, Utc};
+use chrono::{DateTime, NaiveDateTime, TimeDelta, Utc};
 use derive_more::{AsRef, Deref, Display, From};
 use serde_json::Value;
 
@@ -48,6 +48,14 @@ db_object! {
     }
 }
 
+pub enum CipherType {
+    Login = 1,
+    SecureNote = 2,
+    Card = 3,
+    Identity = 4,
+    SshKey = 5,
+}
+
 pub enum RepromptType {
     None = 0,
     Password = 1,
@@ -112,6 +120,23 @@ impl Cipher {
                     }
                 }
             }
+
+            // Validate the passkeys (Fido2Credentials), every passkey needs a credential id and a valid creation date
+            if let Some(Value::Array(credentials)) = cipher.login.as_ref().map(|l| &l["fido2Credentials"]) {
+                let invalid = credentials.iter().any(|c| {
+                    c["credentialId"].as_str().is_none_or(str::is_empty)
+                        || c["creationDate"].as_str().is_none_or(|d| DateTime::parse_from_rfc3339(d).is_err())
+                });
+                if invalid {
+                    validation_errors.insert(
+                        format!("Ciphers[{index}].Login.Fido2Credentials"),
+                        serde_json::to_value([
+                            "A passkey is missing its credential id or has an invalid creation date.",
+                        ])
+                        .unwrap(),
+                    );
+                }
+            }
         }
 
         if !validation_errors.is_empty() {
@@ -125,11 +150,49 @@ impl Cipher {
             Ok(())
         }
     }
+
+    /// Removes the oldest entries of the password history beyond PASSWORD_HISTORY_LIMIT.
+    /// Returns whether any entries were removed.
+    pub fn trim_password_history(&mut self) -> bool {
+        match CONFIG.password_history_limit() {
+            Some(limit) => self.trim_password_history_to(limit as usize),
+            None => false,
+        }
+    }
+
+    fn trim_password_history_to(&mut self, limit: usize) -> bool {
+        let Some(mut history) =
+            self.password_history.as_deref().and_then(|h| serde_json::from_str::<Vec<Value>>(h).ok())
+        else {
+            return false;
+        };
+        if history.len() <= limit {
+            return false;
+        }
+
+        // Older data can still use PascalCase keys, entries without a valid date are removed first
+        let last_used = |entry: &Value| {
+            entry
+                .get("lastUsedDate")
+                .or_else(|| entry.get("LastUsedDate"))
+                .and_then(Value::as_str)
+                .and_then(|d| DateTime::parse_from_rfc3339(d).ok())
+        };
+        history.sort_by_key(|entry| std::cmp::Reverse(last_used(entry)));
+        history.truncate(limit);
+
+        self.password_history = if history.is_empty() {
+            None
+        } else {
+            Some(Value::Array(history).to_string())
+        };
+        true
+    }
 }
 
 use crate::db::DbConn;
 
-use crate::api::EmptyResult;
+use crate::api::{ApiResult, EmptyResult};
 use crate::error::MapResult;
 
 /// Database methods
@@ -242,7 +305,7 @@ impl Cipher {
 
         // NOTE: This was marked as *Backwards Compatibility Code*, but as of January 2021 this is still being used by upstream
         // Set the first element of the Uris array as Uri, this is needed several (mobile) clients.
-        if self.atype == 1 {
+        if self.atype == CipherType::Login as i32 {
             // Upstream always has an `uri` key/value
             type_data_json["uri"] = Value::Null;
             if let Some(uris) = type_data_json["uris"].as_array_mut() {
@@ -266,6 +329,18 @@ impl Cipher {
             if let Some(pw_revision) = type_data_json["passwordRevisionDate"].as_str() {
                 type_data_json["passwordRevisionDate"] = json!(validate_and_format_date(pw_revision));
             }
+
+            // Passkeys need to be an array of objects with a valid `creationDate`, else the clients fail to decrypt the item
+            match type_data_json["fido2Credentials"].as_array_mut() {
+                Some(credentials) => {
+                    credentials.retain(Value::is_object);
+                    for credential in credentials.iter_mut() {
+                        let creation_date = credential["creationDate"].as_str().unwrap_or_default();
+                        credential["creationDate"] = json!(validate_and_format_date(creation_date));
+                    }
+                }
+                None => type_data_json["fido2Credentials"] = Value::Null,
+            }
         }
 
         // Fix secure note issues when data is invalid
@@ -451,18 +526,27 @@ impl Cipher {
     }
 
     pub async fn delete(&self, conn: &mut DbConn) -> EmptyResult {
+        let attachment_files = self.delete_records(conn).await?;
+        Attachment::delete_files(&attachment_files).await
+    }
+
+    /// Deletes the cipher from the database, but keeps the files of its attachments, so a transaction can still be
+    /// rolled back. Returns the paths of these files, which have to be removed with `Attachment::delete_files`
+    /// once the deletion is committed.
+    pub async fn delete_records(&self, conn: &mut DbConn) -> ApiResult<Vec<String>> {
         self.update_users_revision(conn).await;
 
         FolderCipher::delete_all_by_cipher(&self.uuid, conn).await?;
         CollectionCipher::delete_all_by_cipher(&self.uuid, conn).await?;
-        Attachment::delete_all_by_cipher(&self.uuid, conn).await?;
+        let attachment_files = Attachment::delete_records_by_cipher(&self.uuid, conn).await?;
         Favorite::delete_all_by_cipher(&self.uuid, conn).await?;
 
         db_run! { conn: {
             diesel::delete(ciphers::table.filter(ciphers::uuid.eq(&self.uuid)))
                 .execute(conn)
                 .map_res("Error deleting cipher")
-        }}
+        }}?;
+        Ok(attachment_files)
     }
 
     pub async fn delete_all_by_organization(org_uuid: &OrganizationId, conn: &mut DbConn) -> EmptyResult {
@@ -484,9 +568,27 @@ impl Cipher {
     pub async fn purge_trash(conn: &mut DbConn) {
         if let Some(auto_delete_days) = CONFIG.trash_auto_delete_days() {
             let now = Utc::now().naive_utc();
-            let dt = now - TimeDelta::try_days(auto_delete_days).unwrap();
+            let Some(dt) = TimeDelta::try_days(auto_delete_days).and_then(|d| now.checked_sub_signed(d)) else {
+                return;
+            };
             for cipher in Self::find_deleted_before(&dt, conn).await {
-                cipher.delete(conn).await.ok();
+                if let Err(e) = cipher.delete(conn).await {
+                    error!("Error purging trashed cipher {}: {e:#?}", cipher.uuid);
+                }
+            }
+        }
+    }
+
+    /// Applies PASSWORD_HISTORY_LIMIT to the existing items.
+    pub async fn trim_password_histories(conn: &mut DbConn) {
+        if CONFIG.password_history_limit().is_none() {
+            return;
+        }
+        for mut cipher in Self::find_with_password_history(conn).await {
+            if cipher.trim_password_history() {
+                if let Err(e) = cipher.save(conn).await {
+                    error!("Error trimming the password history of cipher {}: {e:#?}", cipher.uuid);
+                }
             }
         }
     }
@@ -626,13 +728,14 @@ impl Cipher {
         // and `hide_passwords` columns. This could ideally be done as part of the
         // query, but Diesel doesn't support a min() or bool_and() function on
         // booleans and this behavior isn't portable anyway.
+        // The `manage` permission is granted by any of the collections.
         let mut read_only = true;
         let mut hide_passwords = true;
         let mut manage = false;
         for (ro, hp, mn) in rows.iter() {
             read_only &= ro;
             hide_passwords &= hp;
-            manage &= mn;
+            manage |= mn;
         }
 
         Some((read_only, hide_passwords, manage))
@@ -908,6 +1011,15 @@ impl Cipher {
         }}
     }
 
+    /// Find all ciphers which have a password history.
+    async fn find_with_password_history(conn: &mut DbConn) -> Vec<Self> {
+        db_run! {conn: {
+            ciphers::table
+                .filter(ciphers::password_history.is_not_null())
+                .load::<CipherDb>(conn).expect("Error loading ciphers").from_db()
+        }}
+    }
+
     pub async fn get_collections(&self, user_uuid: UserId, conn: &mut DbConn) -> Vec<CollectionId> {
         if CONFIG.org_groups_enabled() {
             db_run! {conn: {
@@ -1089,3 +1201,57 @@ impl Cipher {
     UuidFromParam,
 )]
 pub struct CipherId(String);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cipher_with_history(history: Value) -> Cipher {
+        let mut cipher = Cipher::new(CipherType::Login as i32, String::from("2.name"));
+        cipher.password_history = Some(history.to_string());
+        cipher
+    }
+
+    fn passwords(cipher: &Cipher) -> Vec<String> {
+        let history: Vec<Value> = serde_json::from_str(cipher.password_history.as_deref().unwrap()).unwrap();
+        history
+            .iter()
+            .map(|e| e.get("password").or_else(|| e.get("Password")).unwrap().as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn trim_password_history_keeps_the_newest_entries() {
+        let mut cipher = cipher_with_history(json!([
+            {"lastUsedDate": "2024-01-01T00:00:00.000Z", "password": "oldest"},
+            {"LastUsedDate": "2024-03-01T00:00:00.000Z", "Password": "newest"},
+            {"lastUsedDate": "not a date", "password": "invalid"},
+            {"password": "missing"},
+            {"lastUsedDate": "2024-02-01T00:00:00.000Z", "password": "middle"},
+        ]));
+
+        assert!(cipher.trim_password_history_to(2));
+        assert_eq!(passwords(&cipher), ["newest", "middle"]);
+
+        // Entries without a valid date are only kept when there is room for them
+        let mut cipher = cipher_with_history(json!([
+            {"lastUsedDate": "not a date", "password": "invalid"},
+            {"lastUsedDate": "2024-01-01T00:00:00.000Z", "password": "valid"},
+        ]));
+        assert!(cipher.trim_password_history_to(1));
+        assert_eq!(passwords(&cipher), ["valid"]);
+    }
+
+    #[test]
+    fn trim_password_history_within_limit_is_unchanged() {
+        let history = json!([{"lastUsedDate": "not a date", "password": "invalid"}]);
+        let mut cipher = cipher_with_history(history.clone());
+
+        assert!(!cipher.trim_password_history_to(1));
+        assert_eq!(cipher.password_history, Some(history.to_string()));
+
+        // A limit of zero removes the complete history
+        assert!(cipher.trim_password_history_to(0));
+        assert_eq!(cipher.password_history, None);
+    }
+}
@@ -65,18 +65,11 @@ impl Collection {
     }
 
     pub fn set_external_id(&mut self, external_id: Option<String>) {
-        //Check if external id is empty. We don't want to have
-        //empty strings in the database
-        match external_id {
-            Some(external_id) => {
-                if external_id.is_empty() {
-                    self.external_id = None;
-                } else {
-                    self.external_id = Some(external_id)
-                }
-            }
-            None => self.external_id = None,
-        }
+        // Check if external_id is empty. We do not want to have empty strings in the database
+        self.external_id = match external_id {
+            Some(external_id) if !external_id.trim().is_empty() => Some(external_id),
+            _ => None,
+        };
     }
 
     pub async fn to_json_details(
@@ -133,6 +126,18 @@ impl Collection {
         json_object
     }
 
+    /// Whether the member may edit the collection and its access, besides managers this is allowed for members
+    /// who were given the manage permission on the collection.
+    pub async fn can_manage_collection(member: &Membership, col_id: &CollectionId, conn: &mut DbConn) -> bool {
+        if !member.has_status(MembershipStatus::Confirmed) {
+            return false;
+        }
+        match Collection::find_by_uuid_and_org(col_id, &member.org_uuid, conn).await {
+            Some(collection) => collection.is_manageable_by_user(&member.user_uuid, conn).await,
+            None => false,
+        }
+    }
+
     pub async fn can_access_collection(member: &Membership, col_id: &CollectionId, conn: &mut DbConn) -> bool {
         member.has_status(MembershipStatus::Confirmed)
             && (member.has_full_access()
@@ -1,4 +1,4 @@
-use chrono::{NaiveDateTime, Utc};
+use chrono::{NaiveDateTime, TimeDelta, Utc};
 use derive_more::{Display, From};
 use serde_json::Value;
 
@@ -24,7 +24,19 @@ db_object! {
         pub push_token: Option<String>,
 
         pub refresh_token: String,
+        // Identifies the login session of the refresh token, it stays the same when the refresh token is rotated
+        pub refresh_token_family: Option<String>,
+        // The refresh token replaced by the last rotation, still accepted for a short while to cover concurrent refreshes
+        pub previous_refresh_token: Option<String>,
+        pub refresh_token_rotated_at: Option<NaiveDateTime>,
         pub twofactor_remember: Option<String>,
+        pub twofactor_remember_at: Option<NaiveDateTime>,
+
+        // Keys of trusted device encryption: the user key is encrypted with the public key of the device,
+        // the public key with the user key, and the private key with the device key which never leaves the device
+        pub encrypted_user_key: Option<String>,
+        pub encrypted_public_key: Option<String>,
+        pub encrypted_private_key: Option<String>,
     }
 }
 
@@ -45,7 +57,15 @@ impl Device {
             push_uuid: None,
             push_token: None,
             refresh_token: String::new(),
+            refresh_token_family: None,
+            previous_refresh_token: None,
+            refresh_token_rotated_at: None,
             twofactor_remember: None,
+            twofactor_remember_at: None,
+
+            encrypted_user_key: None,
+            encrypted_public_key: None,
+            encrypted_private_key: None,
         }
     }
 
@@ -56,28 +76,111 @@ impl Device {
             "type": self.atype,
             "identifier": self.push_uuid,
             "creationDate": format_date(&self.created_at),
-            "isTrusted": false,
+            "isTrusted": self.is_trusted(),
+            "encryptedUserKey": self.encrypted_user_key,
+            "encryptedPublicKey": self.encrypted_public_key,
             "object":"device"
         })
     }
 
+    pub fn is_trusted(&self) -> bool {
+        self.encrypted_user_key.is_some() && self.encrypted_public_key.is_some() && self.encrypted_private_key.is_some()
+    }
+
+    pub fn set_trust_keys(&mut self, user_key: String, public_key: String, private_key: String) {
+        self.encrypted_user_key = Some(user_key);
+        self.encrypted_public_key = Some(public_key);
+        self.encrypted_private_key = Some(private_key);
+    }
+
+    pub fn delete_trust_keys(&mut self) {
+        self.encrypted_user_key = None;
+        self.encrypted_public_key = None;
+        self.encrypted_private_key = None;
+    }
+
     pub fn refresh_twofactor_remember(&mut self) -> String {
         use data_encoding::BASE64;
         let twofactor_remember = crypto::encode_random_bytes::<180>(BASE64);
         self.twofactor_remember = Some(twofactor_remember.clone());
+        self.twofactor_remember_at = Some(Utc::now().naive_utc());
+
+        twofactor_remember
+    }
+
+    /// Replaces the remember token after it was used, but keeps the time it was first issued at.
+    /// This way the second factor is still asked again once `TWOFACTOR_REMEMBER_DAYS` have passed.
+    pub fn rotate_twofactor_remember(&mut self) -> String {
+        let remember_at = self.twofactor_remember_at;
+        let twofactor_remember = self.refresh_twofactor_remember();
+        if remember_at.is_some() {
+            self.twofactor_remember_at = remember_at;
+        }
 
         twofactor_remember
     }
 
     pub fn delete_twofactor_remember(&mut self) {
         self.twofactor_remember = None;
+        self.twofactor_remember_at = None;
+    }
+
+    /// Checks the given remember token, which is only valid for `TWOFACTOR_REMEMBER_DAYS` after it was issued.
+    pub fn check_twofactor_remember(&self, token: &str) -> bool {
+        match (&self.twofactor_remember, self.twofactor_remember_at) {
+            (Some(remember), Some(remember_at)) => {
+                let valid_until =
+                    remember_at + TimeDelta::try_days(CONFIG.twofactor_remember_days()).unwrap_or_default();
+                valid_until > Utc::now().naive_utc() && crypto::ct_eq(remember, token)
+            }
+            _ => false,
+        }
+    }
+
+    /// Starts a new login session, which invalidates the previous refresh token of this device.
+    pub fn start_refresh_token_family(&mut self) {
+        self.refresh_token_family = Some(crypto::encode_random_bytes::<16>(data_encoding::HEXLOWER));
+        self.rotate_refresh_token();
+        // The token of an older session must not be accepted during the grace period of the new one
+        self.previous_refresh_token = None;
+    }
+
+    /// Issues a new refresh token of the current login session.
+    /// The family is part of the token, so the reuse of a stale token can be traced back to its session.
+    pub fn rotate_refresh_token(&mut self) {
+        let Some(family) = &self.refresh_token_family else {
+            return self.start_refresh_token_family();
+        };
+        let secret = crypto::encode_random_bytes::<64>(data_encoding::BASE64URL_NOPAD);
+        let previous = std::mem::replace(&mut self.refresh_token, format!("{family}.{secret}"));
+        self.previous_refresh_token = Some(previous).filter(|t| !t.is_empty());
+        self.refresh_token_rotated_at = Some(Utc::now().naive_utc());
+    }
+
+    pub fn revoke_refresh_token(&mut self) {
+        self.refresh_token = String::new();
+        self.refresh_token_family = None;
+        self.previous_refresh_token = None;
+        self.refresh_token_rotated_at = None;
+    }
+
+    /// Checks if the given token is the one replaced by the last rotation and still within `REFRESH_TOKEN_GRACE_SECONDS`.
+    /// A client refreshing twice at the same time, or retrying after a lost response, presents it again without being a replay.
+    pub fn check_previous_refresh_token(&self, token: &str) -> bool {
+        match (&self.previous_refresh_token, self.refresh_token_rotated_at) {
+            (Some(previous), Some(rotated_at)) => {
+                let valid_until =
+                    rotated_at + TimeDelta::try_seconds(CONFIG.refresh_token_grace_seconds()).unwrap_or_default();
+                valid_until > Utc::now().naive_utc() && crypto::ct_eq(previous, token)
+            }
+            _ => false,
+        }
     }
 
     pub fn refresh_tokens(&mut self, user: &super::User, scope: Vec<String>) -> (String, i64) {
         // If there is no refresh token, we create one
         if self.refresh_token.is_empty() {
-            use data_encoding::BASE64URL;
-            self.refresh_token = crypto::encode_random_bytes::<64>(BASE64URL);
+            self.start_refresh_token_family();
         }
 
         // Update the expiration of the device and the last update date
@@ -159,7 +262,7 @@ impl DeviceWithAuthRequest {
             "identifier": self.device.push_uuid,
             "creationDate": format_date(&self.device.created_at),
             "devicePendingAuthRequest": auth_request,
-            "isTrusted": false,
+            "isTrusted": self.device.is_trusted(),
             "object": "device",
         })
     }
@@ -237,6 +340,17 @@ impl Device {
         }}
     }
 
+    pub async fn count_by_user(user_uuid: &UserId, conn: &mut DbConn) -> i64 {
+        db_run! { conn: {
+            devices::table
+                .filter(devices::user_uuid.eq(user_uuid))
+                .count()
+                .first::<i64>(conn)
+                .ok()
+                .unwrap_or(0)
+        }}
+    }
+
     pub async fn find_by_uuid(uuid: &DeviceId, conn: &mut DbConn) -> Option<Self> {
         db_run! { conn: {
             devices::table
@@ -256,6 +370,58 @@ impl Device {
                 .map_res("Error removing push token")
         }}
     }
+
+    pub async fn clear_twofactor_remember_by_user(user_uuid: &UserId, conn: &mut DbConn) -> EmptyResult {
+        db_run! { conn: {
+            diesel::update(devices::table)
+                .filter(devices::user_uuid.eq(user_uuid))
+                .set((
+                    devices::twofactor_remember.eq::<Option<String>>(None),
+                    devices::twofactor_remember_at.eq::<Option<NaiveDateTime>>(None),
+                ))
+                .execute(conn)
+                .map_res("Error removing remembered two-factor devices")
+        }}
+    }
+
+    /// Removes the user keys of the trusted devices, after the user key was rotated.
+    /// The private keys are kept, they are encrypted with the device keys and are needed to trust the devices again.
+    pub async fn clear_trust_keys_by_user(user_uuid: &UserId, conn: &mut DbConn) -> EmptyResult {
+        db_run! { conn: {
+            diesel::update(devices::table)
+                .filter(devices::user_uuid.eq(user_uuid))
+                .set((
+                    devices::encrypted_user_key.eq::<Option<String>>(None),
+                    devices::encrypted_public_key.eq::<Option<String>>(None),
+                ))
+                .execute(conn)
+                .map_res("Error removing the keys of trusted devices")
+        }}
+    }
+
+    /// Devices of which the id, user id or name contains the filter, used by the data browser of the admin panel.
+    pub async fn search(filter: Option<&str>, limit: i64, conn: &mut DbConn) -> Vec<Self> {
+        db_run! { conn: {
+            define_sql_function!{
+                fn lower(x: diesel::sql_types::Text) -> diesel::sql_types::Text;
+            }
+            let mut query = devices::table.into_boxed();
+            if let Some(pattern) = filter.map(crate::util::like_contains_pattern) {
+                query = query.filter(
+                    devices::uuid.like(pattern.clone()).escape('\\')
+                        .or(devices::user_uuid.like(pattern.clone()).escape('\\'))
+                        .or(lower(devices::name).like(pattern).escape('\\'))
+                );
+            }
+            query
+                .order_by(devices::updated_at.desc())
+                .limit(limit)
+                .load::<DeviceDb>(conn)
+                .expect("Error loading devices")
+                .from_db()
+        }}
+    }
+
     pub async fn find_by_refresh_token(refresh_token: &str, conn: &mut DbConn) -> Option<Self> {
         db_run! { conn: {
             devices::table
@@ -266,6 +432,31 @@ impl Device {
         }}
     }
 
+    pub async fn find_by_refresh_token_family(family: &str, conn: &mut DbConn) -> Option<Self> {
+        db_run! { conn: {
+            devices::table
+                .filter(devices::refresh_token_family.eq(family))
+                .first::<DeviceDb>(conn)
+                .ok()
+                .from_db()
+        }}
+    }
+
+    pub async fn revoke_refresh_tokens_by_user(user_uuid: &UserId, conn: &mut DbConn) -> EmptyResult {
+        db_run! { conn: {
+            diesel::update(devices::table)
+                .filter(devices::user_uuid.eq(user_uuid))
+                .set((
+                    devices::refresh_token.eq(""),
+                    devices::refresh_token_family.eq::<Option<String>>(None),
+                    devices::previous_refresh_token.eq::<Option<String>>(None),
+                    devices::refresh_token_rotated_at.eq::<Option<NaiveDateTime>>(None),
+                ))
+                .execute(conn)
+                .map_res("Error revoking the refresh tokens")
+        }}
+    }
+
     pub async fn find_latest_active_by_user(user_uuid: &UserId, conn: &mut DbConn) -> Option<Self> {
         db_run! { conn: {
             devices::table
@@ -389,6 +580,32 @@ impl DeviceType {
             _ => DeviceType::UnknownBrowser,
         }
     }
+
+    /// Broad category of the client, used by the device enrollment policy
+    pub fn client_type(&self) -> &'static str {
+        match self {
+            DeviceType::Android | DeviceType::Ios | DeviceType::AndroidAmazon => "mobile",
+            DeviceType::ChromeExtension
+            | DeviceType::FirefoxExtension
+            | DeviceType::OperaExtension
+            | DeviceType::EdgeExtension
+            | DeviceType::VivaldiExtension
+            | DeviceType::SafariExtension => "extension",
+            DeviceType::WindowsDesktop | DeviceType::MacOsDesktop | DeviceType::LinuxDesktop | DeviceType::Uwp => {
+                "desktop"
+            }
+            DeviceType::ChromeBrowser
+            | DeviceType::FirefoxBrowser
+            | DeviceType::OperaBrowser
+            | DeviceType::EdgeBrowser
+            | DeviceType::IEBrowser
+            | DeviceType::UnknownBrowser
+            | DeviceType::SafariBrowser
+            | DeviceType::VivaldiBrowser => "web",
+            DeviceType::WindowsCLI | DeviceType::MacOsCLI | DeviceType::LinuxCLI => "cli",
+            DeviceType::Sdk | DeviceType::Server => "sdk",
+        }
+    }
 }
 
 #[derive(
@@ -33,6 +33,7 @@ db_object! {
         pub provider_uuid: Option<String>,
         pub provider_user_uuid: Option<String>,
         pub provider_org_uuid: Option<String>,
+        pub geo_location: Option<String>,
     }
 }
 
@@ -160,6 +161,7 @@ impl Event {
             provider_uuid: None,
             provider_user_uuid: None,
             provider_org_uuid: None,
+            geo_location: None,
         }
     }
 
@@ -182,9 +184,29 @@ impl Event {
             "providerId": self.provider_uuid,
             "providerUserId": self.provider_user_uuid,
             "providerOrganizationId": self.provider_org_uuid,
+            "geoLocation": self.geo_location,
             // "installationId": null, // Not supported
         })
     }
+
+    /// The event as returned by the public API, which uses other names for some of the fields
+    pub fn to_json_public(&self) -> Value {
+        use crate::util::format_date;
+
+        json!({
+            "type": self.event_type,
+            "itemId": self.cipher_uuid,
+            "collectionId": self.collection_uuid,
+            "groupId": self.group_uuid,
+            "policyId": self.policy_uuid,
+            "memberId": self.org_user_uuid,
+            "actingUserId": self.act_user_uuid,
+            "date": format_date(&self.event_date),
+            "device": self.device_type,
+            "ipAddress": self.ip_address,
+            "object": "event",
+        })
+    }
 }
 
 /// Database methods
@@ -279,6 +301,41 @@ impl Event {
         }}
     }
 
+    /// The latest events of which one of the ids or the IP address contains the filter,
+    /// used by the data browser of the admin panel.
+    pub async fn search(filter: Option<&str>, limit: i64, conn: &mut DbConn) -> Vec<Self> {
+        db_run! { conn: {
+            let mut query = event::table.into_boxed();
+            if let Some(pattern) = filter.map(crate::util::like_contains_pattern) {
+                query = query.filter(
+                    event::user_uuid.like(pattern.clone()).escape('\\')
+                        .or(event::act_user_uuid.like(pattern.clone()).escape('\\'))
+                        .or(event::org_uuid.like(pattern.clone()).escape('\\'))
+                        .or(event::cipher_uuid.like(pattern.clone()).escape('\\'))
+                        .or(event::ip_address.like(pattern).escape('\\'))
+                );
+            }
+            query
+                .order_by(event::event_date.desc())
+                .limit(limit)
+                .load::<EventDb>(conn)
+                .expect("Error loading events")
+                .from_db()
+        }}
+    }
+
+    /// All the events about or by the user, used for the personal data export.
+    pub async fn find_by_user(user_uuid: &UserId, conn: &mut DbConn) -> Vec<Self> {
+        db_run! { conn: {
+            event::table
+                .filter(event::user_uuid.eq(user_uuid).or(event::act_user_uuid.eq(user_uuid)))
+                .order_by(event::event_date.desc())
+                .load::<EventDb>(conn)
+                .expect("Error loading events")
+                .from_db()
+        }}
+    }
+
     pub async fn count_by_org(org_uuid: &OrganizationId, conn: &mut DbConn) -> i64 {
         db_run! { conn: {
             event::table
@@ -1,4 +1,4 @@
-use super::{CipherId, User, UserId};
+use super::{CipherId, OrganizationId, User, UserId};
 
 db_object! {
     #[derive(Identifiable, Queryable, Insertable)]
@@ -84,6 +84,26 @@ impl Favorite {
         }}
     }
 
+    // Delete the favorite entries of the specified user on the ciphers of an organization.
+    // Used when the user leaves the organization, so these don't show up again when rejoining.
+    pub async fn delete_all_by_user_and_org(
+        user_uuid: &UserId,
+        org_uuid: &OrganizationId,
+        conn: &mut DbConn,
+    ) -> EmptyResult {
+        db_run! { conn: {
+            diesel::delete(
+                favorites::table
+                    .filter(favorites::user_uuid.eq(user_uuid))
+                    .filter(favorites::cipher_uuid.eq_any(
+                        ciphers::table.filter(ciphers::organization_uuid.eq(org_uuid)).select(ciphers::uuid),
+                    )),
+            )
+            .execute(conn)
+            .map_res("Error removing favorites by user and organization")
+        }}
+    }
+
     /// Return a vec with (cipher_uuid) this will only contain favorite flagged ciphers
     /// This is used during a full sync so we only need one query for all favorite cipher matches.
     pub async fn get_all_cipher_uuid_by_user(user_uuid: &UserId, conn: &mut DbConn) -> Vec<CipherId> {
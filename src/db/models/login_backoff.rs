@@ -0,0 +1,101 @@
+use crate::{api::EmptyResult, db::DbConn, error::MapResult};
+
+db_object! {
+    // The failed login attempts counted for the exponential backoff, see `ratelimit` for the keys and the escalation
+    #[derive(Identifiable, Queryable, Insertable, AsChangeset)]
+    #[diesel(table_name = login_backoffs)]
+    #[diesel(primary_key(backoff_key))]
+    pub struct LoginBackoff {
+        pub backoff_key: String,
+        pub failures: i32,
+        pub last_failure: i64,
+        pub blocked_until: i64,
+    }
+}
+
+impl LoginBackoff {
+    pub fn new(backoff_key: String) -> Self {
+        Self {
+            backoff_key,
+            failures: 0,
+            last_failure: 0,
+            blocked_until: 0,
+        }
+    }
+}
+
+impl LoginBackoff {
+    pub async fn save(&self, conn: &mut DbConn) -> EmptyResult {
+        db_run! { conn:
+            sqlite, mysql {
+                diesel::replace_into(login_backoffs::table)
+                    .values(LoginBackoffDb::to_db(self))
+                    .execute(conn)
+                    .map_res("Error saving login backoff")
+            }
+            postgresql {
+                let value = LoginBackoffDb::to_db(self);
+                diesel::insert_into(login_backoffs::table)
+                    .values(&value)
+                    .on_conflict(login_backoffs::backoff_key)
+                    .do_update()
+                    .set(&value)
+                    .execute(conn)
+                    .map_res("Error saving login backoff")
+            }
+        }
+    }
+
+    pub async fn find_by_key(backoff_key: &str, conn: &mut DbConn) -> Option<Self> {
+        db_run! { conn: {
+            login_backoffs::table
+                .filter(login_backoffs::backoff_key.eq(backoff_key))
+                .first::<LoginBackoffDb>(conn)
+                .ok()
+                .from_db()
+        }}
+    }
+
+    pub async fn delete_by_key(backoff_key: &str, conn: &mut DbConn) -> EmptyResult {
+        db_run! { conn: {
+            diesel::delete(login_backoffs::table.filter(login_backoffs::backoff_key.eq(backoff_key)))
+                .execute(conn)
+                .map_res("Error deleting login backoff")
+        }}
+    }
+
+    pub async fn count_by_key_prefix(prefix: &str, conn: &mut DbConn) -> i64 {
+        db_run! { conn: {
+            login_backoffs::table
+                .filter(login_backoffs::backoff_key.like(format!("{prefix}%")))
+                .count()
+                .first(conn)
+                .unwrap_or(0)
+        }}
+    }
+
+    /// Removes the `count` backoffs with the given key prefix of which the last failure was the longest time ago.
+    pub async fn delete_oldest_by_key_prefix(prefix: &str, count: i64, conn: &mut DbConn) -> EmptyResult {
+        db_run! { conn: {
+            let oldest: Vec<String> = login_backoffs::table
+                .filter(login_backoffs::backoff_key.like(format!("{prefix}%")))
+                .order(login_backoffs::last_failure.asc())
+                .limit(count)
+                .select(login_backoffs::backoff_key)
+                .load(conn)
+                .map_res("Error loading login backoffs")?;
+            diesel::delete(login_backoffs::table.filter(login_backoffs::backoff_key.eq_any(oldest)))
+                .execute(conn)
+                .map_res("Error deleting login backoffs")
+        }}
+    }
+
+    /// Removes the backoffs of which the last failure was before the given timestamp.
+    pub async fn delete_failed_before(timestamp: i64, conn: &mut DbConn) -> EmptyResult {
+        db_run! { conn: {
+            diesel::delete(login_backoffs::table.filter(login_backoffs::last_failure.lt(timestamp)))
+                .execute(conn)
+                .map_res("Error deleting expired login backoffs")
+        }}
+    }
+}
@@ -0,0 +1,216 @@
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use derive_more::{AsRef, Deref, Display, From};
+use macros::UuidFromParam;
+use serde_json::Value;
+
+use super::OrganizationId;
+use crate::api::EmptyResult;
+use crate::db::DbConn;
+use crate::error::MapResult;
+use crate::util::format_date;
+
+// The background job checks an unverified domain every 12 hours, for at most 72 hours
+pub const ORG_DOMAIN_CHECK_INTERVAL_HOURS: i64 = 12;
+pub const ORG_DOMAIN_MAX_JOB_RUNS: i32 = 6;
+
+db_object! {
+    #[derive(Identifiable, Queryable, Insertable, AsChangeset)]
+    #[diesel(table_name = organization_domains)]
+    #[diesel(treat_none_as_null = true)]
+    #[diesel(primary_key(uuid))]
+    pub struct OrgDomain {
+        pub uuid: OrgDomainId,
+        pub org_uuid: OrganizationId,
+        pub domain_name: String,
+        pub txt: String,
+        pub creation_date: NaiveDateTime,
+        pub verified_date: Option<NaiveDateTime>,
+        pub last_checked_date: Option<NaiveDateTime>,
+        pub job_run_count: i32,
+    }
+}
+
+/// Local methods
+impl OrgDomain {
+    pub fn new(org_uuid: OrganizationId, domain_name: String) -> Self {
+        Self {
+            uuid: OrgDomainId(crate::util::get_uuid()),
+            org_uuid,
+            domain_name,
+            // The value of the TXT record which has to be added to the domain to verify it
+            txt: format!("bw={}", crate::crypto::get_random_string_alphanum(44)),
+            creation_date: Utc::now().naive_utc(),
+            verified_date: None,
+            last_checked_date: None,
+            job_run_count: 0,
+        }
+    }
+
+    pub fn is_verified(&self) -> bool {
+        self.verified_date.is_some()
+    }
+
+    /// The earliest time the background job will check this domain again, if it still does
+    pub fn next_run_date(&self) -> Option<NaiveDateTime> {
+        if self.is_verified() || self.job_run_count >= ORG_DOMAIN_MAX_JOB_RUNS {
+            return None;
+        }
+        Some(self.last_checked_date.unwrap_or(self.creation_date) + TimeDelta::hours(ORG_DOMAIN_CHECK_INTERVAL_HOURS))
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": self.uuid,
+            "organizationId": self.org_uuid,
+            "txt": self.txt,
+            "domainName": self.domain_name,
+            "creationDate": format_date(&self.creation_date),
+            "nextRunDate": self.next_run_date().as_ref().map(format_date),
+            "jobRunCount": self.job_run_count,
+            "verifiedDate": self.verified_date.as_ref().map(format_date),
+            "lastCheckedDate": self.last_checked_date.as_ref().map(format_date),
+            "object": "organizationDomain",
+        })
+    }
+}
+
+/// Database methods
+impl OrgDomain {
+    pub async fn save(&self, conn: &mut DbConn) -> EmptyResult {
+        db_run! { conn:
+            sqlite, mysql {
+                match diesel::replace_into(organization_domains::table)
+                    .values(OrgDomainDb::to_db(self))
+                    .execute(conn)
+                {
+                    Ok(_) => Ok(()),
+                    // Record already exists and causes a Foreign Key Violation because replace_into() wants to delete the record first.
+                    Err(diesel::result::Error::DatabaseError(diesel::result::DatabaseErrorKind::ForeignKeyViolation, _)) => {
+                        diesel::update(organization_domains::table)
+                            .filter(organization_domains::uuid.eq(&self.uuid))
+                            .set(OrgDomainDb::to_db(self))
+                            .execute(conn)
+                            .map_res("Error saving organization domain")
+                    }
+                    Err(e) => Err(e.into()),
+                }.map_res("Error saving organization domain")
+            }
+            postgresql {
+                let value = OrgDomainDb::to_db(self);
+                diesel::insert_into(organization_domains::table)
+                    .values(&value)
+                    .on_conflict(organization_domains::uuid)
+                    .do_update()
+                    .set(&value)
+                    .execute(conn)
+                    .map_res("Error saving organization domain")
+            }
+        }
+    }
+
+    pub async fn delete(self, conn: &mut DbConn) -> EmptyResult {
+        db_run! { conn: {
+            diesel::delete(organization_domains::table.filter(organization_domains::uuid.eq(self.uuid)))
+                .execute(conn)
+                .map_res("Error deleting organization domain")
+        }}
+    }
+
+    pub async fn delete_all_by_organization(org_uuid: &OrganizationId, conn: &mut DbConn) -> EmptyResult {
+        db_run! { conn: {
+            diesel::delete(organization_domains::table.filter(organization_domains::org_uuid.eq(org_uuid)))
+                .execute(conn)
+                .map_res("Error deleting organization domains")
+        }}
+    }
+
+    pub async fn find_by_uuid_and_org(
+        uuid: &OrgDomainId,
+        org_uuid: &OrganizationId,
+        conn: &mut DbConn,
+    ) -> Option<Self> {
+        db_run! { conn: {
+            organization_domains::table
+                .filter(organization_domains::uuid.eq(uuid))
+                .filter(organization_domains::org_uuid.eq(org_uuid))
+                .first::<OrgDomainDb>(conn)
+                .ok()
+                .from_db()
+        }}
+    }
+
+    pub async fn find_by_org(org_uuid: &OrganizationId, conn: &mut DbConn) -> Vec<Self> {
+        db_run! { conn: {
+            organization_domains::table
+                .filter(organization_domains::org_uuid.eq(org_uuid))
+                .order(organization_domains::creation_date.asc())
+                .load::<OrgDomainDb>(conn)
+                .expect("Error loading organization domains")
+                .from_db()
+        }}
+    }
+
+    pub async fn find_by_org_and_name(org_uuid: &OrganizationId, domain_name: &str, conn: &mut DbConn) -> Option<Self> {
+        db_run! { conn: {
+            organization_domains::table
+                .filter(organization_domains::org_uuid.eq(org_uuid))
+                .filter(organization_domains::domain_name.eq(domain_name))
+                .first::<OrgDomainDb>(conn)
+                .ok()
+                .from_db()
+        }}
+    }
+
+    /// A domain name can only be added by one organization, this finds it regardless of its verification
+    pub async fn find_by_name(domain_name: &str, conn: &mut DbConn) -> Option<Self> {
+        db_run! { conn: {
+            organization_domains::table
+                .filter(organization_domains::domain_name.eq(domain_name))
+                .first::<OrgDomainDb>(conn)
+                .ok()
+                .from_db()
+        }}
+    }
+
+    /// Returns the verified domains with this name, a domain can only be verified by one organization.
+    pub async fn find_verified_by_name(domain_name: &str, conn: &mut DbConn) -> Option<Self> {
+        db_run! { conn: {
+            organization_domains::table
+                .filter(organization_domains::domain_name.eq(domain_name))
+                .filter(organization_domains::verified_date.is_not_null())
+                .first::<OrgDomainDb>(conn)
+                .ok()
+                .from_db()
+        }}
+    }
+
+    /// Returns the unverified domains which the background job still has to check
+    pub async fn find_pending_verification(conn: &mut DbConn) -> Vec<Self> {
+        db_run! { conn: {
+            organization_domains::table
+                .filter(organization_domains::verified_date.is_null())
+                .filter(organization_domains::job_run_count.lt(ORG_DOMAIN_MAX_JOB_RUNS))
+                .load::<OrgDomainDb>(conn)
+                .expect("Error loading organization domains")
+                .from_db()
+        }}
+    }
+}
+
+#[derive(
+    Clone,
+    Debug,
+    AsRef,
+    Deref,
+    DieselNewType,
+    Display,
+    From,
+    FromForm,
+    Hash,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    UuidFromParam,
+)]
+pub struct OrgDomainId(String);
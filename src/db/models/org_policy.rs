@@ -6,7 +6,9 @@ use crate::api::EmptyResult;
 use crate::db::DbConn;
 use crate::error::MapResult;
 
-use super::{Membership, MembershipId, MembershipStatus, MembershipType, OrganizationId, TwoFactor, UserId};
+use super::{
+    Device, DeviceType, Membership, MembershipId, MembershipStatus, MembershipType, OrganizationId, TwoFactor, UserId,
+};
 
 db_object! {
     #[derive(Identifiable, Queryable, Insertable, AsChangeset)]
@@ -39,6 +41,9 @@ pub enum OrgPolicyType {
     // AutomaticAppLogIn = 12,
     // FreeFamiliesSponsorshipPolicy = 13,
     RemoveUnlockWithPin = 14,
+
+    // Vaultwarden specific policies, these are not available in the upstream clients and need to be managed via the API
+    DeviceEnrollment = 1000,
 }
 
 // https://github.com/bitwarden/server/blob/5cbdee137921a19b1f722920f0fa3cd45af2ef0f/src/Core/Models/Data/Organizations/Policies/SendOptionsPolicyData.cs
@@ -57,12 +62,28 @@ pub struct ResetPasswordDataModel {
     pub auto_enroll_enabled: bool,
 }
 
+// Vaultwarden specific, there is no upstream equivalent
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceEnrollmentPolicyData {
+    /// Maximum amount of devices a member may have enrolled, `None` means unlimited
+    #[serde(default)]
+    pub max_devices: Option<u32>,
+    /// Client types which are not allowed to login, see `DeviceType::client_type()` for the possible values
+    #[serde(default)]
+    pub blocked_client_types: Vec<String>,
+    /// Members this policy does not apply to
+    #[serde(default)]
+    pub exempt_members: Vec<MembershipId>,
+}
+
 pub type OrgPolicyResult = Result<(), OrgPolicyErr>;
 
 #[derive(Debug)]
 pub enum OrgPolicyErr {
     TwoFactorMissing,
     SingleOrgEnforced,
+    SingleOrgOtherMembership,
 }
 
 /// Local methods
@@ -81,6 +102,11 @@ impl OrgPolicy {
         self.atype == policy_type as i32
     }
 
+    /// Vaultwarden specific policies are unknown to the official clients, so they are only exposed via the public API.
+    pub fn is_client_visible(&self) -> bool {
+        self.atype < OrgPolicyType::DeviceEnrollment as i32
+    }
+
     pub fn to_json(&self) -> Value {
         let data_json: Value = serde_json::from_str(&self.data).unwrap_or(Value::Null);
         json!({
@@ -302,6 +328,20 @@ impl OrgPolicy {
             return Err(OrgPolicyErr::SingleOrgEnforced);
         }
 
+        // Enforce the Single Organization Policy of this organization itself,
+        // the user may not be an accepted or confirmed member of any other organization.
+        if let Some(p) = Self::find_by_org_and_type(org_uuid, OrgPolicyType::SingleOrg, conn).await {
+            if p.enabled
+                && Membership::find_by_user(user_uuid, conn).await.iter().any(|m| {
+                    m.org_uuid != *org_uuid
+                        && (m.status == MembershipStatus::Accepted as i32
+                            || m.status == MembershipStatus::Confirmed as i32)
+                })
+            {
+                return Err(OrgPolicyErr::SingleOrgOtherMembership);
+            }
+        }
+
         Ok(())
     }
 
@@ -341,6 +381,52 @@ impl OrgPolicy {
         false
     }
 
+    /// Checks the `DeviceEnrollment` policy of all organizations the user is a confirmed member of.
+    /// Owners, admins and explicitly exempted members are not affected by this policy.
+    /// The device limit is only checked for devices which are not yet enrolled.
+    pub async fn check_device_enrollment(
+        user_uuid: &UserId,
+        device: &Device,
+        new_device: bool,
+        conn: &mut DbConn,
+    ) -> EmptyResult {
+        for policy in
+            OrgPolicy::find_confirmed_by_user_and_active_policy(user_uuid, OrgPolicyType::DeviceEnrollment, conn).await
+        {
+            let Some(member) = Membership::find_by_user_and_org(user_uuid, &policy.org_uuid, conn).await else {
+                continue;
+            };
+            if member.atype >= MembershipType::Admin {
+                continue;
+            }
+
+            let opts = match serde_json::from_str::<DeviceEnrollmentPolicyData>(&policy.data) {
+                Ok(opts) => opts,
+                _ => {
+                    error!("Failed to deserialize DeviceEnrollmentPolicyData: {}", policy.data);
+                    continue;
+                }
+            };
+            if opts.exempt_members.contains(&member.uuid) {
+                continue;
+            }
+
+            let client_type = DeviceType::from_i32(device.atype).client_type();
+            if opts.blocked_client_types.iter().any(|t| t.eq_ignore_ascii_case(client_type)) {
+                err!(format!("Logging in from this type of client ({client_type}) is not allowed by your organization"))
+            }
+
+            if let Some(max_devices) = opts.max_devices {
+                if new_device && Device::count_by_user(user_uuid, conn).await >= i64::from(max_devices) {
+                    err!(format!(
+                        "You have reached the maximum of {max_devices} devices allowed by your organization. Remove an existing device first"
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+
     pub async fn is_enabled_for_member(
         member_uuid: &MembershipId,
         policy_type: OrgPolicyType,
@@ -8,8 +8,8 @@ use std::{
 };
 
 use super::{
-    CipherId, Collection, CollectionGroup, CollectionId, CollectionUser, Group, GroupId, GroupUser, OrgPolicy,
-    OrgPolicyType, TwoFactor, User, UserId,
+    CipherId, Collection, CollectionGroup, CollectionId, CollectionUser, Favorite, Group, GroupId, GroupUser,
+    OrgDomain, OrgPolicy, OrgPolicyType, TwoFactor, User, UserId,
 };
 use crate::CONFIG;
 use macros::UuidFromParam;
@@ -25,6 +25,20 @@ db_object! {
         pub billing_email: String,
         pub private_key: Option<String>,
         pub public_key: Option<String>,
+        pub delete_scheduled_at: Option<NaiveDateTime>,
+        pub max_seats: Option<i32>,
+        pub max_collections: Option<i32>,
+        // Whether the export job periodically writes the (encrypted) vault of the organization to the export folder
+        pub auto_export: bool,
+        // Only managers, admins and owners can create or delete collections, otherwise members can too
+        pub limit_collection_creation: bool,
+        pub limit_collection_deletion: bool,
+        // Attachment storage limit (KB) of the organization, overrides ORG_ATTACHMENT_LIMIT when set
+        pub attachment_limit: Option<i64>,
+        // The time of the latest deletion request, only the emailed confirmation of this request can schedule the deletion
+        pub delete_requested_at: Option<NaiveDateTime>,
+        // Whether the automatic exports are also mailed to the confirmed owners
+        pub auto_export_mail: bool,
     }
 
     #[derive(Identifiable, Queryable, Insertable, AsChangeset)]
@@ -42,6 +56,8 @@ db_object! {
         pub atype: i32,
         pub reset_password_key: Option<String>,
         pub external_id: Option<String>,
+        // When the member was invited or the invitation was last sent again, unknown for older memberships
+        pub invited_at: Option<NaiveDateTime>,
     }
 
     #[derive(Identifiable, Queryable, Insertable, AsChangeset)]
@@ -175,6 +191,15 @@ impl Organization {
             billing_email,
             private_key,
             public_key,
+            delete_scheduled_at: None,
+            max_seats: None,
+            max_collections: None,
+            auto_export: false,
+            limit_collection_creation: true,
+            limit_collection_deletion: true,
+            attachment_limit: None,
+            delete_requested_at: None,
+            auto_export_mail: false,
         }
     }
     // https://github.com/bitwarden/server/blob/13d1e74d6960cf0d042620b72d85bf583a4236f7/src/Api/Models/Response/Organizations/OrganizationResponseModel.cs
@@ -182,8 +207,8 @@ impl Organization {
         json!({
             "id": self.uuid,
             "name": self.name,
-            "seats": null,
-            "maxCollections": null,
+            "seats": self.max_seats,
+            "maxCollections": self.max_collections,
             "maxStorageGb": i16::MAX, // The value doesn't matter, we don't check server-side
             "use2fa": true,
             "useCustomPermissions": true,
@@ -202,9 +227,9 @@ impl Organization {
             "hasPublicAndPrivateKeys": self.private_key.is_some() && self.public_key.is_some(),
             "useResetPassword": CONFIG.mail_enabled(),
             "allowAdminAccessToAllCollectionItems": true,
-            "limitCollectionCreation": true,
-            "limitCollectionCreationDeletion": true,
-            "limitCollectionDeletion": true,
+            "limitCollectionCreation": self.limit_collection_creation,
+            "limitCollectionCreationDeletion": self.limit_collection_creation || self.limit_collection_deletion,
+            "limitCollectionDeletion": self.limit_collection_deletion,
 
             "businessName": self.name,
             "businessAddress1": null,
@@ -249,6 +274,7 @@ impl Membership {
             atype: MembershipType::User as i32,
             reset_password_key: None,
             external_id: None,
+            invited_at: Some(Utc::now().naive_utc()),
         }
     }
 
@@ -373,6 +399,7 @@ impl Organization {
         OrgPolicy::delete_all_by_organization(&self.uuid, conn).await?;
         Group::delete_all_by_organization(&self.uuid, conn).await?;
         OrganizationApiKey::delete_all_by_organization(&self.uuid, conn).await?;
+        OrgDomain::delete_all_by_organization(&self.uuid, conn).await?;
 
         db_run! { conn: {
             diesel::delete(organizations::table.filter(organizations::uuid.eq(self.uuid)))
@@ -395,6 +422,75 @@ impl Organization {
             organizations::table.load::<OrganizationDb>(conn).expect("Error loading organizations").from_db()
         }}
     }
+
+    /// Organizations of which the id, name or billing email contains the filter, used by the data browser of the admin panel.
+    pub async fn search(filter: Option<&str>, limit: i64, conn: &mut DbConn) -> Vec<Self> {
+        db_run! { conn: {
+            define_sql_function!{
+                fn lower(x: diesel::sql_types::Text) -> diesel::sql_types::Text;
+            }
+            let mut query = organizations::table.into_boxed();
+            if let Some(pattern) = filter.map(crate::util::like_contains_pattern) {
+                query = query.filter(
+                    organizations::uuid.like(pattern.clone()).escape('\\')
+                        .or(lower(organizations::name).like(pattern.clone()).escape('\\'))
+                        .or(lower(organizations::billing_email).like(pattern).escape('\\'))
+                );
+            }
+            query
+                .order_by(organizations::name)
+                .limit(limit)
+                .load::<OrganizationDb>(conn)
+                .expect("Error loading organizations")
+                .from_db()
+        }}
+    }
+
+    pub async fn find_scheduled_for_deletion_before(dt: &NaiveDateTime, conn: &mut DbConn) -> Vec<Self> {
+        db_run! { conn: {
+            organizations::table
+                .filter(organizations::delete_scheduled_at.le(dt))
+                .load::<OrganizationDb>(conn)
+                .expect("Error loading organizations")
+                .from_db()
+        }}
+    }
+
+    pub async fn find_auto_export(conn: &mut DbConn) -> Vec<Self> {
+        db_run! { conn: {
+            organizations::table
+                .filter(organizations::auto_export.eq(true))
+                .load::<OrganizationDb>(conn)
+                .expect("Error loading organizations")
+                .from_db()
+        }}
+    }
+
+    /// Fails when the seat limit of the organization doesn't allow this amount of new members.
+    /// Revoked members do not occupy a seat.
+    pub async fn check_seats_available(&self, new_members: i64, conn: &mut DbConn) -> EmptyResult {
+        if let Some(max_seats) = self.max_seats {
+            if Membership::count_occupied_seats_by_org(&self.uuid, conn).await + new_members > i64::from(max_seats) {
+                err!(format!("This organization has reached its limit of {max_seats} seats"))
+            }
+        }
+        Ok(())
+    }
+
+    /// The attachment storage limit (KB) which applies to the ciphers of the organization, if any
+    pub fn attachment_limit_kb(&self) -> Option<i64> {
+        self.attachment_limit.or_else(|| CONFIG.org_attachment_limit())
+    }
+
+    /// Fails when the collection limit of the organization doesn't allow this amount of new collections.
+    pub async fn check_collections_available(&self, new_collections: i64, conn: &mut DbConn) -> EmptyResult {
+        if let Some(max_collections) = self.max_collections {
+            if Collection::count_by_org(&self.uuid, conn).await + new_collections > i64::from(max_collections) {
+                err!(format!("This organization has reached its limit of {max_collections} collections"))
+            }
+        }
+        Ok(())
+    }
 }
 
 impl Membership {
@@ -429,8 +525,8 @@ impl Membership {
             "id": self.org_uuid,
             "identifier": null, // Not supported
             "name": org.name,
-            "seats": null,
-            "maxCollections": null,
+            "seats": org.max_seats,
+            "maxCollections": org.max_collections,
             "usersGetPremium": true,
             "use2fa": true,
             "useDirectory": false, // Is supported, but this value isn't checked anywhere (yet)
@@ -466,9 +562,10 @@ impl Membership {
             "familySponsorshipValidUntil": null,
             "familySponsorshipToDelete": null,
             "accessSecretsManager": false,
-            "limitCollectionCreation": self.atype < MembershipType::Manager, // If less then a manager return true, to limit collection creations
-            "limitCollectionCreationDeletion": true,
-            "limitCollectionDeletion": true,
+            // Managers are never limited, the clients would otherwise hide these actions for them
+            "limitCollectionCreation": self.atype < MembershipType::Manager && org.limit_collection_creation,
+            "limitCollectionCreationDeletion": self.atype < MembershipType::Manager && (org.limit_collection_creation || org.limit_collection_deletion),
+            "limitCollectionDeletion": self.atype < MembershipType::Manager && org.limit_collection_deletion,
             "allowAdminAccessToAllCollectionItems": true,
             "userIsManagedByOrganization": false, // Means not managed via the Members UI, like SSO
 
@@ -666,6 +763,7 @@ impl Membership {
             "status": status,
             "type": self.atype,
             "accessAll": self.access_all,
+            "externalId": self.external_id,
             "collections": coll_uuids,
 
             "object": "organizationUserDetails",
@@ -732,6 +830,7 @@ impl Membership {
         User::update_uuid_revision(&self.user_uuid, conn).await;
 
         CollectionUser::delete_all_by_user_and_org(&self.user_uuid, &self.org_uuid, conn).await?;
+        Favorite::delete_all_by_user_and_org(&self.user_uuid, &self.org_uuid, conn).await?;
         GroupUser::delete_all_by_member(&self.uuid, conn).await?;
 
         db_run! { conn: {
@@ -874,6 +973,17 @@ impl Membership {
         }}
     }
 
+    pub async fn count_occupied_seats_by_org(org_uuid: &OrganizationId, conn: &mut DbConn) -> i64 {
+        db_run! { conn: {
+            users_organizations::table
+                .filter(users_organizations::org_uuid.eq(org_uuid))
+                .filter(users_organizations::status.ne(MembershipStatus::Revoked as i32))
+                .count()
+                .first::<i64>(conn)
+                .unwrap_or(0)
+        }}
+    }
+
     pub async fn find_by_org_and_type(
         org_uuid: &OrganizationId,
         atype: MembershipType,
@@ -3,7 +3,7 @@ use serde_json::Value;
 
 use crate::util::LowerCase;
 
-use super::{OrganizationId, User, UserId};
+use super::{OrgPolicy, OrganizationId, User, UserId};
 use id::SendId;
 
 db_object! {
@@ -104,13 +104,12 @@ impl Send {
     }
 
     pub async fn creator_identifier(&self, conn: &mut DbConn) -> Option<String> {
-        if let Some(hide_email) = self.hide_email {
-            if hide_email {
+        if let Some(user_uuid) = &self.user_uuid {
+            // The Send Options policy can forbid hiding the email, this also applies to existing Sends
+            if self.hide_email == Some(true) && !OrgPolicy::is_hide_email_disabled(user_uuid, conn).await {
                 return None;
             }
-        }
 
-        if let Some(user_uuid) = &self.user_uuid {
             if let Some(user) = User::find_by_uuid(user_uuid, conn).await {
                 return Some(user.email);
             }
@@ -184,6 +183,7 @@ use crate::db::DbConn;
 
 use crate::api::EmptyResult;
 use crate::error::MapResult;
+use crate::storage::{FileBackend, FileStorage};
 use crate::util::NumberOrString;
 
 impl Send {
@@ -226,7 +226,10 @@ impl Send {
         self.update_users_revision(conn).await;
 
         if self.atype == SendType::File as i32 {
-            std::fs::remove_dir_all(std::path::Path::new(&crate::CONFIG.sends_folder()).join(&self.uuid)).ok();
+            let _io_permit = crate::util::acquire_io_permit(crate::util::FileStore::Sends).await;
+            if let Err(e) = FileStorage::sends().delete_folder(&self.uuid).await {
+                error!("Error removing the files of send {}: {e:#?}", self.uuid);
+            }
         }
 
         db_run! { conn: {
@@ -239,7 +242,9 @@ impl Send {
     /// Purge all sends that are past their deletion date.
     pub async fn purge(conn: &mut DbConn) {
         for send in Self::find_by_past_deletion_date(conn).await {
-            send.delete(conn).await.ok();
+            if let Err(e) = send.delete(conn).await {
+                error!("Error purging send {}: {e:#?}", send.uuid);
+            }
         }
     }
 
@@ -1,7 +1,11 @@
 use serde_json::Value;
 
 use super::UserId;
-use crate::{api::EmptyResult, db::DbConn, error::MapResult};
+use crate::{
+    api::EmptyResult,
+    db::DbConn,
+    error::{Error, MapResult},
+};
 
 db_object! {
     #[derive(Identifiable, Queryable, Insertable, AsChangeset)]
@@ -28,6 +32,7 @@ pub enum TwoFactorType {
     Remember = 5,
     OrganizationDuo = 6,
     Webauthn = 7,
+    RecoveryCode = 8,
 
     // These are implementation details
     U2fRegisterChallenge = 1000,
@@ -38,8 +43,37 @@ pub enum TwoFactorType {
 
     // Special type for Protected Actions verification via email
     ProtectedActions = 2000,
+
+    // Special type holding the set of single-use recovery codes
+    RecoveryCodes = 2001,
+
+    // Special type tracking failed two-step login attempts
+    LoginLockout = 2002,
+
+    // Special type holding the passkeys which can be used to log in without the master password
+    PasskeyCredentials = 2003,
+
+    // Special type holding the new devices of which the login is pending approval
+    NewDeviceApproval = 2004,
+
+    // Special type holding the IP addresses of the recent successful logins, used for the password hints
+    KnownLoginIps = 2005,
+
+    // Special type holding the consumed challenges of passkey logins until they expire
+    PasskeyLoginChallenges = 2006,
+
+    // Special type holding the time the user last verified themselves for the protected actions
+    FreshVerification = 2007,
 }
 
+// The special types which belong to the account instead of its two-step login,
+// these are kept when the two-step login methods of a user are removed
+const ACCOUNT_TYPES: [i32; 3] = [
+    TwoFactorType::PasskeyCredentials as i32,
+    TwoFactorType::KnownLoginIps as i32,
+    TwoFactorType::PasskeyLoginChallenges as i32,
+];
+
 /// Local methods
 impl TwoFactor {
     pub fn new(user_uuid: UserId, atype: TwoFactorType, data: String) -> Self {
@@ -111,6 +145,30 @@ impl TwoFactor {
         }
     }
 
+    /// Saves this twofactor only when its data is still `old_data`, or when it does not exist yet if `old_data`
+    /// is `None`. Returns `false` when it was changed in the meantime, for example by a concurrent request.
+    pub async fn save_if_unchanged(&self, old_data: Option<&str>, conn: &mut DbConn) -> Result<bool, Error> {
+        db_run! { conn: {
+            match old_data {
+                Some(old_data) => diesel::update(twofactor::table)
+                    .filter(twofactor::uuid.eq(&self.uuid))
+                    .filter(twofactor::data.eq(old_data))
+                    .set(twofactor::data.eq(&self.data))
+                    .execute(conn)
+                    .map(|updated| updated == 1)
+                    .map_res("Error saving twofactor"),
+                // The unique constraint on user_uuid and atype rejects a record which was inserted in the meantime
+                None => match diesel::insert_into(twofactor::table).values(TwoFactorDb::to_db(self)).execute(conn) {
+                    Ok(_) => Ok(true),
+                    Err(diesel::result::Error::DatabaseError(diesel::result::DatabaseErrorKind::UniqueViolation, _)) => {
+                        Ok(false)
+                    }
+                    Err(e) => Err(e.into()),
+                },
+            }
+        }}
+    }
+
     pub async fn delete(self, conn: &mut DbConn) -> EmptyResult {
         db_run! { conn: {
             diesel::delete(twofactor::table.filter(twofactor::uuid.eq(self.uuid)))
@@ -149,6 +207,19 @@ impl TwoFactor {
         }}
     }
 
+    /// Removes all the two-step login methods of the user, keeping the account data like the passkeys.
+    pub async fn delete_two_step_login_by_user(user_uuid: &UserId, conn: &mut DbConn) -> EmptyResult {
+        db_run! { conn: {
+            diesel::delete(
+                twofactor::table
+                    .filter(twofactor::user_uuid.eq(user_uuid))
+                    .filter(twofactor::atype.ne_all(ACCOUNT_TYPES)),
+            )
+            .execute(conn)
+            .map_res("Error deleting twofactors")
+        }}
+    }
+
     pub async fn migrate_u2f_to_webauthn(conn: &mut DbConn) -> EmptyResult {
         let u2f_factors = db_run! { conn: {
             twofactor::table
@@ -164,19 +235,26 @@ impl TwoFactor {
 
         for mut u2f in u2f_factors {
             let mut regs: Vec<U2FRegistration> = serde_json::from_str(&u2f.data)?;
-            // If there are no registrations or they are migrated (we do the migration in batch so we can consider them all migrated when the first one is)
-            if regs.is_empty() || regs[0].migrated == Some(true) {
+            // If there are no registrations or all of them are migrated already, there is nothing to do
+            if regs.iter().all(|r| r.migrated == Some(true)) {
                 continue;
             }
 
+            // Registrations which were added via WebAuthn are kept, the migrated U2F keys are added to them
             let (_, mut webauthn_regs) = get_webauthn_registrations(&u2f.user_uuid, conn).await?;
 
-            // If the user already has webauthn registrations saved, don't overwrite them
-            if !webauthn_regs.is_empty() {
-                continue;
-            }
+            for reg in regs.iter_mut().filter(|r| r.migrated != Some(true)) {
+                // The key could already have been registered again via WebAuthn by the user
+                if webauthn_regs.iter().any(|r| r.credential.cred_id == reg.reg.key_handle) {
+                    reg.migrated = Some(true);
+                    continue;
+                }
 
-            for reg in &mut regs {
+                // An uncompressed P-256 public key, prevent a malformed registration from aborting the startup
+                if reg.reg.pub_key.len() != 65 || reg.reg.pub_key[0] != 0x04 {
+                    warn!("Unable to migrate U2F key '{}' of user {}: invalid public key", reg.name, u2f.user_uuid);
+                    continue;
+                }
                 let x: [u8; 32] = reg.reg.pub_key[1..33].try_into().unwrap();
                 let y: [u8; 32] = reg.reg.pub_key[33..65].try_into().unwrap();
 
@@ -189,8 +267,15 @@ impl TwoFactor {
                     }),
                 };
 
+                // Keep the id unique, as it is used to select the key to delete
+                let id = if webauthn_regs.iter().any(|r| r.id == reg.id) {
+                    webauthn_regs.iter().map(|r| r.id).max().unwrap_or_default() + 1
+                } else {
+                    reg.id
+                };
+
                 let new_reg = WebauthnRegistration {
-                    id: reg.id,
+                    id,
                     migrated: true,
                     name: reg.name.clone(),
                     credential: Credential {
@@ -210,9 +295,11 @@ impl TwoFactor {
             u2f.data = serde_json::to_string(&regs)?;
             u2f.save(conn).await?;
 
-            TwoFactor::new(u2f.user_uuid.clone(), TwoFactorType::Webauthn, serde_json::to_string(&webauthn_regs)?)
-                .save(conn)
-                .await?;
+            if !webauthn_regs.is_empty() {
+                TwoFactor::new(u2f.user_uuid.clone(), TwoFactorType::Webauthn, serde_json::to_string(&webauthn_regs)?)
+                    .save(conn)
+                    .await?;
+            }
         }
 
         Ok(())
@@ -3,7 +3,8 @@ use derive_more::{AsRef, Deref, Display, From};
 use serde_json::Value;
 
 use super::{
-    Cipher, Device, EmergencyAccess, Favorite, Folder, Membership, MembershipType, TwoFactor, TwoFactorIncomplete,
+    Attachment, Cipher, Device, EmergencyAccess, Favorite, Folder, Membership, MembershipType, TwoFactor,
+    TwoFactorIncomplete,
 };
 use crate::{
     api::EmptyResult,
@@ -62,7 +63,21 @@ db_object! {
 
         pub avatar_color: Option<String>,
 
-        pub external_id: Option<String>, // Todo: Needs to be removed in the future, this is not used anymore.
+        // The id of the user in the directory, set by the Directory Connector to correlate the accounts
+        pub external_id: Option<String>,
+
+        // When the pending email change was requested, and when it was confirmed from the current address
+        pub email_new_requested_at: Option<NaiveDateTime>,
+        pub email_new_confirmed_at: Option<NaiveDateTime>,
+
+        pub delete_scheduled_at: Option<NaiveDateTime>,
+
+        // Set when an organization admin reset the master password, the user has to choose a new one at the next login
+        pub force_password_reset: bool,
+
+        // Set for password hashes imported from an official server which use PBKDF2-HMAC-SHA512,
+        // these are replaced by a regular hash at the next login
+        pub password_sha512: bool,
     }
 
     #[derive(Identifiable, Queryable, Insertable)]
@@ -140,11 +155,25 @@ impl User {
 
             avatar_color: None,
 
-            external_id: None, // Todo: Needs to be removed in the future, this is not used anymore.
+            external_id: None,
+            email_new_requested_at: None,
+            email_new_confirmed_at: None,
+
+            delete_scheduled_at: None,
+            force_password_reset: false,
+            password_sha512: false,
         }
     }
 
     pub fn check_valid_password(&self, password: &str) -> bool {
+        if self.password_sha512 {
+            return crypto::verify_password_hash_sha512(
+                password.as_bytes(),
+                &self.salt,
+                &self.password_hash,
+                self.password_iterations as u32,
+            );
+        }
         crypto::verify_password_hash(
             password.as_bytes(),
             &self.salt,
@@ -153,6 +182,18 @@ impl User {
         )
     }
 
+    /// Whether the client side KDF of the account is below the configured minimum, only relevant for existing accounts
+    /// as the minimum is enforced on registration and KDF changes.
+    pub fn has_weak_kdf(&self) -> bool {
+        if self.client_kdf_type == UserKdfType::Argon2id as i32 {
+            self.client_kdf_iter < CONFIG.kdf_min_argon2_iterations()
+                || self.client_kdf_memory.is_none_or(|m| m < CONFIG.kdf_min_argon2_memory())
+                || self.client_kdf_parallelism.is_none_or(|p| p < CONFIG.kdf_min_argon2_parallelism())
+        } else {
+            self.client_kdf_iter < CONFIG.kdf_min_pbkdf2_iterations()
+        }
+    }
+
     pub fn check_valid_recovery_code(&self, recovery_code: &str) -> bool {
         if let Some(ref totp_recover) = self.totp_recover {
             crypto::ct_eq(recovery_code, totp_recover.to_lowercase())
@@ -184,6 +225,7 @@ impl User {
         allow_next_route: Option<Vec<String>>,
     ) {
         self.password_hash = crypto::hash_password(password.as_bytes(), &self.salt, self.password_iterations as u32);
+        self.password_sha512 = false;
 
         if let Some(route) = allow_next_route {
             self.set_stamp_exception(route);
@@ -196,6 +238,9 @@ impl User {
         if reset_security_stamp {
             self.reset_security_stamp()
         }
+
+        // Choosing a new password always completes a pending forced reset
+        self.force_password_reset = false;
     }
 
     pub fn reset_security_stamp(&mut self) {
@@ -234,6 +279,11 @@ impl User {
 
         let twofactor_enabled = !TwoFactor::find_by_user(&self.uuid, conn).await.is_empty();
 
+        // Attachment storage of the personal vault, the limit and remaining space are null when unlimited
+        let storage_used = Attachment::size_by_user(&self.uuid, conn).await;
+        let storage_limit = CONFIG.user_attachment_limit().map(|limit_kb| limit_kb.saturating_mul(1024));
+        let storage_remaining = storage_limit.map(|limit| limit.saturating_sub(storage_used).max(0));
+
         // TODO: Might want to save the status field in the DB
         let status = if self.password_hash.is_empty() {
             UserStatus::Invited
@@ -258,10 +308,13 @@ impl User {
             "organizations": orgs_json,
             "providers": [],
             "providerOrganizations": [],
-            "forcePasswordReset": false,
+            "forcePasswordReset": self.force_password_reset,
             "avatarColor": self.avatar_color,
             "usesKeyConnector": false,
             "creationDate": format_date(&self.created_at),
+            "attachmentStorageUsed": storage_used,
+            "attachmentStorageLimit": storage_limit,
+            "attachmentStorageRemaining": storage_remaining,
             "object": "profile",
         })
     }
@@ -304,7 +357,8 @@ impl User {
         }
     }
 
-    pub async fn delete(self, conn: &mut DbConn) -> EmptyResult {
+    /// Checks if the user can be deleted, the last owner of an organization can't be.
+    pub async fn check_deletable(&self, conn: &mut DbConn) -> EmptyResult {
         for member in Membership::find_confirmed_by_user(&self.uuid, conn).await {
             if member.atype == MembershipType::Owner
                 && Membership::count_confirmed_by_org_and_type(&member.org_uuid, MembershipType::Owner, conn).await <= 1
@@ -312,6 +366,11 @@ impl User {
                 err!("Can't delete last owner")
             }
         }
+        Ok(())
+    }
+
+    pub async fn delete(self, conn: &mut DbConn) -> EmptyResult {
+        self.check_deletable(conn).await?;
 
         super::Send::delete_all_by_user(&self.uuid, conn).await?;
         EmergencyAccess::delete_all_by_user(&self.uuid, conn).await?;
@@ -379,6 +438,21 @@ impl User {
         }}
     }
 
+    /// Finds an account for the same mailbox when ignoring plus addresses, so `user@example.org` or `user+...@example.org`
+    pub async fn find_by_normalized_mail(mail: &str, conn: &mut DbConn) -> Option<Self> {
+        let normalized = crate::util::normalize_plus_address(mail);
+        let escaped = normalized.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+        let (local, domain) = escaped.rsplit_once('@')?;
+        let pattern = format!("{local}+%@{domain}");
+        db_run! {conn: {
+            users::table
+                .filter(users::email.eq(&normalized).or(users::email.like(pattern).escape('\\')))
+                .first::<UserDb>(conn)
+                .ok()
+                .from_db()
+        }}
+    }
+
     pub async fn find_by_uuid(uuid: &UserId, conn: &mut DbConn) -> Option<Self> {
         db_run! {conn: {
             users::table.filter(users::uuid.eq(uuid)).first::<UserDb>(conn).ok().from_db()
@@ -391,6 +465,39 @@ impl User {
         }}
     }
 
+    /// Users of which the id, email or name contains the filter, used by the data browser of the admin panel.
+    pub async fn search(filter: Option<&str>, limit: i64, conn: &mut DbConn) -> Vec<Self> {
+        db_run! {conn: {
+            define_sql_function!{
+                fn lower(x: diesel::sql_types::Text) -> diesel::sql_types::Text;
+            }
+            let mut query = users::table.into_boxed();
+            if let Some(pattern) = filter.map(crate::util::like_contains_pattern) {
+                query = query.filter(
+                    users::uuid.like(pattern.clone()).escape('\\')
+                        .or(users::email.like(pattern.clone()).escape('\\'))
+                        .or(lower(users::name).like(pattern).escape('\\'))
+                );
+            }
+            query
+                .order_by(users::email)
+                .limit(limit)
+                .load::<UserDb>(conn)
+                .expect("Error loading users")
+                .from_db()
+        }}
+    }
+
+    pub async fn find_scheduled_for_deletion_before(dt: &NaiveDateTime, conn: &mut DbConn) -> Vec<Self> {
+        db_run! {conn: {
+            users::table
+                .filter(users::delete_scheduled_at.le(dt))
+                .load::<UserDb>(conn)
+                .expect("Error loading users")
+                .from_db()
+        }}
+    }
+
     pub async fn last_active(&self, conn: &mut DbConn) -> Option<NaiveDateTime> {
         match Device::find_latest_active_by_user(&self.uuid, conn).await {
             Some(device) => Some(device.updated_at),
@@ -54,7 +54,14 @@ table! {
         push_uuid -> Nullable<Text>,
         push_token -> Nullable<Text>,
         refresh_token -> Text,
+        refresh_token_family -> Nullable<Text>,
+        previous_refresh_token -> Nullable<Text>,
+        refresh_token_rotated_at -> Nullable<Timestamp>,
         twofactor_remember -> Nullable<Text>,
+        twofactor_remember_at -> Nullable<Timestamp>,
+        encrypted_user_key -> Nullable<Text>,
+        encrypted_public_key -> Nullable<Text>,
+        encrypted_private_key -> Nullable<Text>,
     }
 }
 
@@ -76,6 +83,7 @@ table! {
         provider_uuid -> Nullable<Text>,
         provider_user_uuid -> Nullable<Text>,
         provider_org_uuid -> Nullable<Text>,
+        geo_location -> Nullable<Text>,
     }
 }
 
@@ -126,6 +134,15 @@ table! {
         billing_email -> Text,
         private_key -> Nullable<Text>,
         public_key -> Nullable<Text>,
+        delete_scheduled_at -> Nullable<Timestamp>,
+        max_seats -> Nullable<Integer>,
+        max_collections -> Nullable<Integer>,
+        auto_export -> Bool,
+        limit_collection_creation -> Bool,
+        limit_collection_deletion -> Bool,
+        attachment_limit -> Nullable<BigInt>,
+        delete_requested_at -> Nullable<Timestamp>,
+        auto_export_mail -> Bool,
     }
 }
 
@@ -184,6 +201,15 @@ table! {
     }
 }
 
+table! {
+    login_backoffs (backoff_key) {
+        backoff_key -> Text,
+        failures -> Integer,
+        last_failure -> BigInt,
+        blocked_until -> BigInt,
+    }
+}
+
 table! {
     users (uuid) {
         uuid -> Text,
@@ -217,6 +243,11 @@ table! {
         api_key -> Nullable<Text>,
         avatar_color -> Nullable<Text>,
         external_id -> Nullable<Text>,
+        email_new_requested_at -> Nullable<Timestamp>,
+        email_new_confirmed_at -> Nullable<Timestamp>,
+        delete_scheduled_at -> Nullable<Timestamp>,
+        force_password_reset -> Bool,
+        password_sha512 -> Bool,
     }
 }
 
@@ -241,6 +272,7 @@ table! {
         atype -> Integer,
         reset_password_key -> Nullable<Text>,
         external_id -> Nullable<Text>,
+        invited_at -> Nullable<Timestamp>,
     }
 }
 
@@ -320,6 +352,19 @@ table! {
     }
 }
 
+table! {
+    organization_domains (uuid) {
+        uuid -> Text,
+        org_uuid -> Text,
+        domain_name -> Text,
+        txt -> Text,
+        creation_date -> Timestamp,
+        verified_date -> Nullable<Timestamp>,
+        last_checked_date -> Nullable<Timestamp>,
+        job_run_count -> Integer,
+    }
+}
+
 joinable!(attachments -> ciphers (cipher_uuid));
 joinable!(ciphers -> organizations (organization_uuid));
 joinable!(ciphers -> users (user_uuid));
@@ -348,6 +393,7 @@ joinable!(collections_groups -> collections (collections_uuid));
 joinable!(collections_groups -> groups (groups_uuid));
 joinable!(event -> users_organizations (uuid));
 joinable!(auth_requests -> users (user_uuid));
+joinable!(organization_domains -> organizations (org_uuid));
 
 allow_tables_to_appear_in_same_query!(
     attachments,
@@ -372,4 +418,5 @@ allow_tables_to_appear_in_same_query!(
     collections_groups,
     event,
     auth_requests,
+    organization_domains,
 );
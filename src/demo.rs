@@ -0,0 +1,123 @@
+//
+// Example data for the demo mode
+//
+// The vault data is encrypted by the clients, so the demo account is created the same way a client would:
+// the keys are derived from the demo account password and all example items are encrypted with the user key.
+// See https://bitwarden.com/help/bitwarden-security-white-paper/ for the details of the encryption.
+//
+use data_encoding::BASE64;
+use openssl::{rsa::Rsa, symm};
+use ring::hmac;
+
+use crate::{
+    api::EmptyResult,
+    crypto,
+    db::{
+        models::{Cipher, CipherType, Folder, FolderCipher, User},
+        DbConn,
+    },
+    error::{Error, MapResult},
+    CONFIG,
+};
+
+/// The AES-256 encryption key and the HMAC-SHA256 key used to create encrypted strings.
+struct SymmetricKey {
+    enc: [u8; 32],
+    mac: [u8; 32],
+}
+
+impl SymmetricKey {
+    fn from_bytes(key: &[u8; 64]) -> Self {
+        Self {
+            enc: key[..32].try_into().unwrap(),
+            mac: key[32..].try_into().unwrap(),
+        }
+    }
+
+    /// Stretches the master key with HKDF-Expand, which is a single HMAC for a 32 byte output.
+    fn stretch(master_key: &[u8]) -> Self {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, master_key);
+        let expand = |info: &[u8]| -> [u8; 32] {
+            let mut data = info.to_vec();
+            data.push(1);
+            hmac::sign(&key, &data).as_ref().try_into().unwrap()
+        };
+        Self {
+            enc: expand(b"enc"),
+            mac: expand(b"mac"),
+        }
+    }
+
+    /// Encrypts the data into an `AesCbc256_HmacSha256_B64` encrypted string, the type the clients use.
+    fn encrypt(&self, data: &[u8]) -> Result<String, Error> {
+        let iv = crypto::get_random_bytes::<16>();
+        let encrypted = symm::encrypt(symm::Cipher::aes_256_cbc(), &self.enc, Some(&iv), data)
+            .map_res("Error encrypting the demo data")?;
+        let mac = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, &self.mac), &[&iv[..], &encrypted[..]].concat());
+        Ok(format!("2.{}|{}|{}", BASE64.encode(&iv), BASE64.encode(&encrypted), BASE64.encode(mac.as_ref())))
+    }
+
+    fn encrypt_str(&self, data: &str) -> Result<String, Error> {
+        self.encrypt(data.as_bytes())
+    }
+}
+
+/// Creates the demo account with some example items when it does not exist yet.
+pub async fn create_demo_account(conn: &mut DbConn) -> EmptyResult {
+    let email = CONFIG.demo_account_email().to_lowercase();
+    if User::find_by_mail(&email, conn).await.is_some() {
+        return Ok(());
+    }
+    let password = CONFIG.demo_account_password();
+
+    let mut user = User::new(email.clone());
+    user.name = String::from("Demo User");
+    user.verified_at = Some(chrono::Utc::now().naive_utc());
+
+    // The same derivation the clients do with the default KDF settings of a new account
+    let master_key = crypto::hash_password(password.as_bytes(), email.as_bytes(), user.client_kdf_iter as u32);
+    let master_password_hash = BASE64.encode(&crypto::hash_password(&master_key, password.as_bytes(), 1));
+
+    let user_key_bytes = crypto::get_random_bytes::<64>();
+    let user_key = SymmetricKey::from_bytes(&user_key_bytes);
+    let akey = SymmetricKey::stretch(&master_key).encrypt(&user_key_bytes)?;
+    user.set_password(&master_password_hash, Some(akey), false, None);
+
+    let rsa = Rsa::generate(2048).map_res("Error generating the demo account key pair")?;
+    let private_key = openssl::pkey::PKey::from_rsa(rsa).map_res("Error generating the demo account key pair")?;
+    let private_key_der = private_key.private_key_to_pkcs8().map_res("Error encoding the demo account key")?;
+    let public_key_der = private_key.public_key_to_der().map_res("Error encoding the demo account key")?;
+    user.private_key = Some(user_key.encrypt(&private_key_der)?);
+    user.public_key = Some(BASE64.encode(&public_key_der));
+    user.save(conn).await?;
+
+    let mut folder = Folder::new(user.uuid.clone(), user_key.encrypt_str("Examples")?);
+    folder.save(conn).await?;
+
+    let mut login = Cipher::new(CipherType::Login as i32, user_key.encrypt_str("Example website")?);
+    login.user_uuid = Some(user.uuid.clone());
+    login.notes = Some(user_key.encrypt_str("Changes can't be saved on this demo instance.")?);
+    login.data = json!({
+        "username": user_key.encrypt_str("demo-user")?,
+        "password": user_key.encrypt_str("correct-horse-battery-staple")?,
+        "uris": [{
+            "uri": user_key.encrypt_str("https://example.com")?,
+            "match": null,
+        }],
+        "totp": null,
+    })
+    .to_string();
+    login.save(conn).await?;
+    FolderCipher::new(folder.uuid.clone(), login.uuid.clone()).save(conn).await?;
+
+    let mut note = Cipher::new(CipherType::SecureNote as i32, user_key.encrypt_str("Welcome to the demo")?);
+    note.user_uuid = Some(user.uuid.clone());
+    note.notes = Some(user_key.encrypt_str(&format!(
+        "This is a read-only demo of Vaultwarden, log in with {email} and the password {password}."
+    ))?);
+    note.data = json!({ "type": 0 }).to_string();
+    note.save(conn).await?;
+
+    info!("Created the demo account {email}");
+    Ok(())
+}
@@ -13,14 +13,14 @@ macro_rules! make_error {
 
         #[derive(Debug)]
         pub struct ErrorEvent { pub event: EventType }
-        pub struct Error { message: String, error: ErrorKind, error_code: u16, event: Option<ErrorEvent> }
+        pub struct Error { message: String, error: ErrorKind, error_code: u16, api_code: Option<ApiErrorCode>, event: Option<ErrorEvent>, retry_after: Option<u64> }
 
         $(impl From<$ty> for Error {
             fn from(err: $ty) -> Self { Error::from((stringify!($name), err)) }
         })+
         $(impl<S: Into<String>> From<(S, $ty)> for Error {
             fn from(val: (S, $ty)) -> Self {
-                Error { message: val.0.into(), error: ErrorKind::$name(val.1), error_code: BAD_REQUEST, event: None }
+                Error { message: val.0.into(), error: ErrorKind::$name(val.1), error_code: BAD_REQUEST, api_code: None, event: None, retry_after: None }
             }
         })+
         impl StdError for Error {
@@ -31,13 +31,60 @@ macro_rules! make_error {
         impl std::fmt::Display for Error {
             fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
                 match &self.error {$(
-                   ErrorKind::$name(e) => f.write_str(&$usr_msg_fun(e, &self.message)),
+                   ErrorKind::$name(e) => f.write_str(&$usr_msg_fun(e, &self.message, self.api_code())),
                 )+}
             }
         }
     };
 }
 
+//
+// Stable error codes
+//
+// These codes are part of the API, integrations can rely on them instead of matching the human readable messages.
+// Never change or reuse the identifier of an existing code, only add new ones.
+macro_rules! make_api_error_codes {
+    ( $( $name:ident: $id:literal, $status:literal, $description:literal; )+ ) => {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+        pub enum ApiErrorCode { $($name),+ }
+
+        impl ApiErrorCode {
+            pub const ALL: &'static [ApiErrorCode] = &[$(ApiErrorCode::$name),+];
+
+            pub const fn id(self) -> &'static str {
+                match self {$( ApiErrorCode::$name => $id, )+}
+            }
+
+            pub const fn status(self) -> u16 {
+                match self {$( ApiErrorCode::$name => $status, )+}
+            }
+
+            pub const fn description(self) -> &'static str {
+                match self {$( ApiErrorCode::$name => $description, )+}
+            }
+
+            pub fn from_id(id: &str) -> Option<Self> {
+                Self::ALL.iter().copied().find(|c| c.id() == id)
+            }
+        }
+    };
+}
+
+make_api_error_codes! {
+    BadRequest:           "bad_request",           400, "The request could not be processed, the message contains the reason.";
+    Unauthorized:         "unauthorized",          401, "The request is not authenticated, or the session is no longer valid.";
+    Forbidden:            "forbidden",             403, "The authenticated user is not allowed to perform this action.";
+    NotFound:             "not_found",             404, "The requested resource does not exist.";
+    RateLimited:          "rate_limited",          429, "Too many requests were made in a short time, try again later.";
+    InternalError:        "internal_error",        500, "An unexpected server side error occurred, the server log contains more details.";
+    InvalidPassword:      "invalid_password",      400, "The provided master password hash is not valid.";
+    InvalidToken:         "invalid_token",         400, "The provided token or code is invalid or has expired, request a new one.";
+    TwoFactorLocked:      "two_factor_locked",     429, "Two-step login is temporarily locked because of too many failed attempts.";
+    VerificationRequired: "verification_required", 403, "The action requires the user to verify their master password or a protected action code again.";
+    ReadOnlyInstance:     "read_only_instance",    403, "The server is running in read-only demo mode, changes can not be saved.";
+    CaptchaRequired:      "captcha_required",      400, "The request needs a valid CAPTCHA response, the site key of the widget is included in the error.";
+}
+
 use diesel::r2d2::PoolError as R2d2Err;
 use diesel::result::Error as DieselErr;
 use diesel::ConnectionError as DieselConErr;
@@ -74,7 +121,7 @@ make_error! {
     CustomHttpClient(CustomHttpClientError): _has_source, _api_error,
 
     // Used for special return values, like 2FA errors
-    Json(Value):     _no_source,  _serialize,
+    Json(Value):     _no_source,  _json_error,
     Db(DieselErr):   _has_source, _api_error,
     R2d2(R2d2Err):   _has_source, _api_error,
     Serde(SerdeErr): _has_source, _api_error,
@@ -147,6 +194,40 @@ impl Error {
     pub fn get_event(&self) -> &Option<ErrorEvent> {
         &self.event
     }
+
+    pub const fn retry_after(&self) -> Option<u64> {
+        self.retry_after
+    }
+
+    /// Adds a `Retry-After` header with the number of seconds after which the request can be retried.
+    #[must_use]
+    pub const fn with_retry_after(mut self, seconds: u64) -> Self {
+        self.retry_after = Some(seconds);
+        self
+    }
+
+    /// Sets a specific stable error code, which also determines the HTTP status code of the response.
+    #[must_use]
+    pub const fn with_api_code(mut self, api_code: ApiErrorCode) -> Self {
+        self.error_code = api_code.status();
+        self.api_code = Some(api_code);
+        self
+    }
+
+    /// The stable error code, derived from the status code when no specific code was set.
+    pub fn api_code(&self) -> ApiErrorCode {
+        if let Some(api_code) = self.api_code {
+            return api_code;
+        }
+        match self.error_code {
+            401 => ApiErrorCode::Unauthorized,
+            403 => ApiErrorCode::Forbidden,
+            404 => ApiErrorCode::NotFound,
+            429 => ApiErrorCode::RateLimited,
+            500..=u16::MAX => ApiErrorCode::InternalError,
+            _ => ApiErrorCode::BadRequest,
+        }
+    }
 }
 
 pub trait MapResult<S> {
@@ -178,13 +259,23 @@ fn _no_source<T, S>(_: T) -> Option<S> {
     None
 }
 
-fn _serialize(e: &impl serde::Serialize, _msg: &str) -> String {
+fn _serialize(e: &impl serde::Serialize, _msg: &str, _api_code: ApiErrorCode) -> String {
     serde_json::to_string(e).unwrap()
 }
 
-fn _api_error(_: &impl std::any::Any, msg: &str) -> String {
+// Special return values keep their format, only the error code is added
+fn _json_error(e: &Value, _msg: &str, api_code: ApiErrorCode) -> String {
+    let mut json = e.clone();
+    if let Some(obj) = json.as_object_mut() {
+        obj.insert(String::from("errorCode"), Value::from(api_code.id()));
+    }
+    serde_json::to_string(&json).unwrap()
+}
+
+fn _api_error(_: &impl std::any::Any, msg: &str, api_code: ApiErrorCode) -> String {
     let json = json!({
         "message": msg,
+        "errorCode": api_code.id(),
         "error": "",
         "error_description": "",
         "validationErrors": {"": [ msg ]},
@@ -197,7 +288,7 @@ fn _api_error(_: &impl std::any::Any, msg: &str) -> String {
         "innerExceptionMessage": null,
         "object": "error"
     });
-    _serialize(&json, "")
+    _serialize(&json, "", api_code)
 }
 
 //
@@ -219,7 +310,12 @@ impl Responder<'_, 'static> for Error {
 
         let code = Status::from_code(self.error_code).unwrap_or(Status::BadRequest);
         let body = self.to_string();
-        Response::build().status(code).header(ContentType::JSON).sized_body(Some(body.len()), Cursor::new(body)).ok()
+        let mut response = Response::build();
+        response.status(code).header(ContentType::JSON);
+        if let Some(seconds) = self.retry_after {
+            response.raw_header("Retry-After", seconds.to_string());
+        }
+        response.sized_body(Some(body.len()), Cursor::new(body)).ok()
     }
 }
 
@@ -268,6 +364,18 @@ macro_rules! err_code {
     }};
 }
 
+#[macro_export]
+macro_rules! err_api {
+    ($api_code:expr, $msg:expr) => {{
+        error!("{}", $msg);
+        return Err($crate::error::Error::new($msg, $msg).with_api_code($api_code));
+    }};
+    ($api_code:expr, $usr_msg:expr, $log_value:expr) => {{
+        error!("{}. {}", $usr_msg, $log_value);
+        return Err($crate::error::Error::new($usr_msg, $log_value).with_api_code($api_code));
+    }};
+}
+
 #[macro_export]
 macro_rules! err_discard {
     ($msg:expr, $data:expr) => {{
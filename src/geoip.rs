@@ -0,0 +1,103 @@
+//
+// GeoIP/ASN enrichment using local MMDB databases
+// No external lookups are done, if no database is configured this is a no-op.
+//
+use once_cell::sync::Lazy;
+use std::{fmt, net::IpAddr};
+
+use maxminddb::{geoip2, Reader};
+
+use crate::CONFIG;
+
+static CITY_DB: Lazy<Option<Reader<Vec<u8>>>> = Lazy::new(|| open_db(CONFIG.geoip_city_db_path()));
+static ASN_DB: Lazy<Option<Reader<Vec<u8>>>> = Lazy::new(|| open_db(CONFIG.geoip_asn_db_path()));
+
+fn open_db(path: Option<String>) -> Option<Reader<Vec<u8>>> {
+    let path = path?;
+    match Reader::open_readfile(&path) {
+        Ok(reader) => Some(reader),
+        Err(e) => {
+            error!("Unable to open GeoIP database `{path}`: {e}");
+            None
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct GeoIpInfo {
+    pub city: Option<String>,
+    pub country: Option<String>,
+    pub asn: Option<u32>,
+    pub as_org: Option<String>,
+}
+
+impl GeoIpInfo {
+    fn is_empty(&self) -> bool {
+        self.city.is_none() && self.country.is_none() && self.asn.is_none()
+    }
+}
+
+impl fmt::Display for GeoIpInfo {
+    /// Formats the info as `City, Country (AS1234 Org)`, leaving out the unknown parts
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let location: Vec<&str> = [&self.city, &self.country].into_iter().flatten().map(String::as_str).collect();
+        write!(f, "{}", location.join(", "))?;
+
+        if let Some(asn) = self.asn {
+            let sep = if location.is_empty() {
+                ""
+            } else {
+                " "
+            };
+            match &self.as_org {
+                Some(org) => write!(f, "{sep}(AS{asn} {org})")?,
+                None => write!(f, "{sep}(AS{asn})")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns true when at least one GeoIP database has been configured and loaded
+pub fn is_enabled() -> bool {
+    CITY_DB.is_some() || ASN_DB.is_some()
+}
+
+/// Lookup the location and ASN of an IP address in the configured local databases.
+/// Returns `None` if no database is configured or the IP address is not found.
+pub fn lookup(ip: &IpAddr) -> Option<GeoIpInfo> {
+    if !is_enabled() {
+        return None;
+    }
+
+    let mut info = GeoIpInfo::default();
+
+    if let Some(reader) = CITY_DB.as_ref() {
+        match reader.lookup::<geoip2::City<'_>>(*ip) {
+            Ok(Some(city)) => {
+                info.city = city.city.and_then(|c| c.names).and_then(|n| n.get("en").map(|s| s.to_string()));
+                info.country = city.country.and_then(|c| c.names).and_then(|n| n.get("en").map(|s| s.to_string()));
+            }
+            Ok(None) => (),
+            Err(e) => debug!("GeoIP city lookup for {ip} failed: {e}"),
+        }
+    }
+
+    if let Some(reader) = ASN_DB.as_ref() {
+        match reader.lookup::<geoip2::Asn<'_>>(*ip) {
+            Ok(Some(asn)) => {
+                info.asn = asn.autonomous_system_number;
+                info.as_org = asn.autonomous_system_organization.map(str::to_string);
+            }
+            Ok(None) => (),
+            Err(e) => debug!("GeoIP ASN lookup for {ip} failed: {e}"),
+        }
+    }
+
+    (!info.is_empty()).then_some(info)
+}
+
+/// Same as `lookup()` but takes the IP as a string (as stored in the database) and returns the formatted location
+pub fn lookup_str(ip: &str) -> Option<String> {
+    ip.parse::<IpAddr>().ok().and_then(|ip| lookup(&ip)).map(|info| info.to_string())
+}
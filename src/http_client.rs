@@ -32,6 +32,27 @@ pub fn make_http_request(method: reqwest::Method, url: &str) -> Result<reqwest::
     Ok(INSTANCE.request(method, url))
 }
 
+/// Like `make_http_request`, but for an endpoint configured by the admin which may be on the internal network.
+/// The addresses blocked by `HTTP_REQUEST_BLOCK_NON_GLOBAL_IPS` and `HTTP_REQUEST_BLOCK_REGEX` are allowed here.
+pub fn make_internal_http_request(method: reqwest::Method, url: &str) -> Result<reqwest::RequestBuilder, crate::Error> {
+    let Ok(url) = url::Url::parse(url) else {
+        err!("Invalid URL");
+    };
+
+    static INSTANCE: Lazy<Client> = Lazy::new(|| {
+        let mut headers = header::HeaderMap::new();
+        headers.insert(header::USER_AGENT, header::HeaderValue::from_static("Vaultwarden"));
+        Client::builder()
+            .default_headers(headers)
+            .redirect(reqwest::redirect::Policy::none())
+            .timeout(Duration::from_secs(10))
+            .build()
+            .expect("Failed to build client")
+    });
+
+    Ok(INSTANCE.request(method, url))
+}
+
 pub fn get_reqwest_client_builder() -> ClientBuilder {
     let mut headers = header::HeaderMap::new();
     headers.insert(header::USER_AGENT, header::HeaderValue::from_static("Vaultwarden"));
@@ -213,6 +234,25 @@ impl CustomDnsResolver {
     }
 }
 
+/// Looks up the TXT records of a domain, the character strings of every record are joined together.
+/// A domain without TXT records returns an empty list.
+pub async fn lookup_txt_records(domain: &str) -> Result<Vec<String>, crate::Error> {
+    pre_resolve(domain)?;
+
+    let CustomDnsResolver::Hickory(resolver) = &*CustomDnsResolver::instance() else {
+        err!("TXT records can't be looked up, the DNS resolver failed to initialize")
+    };
+
+    match resolver.txt_lookup(domain).await {
+        Ok(lookup) => Ok(lookup
+            .iter()
+            .map(|txt| txt.txt_data().iter().map(|data| String::from_utf8_lossy(data)).collect::<String>())
+            .collect()),
+        Err(e) if e.is_no_records_found() => Ok(Vec::new()),
+        Err(e) => err!(format!("Error looking up the TXT records of {domain}: {e}")),
+    }
+}
+
 fn pre_resolve(name: &str) -> Result<(), CustomHttpClientError> {
     if should_block_address(name) {
         return Err(CustomHttpClientError::Blocked {
@@ -0,0 +1,68 @@
+//
+// LDAP / Active Directory lookup of users during the password login
+// The clients only send a hash of the master password, so the directory password can't be verified.
+// Instead the user needs to have a directory entry matching the configured filter, which allows
+// an administrator to revoke access by disabling the account in the directory.
+//
+use std::time::Duration;
+
+use ldap3::{dn_escape, LdapConnAsync, LdapConnSettings, Scope};
+
+use crate::{error::Error, CONFIG};
+
+// The result code returned when the entry with the requested DN does not exist
+const LDAP_NO_SUCH_OBJECT: u32 = 32;
+
+pub fn is_enabled() -> bool {
+    CONFIG._enable_ldap() && CONFIG.ldap_url().is_some()
+}
+
+fn user_dn(email: &str) -> Option<String> {
+    let template = CONFIG.ldap_user_dn_template()?;
+    let username = email.split('@').next().unwrap_or(email);
+    Some(template.replace("{email}", &dn_escape(email)).replace("{username}", &dn_escape(username)))
+}
+
+/// Checks if the user has an entry in the directory which matches the configured user filter.
+/// Returns an error when the directory could not be queried, the caller should deny the login in that case.
+pub async fn user_allowed(email: &str) -> Result<bool, Error> {
+    let (Some(url), Some(dn)) = (CONFIG.ldap_url(), user_dn(email)) else {
+        err!("LDAP is not configured")
+    };
+    let timeout = Duration::from_secs(CONFIG.ldap_timeout());
+
+    let settings = LdapConnSettings::new()
+        .set_conn_timeout(timeout)
+        .set_starttls(CONFIG.ldap_starttls())
+        .set_no_tls_verify(CONFIG.ldap_tls_insecure());
+
+    let (conn, mut ldap) = match LdapConnAsync::with_settings(settings, &url).await {
+        Ok(c) => c,
+        Err(e) => err!(format!("Unable to connect to LDAP server `{url}`: {e}")),
+    };
+    ldap3::drive!(conn);
+
+    if let (Some(bind_dn), Some(bind_password)) = (CONFIG.ldap_bind_dn(), CONFIG.ldap_bind_password()) {
+        let bind = ldap.with_timeout(timeout).simple_bind(&bind_dn, &bind_password).await;
+        if let Err(e) = bind.and_then(|r| r.success()) {
+            err!(format!("Unable to bind to LDAP server as `{bind_dn}`: {e}"))
+        }
+    }
+
+    let search =
+        ldap.with_timeout(timeout).search(&dn, Scope::Base, CONFIG.ldap_user_filter().trim(), vec!["1.1"]).await;
+    let allowed = match search {
+        Ok(result) if result.1.rc == LDAP_NO_SUCH_OBJECT => false,
+        Ok(result) => match result.success() {
+            Ok((entries, _)) => !entries.is_empty(),
+            Err(e) => err!(format!("Unable to look up LDAP entry `{dn}`: {e}")),
+        },
+        Err(e) => err!(format!("Unable to look up LDAP entry `{dn}`: {e}")),
+    };
+
+    if let Err(e) = ldap.unbind().await {
+        debug!("Error closing LDAP connection: {e}");
+    }
+
+    Ok(allowed)
+}
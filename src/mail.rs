@@ -1,6 +1,12 @@
 use chrono::NaiveDateTime;
+use once_cell::sync::Lazy;
 use percent_encoding::{percent_encode, NON_ALPHANUMERIC};
-use std::{env::consts::EXE_SUFFIX, str::FromStr};
+use std::{
+    env::consts::EXE_SUFFIX,
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use tokio::sync::{Notify, Semaphore};
 
 use lettre::{
     message::{Attachment, Body, Mailbox, Message, MultiPart, SinglePart},
@@ -13,12 +19,13 @@ use lettre::{
 use crate::{
     api::EmptyResult,
     auth::{
-        encode_jwt, generate_delete_claims, generate_emergency_access_invite_claims, generate_invite_claims,
-        generate_verify_email_claims,
+        encode_jwt, generate_delete_claims, generate_device_approval_claims, generate_email_change_claims,
+        generate_emergency_access_invite_claims, generate_invite_claims, generate_org_delete_cancel_claims,
+        generate_org_delete_claims, generate_user_delete_cancel_claims, generate_verify_email_claims,
     },
     db::models::{Device, DeviceType, EmergencyAccessId, MembershipId, OrganizationId, User, UserId},
     error::Error,
-    CONFIG,
+    watchdog, CONFIG,
 };
 
 fn sendmail_transport() -> AsyncSendmailTransport<Tokio1Executor> {
@@ -183,6 +190,123 @@ pub async fn send_delete_account(address: &str, user_id: &UserId) -> EmptyResult
     send_email(address, &subject, body_html, body_text).await
 }
 
+pub async fn send_delete_organization(
+    address: &str,
+    org_id: &OrganizationId,
+    org_name: &str,
+    requested_by: &str,
+    requested_at: &NaiveDateTime,
+) -> EmptyResult {
+    let claims = generate_org_delete_claims(org_id, requested_at);
+    let delete_token = encode_jwt(&claims);
+
+    let (subject, body_html, body_text) = get_text(
+        "email/delete_organization",
+        json!({
+            "url": CONFIG.domain(),
+            "img_src": CONFIG._smtp_img_src(),
+            "org_id": org_id,
+            "org_name": org_name,
+            "org_name_encoded": percent_encode(org_name.as_bytes(), NON_ALPHANUMERIC).to_string(),
+            "requested_by": requested_by,
+            "token": delete_token,
+            "delay_hours": CONFIG.org_delete_delay_hours(),
+        }),
+    )?;
+
+    send_email(address, &subject, body_html, body_text).await
+}
+
+pub async fn send_delete_organization_scheduled(
+    address: &str,
+    org_id: &OrganizationId,
+    org_name: &str,
+    delete_at: &NaiveDateTime,
+) -> EmptyResult {
+    let claims = generate_org_delete_cancel_claims(org_id, delete_at);
+    let cancel_token = encode_jwt(&claims);
+
+    let fmt = "%A, %B %_d, %Y at %r %Z";
+    let (subject, body_html, body_text) = get_text(
+        "email/delete_organization_scheduled",
+        json!({
+            "url": CONFIG.domain(),
+            "img_src": CONFIG._smtp_img_src(),
+            "org_id": org_id,
+            "org_name": org_name,
+            "delete_date": crate::util::format_naive_datetime_local(delete_at, fmt),
+            "cancel_token": cancel_token,
+        }),
+    )?;
+
+    send_bulk_email(address, &subject, body_html, body_text).await
+}
+
+pub async fn send_delete_organization_member_notice(
+    address: &str,
+    org_name: &str,
+    delete_at: &NaiveDateTime,
+) -> EmptyResult {
+    let fmt = "%A, %B %_d, %Y at %r %Z";
+    let (subject, body_html, body_text) = get_text(
+        "email/delete_organization_member_notice",
+        json!({
+            "url": CONFIG.domain(),
+            "img_src": CONFIG._smtp_img_src(),
+            "org_name": org_name,
+            "delete_date": crate::util::format_naive_datetime_local(delete_at, fmt),
+        }),
+    )?;
+
+    send_bulk_email(address, &subject, body_html, body_text).await
+}
+
+pub async fn send_delete_account_scheduled(address: &str, user_id: &UserId, delete_at: &NaiveDateTime) -> EmptyResult {
+    let claims = generate_user_delete_cancel_claims(user_id, delete_at);
+    let cancel_token = encode_jwt(&claims);
+
+    let fmt = "%A, %B %_d, %Y at %r %Z";
+    let (subject, body_html, body_text) = get_text(
+        "email/delete_account_scheduled",
+        json!({
+            "url": CONFIG.domain(),
+            "img_src": CONFIG._smtp_img_src(),
+            "delete_date": crate::util::format_naive_datetime_local(delete_at, fmt),
+            "cancel_token": cancel_token,
+        }),
+    )?;
+
+    send_email(address, &subject, body_html, body_text).await
+}
+
+pub async fn send_delete_organization_cancelled(address: &str, org_name: &str, cancelled_by: &str) -> EmptyResult {
+    let (subject, body_html, body_text) = get_text(
+        "email/delete_organization_cancelled",
+        json!({
+            "url": CONFIG.domain(),
+            "img_src": CONFIG._smtp_img_src(),
+            "org_name": org_name,
+            "cancelled_by": cancelled_by,
+        }),
+    )?;
+
+    send_email(address, &subject, body_html, body_text).await
+}
+
+pub async fn send_org_export(address: &str, org_name: &str, file_name: &str, export: Vec<u8>) -> EmptyResult {
+    let (subject, body_html, body_text) = get_text(
+        "email/org_export",
+        json!({
+            "url": CONFIG.domain(),
+            "img_src": CONFIG._smtp_img_src(),
+            "org_name": org_name,
+        }),
+    )?;
+
+    let attachment = Attachment::new(file_name.to_string()).body(export, "application/json".parse().unwrap());
+    send_email_with_class(address, &subject, body_html, body_text, Some(attachment), MailClass::Bulk).await
+}
+
 pub async fn send_verify_email(address: &str, user_id: &UserId) -> EmptyResult {
     let claims = generate_verify_email_claims(user_id.clone());
     let verify_email_token = encode_jwt(&claims);
@@ -231,7 +355,7 @@ pub async fn send_welcome(address: &str) -> EmptyResult {
         }),
     )?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_bulk_email(address, &subject, body_html, body_text).await
 }
 
 pub async fn send_welcome_must_verify(address: &str, user_id: &UserId) -> EmptyResult {
@@ -320,7 +444,7 @@ pub async fn send_invite(
         }),
     )?;
 
-    send_email(&user.email, &subject, body_html, body_text).await
+    send_bulk_email(&user.email, &subject, body_html, body_text).await
 }
 
 pub async fn send_emergency_access_invite(
@@ -363,7 +487,7 @@ pub async fn send_emergency_access_invite(
         }),
     )?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_bulk_email(address, &subject, body_html, body_text).await
 }
 
 pub async fn send_emergency_access_invite_accepted(address: &str, grantee_email: &str) -> EmptyResult {
@@ -442,7 +566,7 @@ pub async fn send_emergency_access_recovery_reminder(
         }),
     )?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_bulk_email(address, &subject, body_html, body_text).await
 }
 
 pub async fn send_emergency_access_recovery_rejected(address: &str, grantor_name: &str) -> EmptyResult {
@@ -483,7 +607,7 @@ pub async fn send_invite_accepted(new_user_email: &str, address: &str, org_name:
         }),
     )?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_bulk_email(address, &subject, body_html, body_text).await
 }
 
 pub async fn send_invite_confirmed(address: &str, org_name: &str) -> EmptyResult {
@@ -496,7 +620,7 @@ pub async fn send_invite_confirmed(address: &str, org_name: &str) -> EmptyResult
         }),
     )?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_bulk_email(address, &subject, body_html, body_text).await
 }
 
 pub async fn send_new_device_logged_in(address: &str, ip: &str, dt: &NaiveDateTime, device: &Device) -> EmptyResult {
@@ -509,6 +633,59 @@ pub async fn send_new_device_logged_in(address: &str, ip: &str, dt: &NaiveDateTi
             "url": CONFIG.domain(),
             "img_src": CONFIG._smtp_img_src(),
             "ip": ip,
+            "location": crate::geoip::lookup_str(ip),
+            "device_name": upcase_first(&device.name),
+            "device_type": DeviceType::from_i32(device.atype).to_string(),
+            "datetime": crate::util::format_naive_datetime_local(dt, fmt),
+        }),
+    )?;
+
+    send_email(address, &subject, body_html, body_text).await
+}
+
+pub async fn send_new_device_approval(address: &str, ip: &str, dt: &NaiveDateTime, device: &Device) -> EmptyResult {
+    use crate::util::upcase_first;
+
+    let claims = generate_device_approval_claims(device.user_uuid.clone(), device.uuid.clone());
+    let approval_token = encode_jwt(&claims);
+
+    let fmt = "%A, %B %_d, %Y at %r %Z";
+    let (subject, body_html, body_text) = get_text(
+        "email/new_device_approval",
+        json!({
+            "url": CONFIG.domain(),
+            "img_src": CONFIG._smtp_img_src(),
+            "ip": ip,
+            "location": crate::geoip::lookup_str(ip),
+            "device_name": upcase_first(&device.name),
+            "device_type": DeviceType::from_i32(device.atype).to_string(),
+            "datetime": crate::util::format_naive_datetime_local(dt, fmt),
+            "expiration_minutes": CONFIG.new_device_approval_expiration_minutes(),
+            "token": approval_token,
+        }),
+    )?;
+
+    send_email(address, &subject, body_html, body_text).await
+}
+
+pub async fn send_vault_exported(
+    address: &str,
+    ip: &str,
+    dt: &NaiveDateTime,
+    device: &Device,
+    org_name: Option<&str>,
+) -> EmptyResult {
+    use crate::util::upcase_first;
+
+    let fmt = "%A, %B %_d, %Y at %r %Z";
+    let (subject, body_html, body_text) = get_text(
+        "email/vault_exported",
+        json!({
+            "url": CONFIG.domain(),
+            "img_src": CONFIG._smtp_img_src(),
+            "org_name": org_name,
+            "ip": ip,
+            "location": crate::geoip::lookup_str(ip),
             "device_name": upcase_first(&device.name),
             "device_type": DeviceType::from_i32(device.atype).to_string(),
             "datetime": crate::util::format_naive_datetime_local(dt, fmt),
@@ -534,6 +711,7 @@ pub async fn send_incomplete_2fa_login(
             "url": CONFIG.domain(),
             "img_src": CONFIG._smtp_img_src(),
             "ip": ip,
+            "location": crate::geoip::lookup_str(ip),
             "device_name": upcase_first(device_name),
             "device_type": device_type,
             "datetime": crate::util::format_naive_datetime_local(dt, fmt),
@@ -544,6 +722,49 @@ pub async fn send_incomplete_2fa_login(
     send_email(address, &subject, body_html, body_text).await
 }
 
+pub async fn send_twofactor_lockout(
+    address: &str,
+    ip: &str,
+    dt: &NaiveDateTime,
+    device_name: &str,
+    device_type: &str,
+) -> EmptyResult {
+    use crate::util::upcase_first;
+
+    let fmt = "%A, %B %_d, %Y at %r %Z";
+    let (subject, body_html, body_text) = get_text(
+        "email/twofactor_lockout",
+        json!({
+            "url": CONFIG.domain(),
+            "img_src": CONFIG._smtp_img_src(),
+            "ip": ip,
+            "location": crate::geoip::lookup_str(ip),
+            "device_name": upcase_first(device_name),
+            "device_type": device_type,
+            "datetime": crate::util::format_naive_datetime_local(dt, fmt),
+            "attempts": CONFIG.twofactor_lockout_attempts(),
+            "lockout_minutes": (CONFIG.twofactor_lockout_duration() + 59) / 60,
+        }),
+    )?;
+
+    send_email(address, &subject, body_html, body_text).await
+}
+
+pub async fn send_admin_alert(address: &str, component: &str, message: &str) -> EmptyResult {
+    let (subject, body_html, body_text) = get_text(
+        "email/admin_alert",
+        json!({
+            "url": CONFIG.domain(),
+            "img_src": CONFIG._smtp_img_src(),
+            "component": component,
+            "message": message,
+            "admin_url": format!("{}/admin/diagnostics", CONFIG.domain()),
+        }),
+    )?;
+
+    send_email(address, &subject, body_html, body_text).await
+}
+
 pub async fn send_token(address: &str, token: &str) -> EmptyResult {
     let (subject, body_html, body_text) = get_text(
         "email/twofactor_email",
@@ -570,6 +791,29 @@ pub async fn send_change_email(address: &str, token: &str) -> EmptyResult {
     send_email(address, &subject, body_html, body_text).await
 }
 
+pub async fn send_change_email_confirm_current(
+    address: &str,
+    user_id: &UserId,
+    new_email: &str,
+    requested_at: &NaiveDateTime,
+) -> EmptyResult {
+    let claims = generate_email_change_claims(user_id.clone(), new_email.to_string(), requested_at);
+    let confirm_token = encode_jwt(&claims);
+
+    let (subject, body_html, body_text) = get_text(
+        "email/change_email_confirm_current",
+        json!({
+            "url": CONFIG.domain(),
+            "img_src": CONFIG._smtp_img_src(),
+            "new_email": new_email,
+            "token": confirm_token,
+            "expiration_hours": CONFIG.email_change_expiration_hours(),
+        }),
+    )?;
+
+    send_email(address, &subject, body_html, body_text).await
+}
+
 pub async fn send_test(address: &str) -> EmptyResult {
     let (subject, body_html, body_text) = get_text(
         "email/smtp_test",
@@ -660,7 +904,54 @@ async fn send_with_selected_transport(email: Message) -> EmptyResult {
     }
 }
 
+/// Security critical mail (tokens, login alerts) is sent in the priority class, bulk mail (invites, reminders)
+/// waits as long as priority mail is queued, and both classes have their own concurrency limit.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum MailClass {
+    Priority,
+    Bulk,
+}
+
+static PRIORITY_SLOTS: Lazy<Semaphore> = Lazy::new(|| Semaphore::new(CONFIG.smtp_priority_concurrency().max(1)));
+static BULK_SLOTS: Lazy<Semaphore> = Lazy::new(|| Semaphore::new(CONFIG.smtp_bulk_concurrency().max(1)));
+// The amount of priority mails which are waiting or being sent, and a notification for when one finished
+static PRIORITY_PENDING: AtomicUsize = AtomicUsize::new(0);
+static PRIORITY_DONE: Lazy<Notify> = Lazy::new(Notify::new);
+
+/// Counts a priority mail as pending while it's alive, so the count is also decremented
+/// when sending returns early or the request is cancelled.
+struct PriorityPending;
+
+impl PriorityPending {
+    fn new() -> Self {
+        PRIORITY_PENDING.fetch_add(1, Ordering::SeqCst);
+        Self
+    }
+}
+
+impl Drop for PriorityPending {
+    fn drop(&mut self) {
+        PRIORITY_PENDING.fetch_sub(1, Ordering::SeqCst);
+        PRIORITY_DONE.notify_waiters();
+    }
+}
+
 async fn send_email(address: &str, subject: &str, body_html: String, body_text: String) -> EmptyResult {
+    send_email_with_class(address, subject, body_html, body_text, None, MailClass::Priority).await
+}
+
+async fn send_bulk_email(address: &str, subject: &str, body_html: String, body_text: String) -> EmptyResult {
+    send_email_with_class(address, subject, body_html, body_text, None, MailClass::Bulk).await
+}
+
+async fn send_email_with_class(
+    address: &str,
+    subject: &str,
+    body_html: String,
+    body_text: String,
+    attachment: Option<SinglePart>,
+    class: MailClass,
+) -> EmptyResult {
     let smtp_from = &CONFIG.smtp_from();
 
     let body = if CONFIG.smtp_embed_images() {
@@ -681,6 +972,10 @@ async fn send_email(address: &str, subject: &str, body_html: String, body_text:
     } else {
         MultiPart::alternative_plain_html(body_text, body_html)
     };
+    let body = match attachment {
+        Some(attachment) => MultiPart::mixed().multipart(body).singlepart(attachment),
+        None => body,
+    };
 
     let email = Message::builder()
         .message_id(Some(format!("<{}@{}>", crate::util::get_uuid(), smtp_from.split('@').collect::<Vec<&str>>()[1])))
@@ -689,5 +984,49 @@ async fn send_email(address: &str, subject: &str, body_html: String, body_text:
         .subject(subject)
         .multipart(body)?;
 
-    send_with_selected_transport(email).await
+    match class {
+        MailClass::Priority => {
+            let _pending = PriorityPending::new();
+            let Ok(_permit) = PRIORITY_SLOTS.acquire().await else {
+                err!("The mail queue is closed")
+            };
+            send_with_watchdog(email).await
+        }
+        MailClass::Bulk => {
+            let Ok(_permit) = BULK_SLOTS.acquire().await else {
+                err!("The mail queue is closed")
+            };
+            // Let all queued priority mail go first
+            loop {
+                let done = PRIORITY_DONE.notified();
+                tokio::pin!(done);
+                done.as_mut().enable();
+                if PRIORITY_PENDING.load(Ordering::SeqCst) == 0 {
+                    break;
+                }
+                done.await;
+            }
+            send_with_watchdog(email).await
+        }
+    }
+}
+
+async fn send_with_watchdog(email: Message) -> EmptyResult {
+    // Report to the watchdog, a mail server which stops responding should not go unnoticed
+    let timeout = std::time::Duration::from_secs(CONFIG.watchdog_stall_secs());
+    match tokio::time::timeout(timeout, send_with_selected_transport(email)).await {
+        Ok(Ok(())) => {
+            watchdog::heartbeat(watchdog::Component::Mail);
+            crate::usage_stats::record_mail();
+            Ok(())
+        }
+        Ok(Err(e)) => {
+            watchdog::report_failure(watchdog::Component::Mail, &e.to_string());
+            Err(e)
+        }
+        Err(_) => {
+            watchdog::report_failure(watchdog::Component::Mail, "Sending mail timed out");
+            err!("Sending mail timed out")
+        }
+    }
 }
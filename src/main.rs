@@ -31,10 +31,11 @@ use std::{
     collections::HashMap,
     fs::{canonicalize, create_dir_all},
     panic,
-    path::Path,
+    path::{Path, PathBuf},
     process::exit,
     str::FromStr,
     thread,
+    time::Duration,
 };
 
 use tokio::{
@@ -49,14 +50,26 @@ use tokio::signal::unix::SignalKind;
 mod error;
 mod api;
 mod auth;
+mod bitwarden_import;
+mod captcha;
 mod config;
 mod crypto;
 #[macro_use]
 mod db;
+mod demo;
+mod geoip;
 mod http_client;
+mod ldap;
+mod log_redaction;
 mod mail;
 mod ratelimit;
+mod storage;
+mod usage_stats;
 mod util;
+mod watchdog;
+
+#[cfg(all(test, sqlite))]
+mod tests;
 
 use crate::api::core::two_factor::duo_oidc::purge_duo_contexts;
 use crate::api::purge_auth_requests;
@@ -64,7 +77,10 @@ use crate::api::{WS_ANONYMOUS_SUBSCRIPTIONS, WS_USERS};
 pub use config::CONFIG;
 pub use error::{Error, MapResult};
 use rocket::data::{Limits, ToByteUnit};
-use std::sync::{atomic::Ordering, Arc};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
 pub use util::is_running_in_container;
 
 #[rocket::main]
@@ -85,10 +101,17 @@ async fn main() -> Result<(), Error> {
     create_dir(&CONFIG.tmp_folder(), "tmp folder");
     create_dir(&CONFIG.sends_folder(), "sends folder");
     create_dir(&CONFIG.attachments_folder(), "attachments folder");
+    if let Some(backup_folder) = CONFIG.backup_folder() {
+        create_dir(&backup_folder, "backup folder");
+    }
 
+    seed_demo_database();
     let pool = create_db_pool().await;
+    create_demo_account(&pool).await;
     schedule_jobs(pool.clone());
-    db::models::TwoFactor::migrate_u2f_to_webauthn(&mut pool.get().await.unwrap()).await.unwrap();
+    if let Err(e) = db::models::TwoFactor::migrate_u2f_to_webauthn(&mut pool.get().await.unwrap()).await {
+        error!("Error migrating U2F keys to WebAuthn: {e:?}");
+    }
 
     let extra_debug = matches!(level, log::LevelFilter::Trace | log::LevelFilter::Debug);
     launch_rocket(pool, extra_debug).await // Blocks until program termination.
@@ -108,6 +131,9 @@ COMMAND:
     hash [--preset {bitwarden|owasp}]  Generate an Argon2id PHC ADMIN_TOKEN
     backup                             Create a backup of the SQLite database
                                        You can also send the USR1 signal to trigger a backup
+    import-bitwarden [--attachments <folder>] <folder>
+                                       Import the tables exported from an official Bitwarden
+                                       server database, into an empty database
 
 PRESETS:                  m=         t=          p=
     bitwarden (default) 64MiB, 3 Iterations, 4 Threads
@@ -198,6 +224,44 @@ async fn parse_args() {
                     exit(1);
                 }
             }
+        } else if command == "import-bitwarden" {
+            let attachments: Option<PathBuf> = pargs.opt_value_from_str("--attachments").unwrap_or_default();
+            let Some(folder) = pargs.opt_free_from_str::<PathBuf>().unwrap_or_default() else {
+                println!("The folder with the exported tables is missing\n\n{HELP}");
+                exit(1);
+            };
+
+            match import_bitwarden(&folder, attachments.as_deref()).await {
+                Ok(summary) => {
+                    for warning in &summary.warnings {
+                        println!("Warning: {warning}");
+                    }
+                    if !summary.unconverted_users.is_empty() {
+                        println!(
+                            "The master password hash of these users is in an unsupported format, they can't log in with their master password:"
+                        );
+                        for email in &summary.unconverted_users {
+                            println!("  {email}");
+                        }
+                    }
+                    println!(
+                        "Imported {} users, {} organizations, {} memberships, {} collections, {} folders, {} ciphers and {} attachments",
+                        summary.users,
+                        summary.organizations,
+                        summary.memberships,
+                        summary.collections,
+                        summary.folders,
+                        summary.ciphers,
+                        summary.attachments
+                    );
+                    println!("Users have to set up two-step login again, and devices have to log in again.");
+                    exit(0);
+                }
+                Err(e) => {
+                    println!("Import failed. {e:?}");
+                    exit(1);
+                }
+            }
         }
         exit(0);
     }
@@ -220,6 +284,12 @@ async fn backup_sqlite() -> Result<String, Error> {
     }
 }
 
+async fn import_bitwarden(folder: &Path, attachments: Option<&Path>) -> Result<bitwarden_import::ImportSummary, Error> {
+    create_dir(&CONFIG.attachments_folder(), "attachments folder");
+    let mut conn = db::DbPool::from_config()?.get().await?;
+    bitwarden_import::import(folder, attachments, &mut conn).await
+}
+
 fn launch_info() {
     println!(
         "\
@@ -364,8 +434,18 @@ fn init_logging() -> Result<log::LevelFilter, Error> {
         logger = logger.level_for(path.to_string(), level);
     }
 
+    // Debug logging is meant for troubleshooting, in which case the full messages are needed
+    let redact = CONFIG.log_redaction() && level < log::LevelFilter::Debug;
+    let redact_ips = CONFIG.log_redact_ips();
+
     if CONFIG.extended_logging() {
-        logger = logger.format(|out, message, record| {
+        logger = logger.format(move |out, message, record| {
+            let message = message.to_string();
+            let message = if redact {
+                log_redaction::redact(&message, redact_ips)
+            } else {
+                message.as_str().into()
+            };
             out.finish(format_args!(
                 "[{}][{}][{}] {}",
                 chrono::Local::now().format(&CONFIG.log_timestamp_format()),
@@ -374,6 +454,10 @@ fn init_logging() -> Result<log::LevelFilter, Error> {
                 message
             ))
         });
+    } else if redact {
+        logger = logger.format(move |out, message, _| {
+            out.finish(format_args!("{}", log_redaction::redact(&message.to_string(), redact_ips)))
+        });
     } else {
         logger = logger.format(|out, message, _| out.finish(format_args!("{message}")));
     }
@@ -548,6 +632,41 @@ fn check_web_vault() {
     }
 }
 
+// The seed is only copied when the database does not exist yet. Visitors of the demo can't change the data,
+// but logging in still stores devices, events and failed attempts, so delete the database to reset the demo.
+fn seed_demo_database() {
+    let Some(seed) = CONFIG.demo_seed_database() else {
+        return;
+    };
+    let db_url = CONFIG.database_url();
+    if db::DbConnType::from_url(&db_url).map(|t| t != db::DbConnType::sqlite).unwrap_or(true) {
+        warn!("DEMO_SEED_DATABASE is only supported for SQLite databases, ignoring it");
+        return;
+    }
+    if Path::new(&db_url).exists() {
+        return;
+    }
+
+    match std::fs::copy(&seed, &db_url) {
+        Ok(_) => info!("Seeded the demo database from '{seed}'"),
+        Err(e) => {
+            error!("Error copying the demo seed database '{seed}': {e:?}");
+            exit(1);
+        }
+    }
+}
+
+async fn create_demo_account(pool: &db::DbPool) {
+    if !CONFIG.demo_mode() {
+        return;
+    }
+    let mut conn = pool.get().await.unwrap();
+    if let Err(e) = demo::create_demo_account(&mut conn).await {
+        error!("Error creating the demo account: {e:?}");
+        exit(1);
+    }
+}
+
 async fn create_db_pool() -> db::DbPool {
     match util::retry_db(db::DbPool::from_config, CONFIG.db_connection_retries()).await {
         Ok(p) => p,
@@ -559,40 +678,14 @@ async fn create_db_pool() -> db::DbPool {
 }
 
 async fn launch_rocket(pool: db::DbPool, extra_debug: bool) -> Result<(), Error> {
-    let basepath = &CONFIG.domain_path();
-
-    let mut config = rocket::Config::from(rocket::Config::figment());
-    config.temp_dir = canonicalize(CONFIG.tmp_folder()).unwrap().into();
-    config.cli_colors = false; // Make sure Rocket does not color any values for logging.
-    config.limits = Limits::new()
-        .limit("json", 20.megabytes()) // 20MB should be enough for very large imports, something like 5000+ vault entries
-        .limit("data-form", 525.megabytes()) // This needs to match the maximum allowed file size for Send
-        .limit("file", 525.megabytes()); // This needs to match the maximum allowed file size for attachments
-
-    // If adding more paths here, consider also adding them to
-    // crate::utils::LOGGED_ROUTES to make sure they appear in the log
-    let instance = rocket::custom(config)
-        .mount([basepath, "/"].concat(), api::web_routes())
-        .mount([basepath, "/api"].concat(), api::core_routes())
-        .mount([basepath, "/admin"].concat(), api::admin_routes())
-        .mount([basepath, "/events"].concat(), api::core_events_routes())
-        .mount([basepath, "/identity"].concat(), api::identity_routes())
-        .mount([basepath, "/icons"].concat(), api::icons_routes())
-        .mount([basepath, "/notifications"].concat(), api::notifications_routes())
-        .register([basepath, "/"].concat(), api::web_catchers())
-        .register([basepath, "/api"].concat(), api::core_catchers())
-        .register([basepath, "/admin"].concat(), api::admin_catchers())
-        .manage(pool)
-        .manage(Arc::clone(&WS_USERS))
-        .manage(Arc::clone(&WS_ANONYMOUS_SUBSCRIPTIONS))
-        .attach(util::AppHeaders())
-        .attach(util::Cors())
-        .attach(util::BetterLogging(extra_debug))
-        .ignite()
-        .await?;
+    let instance = build_rocket(pool, extra_debug).ignite().await?;
 
     CONFIG.set_rocket_shutdown_handle(instance.shutdown());
 
+    watchdog::register_restart(watchdog::Component::WebSocket, api::prune_stalled_ws_connections);
+    watchdog::register_restart(watchdog::Component::Push, api::reset_push_token);
+    tokio::spawn(watchdog::run());
+
     tokio::spawn(async move {
         tokio::signal::ctrl_c().await.expect("Error setting Ctrl-C handler");
         info!("Exiting Vaultwarden!");
@@ -621,13 +714,106 @@ async fn launch_rocket(pool: db::DbPool, extra_debug: bool) -> Result<(), Error>
     Ok(())
 }
 
+/// Builds the Rocket instance with all routes, catchers, state and fairings, but without launching it.
+fn build_rocket(pool: db::DbPool, extra_debug: bool) -> rocket::Rocket<rocket::Build> {
+    let basepath = &CONFIG.domain_path();
+
+    let mut config = rocket::Config::from(rocket::Config::figment());
+    config.temp_dir = canonicalize(CONFIG.tmp_folder()).unwrap().into();
+    config.cli_colors = false; // Make sure Rocket does not color any values for logging.
+
+    // The clients allow attachments of up to 500 MB, a Send can be larger when `SEND_FILE_SIZE_LIMIT` allows it
+    let upload_limit = std::cmp::max(525.megabytes(), (CONFIG.send_file_size_limit() as u64).kibibytes());
+    config.limits = Limits::new()
+        // The default of 20MB should be enough for very large imports, something like 5000+ vault entries
+        .limit("json", CONFIG.json_size_limit().megabytes())
+        .limit("data-form", upload_limit)
+        .limit("file", upload_limit);
+
+    // If adding more paths here, consider also adding them to
+    // crate::utils::LOGGED_ROUTES to make sure they appear in the log
+    rocket::custom(config)
+        .mount([basepath, "/"].concat(), api::web_routes())
+        .mount([basepath, "/api"].concat(), api::core_routes())
+        .mount([basepath, "/admin"].concat(), api::admin_routes())
+        .mount([basepath, "/events"].concat(), api::core_events_routes())
+        .mount([basepath, "/identity"].concat(), api::identity_routes())
+        .mount([basepath, "/icons"].concat(), api::icons_routes())
+        .mount([basepath, "/notifications"].concat(), api::notifications_routes())
+        .register([basepath, "/"].concat(), api::web_catchers())
+        .register([basepath, "/api"].concat(), api::core_catchers())
+        .register([basepath, "/admin"].concat(), api::admin_catchers())
+        .manage(pool)
+        .manage(Arc::clone(&WS_USERS))
+        .manage(Arc::clone(&WS_ANONYMOUS_SUBSCRIPTIONS))
+        .attach(util::DemoMode())
+        .attach(util::AppHeaders())
+        .attach(util::Cors())
+        .attach(util::BetterLogging(extra_debug))
+}
+
+static SCHEDULER_RESTART_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+fn request_scheduler_restart() {
+    SCHEDULER_RESTART_REQUESTED.store(true, Ordering::Relaxed);
+}
+
 fn schedule_jobs(pool: db::DbPool) {
     if CONFIG.job_poll_interval_ms() == 0 {
         info!("Job scheduler disabled.");
         return;
     }
+    // The jobs clean up and change data, which the demo has to keep as it is
+    if CONFIG.demo_mode() {
+        info!("Job scheduler disabled in demo mode.");
+        return;
+    }
+
+    let runtime = Arc::new(tokio::runtime::Runtime::new().unwrap());
+
+    // The scheduler should tick at least once every poll interval, allow for some slack before considering it stalled
+    let max_silence =
+        Duration::from_millis(CONFIG.job_poll_interval_ms() * 3).max(Duration::from_secs(CONFIG.watchdog_stall_secs()));
+    watchdog::expect_heartbeat(watchdog::Component::Scheduler, max_silence);
+    watchdog::register_restart(watchdog::Component::Scheduler, request_scheduler_restart);
+
+    // Supervise the scheduler thread, and start a new one when it panicked or was reported stalled by the watchdog.
+    // A stalled thread can not be killed, it will stop by itself once it notices a newer generation is running.
+    thread::Builder::new()
+        .name("job-supervisor".to_string())
+        .spawn(move || {
+            // Reporting an incident spawns the alert mail as a task, which needs a runtime on this thread
+            let _runtime_guard = runtime.enter();
+            let mut handle = spawn_job_scheduler(pool.clone(), Arc::clone(&runtime));
+            loop {
+                thread::sleep(Duration::from_secs(1));
+
+                if handle.is_finished() {
+                    if let Err(e) = handle.join() {
+                        let message = e
+                            .downcast_ref::<&str>()
+                            .map(|s| s.to_string())
+                            .or_else(|| e.downcast_ref::<String>().cloned())
+                            .unwrap_or_else(|| "unknown panic".to_string());
+                        watchdog::incident(
+                            watchdog::Component::Scheduler,
+                            &format!("Job scheduler panicked: {message}"),
+                        );
+                    }
+                } else if !SCHEDULER_RESTART_REQUESTED.swap(false, Ordering::Relaxed) {
+                    continue;
+                }
+
+                // Prevent a tight restart loop in case the scheduler keeps failing right away
+                thread::sleep(Duration::from_secs(5));
+                handle = spawn_job_scheduler(pool.clone(), Arc::clone(&runtime));
+            }
+        })
+        .expect("Error spawning job supervisor thread");
+}
 
-    let runtime = tokio::runtime::Runtime::new().unwrap();
+fn spawn_job_scheduler(pool: db::DbPool, runtime: Arc<tokio::runtime::Runtime>) -> thread::JoinHandle<()> {
+    let generation = watchdog::next_generation(watchdog::Component::Scheduler);
 
     thread::Builder::new()
         .name("job-scheduler".to_string())
@@ -651,6 +837,13 @@ fn schedule_jobs(pool: db::DbPool) {
                 }));
             }
 
+            // Remove the password history entries beyond the configured limit.
+            if !CONFIG.password_history_trim_schedule().is_empty() {
+                sched.add(Job::new(CONFIG.password_history_trim_schedule().parse().unwrap(), || {
+                    runtime.spawn(api::trim_password_histories(pool.clone()));
+                }));
+            }
+
             // Send email notifications about incomplete 2FA logins, which potentially
             // indicates that a user's master password has been compromised.
             if !CONFIG.incomplete_2fa_schedule().is_empty() {
@@ -676,6 +869,34 @@ fn schedule_jobs(pool: db::DbPool) {
                 }));
             }
 
+            // Delete organizations of which the deletion delay has passed.
+            if !CONFIG.org_deletion_schedule().is_empty() {
+                sched.add(Job::new(CONFIG.org_deletion_schedule().parse().unwrap(), || {
+                    runtime.spawn(api::purge_scheduled_org_deletions(pool.clone()));
+                }));
+            }
+
+            // Check the DNS TXT records of the unverified organization domains.
+            if !CONFIG.org_domain_verification_schedule().is_empty() {
+                sched.add(Job::new(CONFIG.org_domain_verification_schedule().parse().unwrap(), || {
+                    runtime.spawn(api::verify_org_domains_job(pool.clone()));
+                }));
+            }
+
+            // Export the vault of the organizations which have automatic exports enabled.
+            if !CONFIG.org_export_schedule().is_empty() {
+                sched.add(Job::new(CONFIG.org_export_schedule().parse().unwrap(), || {
+                    runtime.spawn(api::org_export_job(pool.clone()));
+                }));
+            }
+
+            // Delete user accounts of which the deletion delay has passed.
+            if !CONFIG.user_deletion_schedule().is_empty() {
+                sched.add(Job::new(CONFIG.user_deletion_schedule().parse().unwrap(), || {
+                    runtime.spawn(api::purge_scheduled_user_deletions(pool.clone()));
+                }));
+            }
+
             if !CONFIG.auth_request_purge_schedule().is_empty() {
                 sched.add(Job::new(CONFIG.auth_request_purge_schedule().parse().unwrap(), || {
                     runtime.spawn(purge_auth_requests(pool.clone()));
@@ -708,10 +929,17 @@ fn schedule_jobs(pool: db::DbPool) {
             // Note that the scheduler checks jobs in the order in which they
             // were added, so if two jobs are both eligible to run at a given
             // tick, the one that was added earlier will run first.
+            //
+            // Every tick is reported to the watchdog, which restarts the scheduler when it stops ticking.
             loop {
+                if !watchdog::is_current_generation(watchdog::Component::Scheduler, generation) {
+                    warn!("Job scheduler generation {generation} was superseded, stopping");
+                    break;
+                }
                 sched.tick();
+                watchdog::heartbeat(watchdog::Component::Scheduler);
                 runtime.block_on(tokio::time::sleep(tokio::time::Duration::from_millis(CONFIG.job_poll_interval_ms())));
             }
         })
-        .expect("Error spawning job scheduler thread");
+        .expect("Error spawning job scheduler thread")
 }
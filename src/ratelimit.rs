@@ -1,9 +1,21 @@
 use once_cell::sync::Lazy;
 use std::{net::IpAddr, num::NonZeroU32, time::Duration};
 
-use governor::{clock::DefaultClock, state::keyed::DashMapStateStore, Quota, RateLimiter};
+use chrono::Utc;
+use governor::{
+    clock::{Clock, DefaultClock},
+    state::keyed::DashMapStateStore,
+    NotUntil, Quota, RateLimiter,
+};
 
-use crate::{Error, CONFIG};
+use crate::{
+    api::EmptyResult,
+    db::{
+        models::{LoginBackoff, SendId, UserId},
+        DbConn,
+    },
+    Error, CONFIG,
+};
 
 type Limiter<T = IpAddr> = RateLimiter<T, DashMapStateStore<T>, DefaultClock>;
 
@@ -19,20 +31,172 @@ static LIMITER_ADMIN: Lazy<Limiter> = Lazy::new(|| {
     RateLimiter::keyed(Quota::with_period(seconds).expect("Non-zero admin ratelimit seconds").allow_burst(burst))
 });
 
+static LIMITER_EXPORT: Lazy<Limiter<String>> = Lazy::new(|| {
+    let seconds = Duration::from_secs(CONFIG.export_ratelimit_seconds());
+    let burst = NonZeroU32::new(CONFIG.export_ratelimit_max_burst()).expect("Non-zero export ratelimit burst");
+    RateLimiter::keyed(Quota::with_period(seconds).expect("Non-zero export ratelimit seconds").allow_burst(burst))
+});
+
+static LIMITER_PASSWORD_HINT: Lazy<Limiter> = Lazy::new(|| {
+    let seconds = Duration::from_secs(CONFIG.password_hint_ratelimit_seconds());
+    let burst =
+        NonZeroU32::new(CONFIG.password_hint_ratelimit_max_burst()).expect("Non-zero password hint ratelimit burst");
+    RateLimiter::keyed(
+        Quota::with_period(seconds).expect("Non-zero password hint ratelimit seconds").allow_burst(burst),
+    )
+});
+
+fn too_many_requests(msg: &str, retry_after: u64) -> Error {
+    error!("{msg}");
+    Error::new(msg, msg).with_code(429).with_retry_after(retry_after.max(1))
+}
+
+fn wait_seconds(not_until: &NotUntil<<DefaultClock as Clock>::Instant>) -> u64 {
+    not_until.wait_time_from(DefaultClock::default().now()).as_secs_f64().ceil() as u64
+}
+
 pub fn check_limit_login(ip: &IpAddr) -> Result<(), Error> {
     match LIMITER_LOGIN.check_key(ip) {
         Ok(_) => Ok(()),
-        Err(_e) => {
-            err_code!("Too many login requests", 429);
-        }
+        Err(e) => Err(too_many_requests("Too many login requests", wait_seconds(&e))),
     }
 }
 
 pub fn check_limit_admin(ip: &IpAddr) -> Result<(), Error> {
     match LIMITER_ADMIN.check_key(ip) {
         Ok(_) => Ok(()),
-        Err(_e) => {
-            err_code!("Too many admin requests", 429);
+        Err(e) => Err(too_many_requests("Too many admin requests", wait_seconds(&e))),
+    }
+}
+
+pub fn check_limit_export(user_id: &str) -> Result<(), Error> {
+    match LIMITER_EXPORT.check_key(&user_id.to_string()) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(too_many_requests("Too many export requests", wait_seconds(&e))),
+    }
+}
+
+pub fn check_limit_password_hint(ip: &IpAddr) -> Result<(), Error> {
+    match LIMITER_PASSWORD_HINT.check_key(ip) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(too_many_requests("Too many password hint requests", wait_seconds(&e))),
+    }
+}
+
+//
+// Exponential backoff after failed logins
+// The failures are kept in the database, so the backoff survives a restart and applies to all the instances using it
+//
+
+/// Backoff of the password and API key logins of an account, kept per IP address
+/// so the failures from other addresses can't lock the owner out of the account.
+fn account_backoff_key(user_id: &UserId, ip: &IpAddr) -> String {
+    format!("account:{user_id}:{ip}")
+}
+
+/// Backoff of the logins of an account from all IP addresses together. It only starts after
+/// `LOGIN_BACKOFF_ACCOUNT_ATTEMPTS`, so guesses spread over many addresses are slowed down as well.
+fn account_all_ips_backoff_key(user_id: &UserId) -> String {
+    format!("account:{user_id}")
+}
+
+/// The admin panel has a single token, so its backoff is kept per IP address.
+pub fn admin_backoff_key(ip: &IpAddr) -> String {
+    format!("admin:{ip}")
+}
+
+const UNKNOWN_ACCOUNT_PREFIX: &str = "unknown:";
+
+// The usernames are chosen by the client, so the number of tracked unknown accounts is limited
+const MAX_UNKNOWN_ACCOUNT_BACKOFFS: i64 = 10_000;
+
+/// Failed logins of unknown accounts are backed off like the existing ones when hiding which accounts exist.
+pub fn unknown_account_backoff_key(username: &str, ip: &IpAddr) -> String {
+    // The username is chosen by the client, only the start is used to keep the key within the column size
+    let username = username.to_lowercase().chars().take(128).collect::<String>();
+    format!("{UNKNOWN_ACCOUNT_PREFIX}{username}:{ip}")
+}
+
+/// Backoff of the password of a Send, kept per IP address so someone knowing the link can't lock out the recipients.
+pub fn send_password_backoff_key(send_id: &SendId, ip: &IpAddr) -> String {
+    format!("send:{send_id}:{ip}")
+}
+
+/// Returns an error with the remaining number of seconds while the next attempt is not allowed yet.
+pub async fn check_backoff(key: &str, msg: &str, conn: &mut DbConn) -> EmptyResult {
+    let Some(backoff) = LoginBackoff::find_by_key(key, conn).await else {
+        return Ok(());
+    };
+
+    let remaining = backoff.blocked_until - Utc::now().timestamp();
+    if remaining > 0 {
+        return Err(too_many_requests(msg, remaining as u64));
+    }
+    Ok(())
+}
+
+/// Registers a failed attempt. Once `LOGIN_BACKOFF_ATTEMPTS` is reached, every further failure doubles the
+/// time until the next attempt is allowed, starting at `LOGIN_BACKOFF_BASE_SECONDS`.
+/// The count starts over when there was no failure during `LOGIN_BACKOFF_MAX_SECONDS`.
+pub async fn register_failure(key: &str, conn: &mut DbConn) -> EmptyResult {
+    register_failure_after(key, CONFIG.login_backoff_attempts(), conn).await
+}
+
+async fn register_failure_after(key: &str, attempts: u32, conn: &mut DbConn) -> EmptyResult {
+    if attempts == 0 || CONFIG.login_backoff_attempts() == 0 {
+        return Ok(());
+    }
+
+    // Forget the failures which are too old to count, the blocking time of those has passed as well
+    let now = Utc::now().timestamp();
+    LoginBackoff::delete_failed_before(now - CONFIG.login_backoff_max_seconds() as i64, conn).await?;
+
+    let mut backoff = match LoginBackoff::find_by_key(key, conn).await {
+        Some(backoff) => backoff,
+        None => {
+            if key.starts_with(UNKNOWN_ACCOUNT_PREFIX) {
+                make_room_for_unknown_account(MAX_UNKNOWN_ACCOUNT_BACKOFFS, conn).await?;
+            }
+            LoginBackoff::new(key.to_string())
         }
+    };
+    backoff.failures = backoff.failures.saturating_add(1);
+    backoff.last_failure = now;
+
+    let failures = backoff.failures as u32;
+    if failures >= attempts {
+        let exponent = (failures - attempts).min(31);
+        let wait = CONFIG.login_backoff_base_seconds().saturating_mul(1 << exponent);
+        backoff.blocked_until = now + wait.min(CONFIG.login_backoff_max_seconds()) as i64;
+    }
+    backoff.save(conn).await
+}
+
+pub async fn reset_failures(key: &str, conn: &mut DbConn) -> EmptyResult {
+    LoginBackoff::delete_by_key(key, conn).await
+}
+
+/// Forgets the unknown accounts which failed the longest time ago, until there is room for a new one.
+pub(crate) async fn make_room_for_unknown_account(max: i64, conn: &mut DbConn) -> EmptyResult {
+    let excess = LoginBackoff::count_by_key_prefix(UNKNOWN_ACCOUNT_PREFIX, conn).await - max + 1;
+    if excess > 0 {
+        LoginBackoff::delete_oldest_by_key_prefix(UNKNOWN_ACCOUNT_PREFIX, excess, conn).await?;
     }
+    Ok(())
+}
+
+/// Returns an error while the logins of the account are backed off, from this IP address or from all of them.
+pub async fn check_account_backoff(user_id: &UserId, ip: &IpAddr, msg: &str, conn: &mut DbConn) -> EmptyResult {
+    check_backoff(&account_backoff_key(user_id, ip), msg, conn).await?;
+    check_backoff(&account_all_ips_backoff_key(user_id), msg, conn).await
+}
+
+pub async fn register_account_failure(user_id: &UserId, ip: &IpAddr, conn: &mut DbConn) -> EmptyResult {
+    register_failure(&account_backoff_key(user_id, ip), conn).await?;
+    register_failure_after(&account_all_ips_backoff_key(user_id), CONFIG.login_backoff_account_attempts(), conn).await
+}
+
+pub async fn reset_account_failures(user_id: &UserId, ip: &IpAddr, conn: &mut DbConn) -> EmptyResult {
+    reset_failures(&account_backoff_key(user_id, ip), conn).await?;
+    reset_failures(&account_all_ips_backoff_key(user_id), conn).await
 }
@@ -30,10 +30,123 @@ function deleteOrganization(event) {
     }
 }
 
+function parseLimit(value) {
+    if (value === null || value.trim() === "") {
+        return null;
+    }
+    const limit = Number(value.trim());
+    return Number.isInteger(limit) ? limit : NaN;
+}
+
+function updateOrganizationLimits(event) {
+    event.preventDefault();
+    event.stopPropagation();
+    const org_uuid = event.target.dataset.vwOrgUuid;
+    const org_name = event.target.dataset.vwOrgName;
+    if (!org_uuid) {
+        alert("Required parameters not found!");
+        return false;
+    }
+
+    const input_seats = prompt(`Maximum number of members (seats) of "${org_name}", leave empty for no limit.`, event.target.dataset.vwMaxSeats);
+    if (input_seats === null) {
+        return false;
+    }
+    const input_collections = prompt(`Maximum number of collections of "${org_name}", leave empty for no limit.`, event.target.dataset.vwMaxCollections);
+    if (input_collections === null) {
+        return false;
+    }
+
+    const input_attachments = prompt(`Attachment storage limit in KB of "${org_name}", 0 disables attachments, leave empty to use the server default.`, event.target.dataset.vwAttachmentLimit);
+    if (input_attachments === null) {
+        return false;
+    }
+
+    const max_seats = parseLimit(input_seats);
+    const max_collections = parseLimit(input_collections);
+    const attachment_limit = parseLimit(input_attachments);
+    if (Number.isNaN(max_seats) || Number.isNaN(max_collections) || Number.isNaN(attachment_limit)) {
+        alert("The limits need to be whole numbers");
+        return false;
+    }
+
+    _post(`${BASE_URL}/admin/organizations/${org_uuid}/limits`,
+        "Organization limits updated correctly",
+        "Error updating organization limits",
+        JSON.stringify({ "max_seats": max_seats, "max_collections": max_collections, "attachment_limit": attachment_limit })
+    );
+}
+
+function toggleOrganizationAutoExport(event) {
+    event.preventDefault();
+    event.stopPropagation();
+    const org_uuid = event.target.dataset.vwOrgUuid;
+    const org_name = event.target.dataset.vwOrgName;
+    if (!org_uuid) {
+        alert("Required parameters not found!");
+        return false;
+    }
+
+    const enable = event.target.dataset.vwAutoExport !== "true";
+    const action = enable ? "enable" : "disable";
+    if (confirm(`Are you sure you want to ${action} the automatic exports of "${org_name}"?`)) {
+        _post(`${BASE_URL}/admin/organizations/${org_uuid}/auto-export`,
+            `Automatic exports ${action}d correctly`,
+            `Error trying to ${action} the automatic exports`,
+            JSON.stringify({ "enabled": enable })
+        );
+    }
+}
+
+function inviteOrganizationMembers(event) {
+    event.preventDefault();
+    event.stopPropagation();
+    const org_uuid = event.target.dataset.vwOrgUuid;
+    const org_name = event.target.dataset.vwOrgName;
+    if (!org_uuid) {
+        alert("Required parameters not found!");
+        return false;
+    }
+
+    const input_emails = prompt(`Email addresses to invite to "${org_name}", separated by commas or spaces.`);
+    if (input_emails === null || input_emails.trim() === "") {
+        return false;
+    }
+
+    fetch(`${BASE_URL}/admin/organizations/${org_uuid}/invite`, {
+        method: "POST",
+        body: JSON.stringify({ "emails": input_emails }),
+        mode: "same-origin",
+        credentials: "same-origin",
+        headers: { "Content-Type": "application/json" }
+    }).then(resp => resp.json().then(json => ({ ok: resp.ok, json: json }))).then(({ ok, json }) => {
+        if (!ok) {
+            const message = json.errorModel && json.errorModel.message ? json.errorModel.message : "Unknown error";
+            alert(`Error inviting members\n${message}`);
+            return;
+        }
+        const lines = json.map(r => `${r.email}: ${r.status}${r.message ? ` (${r.message})` : ""}`);
+        const invited = json.filter(r => r.status === "invited").length;
+        alert(`Invited ${invited} of ${json.length} addresses\n\n${lines.join("\n")}`);
+        reload();
+    }).catch(e => {
+        alert(`Error inviting members\n${e}`);
+    });
+}
+
 function initActions() {
     document.querySelectorAll("button[vw-delete-organization]").forEach(btn => {
         btn.addEventListener("click", deleteOrganization);
     });
+    document.querySelectorAll("button[vw-org-limits]").forEach(btn => {
+        btn.addEventListener("click", updateOrganizationLimits);
+    });
+    document.querySelectorAll("button[vw-org-invite]").forEach(btn => {
+        btn.addEventListener("click", inviteOrganizationMembers);
+    });
+    document.querySelectorAll("button[vw-org-auto-export]").forEach(btn => {
+        btn.addEventListener("click", toggleOrganizationAutoExport);
+    });
 
     if (jdenticon) {
         jdenticon();
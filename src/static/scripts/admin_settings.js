@@ -79,6 +79,102 @@ function backupDatabase(event) {
     );
 }
 
+function exportConfig(event) {
+    event.preventDefault();
+    event.stopPropagation();
+    const passphrase = document.getElementById("config-backup-passphrase").value;
+    if (passphrase.length < 12) {
+        alert("The passphrase must be at least 12 characters long");
+        return false;
+    }
+    fetch(`${BASE_URL}/admin/config/export`, {
+        method: "POST",
+        body: JSON.stringify({ "passphrase": passphrase }),
+        mode: "same-origin",
+        credentials: "same-origin",
+        headers: { "Content-Type": "application/json" }
+    }).then(resp => {
+        if (!resp.ok) {
+            return resp.json().then(json => Promise.reject(json.errorModel ? json.errorModel.message : resp.statusText));
+        }
+        return resp.blob();
+    }).then(blob => {
+        const link = document.createElement("a");
+        link.href = URL.createObjectURL(blob);
+        link.download = `vaultwarden-config-${new Date().toISOString().slice(0, 10)}.json`;
+        link.click();
+        URL.revokeObjectURL(link.href);
+    }).catch(e => {
+        alert(`Error exporting config\n${e}`);
+    });
+}
+
+function importConfig(event) {
+    event.preventDefault();
+    event.stopPropagation();
+    const passphrase = document.getElementById("config-backup-passphrase").value;
+    const file = document.getElementById("config-backup-file").files[0];
+    if (!file) {
+        alert("Please select a backup file first");
+        return false;
+    }
+    const input = prompt(
+        "This will overwrite the current user configuration and custom templates " +
+        "with the content of the backup. Type 'RESTORE' to proceed:"
+    );
+    if (input !== "RESTORE") {
+        alert("Wrong input, please try again");
+        return false;
+    }
+    file.text().then(text => {
+        const data = JSON.stringify({ "passphrase": passphrase, "bundle": JSON.parse(text) });
+        _post(`${BASE_URL}/admin/config/import`,
+            "Config restored correctly",
+            "Error restoring config",
+            data
+        );
+    }).catch(e => {
+        alert(`Error reading backup file\n${e}`);
+    });
+}
+
+function generateAdminTotp(event) {
+    event.preventDefault();
+    event.stopPropagation();
+    fetch(`${BASE_URL}/admin/totp/generate`, {
+        method: "POST",
+        mode: "same-origin",
+        credentials: "same-origin",
+        headers: { "Content-Type": "application/json" }
+    }).then(resp => {
+        if (!resp.ok) {
+            return Promise.reject(resp.statusText);
+        }
+        return resp.json();
+    }).then(json => {
+        document.getElementById("admin-totp-secret").value = json.secret;
+        document.getElementById("admin-totp-uri").textContent = json.uri;
+    }).catch(e => {
+        alert(`Error generating secret\n${e}`);
+    });
+}
+
+function enableAdminTotp(event) {
+    event.preventDefault();
+    event.stopPropagation();
+    const secret = document.getElementById("admin-totp-secret").value;
+    const code = document.getElementById("admin-totp-code").value;
+    if (!secret || !code) {
+        alert("Generate a secret and enter the current code of your authenticator app first");
+        return false;
+    }
+    _post(`${BASE_URL}/admin/totp/enable`,
+        "Admin two-step login enabled",
+        "Error enabling admin two-step login",
+        JSON.stringify({ "secret": secret, "code": code })
+    );
+}
+
 // Two functions to help check if there were changes to the form fields
 // Useful for example during the smtp test to prevent people from clicking save before testing there new settings
 function initChangeDetection(form) {
@@ -217,6 +313,22 @@ document.addEventListener("DOMContentLoaded", (/*event*/) => {
     if (btnBackupDatabase) {
         btnBackupDatabase.addEventListener("click", backupDatabase);
     }
+    const btnExportConfig = document.getElementById("exportConfig");
+    if (btnExportConfig) {
+        btnExportConfig.addEventListener("click", exportConfig);
+    }
+    const btnImportConfig = document.getElementById("importConfig");
+    if (btnImportConfig) {
+        btnImportConfig.addEventListener("click", importConfig);
+    }
+    const btnGenerateAdminTotp = document.getElementById("generateAdminTotp");
+    if (btnGenerateAdminTotp) {
+        btnGenerateAdminTotp.addEventListener("click", generateAdminTotp);
+    }
+    const btnEnableAdminTotp = document.getElementById("enableAdminTotp");
+    if (btnEnableAdminTotp) {
+        btnEnableAdminTotp.addEventListener("click", enableAdminTotp);
+    }
     const btnDeleteConf = document.getElementById("deleteConf");
     if (btnDeleteConf) {
         btnDeleteConf.addEventListener("click", deleteConf);
@@ -0,0 +1,202 @@
+use rocket::http::Status;
+
+use super::{take_mail, unique_email, TestServer, PASSWORD_HASH};
+use crate::util::get_uuid;
+
+const ENC_STRING: &str = "2.dGVzdA==|dGVzdA==|dGVzdA==";
+
+/// Creates an organization with a default collection and returns its id.
+async fn create_organization(server: &TestServer, owner: &str, owner_token: &str) -> String {
+    let org = json!({
+        "billingEmail": owner,
+        "collectionName": ENC_STRING,
+        "key": ENC_STRING,
+        "name": "Test Organization",
+        "keys": {
+            "encryptedPrivateKey": ENC_STRING,
+            "publicKey": "dGVzdC1wdWJsaWMta2V5",
+        },
+        "planType": 0,
+    });
+    let (status, body) = server.post("/api/organizations", Some(owner_token), org).await;
+    assert_eq!(status, Status::Ok, "creating the organization failed: {body}");
+    body["id"].as_str().unwrap().to_string()
+}
+
+#[rocket::async_test]
+async fn registration_invite_two_factor_and_sync() {
+    let server = TestServer::start().await;
+    let owner = unique_email("owner");
+    let member = unique_email("member");
+
+    // Registration
+    let (status, body) = server.register(&owner, None).await;
+    assert_eq!(status, Status::Ok, "registration failed: {body}");
+    take_mail(&owner, "Welcome").await;
+    let owner_token = server.access_token(&owner).await;
+
+    // Invite a new user to an organization
+    let org_id = create_organization(&server, &owner, &owner_token).await;
+
+    let invite = json!({
+        "emails": [member],
+        "groups": [],
+        "type": 2,
+        "collections": [],
+        "accessAll": false,
+    });
+    let (status, body) =
+        server.post(&format!("/api/organizations/{org_id}/users/invite"), Some(&owner_token), invite).await;
+    assert_eq!(status, Status::Ok, "inviting failed: {body}");
+    let invite_token = take_mail(&member, "You have been invited to join")
+        .await
+        .find(r"token=([A-Za-z0-9_.\-]+)")
+        .expect("The invite mail does not contain a token");
+
+    // The invited user registers, accepts the invite and gets confirmed by the owner
+    let (status, body) = server.register(&member, Some(&invite_token)).await;
+    assert_eq!(status, Status::Ok, "registration via invite failed: {body}");
+    let member_token = server.access_token(&member).await;
+
+    let (status, body) = server
+        .post(
+            &format!("/api/organizations/{org_id}/users/{}/accept", get_uuid()),
+            Some(&member_token),
+            json!({ "token": invite_token }),
+        )
+        .await;
+    assert_eq!(status, Status::Ok, "accepting the invite failed: {body}");
+
+    let (status, body) = server.get(&format!("/api/organizations/{org_id}/users"), &owner_token).await;
+    assert_eq!(status, Status::Ok, "listing the members failed: {body}");
+    let membership = body["data"].as_array().unwrap().iter().find(|m| m["email"] == member.as_str()).unwrap();
+    assert_eq!(membership["status"], 1, "the invite should be accepted");
+    let member_id = membership["id"].as_str().unwrap();
+
+    let (status, body) = server
+        .post(
+            &format!("/api/organizations/{org_id}/users/{member_id}/confirm"),
+            Some(&owner_token),
+            json!({ "key": ENC_STRING }),
+        )
+        .await;
+    assert_eq!(status, Status::Ok, "confirming the member failed: {body}");
+
+    // Enable email based two-step login for the invited user
+    let (status, body) = server
+        .post(
+            "/api/two-factor/send-email",
+            Some(&member_token),
+            json!({ "email": member, "masterPasswordHash": PASSWORD_HASH }),
+        )
+        .await;
+    assert_eq!(status, Status::Ok, "sending the 2FA setup token failed: {body}");
+    let setup_code = take_mail(&member, "two-step verification code is")
+        .await
+        .find(r"code is: (\d+)")
+        .expect("The 2FA mail does not contain a code");
+
+    let (status, body) = server
+        .put(
+            "/api/two-factor/email",
+            &member_token,
+            json!({ "email": member, "token": setup_code, "masterPasswordHash": PASSWORD_HASH }),
+        )
+        .await;
+    assert_eq!(status, Status::Ok, "enabling email 2FA failed: {body}");
+
+    // Logging in now requires the code which is sent by mail
+    let device_id = get_uuid();
+    let (status, body) = server.login(&member, &device_id, None).await;
+    assert_eq!(status, Status::BadRequest);
+    assert!(body["TwoFactorProviders"].as_array().unwrap().iter().any(|p| p == "1"), "email 2FA expected: {body}");
+    let login_code = take_mail(&member, "two-step verification code is")
+        .await
+        .find(r"code is: (\d+)")
+        .expect("The 2FA mail does not contain a code");
+
+    let (status, body) = server.login(&member, &device_id, Some((1, "000000000"))).await;
+    assert_eq!(status, Status::BadRequest, "an invalid 2FA code was accepted: {body}");
+
+    let (status, body) = server.login(&member, &device_id, Some((1, &login_code))).await;
+    assert_eq!(status, Status::Ok, "login with 2FA failed: {body}");
+    let member_token = body["access_token"].as_str().unwrap().to_string();
+
+    // The confirmed organization is part of the synced profile
+    let (status, body) = server.get("/api/sync", &member_token).await;
+    assert_eq!(status, Status::Ok, "sync failed: {body}");
+    assert_eq!(body["profile"]["email"], member.as_str());
+    let orgs = body["profile"]["organizations"].as_array().unwrap();
+    let org = orgs.iter().find(|o| o["id"] == org_id.as_str()).expect("The organization is missing from the sync");
+    assert_eq!(org["status"], 2, "the membership should be confirmed");
+}
+
+#[rocket::async_test]
+async fn registration_is_rejected_for_existing_users() {
+    let server = TestServer::start().await;
+    let email = unique_email("existing");
+
+    let (status, body) = server.register(&email, None).await;
+    assert_eq!(status, Status::Ok, "registration failed: {body}");
+
+    let (status, _) = server.register(&email, None).await;
+    assert_eq!(status, Status::BadRequest);
+}
+
+#[rocket::async_test]
+async fn public_api_updates_keep_the_external_id() {
+    let server = TestServer::start().await;
+    let owner = unique_email("owner");
+    let member = unique_email("member");
+
+    let (status, body) = server.register(&owner, None).await;
+    assert_eq!(status, Status::Ok, "registration failed: {body}");
+    let owner_token = server.access_token(&owner).await;
+    let org_id = create_organization(&server, &owner, &owner_token).await;
+
+    let (status, body) = server
+        .post(
+            &format!("/api/organizations/{org_id}/api-key"),
+            Some(&owner_token),
+            json!({ "masterPasswordHash": PASSWORD_HASH }),
+        )
+        .await;
+    assert_eq!(status, Status::Ok, "getting the API key failed: {body}");
+    let token = server.org_access_token(&org_id, body["apiKey"].as_str().unwrap()).await;
+
+    // Collections
+    let (status, body) = server.get("/api/public/collections", &token).await;
+    assert_eq!(status, Status::Ok, "listing the collections failed: {body}");
+    let col_uri = format!("/api/public/collections/{}", body["data"][0]["id"].as_str().unwrap());
+
+    let (status, body) = server.put(&col_uri, &token, json!({ "externalId": "col-ext", "groups": [] })).await;
+    assert_eq!(status, Status::Ok, "updating the collection failed: {body}");
+    assert_eq!(body["externalId"], "col-ext");
+
+    let (status, body) = server.put(&col_uri, &token, json!({ "groups": [] })).await;
+    assert_eq!(status, Status::Ok, "updating the collection failed: {body}");
+    assert_eq!(body["externalId"], "col-ext", "the external id of the collection was removed");
+
+    // Groups
+    let (status, body) =
+        server.post("/api/public/groups", Some(&token), json!({ "name": "Group", "externalId": "group-ext" })).await;
+    assert_eq!(status, Status::Ok, "creating the group failed: {body}");
+    let group_uri = format!("/api/public/groups/{}", body["id"].as_str().unwrap());
+
+    let (status, body) = server.put(&group_uri, &token, json!({ "name": "Renamed" })).await;
+    assert_eq!(status, Status::Ok, "updating the group failed: {body}");
+    assert_eq!(body["name"], "Renamed");
+    assert_eq!(body["externalId"], "group-ext", "the external id of the group was removed");
+
+    // Members
+    let (status, body) = server
+        .post("/api/public/members", Some(&token), json!({ "email": member, "type": 2, "externalId": "member-ext" }))
+        .await;
+    assert_eq!(status, Status::Ok, "inviting the member failed: {body}");
+    let member_uri = format!("/api/public/members/{}", body["id"].as_str().unwrap());
+
+    let (status, body) = server.put(&member_uri, &token, json!({ "type": 3 })).await;
+    assert_eq!(status, Status::Ok, "updating the member failed: {body}");
+    assert_eq!(body["type"], 3);
+    assert_eq!(body["externalId"], "member-ext", "the external id of the member was removed");
+}
@@ -0,0 +1,303 @@
+//
+// In-crate integration test harness
+//
+// Runs the complete server against a temporary SQLite database, and captures all outgoing mail
+// with an in-process SMTP sink, so complete user flows can be tested through the HTTP API.
+// All tests share one server configuration, use unique email addresses to keep them apart.
+//
+mod flows;
+mod passkeys;
+mod ratelimit;
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Mutex, OnceLock},
+    thread,
+    time::Duration,
+};
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use rocket::{
+    http::{ContentType, Header, Status},
+    local::asynchronous::{Client, LocalResponse},
+};
+use serde_json::Value;
+
+use crate::{auth, db::DbPool, util::get_uuid, CONFIG};
+
+static POOL: OnceLock<DbPool> = OnceLock::new();
+static MAILS: Lazy<Mutex<Vec<CapturedMail>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+/// The hash the clients derive from the master password, the server never sees the password itself.
+pub const PASSWORD_HASH: &str = "dGVzdC1tYXN0ZXItcGFzc3dvcmQtaGFzaC1mb3ItdGVzdHM=";
+const DEVICE_TYPE_CHROME_EXTENSION: &str = "2";
+
+#[derive(Clone, Debug)]
+pub struct CapturedMail {
+    pub recipients: Vec<String>,
+    pub data: String,
+}
+
+impl CapturedMail {
+    /// Returns the message with quoted-printable soft line breaks and escaped equal signs decoded,
+    /// which is enough to search it for the links and codes it contains.
+    pub fn text(&self) -> String {
+        self.data.replace("=\r\n", "").replace("=3D", "=")
+    }
+
+    /// Returns the first capture group of `pattern` found in the message.
+    pub fn find(&self, pattern: &str) -> Option<String> {
+        let re = Regex::new(pattern).unwrap();
+        re.captures(&self.text()).map(|c| c[1].to_string())
+    }
+}
+
+/// Configures the server for testing, this has to happen before `CONFIG` is used for the first time.
+fn setup() -> DbPool {
+    POOL.get_or_init(|| {
+        // The configuration is only loaded once, when it was used before this point it contains the environment
+        // of the developer, and the tests would run against their database
+        assert!(Lazy::get(&CONFIG).is_none(), "CONFIG was used before the test setup, call `setup()` first");
+
+        let data_folder = std::env::temp_dir().join(format!("vaultwarden-test-{}", get_uuid()));
+        std::fs::create_dir_all(&data_folder).expect("Error creating test data folder");
+        let data_folder = data_folder.to_str().unwrap().to_string();
+
+        // Prevent a `.env` file of the developer from changing the test configuration
+        let env_file = format!("{data_folder}/.env");
+        std::fs::write(&env_file, "").expect("Error creating test env file");
+
+        let database_url = format!("{data_folder}/db.sqlite3");
+        let smtp_port = start_smtp_sink();
+        for (key, value) in [
+            ("ENV_FILE", env_file),
+            ("DATABASE_URL", database_url.clone()),
+            ("DATA_FOLDER", data_folder),
+            ("DOMAIN", String::from("http://localhost")),
+            ("WEB_VAULT_ENABLED", String::from("false")),
+            ("JOB_POLL_INTERVAL_MS", String::from("0")),
+            ("SMTP_HOST", String::from("127.0.0.1")),
+            ("SMTP_PORT", smtp_port.to_string()),
+            ("SMTP_SECURITY", String::from("off")),
+            ("SMTP_FROM", String::from("vaultwarden@example.com")),
+            ("EMAIL_TOKEN_RESEND_COOLDOWN", String::from("0")),
+            ("LOGIN_RATELIMIT_MAX_BURST", String::from("1000")),
+            ("PASSWORD_ITERATIONS", String::from("100000")),
+            ("ORG_GROUPS_ENABLED", String::from("true")),
+        ] {
+            std::env::set_var(key, value);
+        }
+        assert_eq!(CONFIG.database_url(), database_url, "The tests have to use their own temporary database");
+
+        for folder in
+            [CONFIG.tmp_folder(), CONFIG.attachments_folder(), CONFIG.sends_folder(), CONFIG.icon_cache_folder()]
+        {
+            std::fs::create_dir_all(folder).expect("Error creating test folder");
+        }
+        auth::initialize_keys().expect("Error creating test RSA keys");
+        DbPool::from_config().expect("Error creating test database")
+    })
+    .clone()
+}
+
+/// Starts an SMTP server which accepts every message and stores it in `MAILS`, and returns its port.
+fn start_smtp_sink() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Error starting SMTP sink");
+    let port = listener.local_addr().unwrap().port();
+    thread::Builder::new()
+        .name("smtp-sink".to_string())
+        .spawn(move || {
+            for stream in listener.incoming().flatten() {
+                thread::spawn(move || {
+                    if let Err(e) = handle_smtp_session(stream) {
+                        eprintln!("SMTP sink session failed: {e}");
+                    }
+                });
+            }
+        })
+        .expect("Error spawning SMTP sink thread");
+    port
+}
+
+fn handle_smtp_session(stream: TcpStream) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    writer.write_all(b"220 localhost ESMTP test sink\r\n")?;
+
+    let mut recipients = Vec::new();
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        let command = line.trim_end().to_ascii_uppercase();
+
+        let reply: &[u8] = if command.starts_with("EHLO") || command.starts_with("HELO") {
+            b"250 localhost\r\n"
+        } else if command.starts_with("RCPT TO:") {
+            let address = line.trim_end()[8..].trim().trim_start_matches('<').trim_end_matches('>');
+            recipients.push(address.to_lowercase());
+            b"250 OK\r\n"
+        } else if command == "DATA" {
+            writer.write_all(b"354 End data with <CR><LF>.<CR><LF>\r\n")?;
+            let mut data = String::new();
+            loop {
+                line.clear();
+                if reader.read_line(&mut line)? == 0 {
+                    return Ok(());
+                }
+                if line == ".\r\n" {
+                    break;
+                }
+                // Undo the dot-stuffing done by the client
+                data.push_str(line.strip_prefix('.').unwrap_or(&line));
+            }
+            MAILS.lock().unwrap().push(CapturedMail {
+                recipients: std::mem::take(&mut recipients),
+                data,
+            });
+            b"250 OK\r\n"
+        } else if command == "RSET" {
+            recipients.clear();
+            b"250 OK\r\n"
+        } else if command == "QUIT" {
+            writer.write_all(b"221 Bye\r\n")?;
+            return Ok(());
+        } else {
+            b"250 OK\r\n"
+        };
+        writer.write_all(reply)?;
+    }
+}
+
+/// Waits for a message to `recipient` which contains `needle`, and removes it and all older matching
+/// messages from the sink, so the same message is never returned twice.
+pub async fn take_mail(recipient: &str, needle: &str) -> CapturedMail {
+    let recipient = recipient.to_lowercase();
+    for _ in 0..200 {
+        {
+            let mut mails = MAILS.lock().unwrap();
+            let matches = |m: &CapturedMail| m.recipients.contains(&recipient) && m.text().contains(needle);
+            if let Some(mail) = mails.iter().rev().find(|m| matches(m)).cloned() {
+                mails.retain(|m| !matches(m));
+                return mail;
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("No mail containing `{needle}` was sent to {recipient}");
+}
+
+/// Returns a unique email address, so tests running in parallel do not interfere with each other.
+pub fn unique_email(name: &str) -> String {
+    format!("{name}-{}@example.com", get_uuid())
+}
+
+pub struct TestServer {
+    pub client: Client,
+}
+
+impl TestServer {
+    pub async fn start() -> Self {
+        let pool = setup();
+        let client = Client::tracked(crate::build_rocket(pool, false)).await.expect("Error starting test server");
+        Self {
+            client,
+        }
+    }
+
+    pub async fn get(&self, uri: &str, token: &str) -> (Status, Value) {
+        let request = self.client.get(uri.to_string()).header(bearer(token));
+        into_result(request.dispatch().await).await
+    }
+
+    pub async fn post(&self, uri: &str, token: Option<&str>, body: Value) -> (Status, Value) {
+        let mut request = self.client.post(uri.to_string()).header(ContentType::JSON).body(body.to_string());
+        if let Some(token) = token {
+            request = request.header(bearer(token));
+        }
+        into_result(request.dispatch().await).await
+    }
+
+    pub async fn put(&self, uri: &str, token: &str, body: Value) -> (Status, Value) {
+        let request =
+            self.client.put(uri.to_string()).header(ContentType::JSON).header(bearer(token)).body(body.to_string());
+        into_result(request.dispatch().await).await
+    }
+
+    pub async fn register(&self, email: &str, org_invite_token: Option<&str>) -> (Status, Value) {
+        let body = json!({
+            "email": email,
+            "name": "Test User",
+            "masterPasswordHash": PASSWORD_HASH,
+            "key": "2.dGVzdA==|dGVzdA==|dGVzdA==",
+            "keys": {
+                "encryptedPrivateKey": "2.dGVzdA==|dGVzdA==|dGVzdA==",
+                "publicKey": "dGVzdC1wdWJsaWMta2V5",
+            },
+            "orgInviteToken": org_invite_token,
+        });
+        self.post("/api/accounts/register", None, body).await
+    }
+
+    /// Logs in with the master password hash, `two_factor` contains the provider and token when needed.
+    pub async fn login(&self, email: &str, device_id: &str, two_factor: Option<(i32, &str)>) -> (Status, Value) {
+        let mut form = url::form_urlencoded::Serializer::new(String::new());
+        form.append_pair("grant_type", "password")
+            .append_pair("username", email)
+            .append_pair("password", PASSWORD_HASH)
+            .append_pair("scope", "api offline_access")
+            .append_pair("client_id", "browser")
+            .append_pair("device_identifier", device_id)
+            .append_pair("device_name", "test")
+            .append_pair("device_type", DEVICE_TYPE_CHROME_EXTENSION);
+        if let Some((provider, token)) = two_factor {
+            form.append_pair("two_factor_provider", &provider.to_string()).append_pair("two_factor_token", token);
+        }
+
+        let request = self
+            .client
+            .post("/identity/connect/token")
+            .header(ContentType::Form)
+            .header(Header::new("device-type", DEVICE_TYPE_CHROME_EXTENSION))
+            .body(form.finish());
+        into_result(request.dispatch().await).await
+    }
+
+    /// Logs in with the API key of an organization and returns the access token for the public API.
+    pub async fn org_access_token(&self, org_id: &str, api_key: &str) -> String {
+        let mut form = url::form_urlencoded::Serializer::new(String::new());
+        form.append_pair("grant_type", "client_credentials")
+            .append_pair("client_id", &format!("organization.{org_id}"))
+            .append_pair("client_secret", api_key)
+            .append_pair("scope", "api.organization")
+            .append_pair("device_identifier", &get_uuid())
+            .append_pair("device_name", "test")
+            .append_pair("device_type", DEVICE_TYPE_CHROME_EXTENSION);
+
+        let request = self.client.post("/identity/connect/token").header(ContentType::Form).body(form.finish());
+        let (status, body) = into_result(request.dispatch().await).await;
+        assert_eq!(status, Status::Ok, "organization login failed: {body}");
+        body["access_token"].as_str().unwrap().to_string()
+    }
+
+    /// Logs in a user without two-step login and returns the access token.
+    pub async fn access_token(&self, email: &str) -> String {
+        let (status, body) = self.login(email, &get_uuid(), None).await;
+        assert_eq!(status, Status::Ok, "login failed: {body}");
+        body["access_token"].as_str().unwrap().to_string()
+    }
+}
+
+fn bearer(token: &str) -> Header<'static> {
+    Header::new("Authorization", format!("Bearer {token}"))
+}
+
+async fn into_result(response: LocalResponse<'_>) -> (Status, Value) {
+    let status = response.status();
+    let body = response.into_string().await.unwrap_or_default();
+    (status, serde_json::from_str(&body).unwrap_or(Value::Null))
+}
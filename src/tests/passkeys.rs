@@ -0,0 +1,169 @@
+use data_encoding::BASE64URL_NOPAD;
+use openssl::{
+    bn::BigNumContext,
+    ec::{EcGroup, EcKey, PointConversionForm},
+    hash::MessageDigest,
+    nid::Nid,
+    pkey::{PKey, Private},
+    sha::sha256,
+    sign::Signer,
+};
+use rocket::http::{ContentType, Header, Status};
+use serde_json::Value;
+use webauthn_rs::proto::{
+    COSEAlgorithm, COSEEC2Key, COSEKey, COSEKeyType, Credential, ECDSACurve, UserVerificationPolicy,
+};
+
+use super::{into_result, setup, unique_email, TestServer, DEVICE_TYPE_CHROME_EXTENSION};
+use crate::{
+    api::core::passkeys::{save_passkey_credentials, PasskeyCredential},
+    crypto,
+    db::models::User,
+    util::get_uuid,
+};
+
+/// A software authenticator holding one discoverable P-256 credential.
+struct Authenticator {
+    key: PKey<Private>,
+    credential_id: Vec<u8>,
+    user_handle: Vec<u8>,
+    counter: u32,
+}
+
+impl Authenticator {
+    /// Creates the authenticator and registers its credential as a passkey of the user.
+    async fn register(email: &str) -> Self {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let ec_key = EcKey::generate(&group).unwrap();
+        let mut ctx = BigNumContext::new().unwrap();
+        let public_key = ec_key.public_key().to_bytes(&group, PointConversionForm::UNCOMPRESSED, &mut ctx).unwrap();
+
+        let mut conn = setup().get().await.expect("Error getting a database connection");
+        let user = User::find_by_mail(email, &mut conn).await.expect("The user does not exist");
+        let credential_id = crypto::get_random_bytes::<16>().to_vec();
+        let passkey = PasskeyCredential {
+            id: get_uuid(),
+            name: String::from("Test passkey"),
+            credential: Credential {
+                counter: 0,
+                verified: true,
+                cred: COSEKey {
+                    type_: COSEAlgorithm::ES256,
+                    key: COSEKeyType::EC_EC2(COSEEC2Key {
+                        curve: ECDSACurve::SECP256R1,
+                        x: public_key[1..33].try_into().unwrap(),
+                        y: public_key[33..65].try_into().unwrap(),
+                    }),
+                },
+                cred_id: credential_id.clone(),
+                registration_policy: UserVerificationPolicy::Required,
+            },
+            supports_prf: false,
+            encrypted_user_key: None,
+            encrypted_public_key: None,
+            encrypted_private_key: None,
+        };
+        save_passkey_credentials(&user.uuid, &[passkey], &mut conn).await.unwrap();
+
+        Self {
+            key: PKey::from_ec_key(ec_key).unwrap(),
+            credential_id,
+            user_handle: user.uuid.as_bytes().to_vec(),
+            counter: 0,
+        }
+    }
+
+    /// Signs the challenge like a browser would, and returns the device response the clients send to the server.
+    fn assert(&mut self, challenge: &str) -> Value {
+        let client_data = json!({
+            "type": "webauthn.get",
+            "challenge": challenge,
+            "origin": "http://localhost",
+            "crossOrigin": false,
+        })
+        .to_string();
+
+        // The RP id hash, the user present and user verified flags and the signature counter
+        self.counter += 1;
+        let mut authenticator_data = sha256(b"localhost").to_vec();
+        authenticator_data.push(0x05);
+        authenticator_data.extend_from_slice(&self.counter.to_be_bytes());
+
+        let mut signer = Signer::new(MessageDigest::sha256(), &self.key).unwrap();
+        signer.update(&authenticator_data).unwrap();
+        signer.update(&sha256(client_data.as_bytes())).unwrap();
+        let signature = signer.sign_to_vec().unwrap();
+
+        let credential_id = BASE64URL_NOPAD.encode(&self.credential_id);
+        json!({
+            "id": credential_id,
+            "rawId": credential_id,
+            "type": "public-key",
+            "response": {
+                "authenticatorData": BASE64URL_NOPAD.encode(&authenticator_data),
+                "clientDataJson": BASE64URL_NOPAD.encode(client_data.as_bytes()),
+                "signature": BASE64URL_NOPAD.encode(&signature),
+                "userHandle": BASE64URL_NOPAD.encode(&self.user_handle),
+            },
+        })
+    }
+}
+
+async fn passkey_login_options(server: &TestServer) -> (String, String) {
+    let request = server.client.get("/identity/accounts/webauthn/assertion-options");
+    let (status, body) = into_result(request.dispatch().await).await;
+    assert_eq!(status, Status::Ok, "getting the passkey login options failed: {body}");
+    (body["options"]["challenge"].as_str().unwrap().to_string(), body["token"].as_str().unwrap().to_string())
+}
+
+async fn passkey_login(server: &TestServer, token: &str, device_response: &Value) -> (Status, Value) {
+    let mut form = url::form_urlencoded::Serializer::new(String::new());
+    form.append_pair("grant_type", "webauthn")
+        .append_pair("token", token)
+        .append_pair("deviceresponse", &device_response.to_string())
+        .append_pair("scope", "api offline_access")
+        .append_pair("client_id", "browser")
+        .append_pair("device_identifier", &get_uuid())
+        .append_pair("device_name", "test")
+        .append_pair("device_type", DEVICE_TYPE_CHROME_EXTENSION);
+
+    let request = server
+        .client
+        .post("/identity/connect/token")
+        .header(ContentType::Form)
+        .header(Header::new("device-type", DEVICE_TYPE_CHROME_EXTENSION))
+        .body(form.finish());
+    into_result(request.dispatch().await).await
+}
+
+#[rocket::async_test]
+async fn passkey_login_and_replay() {
+    let server = TestServer::start().await;
+    let email = unique_email("passkey");
+    let (status, body) = server.register(&email, None).await;
+    assert_eq!(status, Status::Ok, "registration failed: {body}");
+
+    let mut authenticator = Authenticator::register(&email).await;
+
+    // A login with a signed assertion of the handed out challenge
+    let (challenge, token) = passkey_login_options(&server).await;
+    let device_response = authenticator.assert(&challenge);
+    let (status, body) = passkey_login(&server, &token, &device_response).await;
+    assert_eq!(status, Status::Ok, "passkey login failed: {body}");
+    assert!(body["access_token"].is_string());
+
+    // The same assertion can't be used again
+    let (status, body) = passkey_login(&server, &token, &device_response).await;
+    assert_eq!(status, Status::BadRequest, "a replayed passkey login succeeded: {body}");
+
+    // Neither can a new assertion of an already used challenge
+    let device_response = authenticator.assert(&challenge);
+    let (status, body) = passkey_login(&server, &token, &device_response).await;
+    assert_eq!(status, Status::BadRequest, "a reused passkey challenge was accepted: {body}");
+
+    // While a new challenge can be used to log in again
+    let (challenge, token) = passkey_login_options(&server).await;
+    let device_response = authenticator.assert(&challenge);
+    let (status, body) = passkey_login(&server, &token, &device_response).await;
+    assert_eq!(status, Status::Ok, "second passkey login failed: {body}");
+}
@@ -0,0 +1,39 @@
+use std::net::{IpAddr, Ipv4Addr};
+
+use chrono::Utc;
+
+use super::{setup, unique_email};
+use crate::{db::models::LoginBackoff, ratelimit};
+
+const UNKNOWN_ACCOUNT_PREFIX: &str = "unknown:";
+
+#[rocket::async_test]
+async fn unknown_account_backoffs_are_limited() {
+    let mut conn = setup().get().await.expect("Error getting a database connection");
+    let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+    let existing = LoginBackoff::count_by_key_prefix(UNKNOWN_ACCOUNT_PREFIX, &mut conn).await;
+
+    // Two unknown accounts which failed some time ago, the first one the longest time ago
+    let now = Utc::now().timestamp();
+    let mut keys = Vec::new();
+    for age in [20, 10] {
+        let key = ratelimit::unknown_account_backoff_key(&unique_email("unknown"), &ip);
+        let mut backoff = LoginBackoff::new(key.clone());
+        backoff.failures = 1;
+        backoff.last_failure = now - age;
+        backoff.save(&mut conn).await.unwrap();
+        keys.push(key);
+    }
+
+    // With room for one more there's nothing to remove
+    ratelimit::make_room_for_unknown_account(existing + 3, &mut conn).await.unwrap();
+    assert_eq!(LoginBackoff::count_by_key_prefix(UNKNOWN_ACCOUNT_PREFIX, &mut conn).await, existing + 2);
+
+    // When the limit is reached, the oldest failure is forgotten to make room for a new one
+    ratelimit::make_room_for_unknown_account(existing + 2, &mut conn).await.unwrap();
+    assert_eq!(LoginBackoff::count_by_key_prefix(UNKNOWN_ACCOUNT_PREFIX, &mut conn).await, existing + 1);
+    assert!(LoginBackoff::find_by_key(&keys[0], &mut conn).await.is_none(), "the oldest backoff should be removed");
+    assert!(LoginBackoff::find_by_key(&keys[1], &mut conn).await.is_some(), "the newer backoff should be kept");
+
+    ratelimit::reset_failures(&keys[1], &mut conn).await.unwrap();
+}
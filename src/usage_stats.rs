@@ -0,0 +1,203 @@
+//
+// Local usage statistics for the operator
+// Nothing is ever sent off-box and only counters are kept per calendar day. The ids of the active users of the
+// current day are only kept to count them once and are discarded when the day ends.
+// The statistics are saved to `usage_stats.json` in the data folder, at most once per `SAVE_INTERVAL`.
+//
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use chrono::{NaiveDate, TimeDelta, Utc};
+use once_cell::sync::Lazy;
+use serde_json::Value;
+
+use crate::{db::models::UserId, CONFIG};
+
+// The rolling windows (in calendar days, including the current day) which are aggregated for the admin endpoint
+const WINDOWS: [u32; 3] = [1, 7, 30];
+
+// The counters of the last minute can be lost on a restart, saving on every request would be too costly
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Default, Deserialize, Serialize)]
+struct Day {
+    active_users: usize,
+    syncs: u64,
+    mails: u64,
+    icon_requests: u64,
+    icon_hits: u64,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+struct Stats {
+    days: BTreeMap<NaiveDate, Day>,
+    // The users which were already counted as active on `users_date`
+    users_date: Option<NaiveDate>,
+    users: HashSet<UserId>,
+    #[serde(skip)]
+    saved_at: Option<Instant>,
+}
+
+fn stats_file() -> String {
+    format!("{}/usage_stats.json", CONFIG.data_folder())
+}
+
+/// The first day which is still within `USAGE_STATS_DAYS`, counting the current day as well.
+fn first_kept_day(today: NaiveDate) -> NaiveDate {
+    today - TimeDelta::days(i64::from(CONFIG.usage_stats_days().max(1)) - 1)
+}
+
+impl Stats {
+    fn load() -> Self {
+        let Ok(content) = std::fs::read_to_string(stats_file()) else {
+            return Self::default();
+        };
+        serde_json::from_str(&content).unwrap_or_else(|e| {
+            warn!("Unable to read the saved usage statistics, starting over: {e}");
+            Self::default()
+        })
+    }
+
+    fn save_if_due(&mut self) {
+        if self.saved_at.is_some_and(|saved_at| saved_at.elapsed() < SAVE_INTERVAL) {
+            return;
+        }
+        self.saved_at = Some(Instant::now());
+
+        let result =
+            serde_json::to_vec(self).map_err(std::io::Error::from).and_then(|s| std::fs::write(stats_file(), s));
+        if let Err(e) = result {
+            error!("Unable to save the usage statistics: {e}");
+        }
+    }
+
+    fn today(&mut self) -> &mut Day {
+        let today = Utc::now().date_naive();
+        if self.users_date != Some(today) {
+            self.users_date = Some(today);
+            self.users.clear();
+        }
+
+        let first_kept = first_kept_day(today);
+        self.days.retain(|date, _| *date >= first_kept);
+        self.days.entry(today).or_default()
+    }
+
+    /// The recorded days which are within the retention, the oldest day first.
+    fn kept_days(&self, today: NaiveDate) -> impl Iterator<Item = (&NaiveDate, &Day)> {
+        self.days.range(first_kept_day(today)..)
+    }
+}
+
+static STATS: Lazy<Mutex<Stats>> = Lazy::new(|| Mutex::new(Stats::load()));
+
+fn record(f: impl FnOnce(&mut Stats)) {
+    if !CONFIG.usage_stats_enabled() {
+        return;
+    }
+    let mut stats = STATS.lock().unwrap();
+    f(&mut stats);
+    stats.save_if_due();
+}
+
+/// Counts the user as active for the current day, called for every authenticated request.
+pub fn record_active_user(user_id: &UserId) {
+    record(|stats| {
+        stats.today();
+        if stats.users.insert(user_id.clone()) {
+            stats.today().active_users += 1;
+        }
+    });
+}
+
+pub fn record_sync() {
+    record(|stats| stats.today().syncs += 1);
+}
+
+pub fn record_mail() {
+    record(|stats| stats.today().mails += 1);
+}
+
+/// Counts an icon request, `hit` is true when the icon was served from the cache.
+pub fn record_icon(hit: bool) {
+    record(|stats| {
+        let day = stats.today();
+        day.icon_requests += 1;
+        if hit {
+            day.icon_hits += 1;
+        }
+    });
+}
+
+fn icon_hit_rate(requests: u64, hits: u64) -> Option<f64> {
+    (requests > 0).then(|| (hits as f64 / requests as f64 * 1000.0).round() / 1000.0)
+}
+
+/// Returns the statistics per day and aggregated over the rolling windows.
+/// Days without any recorded activity count as zero, the days before the first recorded day are not counted.
+pub fn stats_json() -> Value {
+    let stats = STATS.lock().unwrap();
+    let today = Utc::now().date_naive();
+    let first_recorded = stats.kept_days(today).next().map(|(date, _)| *date);
+
+    let windows: Vec<Value> = WINDOWS
+        .iter()
+        .map(|&window| {
+            let start = today - TimeDelta::days(i64::from(window) - 1);
+            let days: Vec<&Day> = stats.kept_days(today).filter(|(date, _)| **date >= start).map(|(_, d)| d).collect();
+            let counted_days = match first_recorded {
+                Some(first) => (today - first.max(start)).num_days() + 1,
+                None => 0,
+            };
+            let active_users: usize = days.iter().map(|d| d.active_users).sum();
+            let icon_requests = days.iter().map(|d| d.icon_requests).sum();
+            let icon_hits = days.iter().map(|d| d.icon_hits).sum();
+            json!({
+                "days": window,
+                "maxDailyActiveUsers": days.iter().map(|d| d.active_users).max().unwrap_or(0),
+                "avgDailyActiveUsers": if counted_days > 0 { active_users / counted_days as usize } else { 0 },
+                "syncs": days.iter().map(|d| d.syncs).sum::<u64>(),
+                "mails": days.iter().map(|d| d.mails).sum::<u64>(),
+                "iconRequests": icon_requests,
+                "iconHitRate": icon_hit_rate(icon_requests, icon_hits),
+            })
+        })
+        .collect();
+
+    let days: Vec<Value> = stats
+        .kept_days(today)
+        .map(|(date, d)| {
+            json!({
+                "date": date.to_string(),
+                "activeUsers": d.active_users,
+                "syncs": d.syncs,
+                "mails": d.mails,
+                "iconRequests": d.icon_requests,
+                "iconHitRate": icon_hit_rate(d.icon_requests, d.icon_hits),
+            })
+        })
+        .collect();
+
+    json!({
+        "enabled": CONFIG.usage_stats_enabled(),
+        "windows": windows,
+        "days": days,
+    })
+}
+
+/// Returns the statistics per day as CSV, the oldest day first.
+pub fn stats_csv() -> String {
+    let stats = STATS.lock().unwrap();
+
+    let mut csv = String::from("date,active_users,syncs,mails,icon_requests,icon_hits\r\n");
+    for (date, d) in stats.kept_days(Utc::now().date_naive()) {
+        csv.push_str(&format!(
+            "{},{},{},{},{},{}\r\n",
+            date, d.active_users, d.syncs, d.mails, d.icon_requests, d.icon_hits
+        ));
+    }
+    csv
+}
@@ -183,6 +183,44 @@ impl Fairing for Cors {
     }
 }
 
+// Requests which are still allowed in demo mode, relative to the domain path
+// Logging in stores the device and its tokens, but that does not change the data shown to other visitors
+const DEMO_MODE_ALLOWED_WRITES: &[&str] =
+    &["/identity/connect/token", "/identity/accounts/prelogin", "/api/accounts/prelogin", "/admin"];
+
+pub struct DemoMode();
+
+#[rocket::async_trait]
+impl Fairing for DemoMode {
+    fn info(&self) -> Info {
+        Info {
+            name: "Demo Mode",
+            kind: Kind::Request,
+        }
+    }
+
+    // Rocket fairings can not respond to a request directly,
+    // so any write request is rerouted to an endpoint which only returns an error
+    async fn on_request(&self, request: &mut Request<'_>, _data: &mut Data<'_>) {
+        if !CONFIG.demo_mode() || matches!(request.method(), Method::Get | Method::Head | Method::Options) {
+            return;
+        }
+
+        let domain_path = CONFIG.domain_path();
+        let path = request.uri().path().as_str().to_string();
+        let Some(path) = path.strip_prefix(&domain_path) else {
+            return;
+        };
+        if DEMO_MODE_ALLOWED_WRITES.contains(&path) || path.starts_with("/notifications/") {
+            return;
+        }
+
+        debug!("Demo mode, rejecting {} {path}", request.method());
+        request.set_method(Method::Get);
+        request.set_uri(rocket::http::uri::Origin::parse_owned(format!("{domain_path}/api/demo-mode")).unwrap());
+    }
+}
+
 pub struct Cached<R> {
     response: R,
     is_immutable: bool,
@@ -489,6 +527,25 @@ pub fn is_valid_email(email: &str) -> bool {
     true
 }
 
+/// Removes the `+tag` of the local part of an email address, `user+tag@example.org` becomes `user@example.org`
+pub fn normalize_plus_address(email: &str) -> String {
+    let email = email.to_lowercase();
+    match email.rsplit_once('@') {
+        Some((local, domain)) => match local.split_once('+') {
+            Some((base, _)) if !base.is_empty() => format!("{base}@{domain}"),
+            _ => email,
+        },
+        None => email,
+    }
+}
+
+/// Creates a `LIKE` pattern matching any lowercase value which contains the text,
+/// the wildcards in the text are escaped so the pattern has to be used with `.escape('\\')`
+pub fn like_contains_pattern(text: &str) -> String {
+    let escaped = text.to_lowercase().replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    format!("%{escaped}%")
+}
+
 //
 // Deployment environment methods
 //
@@ -707,6 +764,48 @@ where
     }
 }
 
+//
+// File store IO methods
+//
+
+use once_cell::sync::Lazy;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+#[derive(Clone, Copy)]
+pub enum FileStore {
+    Attachments,
+    Sends,
+    Icons,
+    Backups,
+}
+
+fn new_store_semaphore(limit: usize) -> Semaphore {
+    if limit == 0 {
+        Semaphore::new(Semaphore::MAX_PERMITS)
+    } else {
+        Semaphore::new(limit)
+    }
+}
+
+static ATTACHMENTS_IO: Lazy<Semaphore> = Lazy::new(|| new_store_semaphore(CONFIG.attachments_io_concurrency()));
+static SENDS_IO: Lazy<Semaphore> = Lazy::new(|| new_store_semaphore(CONFIG.sends_io_concurrency()));
+static ICONS_IO: Lazy<Semaphore> = Lazy::new(|| new_store_semaphore(CONFIG.icons_io_concurrency()));
+// Running multiple backups at the same time is never useful
+static BACKUPS_IO: Lazy<Semaphore> = Lazy::new(|| Semaphore::new(1));
+
+/// Waits until the file store allows another IO operation, the operation may run as long as the permit is held.
+/// This keeps large file operations from starving the other volumes, like the one the database is on.
+pub async fn acquire_io_permit(store: FileStore) -> SemaphorePermit<'static> {
+    let semaphore: &'static Semaphore = match store {
+        FileStore::Attachments => &ATTACHMENTS_IO,
+        FileStore::Sends => &SENDS_IO,
+        FileStore::Icons => &ICONS_IO,
+        FileStore::Backups => &BACKUPS_IO,
+    };
+    // The semaphores are never closed, so acquiring a permit can not fail
+    semaphore.acquire().await.expect("File store semaphore closed")
+}
+
 pub fn convert_json_key_lcase_first(src_json: Value) -> Value {
     match src_json {
         Value::Array(elm) => {
@@ -824,13 +923,24 @@ pub fn is_global(ip: std::net::IpAddr) -> bool {
 /// To run while showing progress output:
 /// cargo +nightly test --release --features sqlite,unstable -- --nocapture --ignored
 #[cfg(test)]
-#[cfg(feature = "unstable")]
 mod tests {
     use super::*;
+    #[cfg(feature = "unstable")]
     use std::net::IpAddr;
 
+    #[test]
+    fn test_normalize_plus_address() {
+        assert_eq!(normalize_plus_address("User+Tag@Example.org"), "user@example.org");
+        assert_eq!(normalize_plus_address("user+a+b@example.org"), "user@example.org");
+        assert_eq!(normalize_plus_address("user@example.org"), "user@example.org");
+        // Without a local part before the `+` the address is kept as it is
+        assert_eq!(normalize_plus_address("+tag@example.org"), "+tag@example.org");
+        assert_eq!(normalize_plus_address("no-at-sign"), "no-at-sign");
+    }
+
     #[test]
     #[ignore]
+    #[cfg(feature = "unstable")]
     fn test_ipv4_global() {
         for a in 0..u8::MAX {
             println!("Iter: {}/255", a);
@@ -847,6 +957,7 @@ mod tests {
 
     #[test]
     #[ignore]
+    #[cfg(feature = "unstable")]
     fn test_ipv6_global() {
         use rand::Rng;
 
@@ -0,0 +1,239 @@
+//
+// Supervision of long running background components
+//
+// Components report a heartbeat while they are healthy and a failure whenever an operation
+// could not be completed. The watchdog task periodically checks for components which stopped
+// sending heartbeats, records an incident, alerts the administrator and asks the component
+// to restart itself through the handler it registered.
+//
+use std::{
+    collections::VecDeque,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use chrono::{NaiveDateTime, Utc};
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use serde_json::Value;
+
+use crate::{mail, CONFIG};
+
+// The amount of incidents which are kept in memory to be shown on the diagnostics page
+const MAX_INCIDENTS: usize = 50;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Component {
+    Scheduler,
+    Mail,
+    WebSocket,
+    Push,
+}
+
+impl Component {
+    const ALL: [Component; 4] = [Component::Scheduler, Component::Mail, Component::WebSocket, Component::Push];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Component::Scheduler => "Job scheduler",
+            Component::Mail => "Mail delivery",
+            Component::WebSocket => "WebSocket hub",
+            Component::Push => "Push dispatcher",
+        }
+    }
+}
+
+#[derive(Default)]
+struct ComponentState {
+    // Only components which are expected to report a heartbeat periodically are checked for stalls
+    max_silence: Option<Duration>,
+    last_beat: Option<Instant>,
+    last_beat_date: Option<NaiveDateTime>,
+    failures: u32,
+    restarts: u32,
+    generation: usize,
+    last_alert: Option<Instant>,
+    restart_handler: Option<fn()>,
+}
+
+struct Incident {
+    date: NaiveDateTime,
+    component: Component,
+    message: String,
+}
+
+static STATES: Lazy<DashMap<Component, ComponentState>> = Lazy::new(DashMap::new);
+static INCIDENTS: Lazy<Mutex<VecDeque<Incident>>> = Lazy::new(|| Mutex::new(VecDeque::with_capacity(MAX_INCIDENTS)));
+
+/// Registers the function which restarts the component when it is found to be stalled or failing.
+pub fn register_restart(component: Component, handler: fn()) {
+    STATES.entry(component).or_default().restart_handler = Some(handler);
+}
+
+/// Marks the component as one which reports a heartbeat at least once every `max_silence`.
+pub fn expect_heartbeat(component: Component, max_silence: Duration) {
+    let mut state = STATES.entry(component).or_default();
+    state.max_silence = Some(max_silence);
+    state.last_beat = Some(Instant::now());
+}
+
+pub fn heartbeat(component: Component) {
+    let mut state = STATES.entry(component).or_default();
+    state.last_beat = Some(Instant::now());
+    state.last_beat_date = Some(Utc::now().naive_utc());
+    state.failures = 0;
+}
+
+/// Starts a new generation of the component, any older generation still running should stop.
+pub fn next_generation(component: Component) -> usize {
+    let mut state = STATES.entry(component).or_default();
+    state.generation += 1;
+    state.last_beat = Some(Instant::now());
+    state.generation
+}
+
+pub fn is_current_generation(component: Component, generation: usize) -> bool {
+    STATES.get(&component).is_some_and(|s| s.generation == generation)
+}
+
+/// Reports a failed operation. Once the amount of consecutive failures reaches the configured
+/// threshold an incident is recorded and the component is restarted.
+pub fn report_failure(component: Component, message: &str) {
+    let threshold_reached = {
+        let mut state = STATES.entry(component).or_default();
+        state.failures += 1;
+        state.failures >= CONFIG.watchdog_failure_threshold()
+    };
+
+    if threshold_reached {
+        incident(component, &format!("{message} (repeated failures)"));
+        restart(component);
+    }
+}
+
+/// Records an incident, logs it and notifies the administrator when an alert address is configured.
+pub fn incident(component: Component, message: &str) {
+    error!("Watchdog: {} incident: {message}", component.name());
+
+    {
+        let mut incidents = INCIDENTS.lock().unwrap();
+        if incidents.len() >= MAX_INCIDENTS {
+            incidents.pop_front();
+        }
+        incidents.push_back(Incident {
+            date: Utc::now().naive_utc(),
+            component,
+            message: message.to_string(),
+        });
+    }
+
+    // Alerting via mail is of no use when the mail delivery itself is failing
+    if component == Component::Mail || !CONFIG.mail_enabled() {
+        return;
+    }
+    let Some(address) = CONFIG.admin_alert_email() else {
+        return;
+    };
+
+    // Only send one alert per component within the stall period to prevent flooding the mailbox
+    let alert_interval = Duration::from_secs(CONFIG.watchdog_stall_secs());
+    {
+        let mut state = STATES.entry(component).or_default();
+        if state.last_alert.is_some_and(|t| t.elapsed() < alert_interval) {
+            return;
+        }
+        state.last_alert = Some(Instant::now());
+    }
+
+    let component_name = component.name().to_string();
+    let message = message.to_string();
+    tokio::spawn(async move {
+        if let Err(e) = mail::send_admin_alert(&address, &component_name, &message).await {
+            error!("Error sending watchdog alert email: {e:#?}");
+        }
+    });
+}
+
+fn restart(component: Component) {
+    let handler = {
+        let mut state = STATES.entry(component).or_default();
+        state.failures = 0;
+        state.restarts += 1;
+        // Give the component a full period to come back before considering it stalled again
+        state.last_beat = Some(Instant::now());
+        state.restart_handler
+    };
+
+    if let Some(handler) = handler {
+        warn!("Watchdog: restarting {}", component.name());
+        handler();
+    }
+}
+
+fn check_stalled() {
+    let stalled: Vec<(Component, u64)> = STATES
+        .iter()
+        .filter_map(|s| match (s.max_silence, s.last_beat) {
+            (Some(max), Some(last)) if last.elapsed() > max => Some((*s.key(), last.elapsed().as_secs())),
+            _ => None,
+        })
+        .collect();
+
+    for (component, silent_secs) in stalled {
+        incident(component, &format!("No heartbeat received for {silent_secs} seconds, component seems to be stalled"));
+        restart(component);
+    }
+}
+
+/// Runs the watchdog until the server shuts down.
+pub async fn run() {
+    if CONFIG.watchdog_interval_secs() == 0 {
+        info!("Watchdog disabled.");
+        return;
+    }
+
+    let mut interval = tokio::time::interval(Duration::from_secs(CONFIG.watchdog_interval_secs()));
+    loop {
+        interval.tick().await;
+        check_stalled();
+    }
+}
+
+/// Returns the state of all supervised components and the most recent incidents for the diagnostics page.
+pub fn status_json() -> Value {
+    let components: Vec<Value> = Component::ALL
+        .iter()
+        .map(|c| {
+            let (last_heartbeat, failures, restarts, stalled) = STATES.get(c).map_or((None, 0, 0, false), |s| {
+                let stalled = matches!((s.max_silence, s.last_beat), (Some(max), Some(last)) if last.elapsed() > max);
+                (s.last_beat_date, s.failures, s.restarts, stalled)
+            });
+            json!({
+                "name": c.name(),
+                "healthy": !stalled && failures == 0,
+                "last_heartbeat": last_heartbeat.map(|d| d.format("%Y-%m-%d %H:%M:%S UTC").to_string()),
+                "failures": failures,
+                "restarts": restarts,
+            })
+        })
+        .collect();
+
+    let incidents: Vec<Value> = INCIDENTS
+        .lock()
+        .unwrap()
+        .iter()
+        .rev()
+        .map(|i| {
+            json!({
+                "date": i.date.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+                "component": i.component.name(),
+                "message": i.message,
+            })
+        })
+        .collect();
+
+    json!({
+        "components": components,
+        "incidents": incidents,
+    })
+}